@@ -1,212 +1,1836 @@
-use std::time::Duration;
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
+};
 
+#[cfg(feature = "postgres")]
+use crate::pg_store::PgStore;
 use crate::{
-    database::Database,
+    capture::{
+        capture_files, CaptureConfig, CaptureReader, CaptureRecord, CaptureSink, CaptureWriter,
+    },
+    config::{BitcoindConfig, Config},
+    database::{Database, DEFAULT_NODE_ID},
+    events::EventPublisher,
+    filter::Filter,
+    health::{Health, QUEUE_LAG_FILL},
+    miners::MinerRegistry,
+    now,
+    rate_limit::RateLimiter,
+    rpc::{
+        connect_bitcoind, BitcoinRpc, NodeCapabilities, ReconnectingRpc, RetryingRpc,
+        RpcRetryPolicy,
+    },
+    store::MempoolStore,
+    tip::TipTracker,
     utils::compute_fee_rate,
-    worker::{get_absolute_fee, Task, TaskContext},
-    zmq_factory::BitcoinZmqFactory,
+    worker::{
+        BlockRecorder, DryRunSummary, MempoolPoll, MinedDetection, Queued, SequenceEvent, Task,
+        TaskContext, WorkerStats,
+    },
+    zmq_factory::{BitcoinZmqFactory, SequenceGapDetector, ZmqTopic},
+};
+
+use anyhow::{Context, Result};
+use async_channel::{bounded, Receiver, Sender, TrySendError};
+use bitcoin::{hashes::Hash, Amount, BlockHash};
+use bitcoincore_zmq::{Message, SequenceMessage};
+use bitcoind_async_client::Client;
+use futures_util::{stream, Stream, StreamExt};
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use tokio::{
+    signal::ctrl_c,
+    sync::watch,
+    task::{JoinError, JoinSet},
+    time::{Interval, MissedTickBehavior},
 };
 
-use anyhow::Result;
-use async_channel::{bounded, Receiver, Sender};
-use bitcoind_async_client::{traits::Reader, Client};
-use futures_util::StreamExt;
-use log::{error, info};
-use tokio::signal::ctrl_c;
+/// Raw txs buffered per worker unless `AppConfig::task_channel_capacity` is set
+const TASK_QUEUE_PER_WORKER: usize = 50_000;
+/// Timer driven tasks buffered per worker
+const CONTROL_QUEUE_PER_WORKER: usize = 50;
+/// Mempool txs fetched concurrently during the startup sync
+const MEMPOOL_SYNC_CONCURRENCY: usize = 16;
+/// Fetched txs stored per sqlite transaction during the startup sync
+const MEMPOOL_SYNC_BATCH_SIZE: usize = 500;
+/// The startup sync logs its progress every this many txs
+const MEMPOOL_SYNC_PROGRESS_INTERVAL: usize = 10_000;
+/// Shortest timer periods accepted, each mempool state check lists the whole mempool
+const MIN_MEMPOOL_STATE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const MIN_PRUNE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// The zmq listener warns once the raw tx queue stayed `QUEUE_LAG_FILL` full this long
+const QUEUE_LAG_WARN_AFTER: Duration = Duration::from_secs(5);
+/// How often a send blocked on a full queue adds its wait to `Health::send_blocked`, so a
+/// send that never returns still shows
+const BLOCKED_SEND_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Runtime settings of the tracker
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    /// Concurrent task processors, at least 1. More workers overlap the RPC round trips
+    /// (fee lookups, replacement checks), but SQLite takes a single writer at a time:
+    /// writes from several workers queue on the database lock, and past a few workers
+    /// the extra ones mostly wait on each other
+    pub num_workers: usize,
+    /// At least `MIN_MEMPOOL_STATE_CHECK_INTERVAL`
+    pub mempool_state_check_interval: Duration,
+    /// At least `MIN_PRUNE_CHECK_INTERVAL`. Zero disables the periodic prune check, only
+    /// allowed with the sequence topic, which reports every removal as it happens. The
+    /// check also catches up with blocks whose announcement was lost and detects mass
+    /// evictions (`Database::record_purge_event`), neither happens while it's disabled
+    pub prune_check_interval: Duration,
+    /// How often a checkpoint is recorded for incremental consumers
+    pub checkpoint_interval: Duration,
+    /// Which new transactions get stored
+    pub filter: Filter,
+    /// Refuse to start until the node's verification progress reaches this (0.0 - 1.0)
+    pub min_verification_progress: f64,
+    /// Inclusive block height range to ingest once the workers are up
+    pub backfill: Option<(u64, u64)>,
+    /// Raw txs buffered between the zmq listener and the workers. A larger buffer
+    /// absorbs bursts at the cost of memory and of txs waiting longer before they're
+    /// processed; a smaller one applies backpressure sooner, once full the listener
+    /// stops reading until a worker frees a slot. `None` scales it with `num_workers`
+    pub task_channel_capacity: Option<usize>,
+    /// Warn when the tip hasn't changed for this long
+    pub tip_stale_after: Duration,
+    /// Worker RPC calls per second, 0 is unlimited
+    pub rpc_rate_limit: u32,
+    /// Calls that may go out back to back before the rate limit applies
+    pub rpc_burst: u32,
+    /// Timeout and retries of the worker RPC calls, each retry waits on the rate limit
+    pub rpc_retry: RpcRetryPolicy,
+    /// How long to wait for a first zmq message when the node can't list its zmq
+    /// publishers, zero skips the wait
+    pub zmq_probe_timeout: Duration,
+    pub zmq_reconnect: ZmqReconnectPolicy,
+    /// Topics routed to the workers, messages of other topics are dropped. The node is
+    /// checked for a publisher of each at startup
+    pub zmq_topics: Vec<ZmqTopic>,
+    /// How long the workers get to process the queued tasks on shutdown before the rest
+    /// is dropped
+    pub shutdown_timeout: Duration,
+    /// How the workers find the tracked txs of a new block
+    pub mined_detection: MinedDetection,
+    /// How often the database's size is logged
+    pub stats_log_interval: Duration,
+    /// Under a systemd watchdog, how long without a zmq message or an RPC success before
+    /// the watchdog isn't pinged anymore and systemd restarts the tracker
+    pub watchdog_max_silence: Duration,
+    /// Node the primary's raw tx sightings are attributed to
+    pub node_id: String,
+    /// What a node misconfiguration found at startup does, a node without raw txs on
+    /// zmq or with an unloaded mempool always refuses to start
+    pub startup_checks: StartupCheckMode,
+    /// `-mempoolfullrbf` the replacement analysis assumes, `None` accepts either
+    pub expect_full_rbf: Option<bool>,
+    /// Run the whole pipeline on a `DryRunStore`, counting what would have been written
+    /// and logging the counts on shutdown
+    pub dry_run: bool,
+    /// Feed the workers from a capture file instead of zmq, the app stops once it's
+    /// replayed. The node's mempool isn't extracted, rescanned or pruned against, and the
+    /// replayed txs' prevouts are looked up with `getrawtransaction`, so the node needs
+    /// `-txindex` unless the spent txs are still in its mempool
+    pub replay: Option<ReplaySource>,
+    /// Append every zmq message of the primary node to capture files as it's received
+    pub capture: Option<CaptureConfig>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            num_workers: 2,
+            mempool_state_check_interval: Duration::from_secs(25),
+            prune_check_interval: Duration::from_secs(120),
+            checkpoint_interval: Duration::from_secs(10 * 60),
+            filter: Filter::default(),
+            min_verification_progress: 0.9999,
+            backfill: None,
+            task_channel_capacity: None,
+            tip_stale_after: Duration::from_secs(30 * 60),
+            rpc_rate_limit: 0,
+            rpc_burst: 1,
+            rpc_retry: RpcRetryPolicy::default(),
+            zmq_probe_timeout: Duration::from_secs(10),
+            zmq_reconnect: ZmqReconnectPolicy::default(),
+            zmq_topics: vec![ZmqTopic::RawTx, ZmqTopic::HashBlock],
+            shutdown_timeout: Duration::from_secs(30),
+            mined_detection: MinedDetection::default(),
+            stats_log_interval: Duration::from_secs(60 * 60),
+            watchdog_max_silence: Duration::from_secs(5 * 60),
+            node_id: DEFAULT_NODE_ID.to_string(),
+            startup_checks: StartupCheckMode::default(),
+            expect_full_rbf: None,
+            dry_run: false,
+            replay: None,
+            capture: None,
+        }
+    }
+}
+
+/// Capture file replayed by `replay_capture`
+#[derive(Debug, Clone)]
+pub struct ReplaySource {
+    pub path: PathBuf,
+    /// Wait between records as long as they were apart when captured, otherwise they're
+    /// queued as fast as the workers take them
+    pub realtime: bool,
+}
+
+/// Whether the startup checks of the node's configuration refuse to start
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StartupCheckMode {
+    #[default]
+    Fail,
+    /// Log the problem and start anyway
+    Warn,
+}
+
+impl FromStr for StartupCheckMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "fail" => StartupCheckMode::Fail,
+            "warn" => StartupCheckMode::Warn,
+            _ => return Err(anyhow::anyhow!("Unknown startup check mode: {}", s)),
+        })
+    }
+}
+
+/// How the zmq listener recovers when its subscription errors or ends, e.g. when the
+/// node restarts
+#[derive(Debug, Clone, Copy)]
+pub struct ZmqReconnectPolicy {
+    /// Wait before the first reconnect, doubled after every further failure
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Consecutive failed streams or connection attempts before the listener gives up
+    pub max_failures: u32,
+}
+
+impl Default for ZmqReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            max_failures: 10,
+        }
+    }
+}
+
+/// A worker that exits is replaced after this delay
+const WORKER_RESTART_DELAY: Duration = Duration::from_secs(1);
+/// More restarts than this within `WORKER_RESTART_WINDOW` stops the app instead of
+/// restarting workers in a tight loop
+const MAX_WORKER_RESTARTS: usize = 5;
+const WORKER_RESTART_WINDOW: Duration = Duration::from_secs(60);
+
+/// Counters of a running app
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppMetrics {
+    /// Running workers
+    pub workers: usize,
+    /// Workers replaced after failing or panicking since startup
+    pub worker_restarts: u64,
+    /// Total time workers spent waiting on the RPC rate limit
+    pub rpc_limiter_wait: Duration,
+    /// Seconds since the workers last saw the tip change
+    pub tip_age_secs: u64,
+    /// Raw txs waiting for a worker
+    pub queue_depth: u64,
+    /// Total time the zmq listener waited on a full raw tx queue
+    pub send_blocked: Duration,
+    /// Time the last processed task spent queued
+    pub queue_latency: Duration,
+}
+
+/// Outcome of the startup mempool sync
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MempoolSyncSummary {
+    /// Stored txs, the ones the filter rejected aren't counted
+    pub synced: usize,
+    /// Txs that couldn't be fetched
+    pub failed: usize,
+    pub elapsed: Duration,
+}
+
+/// What became of the queued tasks on shutdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrainReport {
+    /// Processed by the workers before they stopped
+    pub drained: usize,
+    /// Left in the queues, or cut off mid-way, when the shutdown timeout expired
+    pub dropped: usize,
+}
+
+/// A node the startup checks run against, the primary or a `SecondaryNode`
+struct CheckedNode<'a, R: BitcoinRpc> {
+    node_id: &'a str,
+    rpc_client: &'a R,
+    zmq_factory: &'a BitcoinZmqFactory,
+    topics: &'a [ZmqTopic],
+    /// Setting the rawtx endpoint is configured with, for the error messages
+    rawtx_setting: String,
+    /// Secondaries only have their raw txs processed, blocks come from the primary
+    primary: bool,
+}
+
+/// Another node whose raw tx announcements are processed next to the primary's and
+/// attributed to `node_id`, see `App::with_secondary_node`
+#[derive(Debug)]
+pub struct SecondaryNode<R: BitcoinRpc> {
+    pub node_id: String,
+    pub rpc_client: R,
+    /// Only its rawtx endpoint is subscribed to
+    pub zmq_factory: BitcoinZmqFactory,
+    pub rpc_retry: RpcRetryPolicy,
+}
 
 #[derive(Debug)]
-pub struct App {
+pub struct App<R: BitcoinRpc, S: MempoolStore = Database> {
     zmq_factory: BitcoinZmqFactory,
-    db: Database,
-    tasks_tx: Sender<Task>,
-    tasks_rx: Receiver<Task>,
-    rpc_client: Client,
-    num_workers: usize,
-    mempool_state_check_interval: Duration,
-    prune_check_interval: Duration,
+    db: S,
+    events: EventPublisher,
+    /// Timer driven tasks, workers prioritize these over raw txs
+    control_tx: Sender<Queued>,
+    control_rx: Receiver<Queued>,
+    tasks_tx: Sender<Queued>,
+    tasks_rx: Receiver<Queued>,
+    rpc_client: R,
+    /// Last tip observed by the workers
+    tip: TipTracker,
+    /// Last mempool poll, diffed against by whichever worker handles the next one
+    mempool_poll: MempoolPoll,
+    /// Keeps the workers from recording the same block twice
+    block_recorder: BlockRecorder,
+    /// Set for a dry run
+    dry_run: Option<DryRunSummary>,
+    /// Shared by all workers
+    rpc_limiter: RateLimiter,
+    workers: JoinSet<Result<()>>,
+    /// Tells the workers' long tasks to stop, set by `drain_workers`
+    shutdown: watch::Sender<bool>,
+    worker_restarts: u64,
+    /// Of every worker spawned, replaced ones included so their work still counts
+    worker_stats: Vec<WorkerStats>,
+    /// When the restarts within the last `WORKER_RESTART_WINDOW` happened
+    recent_restarts: VecDeque<Instant>,
+    health: Health,
+    config: AppConfig,
+    secondary_nodes: Vec<SecondaryNode<R>>,
 }
 
-impl App {
+/// Client of the node a `[bitcoind]` table describes, rebuilt with fresh credentials on
+/// every reconnect
+fn node_rpc(bitcoind: &BitcoindConfig) -> Result<ReconnectingRpc<Client>> {
+    let auth = bitcoind.auth()?;
+    let url = bitcoind.url.clone();
+    ReconnectingRpc::new(
+        move || connect_bitcoind(&url, &auth),
+        Duration::from_secs(1),
+    )
+}
+
+fn node_rpc_retry(bitcoind: &BitcoindConfig) -> RpcRetryPolicy {
+    RpcRetryPolicy {
+        timeout: Duration::from_secs(bitcoind.timeout),
+        attempts: bitcoind.attempts,
+        ..Default::default()
+    }
+}
+
+/// The sqlite file of a config's `[database]` with its settings applied, opened
+/// read-only for a dry run
+pub fn open_database(config: &Config) -> Result<Database> {
+    let db = if config.database.dry_run {
+        Database::open_read_only(&config.database.path)
+    } else {
+        Database::open_with_pool_size(
+            &config.database.path,
+            config.database.synchronous,
+            config.database.pool_size,
+        )
+    }
+    .with_context(|| format!("opening database {}", config.database.path))?
+    .with_coinbase_maturity(config.database.coinbase_maturity)
+    .with_retention(config.retention.retention());
+    let db = match config.database.dust_threshold {
+        Some(threshold) => db.with_dust_threshold(Amount::from_sat(threshold)),
+        None => db,
+    };
+    let db = match config.database.inputs_hash_tag.clone() {
+        Some(tag) => db.with_inputs_hash_tag(tag),
+        None => db,
+    };
+    let db = match config.workers.large_value_threshold {
+        Some(threshold) => db.with_large_value_threshold(Amount::from_sat(threshold)),
+        None => db,
+    };
+    Ok(match &config.database.miner_mapping {
+        Some(path) => db.with_miner_registry(MinerRegistry::builtin().with_mapping_file(path)?),
+        None => db,
+    })
+}
+
+/// `open_database` for the Postgres server of `database.postgres_url`, migrated on
+/// connect unless it's a dry run
+#[cfg(feature = "postgres")]
+pub async fn connect_postgres(config: &Config) -> Result<PgStore> {
+    let url = config
+        .database
+        .postgres_url
+        .as_deref()
+        .context("database.postgres_url is not set")?;
+    let db = if config.database.dry_run {
+        PgStore::connect_read_only(url).await
+    } else {
+        PgStore::connect(url).await
+    }
+    .context("connecting to the Postgres store")?
+    .with_coinbase_maturity(config.database.coinbase_maturity)
+    .with_retention(config.retention.retention());
+    let db = match config.database.dust_threshold {
+        Some(threshold) => db.with_dust_threshold(Amount::from_sat(threshold)),
+        None => db,
+    };
+    let db = match config.database.inputs_hash_tag.clone() {
+        Some(tag) => db.with_inputs_hash_tag(tag),
+        None => db,
+    };
+    let db = match config.workers.large_value_threshold {
+        Some(threshold) => db.with_large_value_threshold(Amount::from_sat(threshold)),
+        None => db,
+    };
+    Ok(match &config.database.miner_mapping {
+        Some(path) => db.with_miner_registry(MinerRegistry::builtin().with_mapping_file(path)?),
+        None => db,
+    })
+}
+
+impl App<ReconnectingRpc<Client>> {
+    /// Build the node client, zmq subscription and sqlite database a config file
+    /// describes, publishing to `events`, whose sinks need async setup. Every setting the
+    /// file doesn't cover keeps its default. A dry run records into a `DryRunStore` over
+    /// `open_database` instead, see `from_config_with_store`
+    pub fn from_config(config: Config, events: EventPublisher) -> Result<Self> {
+        if config.database.dry_run {
+            return Err(anyhow::anyhow!(
+                "database.dry_run records into a DryRunStore, see from_config_with_store"
+            ));
+        }
+        let db = open_database(&config)?;
+        Self::from_config_with_store(config, db, events)
+    }
+}
+
+impl<S: MempoolStore> App<ReconnectingRpc<Client>, S> {
+    /// The node clients, zmq subscriptions and settings a config file describes, recording
+    /// into `db`, already opened with the file's `[database]` settings, e.g. by
+    /// `open_database`
+    pub fn from_config_with_store(config: Config, db: S, events: EventPublisher) -> Result<Self> {
+        let zmq_factory = config.zmq.zmq_factory()?;
+        let rpc_client = node_rpc(&config.bitcoind)?;
+        let mut secondary_nodes = vec![];
+        for node in &config.nodes {
+            let (host, port) = crate::config::parse_zmq_endpoint(&node.zmq.rawtx)?;
+            secondary_nodes.push(SecondaryNode {
+                node_id: node.id.clone(),
+                rpc_client: node_rpc(&node.bitcoind)
+                    .with_context(|| format!("connecting to node {}", node.id))?,
+                zmq_factory: BitcoinZmqFactory::new(host, port),
+                rpc_retry: node_rpc_retry(&node.bitcoind),
+            });
+        }
+
+        let intervals = &config.intervals;
+        let app_config = AppConfig {
+            num_workers: config.workers.count,
+            mined_detection: config.workers.mined_detection,
+            task_channel_capacity: config.workers.task_channel_capacity,
+            rpc_rate_limit: config.workers.rpc_rate_limit,
+            rpc_burst: config.workers.rpc_burst,
+            filter: config.filter.filter()?,
+            backfill: config.backfill.map(|backfill| (backfill.from, backfill.to)),
+            mempool_state_check_interval: Duration::from_secs(intervals.mempool_state_check),
+            prune_check_interval: Duration::from_secs(intervals.prune_check),
+            checkpoint_interval: Duration::from_secs(intervals.checkpoint),
+            tip_stale_after: Duration::from_secs(intervals.tip_stale_after),
+            zmq_probe_timeout: Duration::from_secs(intervals.zmq_probe_timeout),
+            shutdown_timeout: Duration::from_secs(intervals.shutdown_timeout),
+            stats_log_interval: Duration::from_secs(intervals.stats_log),
+            watchdog_max_silence: Duration::from_secs(intervals.watchdog_max_silence),
+            rpc_retry: node_rpc_retry(&config.bitcoind),
+            node_id: config.node_id.clone(),
+            startup_checks: config.startup_checks.mode,
+            expect_full_rbf: config.startup_checks.expect_full_rbf,
+            dry_run: config.database.dry_run,
+            capture: config.capture.capture_config(),
+            zmq_topics: config
+                .zmq
+                .topics
+                .clone()
+                .unwrap_or_else(|| AppConfig::default().zmq_topics),
+            ..Default::default()
+        };
+        let app = Self::new(rpc_client, zmq_factory, db, events, app_config);
+        Ok(secondary_nodes
+            .into_iter()
+            .fold(app, |app, node| app.with_secondary_node(node)))
+    }
+}
+
+impl<R: BitcoinRpc, S: MempoolStore> App<R, S> {
     pub fn new(
-        rpc_client: Client,
+        rpc_client: R,
         zmq_factory: BitcoinZmqFactory,
-        db: Database,
-        num_workers: usize,
-        mempool_state_check_interval: Duration,
-        prune_check_interval: Duration,
+        db: S,
+        events: EventPublisher,
+        config: AppConfig,
     ) -> Self {
-        let (sender, receiver) = bounded(100_000);
+        let workers = config.num_workers.max(1);
+        let (sender, receiver) = bounded(
+            config
+                .task_channel_capacity
+                .unwrap_or(TASK_QUEUE_PER_WORKER * workers),
+        );
+        let (control_tx, control_rx) = bounded(CONTROL_QUEUE_PER_WORKER * workers);
         Self {
             rpc_client,
             zmq_factory,
             db,
+            events,
+            control_tx,
+            control_rx,
             tasks_tx: sender,
             tasks_rx: receiver,
-            num_workers,
-            mempool_state_check_interval,
-            prune_check_interval,
+            tip: TipTracker::new(config.tip_stale_after),
+            mempool_poll: MempoolPoll::default(),
+            block_recorder: BlockRecorder::default(),
+            dry_run: config.dry_run.then(DryRunSummary::default),
+            rpc_limiter: RateLimiter::new(config.rpc_rate_limit, config.rpc_burst),
+            workers: JoinSet::new(),
+            shutdown: watch::channel(false).0,
+            worker_restarts: 0,
+            worker_stats: vec![],
+            recent_restarts: VecDeque::new(),
+            health: Health::default(),
+            config,
+            secondary_nodes: vec![],
+        }
+    }
+
+    /// Also process `node`'s raw txs once running, to compare when each node announced
+    /// a tx, see `Database::propagation_deltas`
+    pub fn with_secondary_node(mut self, node: SecondaryNode<R>) -> Self {
+        self.secondary_nodes.push(node);
+        self
+    }
+
+    /// Sender side of the raw tx queue
+    pub fn task_sender(&self) -> Sender<Queued> {
+        self.tasks_tx.clone()
+    }
+
+    /// What a dry run would have written so far, `None` unless `AppConfig::dry_run`
+    pub fn dry_run_summary(&self) -> Option<DryRunSummary> {
+        self.dry_run.clone()
+    }
+
+    /// Cached liveness and readiness, for the health probes
+    pub fn health(&self) -> Health {
+        self.health.clone()
+    }
+
+    /// Publisher the workers emit through, for in-process subscribers
+    pub fn events(&self) -> EventPublisher {
+        self.events.clone()
+    }
+
+    /// Shared record of the last observed tip
+    pub fn tip_tracker(&self) -> TipTracker {
+        self.tip.clone()
+    }
+
+    /// Seconds since the workers last saw the tip change, a growing value means our
+    /// view of the chain fell behind the network
+    pub fn tip_age_secs(&self) -> u64 {
+        self.tip.age_secs()
+    }
+
+    /// Total time workers spent waiting on the RPC rate limit
+    pub fn rpc_limiter_wait(&self) -> Duration {
+        self.rpc_limiter.waited()
+    }
+
+    pub fn metrics(&self) -> AppMetrics {
+        AppMetrics {
+            workers: self.workers.len(),
+            worker_restarts: self.worker_restarts,
+            rpc_limiter_wait: self.rpc_limiter.waited(),
+            tip_age_secs: self.tip.age_secs(),
+            queue_depth: self.health.queue_depth(),
+            send_blocked: self.health.send_blocked(),
+            queue_latency: self.health.queue_latency(),
+        }
+    }
+
+    /// Tasks processed and failed by the primary node's workers since startup, with the
+    /// latest task and error of any of them
+    pub fn worker_stats(&self) -> WorkerStats {
+        WorkerStats::aggregate(&self.worker_stats)
+    }
+
+    /// Counters of each worker in the order they were spawned, replaced ones included. A
+    /// single wedged worker shows here as a stale `last_task_at`, the aggregate hides it
+    pub fn per_worker_stats(&self) -> Vec<WorkerStats> {
+        self.worker_stats.clone()
+    }
+
+    fn spawn_worker(&mut self, rpc_client: R) {
+        let bitcoind = RetryingRpc::new(rpc_client, self.config.rpc_retry)
+            .with_rate_limit(self.rpc_limiter.clone());
+        let mut task_context = TaskContext::new(
+            bitcoind,
+            self.db.clone(),
+            self.events.clone(),
+            self.config.filter.clone(),
+            self.control_rx.clone(),
+            self.tasks_rx.clone(),
+            self.tip.clone(),
+        )
+        .with_health(self.health.clone())
+        .with_mined_detection(self.config.mined_detection)
+        .with_node_id(self.config.node_id.clone())
+        .with_mempool_poll(self.mempool_poll.clone())
+        .with_block_recorder(self.block_recorder.clone())
+        .with_shutdown(self.shutdown.subscribe());
+        if let Some(summary) = &self.dry_run {
+            task_context = task_context.with_dry_run(summary.clone());
+        }
+        self.worker_stats.push(task_context.stats());
+        self.workers.spawn(async move { task_context.run().await });
+    }
+
+    /// Start `node`'s zmq listener and workers on `tasks`, they end once `shutdown` fires
+    /// and the queue is drained. Only raw txs are routed: the resyncs a reconnect asks
+    /// for are dropped, a rescan against this node would prune what only the primary has
+    fn spawn_secondary_node(
+        &self,
+        node: &SecondaryNode<R>,
+        tasks: &mut JoinSet<Result<()>>,
+        shutdown: tokio::sync::broadcast::Receiver<()>,
+    ) -> Result<()> {
+        let workers = self.config.num_workers.max(1);
+        let (tasks_tx, tasks_rx) = bounded(TASK_QUEUE_PER_WORKER * workers);
+        let (control_tx, control_rx) = bounded::<Queued>(CONTROL_QUEUE_PER_WORKER);
+        tasks.spawn(async move {
+            while control_rx.recv().await.is_ok() {}
+            Ok(())
+        });
+        let zmq_factory = node.zmq_factory.clone();
+        tasks.spawn(listen_zmq(
+            node.zmq_factory.connect()?,
+            move || zmq_factory.connect(),
+            control_tx,
+            // Kept apart from the primary's queue depth
+            QueueSender::new(tasks_tx, Health::default()),
+            shutdown,
+            self.config.zmq_reconnect,
+            vec![ZmqTopic::RawTx],
+        ));
+
+        // Never sent to, the workers only see raw txs
+        let (_, no_control) = bounded::<Queued>(1);
+        let rpc_limiter = RateLimiter::new(self.config.rpc_rate_limit, self.config.rpc_burst);
+        for _ in 0..workers {
+            let bitcoind = RetryingRpc::new(node.rpc_client.clone(), node.rpc_retry)
+                .with_rate_limit(rpc_limiter.clone());
+            let task_context = TaskContext::new(
+                bitcoind,
+                self.db.clone(),
+                self.events.clone(),
+                self.config.filter.clone(),
+                no_control.clone(),
+                tasks_rx.clone(),
+                self.tip.clone(),
+            )
+            .with_mined_detection(self.config.mined_detection)
+            .with_node_id(node.node_id.clone());
+            tasks.spawn(async move { task_context.run().await });
         }
+        info!(
+            "Processing the announcements of node {} with {} workers",
+            node.node_id, workers
+        );
+        Ok(())
+    }
+
+    /// Wait for the next worker to exit and replace it, see `handle_worker_exit`.
+    /// Returns `false` when there are no workers
+    pub async fn supervise_next_worker_exit(&mut self) -> Result<bool> {
+        let Some(exit) = self.workers.join_next().await else {
+            return Ok(false);
+        };
+        if self.handle_worker_exit(exit)? {
+            tokio::time::sleep(WORKER_RESTART_DELAY).await;
+            self.restart_worker();
+        }
+        Ok(true)
+    }
+
+    /// Whether a worker that returned or panicked is to be replaced, i.e. the queues
+    /// weren't closed for shutdown. Errors once workers keep failing faster than the
+    /// restart limit allows
+    fn handle_worker_exit(&mut self, exit: Result<Result<()>, JoinError>) -> Result<bool> {
+        match exit {
+            Ok(Ok(())) if self.tasks_tx.is_closed() => return Ok(false),
+            Ok(Ok(())) => warn!("Worker exited unexpectedly"),
+            Ok(Err(e)) => error!("Worker failed: {:#}", e),
+            Err(e) => error!("Worker died: {}", e),
+        }
+        let now = Instant::now();
+        self.recent_restarts
+            .retain(|restarted_at| now.duration_since(*restarted_at) < WORKER_RESTART_WINDOW);
+        if self.recent_restarts.len() >= MAX_WORKER_RESTARTS {
+            return Err(anyhow::anyhow!(
+                "Workers failed {} times within {:?}, not restarting",
+                self.recent_restarts.len() + 1,
+                WORKER_RESTART_WINDOW
+            ));
+        }
+        self.recent_restarts.push_back(now);
+        Ok(true)
+    }
+
+    /// Spawn a replacement worker on a fresh RPC connection, the failed worker's may be
+    /// what broke it. Falls back to the shared client when connecting fails
+    fn restart_worker(&mut self) {
+        let rpc_client = self.rpc_client.fresh_connection().unwrap_or_else(|e| {
+            warn!(
+                "Restarting worker on the shared RPC connection, a fresh one failed: {:#}",
+                e
+            );
+            self.rpc_client.clone()
+        });
+        self.spawn_worker(rpc_client);
+        self.worker_restarts += 1;
+        info!(
+            "Restarted worker, {} restarts since startup",
+            self.worker_restarts
+        );
     }
 
-    async fn extract_existing_mempool(&self) -> Result<()> {
-        // let bitcoind = connect_bitcoind(&self.bitcoind_url, self.bitcoind_auth.clone())?;
+    /// Store the node's current mempool. The txs are fetched `MEMPOOL_SYNC_CONCURRENCY` at
+    /// a time and their fees taken from the verbose mempool entries, a tx that can't be
+    /// fetched (e.g. it left the mempool meanwhile) is counted and skipped
+    pub async fn extract_existing_mempool(&self) -> Result<MempoolSyncSummary> {
+        let started = Instant::now();
         let mempool = self.rpc_client.get_raw_mempool_verbose().await?;
-        info!("Found {} transactions in mempool", mempool.len());
-
-        for (txid, mempool_tx) in mempool.iter() {
-            let pool_entrance_time = mempool_tx.time;
-            match self
-                .rpc_client
-                .get_raw_transaction_verbosity_zero(txid)
-                .await
-            {
-                Ok(tx_info) => {
-                    let tx = tx_info.transaction()?;
-                    let absolute_fee = get_absolute_fee(&tx, &self.rpc_client).await?;
-                    let fee_rate = compute_fee_rate(&tx, absolute_fee)?;
-                    self.db.insert_mempool_tx(
-                        tx,
-                        Some(pool_entrance_time),
-                        absolute_fee,
-                        fee_rate,
-                    )?;
+        let total = mempool.len();
+        info!("Found {} transactions in mempool", total);
+        for (txid, entry) in mempool.iter() {
+            self.db
+                .record_tx_links(txid, &entry.depends, &entry.spent_by)
+                .await?;
+        }
+
+        let mut fetches = stream::iter(mempool)
+            .map(|(txid, entry)| async move {
+                let tx = self.rpc_client.get_raw_transaction(&txid).await?;
+                let fee_rate = compute_fee_rate(&tx, entry.fee)?;
+                Ok::<_, anyhow::Error>((tx, entry, fee_rate))
+            })
+            .buffer_unordered(MEMPOOL_SYNC_CONCURRENCY);
+        let mut summary = MempoolSyncSummary::default();
+        let mut batch = Vec::with_capacity(MEMPOOL_SYNC_BATCH_SIZE);
+        let mut fetched = 0;
+        while let Some(fetch) = fetches.next().await {
+            fetched += 1;
+            match fetch {
+                Ok((tx, entry, fee_rate)) => {
+                    if self.config.filter.matches(&tx, fee_rate) {
+                        batch.push((tx, Some(entry.time * 1000), entry.fee, fee_rate));
+                    }
                 }
                 Err(e) => {
-                    error!("Error getting transaction info: {}", e);
+                    warn!("Skipping mempool tx during sync: {:#}", e);
+                    summary.failed += 1;
                 }
             }
+            if batch.len() >= MEMPOOL_SYNC_BATCH_SIZE {
+                self.db.insert_mempool_txs(&batch).await?;
+                summary.synced += batch.len();
+                batch.clear();
+            }
+            if fetched % MEMPOOL_SYNC_PROGRESS_INTERVAL == 0 {
+                info!(
+                    "Mempool sync: {}/{} fetched, {} failed",
+                    fetched, total, summary.failed
+                );
+            }
         }
-
-        Ok(())
+        self.db.insert_mempool_txs(&batch).await?;
+        summary.synced += batch.len();
+        summary.elapsed = started.elapsed();
+        info!(
+            "Synced {} mempool txs in {:?}, {} failed",
+            summary.synced, summary.elapsed, summary.failed
+        );
+        Ok(summary)
     }
 
-    pub async fn init(&mut self) -> Result<()> {
-        let blockchain_info = self.rpc_client.get_blockchain_info().await?;
-        info!("Blockchain info: {:?}", blockchain_info);
+    /// Refuse to start against a node that is still syncing, RPC calls made
+    /// while it catches up fail mid-extraction with confusing errors
+    async fn check_node_health(&self, node: &CheckedNode<'_, R>) -> Result<()> {
+        let blockchain_info = node.rpc_client.get_blockchain_info().await?;
+        info!(
+            "Node {} blockchain info: {:?}",
+            node.node_id, blockchain_info
+        );
 
-        if !blockchain_info.initial_block_download {
-            error!("Blockchain is still in initial block download");
-            return Err(anyhow::anyhow!("Blockchain is still in initial block download"));
+        if blockchain_info.initial_block_download {
+            self.startup_check_failed(
+                "Node health check failed: blockchain is still in initial block download"
+                    .to_string(),
+            )?;
+        }
+        if blockchain_info.verification_progress < self.config.min_verification_progress {
+            self.startup_check_failed(format!(
+                "Node health check failed: verification progress {} is below the required {}",
+                blockchain_info.verification_progress, self.config.min_verification_progress
+            ))?;
         }
 
-        let mempool_info = self.rpc_client.get_mempool_info().await?;
-        info!("Mempool info: {:?}", mempool_info);
+        let mempool_info = node.rpc_client.get_mempool_info().await?;
+        info!("Node {} mempool info: {:?}", node.node_id, mempool_info);
 
         if !mempool_info.loaded {
             error!("Mempool is not loaded");
-            return Err(anyhow::anyhow!("Mempool is not loaded"));
+            return Err(anyhow::anyhow!(
+                "Node health check failed: mempool is not loaded"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Connecting to a zmq endpoint succeeds whether or not the node publishes on it,
+    /// so make sure raw txs will actually arrive
+    async fn check_zmq_notifications(&self, node: &CheckedNode<'_, R>) -> Result<()> {
+        let port = node.zmq_factory.port();
+        let notifications = match node.rpc_client.get_zmq_notifications().await {
+            Ok(notifications) => notifications,
+            Err(e) => {
+                warn!(
+                    "Can't list the zmq publishers of node {}: {:#}",
+                    node.node_id, e
+                );
+                return self.wait_for_zmq_message(node.zmq_factory).await;
+            }
+        };
+        info!(
+            "Node {} zmq notifications: {:?}",
+            node.node_id, notifications
+        );
+
+        let published = |kind: &str| {
+            notifications
+                .iter()
+                .filter(|notification| notification.kind == kind)
+                .collect::<Vec<_>>()
+        };
+        let rawtx = published("pubrawtx");
+        if rawtx.is_empty() {
+            error!("Node {} publishes no raw txs over zmq", node.node_id);
+            return Err(anyhow::anyhow!(
+                "zmq check failed: node {} has no pubrawtx notification, start bitcoind with -zmqpubrawtx=tcp://0.0.0.0:{}",
+                node.node_id,
+                port
+            ));
+        }
+        if !rawtx
+            .iter()
+            .any(|notification| notification.port() == Some(port))
+        {
+            let addresses: Vec<&str> = rawtx
+                .iter()
+                .map(|notification| notification.address.as_str())
+                .collect();
+            error!(
+                "Node {} publishes raw txs on {:?}, not on port {}",
+                node.node_id, addresses, port
+            );
+            return Err(anyhow::anyhow!(
+                "zmq check failed: node {} publishes raw txs on {:?} but the port of {} is {}",
+                node.node_id,
+                addresses,
+                node.rawtx_setting,
+                port
+            ));
+        }
+        let ports = node.zmq_factory.ports();
+        for topic in node.topics {
+            if *topic == ZmqTopic::RawTx {
+                continue;
+            }
+            let notification = topic.notification();
+            if !published(&notification).iter().any(|notification| {
+                notification
+                    .port()
+                    .is_some_and(|notification_port| ports.contains(&notification_port))
+            }) {
+                self.startup_check_failed(format!(
+                    "zmq check failed: node {} publishes no {} messages on ports {:?}, start bitcoind with \
+                    -zmq{}=tcp://0.0.0.0:{} or drop the topic",
+                    node.node_id, topic, ports, notification, port
+                ))?;
+            }
+        }
+        if node.primary
+            && !node.topics.iter().any(|topic| {
+                matches!(
+                    topic,
+                    ZmqTopic::HashBlock | ZmqTopic::RawBlock | ZmqTopic::Sequence
+                )
+            })
+        {
+            warn!("No block topic subscribed, mined txs are left to the prune checks");
+        }
+        Ok(())
+    }
+
+    /// Indexes and mempool policy the tracker relies on, summarized at info level so the
+    /// logs show what the node supports. A node that can't list them fails the check too
+    async fn check_node_capabilities(&self, node: &CheckedNode<'_, R>) -> Result<()> {
+        let capabilities = match node.rpc_client.get_node_capabilities().await {
+            Ok(capabilities) => capabilities,
+            Err(e) => {
+                return self.startup_check_failed(format!(
+                    "Node check failed: can't list the indexes and mempool policy of node {}: {:#}",
+                    node.node_id, e
+                ))
+            }
+        };
+        let mut indexes: Vec<String> = capabilities
+            .indexes
+            .iter()
+            .map(|(name, synced)| {
+                if *synced {
+                    name.clone()
+                } else {
+                    format!("{} (syncing)", name)
+                }
+            })
+            .collect();
+        indexes.sort();
+        info!(
+            "Node {} {} ({}): indexes [{}], mempoolfullrbf {}, zmq topics {:?}",
+            node.node_id,
+            capabilities.subversion,
+            capabilities.version,
+            indexes.join(", "),
+            match capabilities.full_rbf {
+                Some(true) => "on",
+                Some(false) => "off",
+                None => "unknown",
+            },
+            node.topics
+        );
+
+        match capabilities.indexes.get(NodeCapabilities::TXINDEX) {
+            None => self.startup_check_failed(
+                "Node check failed: txindex is off, fees of txs with confirmed parents and the \
+                status of mined txs can't be looked up, start bitcoind with -txindex"
+                    .to_string(),
+            )?,
+            Some(false) => warn!("txindex is still syncing, lookups of older txs may fail"),
+            Some(true) => {}
+        }
+        if node.primary
+            && self.config.mined_detection == MinedDetection::BlockFilter
+            && !capabilities.has_index(NodeCapabilities::BLOCK_FILTER_INDEX)
+        {
+            self.startup_check_failed(
+                "Node check failed: block-filter mined detection needs a node started with \
+                -blockfilterindex"
+                    .to_string(),
+            )?;
+        }
+        if let (Some(expected), Some(full_rbf)) =
+            (self.config.expect_full_rbf, capabilities.full_rbf)
+        {
+            if expected != full_rbf {
+                self.startup_check_failed(format!(
+                    "Node check failed: the node runs with mempoolfullrbf={} but the replacement \
+                    analysis expects {}, set -mempoolfullrbf={} or change expect_full_rbf",
+                    full_rbf as u8, expected as u8, expected as u8
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_intervals(&self) -> Result<()> {
+        if self.config.mempool_state_check_interval < MIN_MEMPOOL_STATE_CHECK_INTERVAL {
+            return Err(anyhow::anyhow!(
+                "The mempool state check interval must be at least {:?}",
+                MIN_MEMPOOL_STATE_CHECK_INTERVAL
+            ));
+        }
+        if self.config.prune_check_interval.is_zero() {
+            if !self.config.zmq_topics.contains(&ZmqTopic::Sequence) {
+                return Err(anyhow::anyhow!(
+                    "The prune check can only be disabled with the sequence topic, nothing else \
+                    notices evicted txs"
+                ));
+            }
+            info!(
+                "Periodic prune check disabled, removals come over the sequence topic. Lost \
+                block announcements aren't caught up with and purges aren't detected"
+            );
+        } else if self.config.prune_check_interval < MIN_PRUNE_CHECK_INTERVAL {
+            return Err(anyhow::anyhow!(
+                "The prune check interval must be at least {:?}",
+                MIN_PRUNE_CHECK_INTERVAL
+            ));
+        }
+        Ok(())
+    }
+
+    /// Refuse to start over `message`, or only warn in `StartupCheckMode::Warn`
+    fn startup_check_failed(&self, message: String) -> Result<()> {
+        match self.config.startup_checks {
+            StartupCheckMode::Fail => {
+                error!("{}", message);
+                Err(anyhow::anyhow!(message))
+            }
+            StartupCheckMode::Warn => {
+                warn!("{}, starting anyway", message);
+                Ok(())
+            }
+        }
+    }
+
+    /// Fallback for nodes without getzmqnotifications. A quiet mempool can legitimately
+    /// stay silent, so only warn
+    async fn wait_for_zmq_message(&self, zmq_factory: &BitcoinZmqFactory) -> Result<()> {
+        let timeout = self.config.zmq_probe_timeout;
+        if timeout.is_zero() {
+            return Ok(());
+        }
+        let mut stream = zmq_factory.connect()?;
+        match tokio::time::timeout(timeout, stream.next()).await {
+            Ok(Some(Ok(message))) => {
+                debug!("First zmq message on topic {}", message.topic_str());
+            }
+            Ok(Some(Err(e))) => return Err(e.into()),
+            Ok(None) | Err(_) => warn!(
+                "No zmq message within {:?}, check that bitcoind runs with -zmqpubrawtx=tcp://0.0.0.0:{}",
+                timeout,
+                zmq_factory.port()
+            ),
+        }
+        Ok(())
+    }
+
+    pub async fn init(&mut self) -> Result<()> {
+        if self.config.num_workers == 0 {
+            return Err(anyhow::anyhow!("At least one worker is required"));
+        }
+        self.check_intervals()?;
+        if self.dry_run.is_some() && !self.db.is_read_only() {
+            return Err(anyhow::anyhow!(
+                "A dry run needs a store that drops its writes, see DryRunStore"
+            ));
+        }
+        if self.dry_run.is_some() {
+            warn!("===== Dry run: nothing will be written, outcomes are counted instead =====");
+        } else if self.db.is_read_only() {
+            warn!("===== Read-only mode: nothing will be written to the database =====");
+        }
+        let secondary_topics = [ZmqTopic::RawTx];
+        let nodes = std::iter::once(CheckedNode {
+            node_id: &self.config.node_id,
+            rpc_client: &self.rpc_client,
+            zmq_factory: &self.zmq_factory,
+            topics: &self.config.zmq_topics,
+            rawtx_setting: "--bitcoind-zmq-port (zmq.rawtx in a config file)".to_string(),
+            primary: true,
+        })
+        .chain(self.secondary_nodes.iter().map(|node| CheckedNode {
+            node_id: &node.node_id,
+            rpc_client: &node.rpc_client,
+            zmq_factory: &node.zmq_factory,
+            topics: &secondary_topics,
+            rawtx_setting: format!("zmq.rawtx of [[nodes]] {}", node.node_id),
+            primary: false,
+        }));
+        for node in nodes {
+            let checked = async {
+                self.check_node_health(&node).await?;
+                if node.primary {
+                    self.health.record_rpc(true);
+                }
+                if self.config.replay.is_none() {
+                    self.check_zmq_notifications(&node).await?;
+                }
+                self.check_node_capabilities(&node).await
+            }
+            .await;
+            if node.primary {
+                checked?;
+            } else {
+                checked
+                    .with_context(|| format!("Startup checks of node {} failed", node.node_id))?;
+            }
         }
 
         info!("Initializing mempool tracker");
         // Run migrations
         info!("Running migrations");
-        self.db.run_migrations()?;
-        // Any txs that are neither pruned nor mined should be removed
-        info!("Removing stale txs");
-        self.db.remove_stale_txs()?;
-        // Extract existing mempool
-        info!("Extracting existing mempool");
-        self.extract_existing_mempool().await?;
+        self.db.run_migrations().await?;
+        self.health.record_db_write(!self.db.is_read_only());
+        // A replay rebuilds the capture's mempool, the node's mempool of today isn't part of it
+        if self.config.replay.is_none() {
+            info!("Extracting existing mempool");
+            self.extract_existing_mempool().await?;
+        }
+        self.health.set_ready();
         // Start workers
-        let mut task_handles = vec![];
-        for _ in 0..self.num_workers {
-            let bitcoind = self.rpc_client.clone();
-            let mut task_context =
-                TaskContext::new(bitcoind, self.db.clone(), self.tasks_rx.clone());
-            task_handles.push(tokio::spawn(async move { task_context.run().await }));
+        for _ in 0..self.config.num_workers {
+            self.spawn_worker(self.rpc_client.clone());
+        }
+        // Rows left pending by a previous run may have been mined or evicted while we were down
+        if self.config.replay.is_none() {
+            self.tasks_tx.send(Task::Rescan.into()).await?;
+        }
+        if let Some((from_height, to_height)) = self.config.backfill {
+            info!(
+                "Queueing backfill of blocks {} to {}",
+                from_height, to_height
+            );
+            self.tasks_tx
+                .send(
+                    Task::Backfill {
+                        from_height,
+                        to_height,
+                    }
+                    .into(),
+                )
+                .await?;
         }
         Ok(())
     }
 
     pub async fn run(&mut self) -> Result<()> {
         info!("===== Starting mempool tracker =====");
-        let tasks_tx = self.tasks_tx.clone();
-        let tasks_tx_2 = self.tasks_tx.clone();
-        let tasks_tx_3 = self.tasks_tx.clone();
+        let capture = match self.config.capture.clone() {
+            Some(config) => {
+                info!("Capturing zmq messages to {}", config.path.display());
+                Some(CaptureSink::new(CaptureWriter::open(config)?))
+            }
+            None => None,
+        };
+        let finish_capture = capture.clone();
+        let control_tx = self.control_tx.clone();
+        let control_tx_2 = self.control_tx.clone();
+        let control_tx_3 = self.control_tx.clone();
+        let control_tx_4 = self.control_tx.clone();
+        let tasks_tx = QueueSender::new(self.tasks_tx.clone(), self.health.clone());
 
         let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
         let shutdown_rx_1 = shutdown_tx.subscribe();
         let shutdown_rx_2 = shutdown_tx.subscribe();
         let shutdown_rx_3 = shutdown_tx.subscribe();
+        let shutdown_rx_4 = shutdown_tx.subscribe();
+        let shutdown_rx_5 = shutdown_tx.subscribe();
 
-        let mempool_state_check_interval = self.mempool_state_check_interval.clone();
-        let prune_check_interval = self.prune_check_interval.clone();
+        let mempool_state_check_interval = self.config.mempool_state_check_interval;
+        let prune_check_interval = self.config.prune_check_interval;
+        let checkpoint_interval = self.config.checkpoint_interval;
+        let stats_log_interval = self.config.stats_log_interval;
+        let rpc_limiter = self.rpc_limiter.clone();
+        // Checks against the node's live mempool would prune or evict the replayed txs
+        let replaying = self.config.replay.is_some();
 
-        let mempool_state_handle = tokio::spawn(async move {
+        let mut mempool_state_handle = tokio::spawn(async move {
             let mut shutdown = shutdown_rx_1;
+            if replaying {
+                let _ = shutdown.recv().await;
+                return Ok(());
+            }
+            let mut ticks = timer(mempool_state_check_interval);
             loop {
                 tokio::select! {
                     _ = shutdown.recv() => {
                         info!("Shutting down mempool state task");
                         break;
                     }
-                    _ = tokio::time::sleep(mempool_state_check_interval) => {
-                        tasks_tx.send(Task::MempoolState).await?;
+                    _ = ticks.tick() => {
+                        control_tx.send(Task::MempoolState.into()).await?;
+                        if rpc_limiter.is_limited() {
+                            info!("Workers waited {:?} on the RPC rate limit so far", rpc_limiter.waited());
+                        }
                     }
                 }
             }
             Ok::<(), anyhow::Error>(())
         });
 
-        let prune_check_handle = tokio::spawn(async move {
+        let mut prune_check_handle = tokio::spawn(async move {
             let mut shutdown = shutdown_rx_2;
+            if replaying || prune_check_interval.is_zero() {
+                let _ = shutdown.recv().await;
+                return Ok(());
+            }
+            let mut ticks = timer(prune_check_interval);
             loop {
                 tokio::select! {
                     _ = shutdown.recv() => {
                         info!("Shutting down prune check task");
                         break;
                     }
-                    _ = tokio::time::sleep(prune_check_interval) => {
-                        tasks_tx_2.send(Task::PruneCheck).await?;
+                    _ = ticks.tick() => {
+                        control_tx_2.send(Task::PruneCheck.into()).await?;
                     }
                 }
             }
             Ok::<(), anyhow::Error>(())
         });
 
-        let mut zmq_message_stream = self.zmq_factory.connect()?;
-        let zmq_handle = {
-            let mut shutdown = shutdown_rx_3;
-            tokio::spawn(async move {
-                info!("Starting zmq handle");
-                loop {
-                    tokio::select! {
-                        _ = shutdown.recv() => {
-                            info!("Shutting down zmq handle");
-                            break;
-                        }
-                        message = zmq_message_stream.next() => {
-                            match message {
-                                Some(Ok(message)) => {
-                                    tasks_tx_3.send(Task::RawTx(message.serialize_data_to_vec())).await?;
-                                }
-                                Some(Err(e)) => return Err(e.into()),
-                                None => break,
-                            }
-                        }
+        let mut checkpoint_handle = tokio::spawn(async move {
+            let mut shutdown = shutdown_rx_4;
+            let mut ticks = timer(checkpoint_interval);
+            loop {
+                tokio::select! {
+                    _ = shutdown.recv() => {
+                        info!("Shutting down checkpoint task");
+                        break;
+                    }
+                    _ = ticks.tick() => {
+                        control_tx_4.send(Task::Checkpoint.into()).await?;
+                    }
+                }
+            }
+            Ok::<(), anyhow::Error>(())
+        });
+
+        let db = self.db.clone();
+        let mut stats_log_handle = tokio::spawn(async move {
+            let mut shutdown = shutdown_rx_5;
+            let mut ticks = timer(stats_log_interval);
+            loop {
+                tokio::select! {
+                    _ = shutdown.recv() => {
+                        info!("Shutting down stats log task");
+                        break;
                     }
+                    _ = ticks.tick() => log_db_stats(&db).await,
                 }
+            }
+            Ok::<(), anyhow::Error>(())
+        });
+
+        let mut zmq_handle = if let Some(source) = self.config.replay.clone() {
+            tokio::spawn(async move {
+                let replayed =
+                    replay_capture(source, control_tx_3, tasks_tx, shutdown_rx_3).await?;
+                info!("Replayed {} captured messages", replayed);
                 Ok::<(), anyhow::Error>(())
             })
+        } else {
+            let zmq_message_stream = capture_zmq_messages(
+                record_zmq_messages(self.zmq_factory.connect()?, self.health.clone()),
+                capture.clone(),
+            );
+            let zmq_factory = self.zmq_factory.clone();
+            let health = self.health.clone();
+            tokio::spawn(listen_zmq(
+                zmq_message_stream,
+                move || {
+                    Ok(capture_zmq_messages(
+                        record_zmq_messages(zmq_factory.connect()?, health.clone()),
+                        capture.clone(),
+                    ))
+                },
+                control_tx_3,
+                tasks_tx,
+                shutdown_rx_3,
+                self.config.zmq_reconnect,
+                self.config.zmq_topics.clone(),
+            ))
         };
+        let mut secondaries = JoinSet::new();
+        for node in &self.secondary_nodes {
+            self.spawn_secondary_node(node, &mut secondaries, shutdown_tx.subscribe())?;
+        }
+        // The mempool was extracted by `init` and the zmq stream is connected
+        #[cfg(feature = "sd-notify")]
+        {
+            crate::systemd::notify_ready();
+            if let Some(interval) = crate::systemd::watchdog_interval() {
+                tokio::spawn(crate::systemd::run_watchdog(
+                    self.health.clone(),
+                    self.config.watchdog_max_silence,
+                    interval,
+                    shutdown_tx.subscribe(),
+                ));
+            }
+        }
 
-        // Wait for ctrl-c
-        tokio::select! {
-            _ = ctrl_c() => {
-                info!("Received shutdown signal");
-                shutdown_tx.send(()).map_err(|e| anyhow::anyhow!("Failed to send shutdown signal: {}", e))?;
+        // Wait for SIGINT or SIGTERM, replacing workers that die in the meantime. The
+        // restart delays run in here too, so they never hold up a signal
+        let mut restart_delays = JoinSet::new();
+        let signalled = loop {
+            tokio::select! {
+                r = shutdown_signal() => {
+                    r?;
+                    info!("Received shutdown signal");
+                    break true;
+                }
+                r = &mut mempool_state_handle => {
+                    r?.map_err(|e| anyhow::anyhow!("Mempool state task failed: {}", e))?;
+                    break false;
+                }
+                r = &mut prune_check_handle => {
+                    r?.map_err(|e| anyhow::anyhow!("Prune check task failed: {}", e))?;
+                    break false;
+                }
+                r = &mut checkpoint_handle => {
+                    r?.map_err(|e| anyhow::anyhow!("Checkpoint task failed: {}", e))?;
+                    break false;
+                }
+                r = &mut stats_log_handle => {
+                    r?.map_err(|e| anyhow::anyhow!("Stats log task failed: {}", e))?;
+                    break false;
+                }
+                r = &mut zmq_handle => {
+                    r?.map_err(|e| anyhow::anyhow!("ZMQ task failed: {}", e))?;
+                    break false;
+                }
+                Some(exit) = self.workers.join_next() => {
+                    if self.handle_worker_exit(exit)? {
+                        restart_delays.spawn(tokio::time::sleep(WORKER_RESTART_DELAY));
+                    }
+                }
+                Some(_) = restart_delays.join_next() => self.restart_worker(),
+                // The primary keeps going without a secondary node
+                Some(exit) = secondaries.join_next() => match exit {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => error!("Secondary node task failed: {:#}", e),
+                    Err(e) => error!("Secondary node task died: {}", e),
+                },
             }
-            r = mempool_state_handle => r?.map_err(|e| anyhow::anyhow!("Mempool state task failed: {}", e))?,
-            r = prune_check_handle => r?.map_err(|e| anyhow::anyhow!("Prune check task failed: {}", e))?,
-            r = zmq_handle => r?.map_err(|e| anyhow::anyhow!("ZMQ task failed: {}", e))?,
         };
+        #[cfg(feature = "sd-notify")]
+        crate::systemd::notify_stopping();
+        shutdown_tx
+            .send(())
+            .map_err(|e| anyhow::anyhow!("Failed to send shutdown signal: {}", e))?;
 
-        // Clean up
-        info!("Shutting down workers...");
+        // Clean up, a second signal gives up on it
+        let shutdown_timeout = self.config.shutdown_timeout;
+        let clean_up = async {
+            if signalled {
+                // The timers and the zmq listener stop before the queues close, so nothing
+                // they were about to queue fails on a closed channel
+                for handle in [
+                    mempool_state_handle,
+                    prune_check_handle,
+                    checkpoint_handle,
+                    stats_log_handle,
+                    zmq_handle,
+                ] {
+                    if let Ok(Err(e)) = handle.await {
+                        warn!("Task failed while shutting down: {:#}", e);
+                    }
+                }
+                // Their listeners stopped too, the workers finish what was queued
+                let secondaries_drained = tokio::time::timeout(shutdown_timeout, async {
+                    while secondaries.join_next().await.is_some() {}
+                })
+                .await;
+                if secondaries_drained.is_err() {
+                    warn!(
+                        "Secondary node workers didn't drain within {:?}",
+                        shutdown_timeout
+                    );
+                }
+            }
+            let report = self.drain_workers(shutdown_timeout).await;
+            // The listener is gone, write out what it captured last
+            if let Some(capture) = &finish_capture {
+                capture.finish().await;
+            }
+            report
+        };
+        let report = tokio::select! {
+            report = clean_up => report?,
+            r = shutdown_signal() => {
+                r?;
+                warn!("Received a second shutdown signal, exiting immediately");
+                std::process::exit(1);
+            }
+        };
+        if report.dropped > 0 {
+            warn!(
+                "Shutdown complete, {} queued tasks drained, {} dropped",
+                report.drained, report.dropped
+            );
+        } else {
+            info!("Shutdown complete, {} queued tasks drained", report.drained);
+        }
+        if let Some(summary) = &self.dry_run {
+            info!("Dry run complete, {}", summary);
+        }
+
+        Ok(())
+    }
+
+    /// Close the queues and give the workers `timeout` to process what is left in them
+    /// before they're aborted, then flush the database. Tasks still queued by then are
+    /// dropped, and so are the ones the aborted workers were in the middle of
+    pub async fn drain_workers(&mut self, timeout: Duration) -> Result<DrainReport> {
+        self.shutdown.send_replace(true);
+        self.control_tx.close();
         self.tasks_tx.close();
-        self.db.flush()?;
-        info!("Shutdown complete");
+        let queued = self.control_rx.len() + self.tasks_rx.len();
+        info!(
+            "Shutting down workers, draining {} queued tasks for up to {:?}...",
+            queued, timeout
+        );
+        let processed_before = self.worker_stats().processed();
+        let drained = tokio::time::timeout(timeout, async {
+            while let Some(exit) = self.workers.join_next().await {
+                match exit {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => error!("Worker failed while draining: {:#}", e),
+                    Err(e) => error!("Worker died while draining: {}", e),
+                }
+            }
+        })
+        .await;
+        // With the queues closed an idle worker exits, those still running are mid-task
+        let mut aborted = 0;
+        if drained.is_err() {
+            warn!("Workers didn't drain within {:?}, aborting them", timeout);
+            aborted = self.workers.len();
+            self.workers.shutdown().await;
+        }
+        self.db.flush().await?;
+        Ok(DrainReport {
+            drained: (self.worker_stats().processed() - processed_before) as usize,
+            dropped: self.control_rx.len() + self.tasks_rx.len() + aborted,
+        })
+    }
+}
+
+/// Mark `health` live on every message `stream` delivers
+fn record_zmq_messages<S, E>(
+    stream: S,
+    health: Health,
+) -> impl Stream<Item = std::result::Result<Message, E>> + Send
+where
+    S: Stream<Item = std::result::Result<Message, E>> + Send,
+{
+    stream.inspect(move |message| {
+        if message.is_ok() {
+            health.record_zmq_message();
+        }
+    })
+}
+
+/// Append every message `stream` delivers to `capture`, before it's routed to a task
+fn capture_zmq_messages<S, E>(
+    stream: S,
+    capture: Option<CaptureSink>,
+) -> impl Stream<Item = std::result::Result<Message, E>> + Send
+where
+    S: Stream<Item = std::result::Result<Message, E>> + Send,
+{
+    stream.inspect(move |message| {
+        if let (Ok(message), Some(capture)) = (message, &capture) {
+            capture.record(message);
+        }
+    })
+}
+
+/// Log the size of the store on one line. Errors are logged too, the next interval tries
+/// again
+async fn log_db_stats<S: MempoolStore>(db: &S) {
+    match db.db_stats(now!().saturating_sub(60 * 60)).await {
+        Ok(stats) => info!(
+            "db_stats total_rows={} pending={} mined={} pruned={} rbf_last_hour={} db_bytes={} wal_bytes={}",
+            stats.total_rows,
+            stats.pending,
+            stats.mined,
+            stats.pruned,
+            stats.rbf_events,
+            stats.db_bytes,
+            stats.wal_bytes
+        ),
+        Err(e) => error!("Error collecting database stats: {:#}", e),
+    }
+}
+
+/// Ticks every `period`, first one `period` from now. Ticks missed while a send waited on
+/// a full control queue are skipped instead of firing back to back
+fn timer(period: Duration) -> Interval {
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    interval
+}
+
+/// Resolves on SIGINT (ctrl-c) or, on unix, SIGTERM
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            r = ctrl_c() => r?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    ctrl_c().await?;
+    Ok(())
+}
+
+/// Route the zmq messages of `topics` to the workers until shutdown. When the stream errors or ends it is
+/// replaced through `connect` with exponential backoff; whatever was published during the
+/// gap is made up for by reconciling the mempool and rescanning the pending rows
+pub async fn listen_zmq<S, E>(
+    stream: S,
+    mut connect: impl FnMut() -> Result<S> + Send,
+    control_tx: Sender<Queued>,
+    mut tasks_tx: QueueSender,
+    mut shutdown: tokio::sync::broadcast::Receiver<()>,
+    policy: ZmqReconnectPolicy,
+    topics: Vec<ZmqTopic>,
+) -> Result<()>
+where
+    S: Stream<Item = std::result::Result<Message, E>> + Send,
+    E: std::fmt::Display,
+{
+    info!("Starting zmq handle");
+    let mut stream = Box::pin(stream);
+    let mut gaps = SequenceGapDetector::default();
+    // Subscribed to several block topics, each block is announced more than once
+    let mut refreshed_for = None;
+    let mut failures = 0;
+    let mut backoff = policy.initial_backoff;
+    loop {
+        let message = tokio::select! {
+            _ = shutdown.recv() => {
+                info!("Shutting down zmq handle");
+                return Ok(());
+            }
+            message = stream.next() => message,
+        };
+        let message = match message {
+            Some(Ok(message)) => message,
+            Some(Err(e)) => {
+                warn!("zmq stream failed: {}", e);
+                None
+            }
+            None => {
+                warn!("zmq stream ended");
+                None
+            }
+        };
+        let Some(message) = message else {
+            loop {
+                failures += 1;
+                if failures >= policy.max_failures {
+                    return Err(anyhow::anyhow!(
+                        "zmq subscription failed {} times in a row, giving up",
+                        policy.max_failures
+                    ));
+                }
+                info!("Reconnecting to zmq in {:?}", backoff);
+                tokio::select! {
+                    _ = shutdown.recv() => {
+                        info!("Shutting down zmq handle");
+                        return Ok(());
+                    }
+                    _ = tokio::time::sleep(backoff) => {}
+                }
+                backoff = (backoff * 2).min(policy.max_backoff);
+                match connect() {
+                    Ok(reconnected) => {
+                        stream = Box::pin(reconnected);
+                        break;
+                    }
+                    Err(e) => warn!("zmq reconnect failed: {:#}", e),
+                }
+            }
+            info!("Reconnected to zmq, resyncing with the node");
+            // A restarted publisher starts its sequences over
+            gaps = SequenceGapDetector::default();
+            control_tx.send(Task::ReconcileMempool.into()).await?;
+            control_tx.send(Task::Rescan.into()).await?;
+            continue;
+        };
+        failures = 0;
+        backoff = policy.initial_backoff;
+
+        let Some(topic) = ZmqTopic::of(&message).filter(|topic| topics.contains(topic)) else {
+            debug!("Ignoring zmq message on topic {}", message.topic_str());
+            continue;
+        };
+        // Dropped notifications leave txs we'll never hear about otherwise
+        if let Some(missed) = gaps.observe(topic.as_str(), message.sequence()) {
+            warn!(
+                "Missed {} zmq {} messages, reconciling the mempool",
+                missed, topic
+            );
+            control_tx.send(Task::ReconcileMempool.into()).await?;
+        }
+        let route = ZMQ_ROUTES
+            .iter()
+            .find(|(routed, _)| *routed == topic)
+            .map(|(_, route)| route)
+            .expect("every topic has a route");
+        match route(message) {
+            Some((Queue::Control, task)) => {
+                let connected = task.connected_block();
+                control_tx.send(task.into()).await?;
+                // The block's txs left the mempool now, not at the next poll
+                if connected.is_some() && connected != refreshed_for {
+                    refreshed_for = connected;
+                    control_tx.send(Task::MempoolState.into()).await?;
+                }
+            }
+            Some((Queue::Tasks, task)) => tasks_tx.send(task).await?,
+            None => debug!("Ignoring unexpected message on zmq topic {}", topic),
+        }
+    }
+}
+
+/// Queue the records of a capture file, and of the files it was rotated to, as tasks
+/// until shutdown, see `CaptureRecord`. Raw txs are found when they were captured, records of the sequence topic or of
+/// topics we don't handle are skipped. Returns the number of records queued
+pub async fn replay_capture(
+    source: ReplaySource,
+    control_tx: Sender<Queued>,
+    mut tasks_tx: QueueSender,
+    mut shutdown: tokio::sync::broadcast::Receiver<()>,
+) -> Result<usize> {
+    info!("Replaying capture {}", source.path.display());
+    let mut replayed = 0;
+    let mut previous_at = None;
+    let records = capture_files(&source.path)
+        .into_iter()
+        .map(CaptureReader::open)
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten();
+    for record in records {
+        let record = record?;
+        if source.realtime {
+            let delay =
+                previous_at.map_or(0, |previous| record.received_at_ms.saturating_sub(previous));
+            tokio::select! {
+                _ = shutdown.recv() => {
+                    info!("Shutting down replay after {} records", replayed);
+                    return Ok(replayed);
+                }
+                _ = tokio::time::sleep(Duration::from_millis(delay)) => {}
+            }
+        }
+        previous_at = Some(record.received_at_ms);
+        match replayed_task(record) {
+            Some((Queue::Control, task)) => control_tx.send(task.into()).await?,
+            Some((Queue::Tasks, task)) => tasks_tx.send(task).await?,
+            None => continue,
+        }
+        replayed += 1;
+    }
+    Ok(replayed)
+}
 
+fn replayed_task(record: CaptureRecord) -> Option<(Queue, Task)> {
+    let Ok(topic) = record.topic.parse::<ZmqTopic>() else {
+        debug!("Skipping captured message on topic {}", record.topic);
+        return None;
+    };
+    match topic {
+        ZmqTopic::RawTx => Some((
+            Queue::Tasks,
+            Task::ReplayedRawTx {
+                raw_tx: record.payload,
+                found_at: record.received_at_ms,
+            },
+        )),
+        ZmqTopic::RawBlock => Some((Queue::Control, Task::RawBlock(record.payload))),
+        ZmqTopic::HashBlock => {
+            let Ok(mut hash) = <[u8; 32]>::try_from(record.payload) else {
+                warn!("Skipping captured hashblock message that isn't a block hash");
+                return None;
+            };
+            // Published in display order
+            hash.reverse();
+            Some((
+                Queue::Control,
+                Task::NewBlock(BlockHash::from_byte_array(hash)),
+            ))
+        }
+        // Its mempool sequence numbers are meaningless without the node's state back then
+        ZmqTopic::Sequence => None,
+    }
+}
+
+/// Sends raw tx tasks to the workers, recording the queue depth and the time spent
+/// waiting on a full queue in `health`. Warns once the queue stayed `QUEUE_LAG_FILL`
+/// full for `QUEUE_LAG_WARN_AFTER`, the workers aren't keeping up with the node, and
+/// every `QUEUE_LAG_WARN_AFTER` a send stays blocked, they may have stalled
+#[derive(Debug)]
+pub struct QueueSender {
+    tx: Sender<Queued>,
+    health: Health,
+    /// Since when the queue is `QUEUE_LAG_FILL` full
+    lagging_since: Option<Instant>,
+    warned: bool,
+}
+
+impl QueueSender {
+    pub fn new(tx: Sender<Queued>, health: Health) -> Self {
+        Self {
+            tx,
+            health,
+            lagging_since: None,
+            warned: false,
+        }
+    }
+
+    pub async fn send(&mut self, task: Task) -> Result<()> {
+        let queued = match self.tx.try_send(task.into()) {
+            Ok(()) => None,
+            Err(TrySendError::Full(queued)) => Some(queued),
+            Err(TrySendError::Closed(_)) => {
+                return Err(anyhow::anyhow!("task queue closed"));
+            }
+        };
+        if let Some(queued) = queued {
+            self.send_blocked(queued).await?;
+        }
+        self.observe_depth();
         Ok(())
     }
+
+    /// Wait for room in the full queue, recording the wait as it goes
+    async fn send_blocked(&self, queued: Queued) -> Result<()> {
+        let blocked_at = Instant::now();
+        let send = self.tx.send(queued);
+        tokio::pin!(send);
+        let mut recorded = Duration::ZERO;
+        let mut next_warning = QUEUE_LAG_WARN_AFTER;
+        loop {
+            let sent = tokio::time::timeout(BLOCKED_SEND_CHECK_INTERVAL, &mut send).await;
+            let waited = blocked_at.elapsed();
+            self.health.record_send_blocked(waited - recorded);
+            recorded = waited;
+            match sent {
+                Ok(sent) => return Ok(sent?),
+                Err(_) if waited >= next_warning => {
+                    warn!(
+                        "Task queue full, a send has been blocked for {:?}, the workers may have stalled",
+                        waited
+                    );
+                    next_warning += QUEUE_LAG_WARN_AFTER;
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    fn observe_depth(&mut self) {
+        self.health
+            .record_queue_depth(self.tx.len(), self.tx.capacity());
+        if self.health.queue_fill() < QUEUE_LAG_FILL {
+            if self.warned {
+                info!("Task queue back to {} queued tasks", self.tx.len());
+            }
+            self.lagging_since = None;
+            self.warned = false;
+            return;
+        }
+        let since = *self.lagging_since.get_or_insert_with(Instant::now);
+        if !self.warned && since.elapsed() >= QUEUE_LAG_WARN_AFTER {
+            warn!(
+                "Task queue over {:.0}% full for {:?}, {} queued tasks, workers are falling behind",
+                QUEUE_LAG_FILL * 100.0,
+                since.elapsed(),
+                self.tx.len()
+            );
+            self.warned = true;
+        }
+    }
+}
+
+/// Queue a routed zmq message is sent to
+#[derive(Debug, Clone, Copy)]
+enum Queue {
+    /// Jumps the raw tx queue, so blocks mark mined txs before they're seen as pruned
+    Control,
+    /// Keeps order with the raw txs
+    Tasks,
+}
+
+type ZmqRoute = fn(Message) -> Option<(Queue, Task)>;
+
+/// How the messages of each topic become tasks, a new topic only needs an entry here
+const ZMQ_ROUTES: &[(ZmqTopic, ZmqRoute)] = &[
+    (ZmqTopic::RawTx, route_raw_tx),
+    (ZmqTopic::RawBlock, route_raw_block),
+    (ZmqTopic::HashBlock, route_hash_block),
+    (ZmqTopic::Sequence, route_sequence),
+];
+
+fn route_raw_tx(message: Message) -> Option<(Queue, Task)> {
+    matches!(message, Message::Tx(..))
+        .then(|| (Queue::Tasks, Task::RawTx(message.serialize_data_to_vec())))
+}
+
+fn route_raw_block(message: Message) -> Option<(Queue, Task)> {
+    matches!(message, Message::Block(..)).then(|| {
+        (
+            Queue::Control,
+            Task::RawBlock(message.serialize_data_to_vec()),
+        )
+    })
+}
+
+fn route_hash_block(message: Message) -> Option<(Queue, Task)> {
+    match message {
+        Message::HashBlock(block_hash, _) => Some((Queue::Control, Task::NewBlock(block_hash))),
+        _ => None,
+    }
+}
+
+fn route_sequence(message: Message) -> Option<(Queue, Task)> {
+    let Message::Sequence(sequence, _) = message else {
+        return None;
+    };
+    Some(match sequence {
+        SequenceMessage::BlockConnect { blockhash } => (
+            Queue::Control,
+            Task::Sequence(SequenceEvent::BlockConnected(blockhash)),
+        ),
+        SequenceMessage::BlockDisconnect { blockhash } => (
+            Queue::Control,
+            Task::Sequence(SequenceEvent::BlockDisconnected(blockhash)),
+        ),
+        SequenceMessage::MempoolAcceptance { txid, .. } => {
+            (Queue::Tasks, Task::Sequence(SequenceEvent::TxAdded(txid)))
+        }
+        SequenceMessage::MempoolRemoval { txid, .. } => {
+            (Queue::Tasks, Task::Sequence(SequenceEvent::TxRemoved(txid)))
+        }
+    })
 }
@@ -0,0 +1,423 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Context, Result};
+use bitcoincore_zmq::Message;
+use log::{info, warn};
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+
+/// Larger payloads are taken for a corrupt length, blocks stay well below it
+const MAX_PAYLOAD_BYTES: u32 = 32 * 1024 * 1024;
+
+/// A zmq message as captured. Records are stored back to back, each as the receive time
+/// in unix milliseconds (u64), the topic's length (u8) and name, then the payload's
+/// length (u32) and bytes, every integer little endian. The payload is the message's
+/// data as published, e.g. the serialized tx of a rawtx message or the big endian hash
+/// of a hashblock one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    pub received_at_ms: u64,
+    /// zmq topic name, e.g. rawtx
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+impl CaptureRecord {
+    /// `message` as received now
+    pub fn of(message: &Message) -> Self {
+        Self {
+            received_at_ms: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64,
+            topic: message.topic_str().to_string(),
+            payload: message.serialize_data_to_vec(),
+        }
+    }
+
+    /// Bytes `write_to` writes
+    pub fn encoded_len(&self) -> u64 {
+        (8 + 1 + self.topic.len() + 4 + self.payload.len()) as u64
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        let topic_len = u8::try_from(self.topic.len())
+            .map_err(|_| anyhow!("topic {} is too long to capture", self.topic))?;
+        let payload_len = u32::try_from(self.payload.len())
+            .ok()
+            .filter(|len| *len <= MAX_PAYLOAD_BYTES)
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} byte payload is too large to capture",
+                    self.payload.len()
+                )
+            })?;
+        writer.write_all(&self.received_at_ms.to_le_bytes())?;
+        writer.write_all(&[topic_len])?;
+        writer.write_all(self.topic.as_bytes())?;
+        writer.write_all(&payload_len.to_le_bytes())?;
+        writer.write_all(&self.payload)?;
+        Ok(())
+    }
+
+    /// Next record of `reader`, `None` at the end of the file. A record cut short errors
+    pub fn read_from(reader: &mut impl Read) -> Result<Option<Self>> {
+        let mut received_at = [0u8; 8];
+        let mut filled = 0;
+        while filled < received_at.len() {
+            match reader.read(&mut received_at[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if filled == 0 {
+            return Ok(None);
+        }
+        if filled < received_at.len() {
+            return Err(anyhow!("capture record truncated in its timestamp"));
+        }
+
+        let mut topic_len = [0u8; 1];
+        reader
+            .read_exact(&mut topic_len)
+            .context("capture record truncated in its topic")?;
+        let mut topic = vec![0u8; topic_len[0] as usize];
+        reader
+            .read_exact(&mut topic)
+            .context("capture record truncated in its topic")?;
+        let topic = String::from_utf8(topic).context("capture record topic isn't utf-8")?;
+
+        let mut payload_len = [0u8; 4];
+        reader
+            .read_exact(&mut payload_len)
+            .context("capture record truncated in its payload")?;
+        let payload_len = u32::from_le_bytes(payload_len);
+        if payload_len > MAX_PAYLOAD_BYTES {
+            return Err(anyhow!(
+                "capture record of {} claims a {} byte payload",
+                topic,
+                payload_len
+            ));
+        }
+        let mut payload = vec![0u8; payload_len as usize];
+        reader
+            .read_exact(&mut payload)
+            .context("capture record truncated in its payload")?;
+
+        Ok(Some(Self {
+            received_at_ms: u64::from_le_bytes(received_at),
+            topic,
+            payload,
+        }))
+    }
+}
+
+/// Iterates over the records of a capture file, see `CaptureRecord` for the format
+#[derive(Debug)]
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    done: bool,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("opening capture {}", path.display()))?;
+        Ok(Self::new(BufReader::new(file)))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            done: false,
+        }
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<CaptureRecord>;
+
+    /// Ends after the first error, the records behind a corrupt one can't be found
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let record = CaptureRecord::read_from(&mut self.reader).transpose();
+        self.done = !matches!(record, Some(Ok(_)));
+        record
+    }
+}
+
+/// Capture file `path` and the files it was rotated to, oldest first, i.e. in the order
+/// they were written
+pub fn capture_files(path: impl AsRef<Path>) -> Vec<PathBuf> {
+    let path = path.as_ref();
+    let mut files: Vec<PathBuf> = (1..)
+        .map(|n| rotated_path(path, n))
+        .take_while(|rotated| rotated.exists())
+        .collect();
+    files.reverse();
+    files.push(path.to_path_buf());
+    files
+}
+
+/// `path` after its `n`th rotation, e.g. capture.bin.2
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", n));
+    PathBuf::from(rotated)
+}
+
+/// Shifts `path.1`, `path.2`, ... one up and moves `path` to `path.1`, deleting what
+/// would leave more than `max_files` files including `path`. With a single file kept
+/// `path` stays where it is, the caller truncates it
+pub fn rotate_files(path: &Path, max_files: usize) -> io::Result<()> {
+    let kept = max_files.saturating_sub(1);
+    if kept == 0 {
+        return Ok(());
+    }
+    let oldest = rotated_path(path, kept);
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+    for n in (1..kept).rev() {
+        let rotated = rotated_path(path, n);
+        if rotated.exists() {
+            fs::rename(&rotated, rotated_path(path, n + 1))?;
+        }
+    }
+    if path.exists() {
+        fs::rename(path, rotated_path(path, 1))?;
+    }
+    Ok(())
+}
+
+/// When captured records are synced to disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FsyncPolicy {
+    /// Leave it to the OS
+    Never,
+    /// Once a file is full, before it's rotated
+    #[default]
+    OnRotate,
+    /// After every record, at the cost of a disk sync per zmq message
+    EveryRecord,
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "never" => FsyncPolicy::Never,
+            "on-rotate" => FsyncPolicy::OnRotate,
+            "every-record" => FsyncPolicy::EveryRecord,
+            _ => return Err(anyhow!("Unknown fsync policy: {}", s)),
+        })
+    }
+}
+
+/// Where and how the zmq messages are captured, see `CaptureWriter`
+#[derive(Debug, Clone)]
+pub struct CaptureConfig {
+    pub path: PathBuf,
+    /// A file is rotated before a record would take it past this size
+    pub max_file_bytes: u64,
+    /// Files kept including the one being written, at least 1. The oldest rotated file
+    /// is deleted to make room
+    pub max_files: usize,
+    pub fsync: FsyncPolicy,
+}
+
+/// Appends records to `CaptureConfig::path`, rotating it to `path.1`, `path.2`, ... by
+/// size. See `capture_files` to read them back in order
+#[derive(Debug)]
+pub struct CaptureWriter {
+    config: CaptureConfig,
+    file: BufWriter<File>,
+    /// Size of the file being written
+    written: u64,
+}
+
+impl CaptureWriter {
+    /// Keeps appending to an existing capture file
+    pub fn open(config: CaptureConfig) -> Result<Self> {
+        if config.max_files == 0 {
+            return Err(anyhow!("A capture keeps at least 1 file"));
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)
+            .with_context(|| format!("opening capture {}", config.path.display()))?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            config,
+            file: BufWriter::new(file),
+            written,
+        })
+    }
+
+    pub fn append(&mut self, record: &CaptureRecord) -> Result<()> {
+        let len = record.encoded_len();
+        if self.written > 0 && self.written + len > self.config.max_file_bytes {
+            self.rotate()?;
+        }
+        record.write_to(&mut self.file)?;
+        self.written += len;
+        if self.config.fsync == FsyncPolicy::EveryRecord {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()?;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        if self.config.fsync == FsyncPolicy::Never {
+            self.file.flush()?;
+        } else {
+            self.sync()?;
+        }
+        let path = &self.config.path;
+        rotate_files(path, self.config.max_files)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        self.file = BufWriter::new(file);
+        self.written = 0;
+        Ok(())
+    }
+}
+
+/// Records queued for the capture writer. A disk slower than the zmq stream drops the
+/// records past these instead of holding up the ingestion
+const QUEUE_CAPACITY: usize = 10_000;
+
+/// Captures the zmq messages as they're received, shared by the listener and the streams
+/// it reconnects. Records are queued to a writer on tokio's blocking pool, so a slow or
+/// full disk can't stall the ingestion. Write failures and records finding the queue full
+/// are logged and counted, never returned
+#[derive(Debug, Clone)]
+pub struct CaptureSink {
+    queue: async_channel::Sender<CaptureRecord>,
+    writer: Arc<Mutex<Option<JoinHandle<()>>>>,
+    failures: Arc<AtomicU64>,
+    dropped: Arc<AtomicU64>,
+    falling_behind: Arc<AtomicBool>,
+}
+
+impl CaptureSink {
+    /// Starts the writer, inside a tokio runtime
+    pub fn new(writer: CaptureWriter) -> Self {
+        let (queue, records) = async_channel::bounded(QUEUE_CAPACITY);
+        let failures = Arc::new(AtomicU64::new(0));
+        let writer = {
+            let failures = failures.clone();
+            tokio::task::spawn_blocking(move || write_records(writer, records, &failures))
+        };
+        Self {
+            queue,
+            writer: Arc::new(Mutex::new(Some(writer))),
+            failures,
+            dropped: Arc::new(AtomicU64::new(0)),
+            falling_behind: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn record(&self, message: &Message) {
+        self.append(CaptureRecord::of(message));
+    }
+
+    /// Queue `record` for the writer without waiting, it's dropped if the queue is full
+    pub fn append(&self, record: CaptureRecord) {
+        match self.queue.try_send(record) {
+            Ok(()) => {
+                if self.falling_behind.swap(false, Ordering::Relaxed) {
+                    info!("Capture writer caught up, capturing zmq messages again");
+                }
+            }
+            Err(async_channel::TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                // Once per backlog, not once per message
+                if !self.falling_behind.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "Capture writer is {} records behind, dropping zmq messages from the capture",
+                        QUEUE_CAPACITY
+                    );
+                }
+            }
+            // Finished, the app is shutting down
+            Err(async_channel::TrySendError::Closed(_)) => {}
+        }
+    }
+
+    /// Write out the queued records and stop the writer, records appended later are
+    /// ignored
+    pub async fn finish(&self) {
+        self.queue.close();
+        let writer = self.writer.lock().expect("capture lock poisoned").take();
+        if let Some(writer) = writer {
+            if let Err(e) = writer.await {
+                warn!("Capture writer died: {}", e);
+            }
+        }
+    }
+
+    /// Records lost since the last one captured
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Records dropped because the writer was behind
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Append the queued records until the sink finishes. A failing write is logged and
+/// skipped, the next record tries again
+fn write_records(
+    mut writer: CaptureWriter,
+    records: async_channel::Receiver<CaptureRecord>,
+    failures: &AtomicU64,
+) {
+    while let Ok(record) = records.recv_blocking() {
+        match writer.append(&record) {
+            Ok(()) => {
+                let failed = failures.swap(0, Ordering::Relaxed);
+                if failed > 0 {
+                    info!("Capturing zmq messages again after {} failures", failed);
+                }
+            }
+            Err(e) => {
+                // Once per outage, not once per message
+                if failures.fetch_add(1, Ordering::Relaxed) == 0 {
+                    warn!("Failed to capture zmq message, ingestion goes on: {:#}", e);
+                }
+            }
+        }
+    }
+}
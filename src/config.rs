@@ -0,0 +1,698 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use bitcoin::{address::NetworkUnchecked, Address, Amount, FeeRate};
+use serde::Deserialize;
+
+use crate::{
+    app::StartupCheckMode,
+    capture::{CaptureConfig, FsyncPolicy},
+    database::{Retention, Synchronous, COINBASE_MATURITY, DEFAULT_NODE_ID},
+    filter::Filter,
+    logging::{LogConfig, LogOutput},
+    worker::MinedDetection,
+    zmq_factory::{BitcoinZmqFactory, ZmqTopic},
+};
+
+/// Environment variables overriding the secrets of the config file's `[bitcoind]`.
+/// `[[nodes]]` have no overrides, point them at a `cookie_file` to keep their secrets out
+/// of the file
+pub const ENV_BITCOIND_USER: &str = "MEMPOOL_TRACKER_BITCOIND_USER";
+pub const ENV_BITCOIND_PASSWORD: &str = "MEMPOOL_TRACKER_BITCOIND_PASSWORD";
+pub const ENV_BITCOIND_COOKIE_FILE: &str = "MEMPOOL_TRACKER_BITCOIND_COOKIE_FILE";
+
+/// Settings read from a TOML file, see `contrib/mempool-tracker.example.toml`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Sightings of `bitcoind`'s announcements are attributed to this id
+    #[serde(default = "default_node_id")]
+    pub node_id: String,
+    /// File the tracker's pid is written to, removed on shutdown
+    pub pid_file: Option<PathBuf>,
+    pub bitcoind: BitcoindConfig,
+    pub zmq: ZmqConfig,
+    /// More nodes to compare announcement times with, `[[nodes]]` tables
+    #[serde(default)]
+    pub nodes: Vec<NodeConfig>,
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    #[serde(default)]
+    pub workers: WorkersConfig,
+    #[serde(default)]
+    pub filter: FilterConfig,
+    /// Mined txs of a block height range to ingest at startup, none when unset
+    pub backfill: Option<BackfillConfig>,
+    #[serde(default)]
+    pub intervals: IntervalsConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub events: EventsConfig,
+    #[serde(default)]
+    pub startup_checks: StartupChecksConfig,
+    #[serde(default)]
+    pub capture: CaptureFileConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+}
+
+fn default_node_id() -> String {
+    DEFAULT_NODE_ID.to_string()
+}
+
+/// A secondary node. Its raw txs go through workers of their own, the timers and resyncs
+/// only run against the primary `bitcoind`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeConfig {
+    pub id: String,
+    pub bitcoind: BitcoindConfig,
+    pub zmq: ZmqConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BitcoindConfig {
+    /// RPC endpoint, e.g. `http://127.0.0.1:8332`
+    pub url: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// bitcoind's `.cookie`, instead of `user` and `password`
+    pub cookie_file: Option<PathBuf>,
+    /// Seconds before a worker RPC call times out
+    #[serde(default = "default_rpc_timeout")]
+    pub timeout: u64,
+    /// Tries per worker RPC call, connection errors and timeouts are retried with backoff
+    #[serde(default = "default_rpc_attempts")]
+    pub attempts: u32,
+}
+
+fn default_rpc_timeout() -> u64 {
+    30
+}
+
+fn default_rpc_attempts() -> u32 {
+    3
+}
+
+/// How the tracker authenticates to bitcoind's RPC
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Auth {
+    UserPass {
+        user: String,
+        password: String,
+    },
+    /// Read on every (re)connection, the node rewrites it when it restarts
+    CookieFile(PathBuf),
+}
+
+impl Auth {
+    /// User and password to connect with
+    pub fn credentials(&self) -> Result<(String, String)> {
+        match self {
+            Auth::UserPass { user, password } => Ok((user.clone(), password.clone())),
+            // The io error goes into the message itself, a missing file or bad permissions
+            // should name the path even where only the outer error is shown
+            Auth::CookieFile(path) => {
+                let cookie = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow!("reading cookie file {}: {}", path.display(), e))?;
+                let (user, password) = cookie.trim().split_once(':').ok_or_else(|| {
+                    anyhow!("cookie file {} is not user:password", path.display())
+                })?;
+                Ok((user.to_string(), password.to_string()))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZmqConfig {
+    /// Endpoint of the node's `-zmqpubrawtx`, e.g. `tcp://127.0.0.1:28332`
+    pub rawtx: String,
+    /// Endpoint of the node's `-zmqpubhashblock`, when it differs from `rawtx`
+    pub hashblock: Option<String>,
+    /// Endpoint of the node's `-zmqpubrawblock`, when it differs from `rawtx`
+    pub rawblock: Option<String>,
+    /// Endpoint of the node's `-zmqpubsequence`, when it differs from `rawtx`
+    pub sequence: Option<String>,
+    /// Topics to process, rawtx and hashblock when unset
+    pub topics: Option<Vec<ZmqTopic>>,
+}
+
+impl ZmqConfig {
+    /// Subscription to `rawtx` and every other topic endpoint that is set
+    pub fn zmq_factory(&self) -> Result<BitcoinZmqFactory> {
+        let (host, port) = parse_zmq_endpoint(&self.rawtx).context("zmq.rawtx")?;
+        let mut zmq_factory = BitcoinZmqFactory::new(host, port);
+        for (topic, endpoint) in [
+            (ZmqTopic::HashBlock, &self.hashblock),
+            (ZmqTopic::RawBlock, &self.rawblock),
+            (ZmqTopic::Sequence, &self.sequence),
+        ] {
+            if let Some(endpoint) = endpoint {
+                let (host, port) =
+                    parse_zmq_endpoint(endpoint).with_context(|| format!("zmq.{}", topic))?;
+                zmq_factory = zmq_factory.with_endpoint(host, port);
+            }
+        }
+        Ok(zmq_factory)
+    }
+
+    /// Secondary nodes only have their raw txs processed
+    fn check_rawtx_only(&self) -> Result<()> {
+        for (field, set) in [
+            ("zmq.hashblock", self.hashblock.is_some()),
+            ("zmq.rawblock", self.rawblock.is_some()),
+            ("zmq.sequence", self.sequence.is_some()),
+            ("zmq.topics", self.topics.is_some()),
+        ] {
+            if set {
+                return Err(anyhow!(
+                    "{} isn't used for secondary nodes, only their zmq.rawtx is subscribed to",
+                    field
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How many days finished rows are kept, for good when unset. Expired rows are deleted
+/// after each checkpoint
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Mined and pruned txs, with their RBF history, sightings and links
+    pub transactions_days: Option<u64>,
+    /// Mempool state, fee histogram, purge event and checkpoint rows
+    pub snapshots_days: Option<u64>,
+}
+
+impl RetentionConfig {
+    pub fn retention(&self) -> Retention {
+        let days = |days: u64| Duration::from_secs(days * 24 * 60 * 60);
+        Retention {
+            transactions: self.transactions_days.map(days),
+            snapshots: self.snapshots_days.map(days),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub path: String,
+    /// Record into this Postgres server instead of the sqlite file, e.g.
+    /// postgres://tracker@db.internal/mempool, `postgres` feature
+    pub postgres_url: Option<String>,
+    pub synchronous: Synchronous,
+    /// Pooled sqlite connections
+    pub pool_size: u32,
+    /// Read the store but drop every write, counting what would have been written
+    pub dry_run: bool,
+    /// Domain tag for the inputs hashes when several monitors share a store. Changing it
+    /// re-keys every transaction
+    pub inputs_hash_tag: Option<String>,
+    /// Outputs below this value (sats) count as dust, the relay dust limit of each
+    /// output's script type when unset
+    pub dust_threshold: Option<u64>,
+    /// Blocks after its own before a coinbase can be spent
+    pub coinbase_maturity: u64,
+    /// JSON file of extra coinbase tags and payout addresses identifying miners
+    pub miner_mapping: Option<PathBuf>,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            path: "mempool-tracker.db".to_string(),
+            postgres_url: None,
+            synchronous: Synchronous::default(),
+            pool_size: crate::database::DEFAULT_POOL_SIZE,
+            dry_run: false,
+            inputs_hash_tag: None,
+            dust_threshold: None,
+            coinbase_maturity: COINBASE_MATURITY,
+            miner_mapping: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkersConfig {
+    pub count: usize,
+    /// Raw txs buffered ahead of the workers, scales with `count` when unset
+    pub task_channel_capacity: Option<usize>,
+    /// full-block, or block-filter on nodes with `-blockfilterindex`
+    pub mined_detection: MinedDetection,
+    /// Inserted txs paying out more than this (sats) are flagged and announced as
+    /// large value, none when unset
+    pub large_value_threshold: Option<u64>,
+    /// Worker RPC calls per second, 0 is unlimited
+    pub rpc_rate_limit: u32,
+    /// RPC calls allowed back to back before the rate limit applies
+    pub rpc_burst: u32,
+}
+
+impl Default for WorkersConfig {
+    fn default() -> Self {
+        Self {
+            count: 2,
+            task_channel_capacity: None,
+            mined_detection: MinedDetection::default(),
+            large_value_threshold: None,
+            rpc_rate_limit: 0,
+            rpc_burst: 1,
+        }
+    }
+}
+
+/// Which txs are stored, every tx unless set
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    /// Lowest fee rate stored, in sat/vB
+    pub min_fee_rate: Option<u64>,
+    /// Lowest total output value stored, in sats
+    pub min_value: Option<u64>,
+    /// Only txs paying to one of these addresses are stored
+    pub watch_addresses: Vec<String>,
+}
+
+impl FilterConfig {
+    pub fn filter(&self) -> Result<Filter> {
+        let addresses = self
+            .watch_addresses
+            .iter()
+            .map(|address| {
+                address
+                    .parse::<Address<NetworkUnchecked>>()
+                    .map(Address::assume_checked)
+                    .with_context(|| format!("filter.watch_addresses: invalid {:?}", address))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Filter {
+            min_fee_rate: self.min_fee_rate.map(FeeRate::from_sat_per_vb_unchecked),
+            min_value: self.min_value.map(Amount::from_sat),
+            ..Default::default()
+        }
+        .with_watched_addresses(&addresses))
+    }
+}
+
+/// Inclusive block height range, its fees are looked up with `getrawtransaction` so the
+/// node needs txindex
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackfillConfig {
+    pub from: u64,
+    pub to: u64,
+}
+
+/// Checks of the node's configuration before the workers start
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StartupChecksConfig {
+    /// fail, or warn to start on a misconfigured node anyway
+    pub mode: StartupCheckMode,
+    /// `-mempoolfullrbf` the node must run with, either when unset
+    pub expect_full_rbf: Option<bool>,
+}
+
+/// Capture of the zmq messages, off unless `path` is set
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureFileConfig {
+    pub path: Option<PathBuf>,
+    /// Size a file is rotated at, in MiB
+    pub max_file_mb: u64,
+    /// Files kept, including the one being written
+    pub max_files: usize,
+    pub fsync: FsyncPolicy,
+}
+
+impl Default for CaptureFileConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_file_mb: 100,
+            max_files: 10,
+            fsync: FsyncPolicy::default(),
+        }
+    }
+}
+
+impl CaptureFileConfig {
+    pub fn capture_config(&self) -> Option<CaptureConfig> {
+        self.path.clone().map(|path| CaptureConfig {
+            path,
+            max_file_bytes: self.max_file_mb * 1024 * 1024,
+            max_files: self.max_files,
+            fsync: self.fsync,
+        })
+    }
+}
+
+/// Where the tracker logs, stderr unless `output` says otherwise
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub output: LogOutput,
+    /// Log file, needed to log to a file
+    pub path: Option<PathBuf>,
+    /// Size a file is rotated at, in MiB, 0 for no size limit
+    pub max_file_mb: u64,
+    /// Rotate at the first line of every UTC day too
+    pub daily: bool,
+    /// Files kept, including the one being written
+    pub max_files: usize,
+    /// Per-module levels, e.g. "info,mempool_tracker::worker=debug". RUST_LOG overrides it
+    pub filters: Option<String>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            output: LogOutput::default(),
+            path: None,
+            max_file_mb: 100,
+            daily: false,
+            max_files: 10,
+            filters: None,
+        }
+    }
+}
+
+impl LoggingConfig {
+    pub fn log_config(&self) -> LogConfig {
+        LogConfig {
+            output: self.output,
+            path: self.path.clone(),
+            max_file_bytes: self.max_file_mb * 1024 * 1024,
+            daily: self.daily,
+            max_files: self.max_files,
+            filters: self.filters.clone(),
+        }
+    }
+}
+
+/// Timer intervals, in seconds
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IntervalsConfig {
+    /// At least 10
+    pub mempool_state_check: u64,
+    /// 0 disables the periodic prune check, only with the sequence topic. Lost block
+    /// announcements aren't caught up with and purges aren't detected then
+    pub prune_check: u64,
+    pub checkpoint: u64,
+    pub tip_stale_after: u64,
+    /// 0 skips the wait for a first zmq message
+    pub zmq_probe_timeout: u64,
+    /// Time the workers get to drain their queues on shutdown
+    pub shutdown_timeout: u64,
+    /// How often the database's size is logged
+    pub stats_log: u64,
+    /// Silence of both zmq and RPC after which the systemd watchdog isn't pinged
+    /// anymore, with the `sd-notify` feature
+    pub watchdog_max_silence: u64,
+}
+
+impl Default for IntervalsConfig {
+    fn default() -> Self {
+        Self {
+            mempool_state_check: 25,
+            prune_check: 120,
+            checkpoint: 600,
+            tip_stale_after: 1800,
+            zmq_probe_timeout: 10,
+            shutdown_timeout: 30,
+            stats_log: 3600,
+            watchdog_max_silence: 300,
+        }
+    }
+}
+
+/// Health probes, the REST API and the WebSocket feed, served when built with the
+/// `http` feature
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Address to serve /healthz and /readyz on, no server when unset
+    pub listen: Option<SocketAddr>,
+    /// Address to serve the read-only query API on, no server when unset
+    pub rest_listen: Option<SocketAddr>,
+    /// Address to serve the live event feed on at /ws, no server when unset
+    pub ws_listen: Option<SocketAddr>,
+    /// Events a feed client may fall behind by before it is dropped
+    pub ws_buffer: usize,
+    /// Seconds without a zmq message before /healthz reports zmq as stalled
+    pub zmq_max_silence: u64,
+}
+
+/// Event sinks besides the WebSocket feed, each needs a build with its feature. Every sink
+/// that is set gets each event
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventsConfig {
+    /// NATS server to publish to, `nats` feature
+    pub nats_url: Option<String>,
+    /// Comma separated host:port list of Kafka brokers to produce to, `kafka` feature
+    pub kafka_brokers: Option<String>,
+    /// Topic the events are produced to, keyed by txid
+    pub kafka_topic: String,
+    /// Address to serve the gRPC transaction stream on, `grpc` feature
+    pub grpc_listen: Option<SocketAddr>,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            nats_url: None,
+            kafka_brokers: None,
+            kafka_topic: "mempool-events".to_string(),
+            grpc_listen: None,
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            listen: None,
+            rest_listen: None,
+            ws_listen: None,
+            ws_buffer: 10_000,
+            zmq_max_silence: 300,
+        }
+    }
+}
+
+impl Config {
+    /// Read and validate a config file, secrets can be overridden from the environment
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("reading config file {}", path.display()))?;
+        Self::parse_with_env(&contents, |name| std::env::var(name).ok())
+            .with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    /// Parse and validate, looking environment overrides up through `env`
+    pub fn parse_with_env(contents: &str, env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut config: Config = toml::from_str(contents)?;
+        if let Some(user) = env(ENV_BITCOIND_USER) {
+            config.bitcoind.user = Some(user);
+        }
+        if let Some(password) = env(ENV_BITCOIND_PASSWORD) {
+            config.bitcoind.password = Some(password);
+        }
+        if let Some(cookie_file) = env(ENV_BITCOIND_COOKIE_FILE) {
+            config.bitcoind.cookie_file = Some(cookie_file.into());
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.bitcoind.validate()?;
+        self.zmq.zmq_factory()?;
+        let mut node_ids = vec![self.node_id.as_str()];
+        for node in &self.nodes {
+            if node.id.is_empty() || node_ids.contains(&node.id.as_str()) {
+                return Err(anyhow!(
+                    "nodes: ids must be set and distinct, {:?} is taken (the primary node is {:?})",
+                    node.id,
+                    self.node_id
+                ));
+            }
+            node_ids.push(&node.id);
+            node.bitcoind
+                .validate()
+                .and_then(|()| parse_zmq_endpoint(&node.zmq.rawtx).context("zmq.rawtx"))
+                .and_then(|_| node.zmq.check_rawtx_only())
+                .with_context(|| format!("nodes.{}", node.id))?;
+        }
+        if self
+            .zmq
+            .topics
+            .as_ref()
+            .is_some_and(|topics| topics.is_empty())
+        {
+            return Err(anyhow!("zmq.topics must list at least one topic"));
+        }
+        if self.database.pool_size == 0 {
+            return Err(anyhow!("database.pool_size must be at least 1"));
+        }
+        if self.workers.count == 0 {
+            return Err(anyhow!("workers.count must be at least 1"));
+        }
+        if self.capture.max_file_mb == 0 || self.capture.max_files == 0 {
+            return Err(anyhow!(
+                "capture.max_file_mb and capture.max_files must be at least 1"
+            ));
+        }
+        if self.logging.output != LogOutput::Stderr && self.logging.path.is_none() {
+            return Err(anyhow!("logging.path must be set to log to a file"));
+        }
+        if self.logging.max_files == 0 {
+            return Err(anyhow!("logging.max_files must be at least 1"));
+        }
+        if self.http.ws_buffer == 0 {
+            return Err(anyhow!("http.ws_buffer must be at least 1"));
+        }
+        if self.workers.task_channel_capacity == Some(0) {
+            return Err(anyhow!("workers.task_channel_capacity must be at least 1"));
+        }
+        self.filter.filter()?;
+        if let Some(backfill) = self.backfill {
+            if backfill.from > backfill.to {
+                return Err(anyhow!(
+                    "backfill: from {} is after to {}",
+                    backfill.from,
+                    backfill.to
+                ));
+            }
+        }
+        for (field, set, built) in [
+            (
+                "events.nats_url",
+                self.events.nats_url.is_some(),
+                cfg!(feature = "nats"),
+            ),
+            (
+                "events.kafka_brokers",
+                self.events.kafka_brokers.is_some(),
+                cfg!(feature = "kafka"),
+            ),
+            (
+                "events.grpc_listen",
+                self.events.grpc_listen.is_some(),
+                cfg!(feature = "grpc"),
+            ),
+            (
+                "database.postgres_url",
+                self.database.postgres_url.is_some(),
+                cfg!(feature = "postgres"),
+            ),
+        ] {
+            if set && !built {
+                return Err(anyhow!("{} needs a build with its feature", field));
+            }
+        }
+        // The mempool state and prune check intervals are validated with the CLI's by
+        // `App::init`
+        for (field, days) in [
+            (
+                "retention.transactions_days",
+                self.retention.transactions_days,
+            ),
+            ("retention.snapshots_days", self.retention.snapshots_days),
+        ] {
+            if days == Some(0) {
+                return Err(anyhow!("{} must be at least 1 day", field));
+            }
+        }
+        for (field, secs) in [
+            ("intervals.checkpoint", self.intervals.checkpoint),
+            ("intervals.tip_stale_after", self.intervals.tip_stale_after),
+            ("intervals.stats_log", self.intervals.stats_log),
+        ] {
+            if secs == 0 {
+                return Err(anyhow!("{} must be at least 1 second", field));
+            }
+        }
+        Ok(())
+    }
+
+    /// Either `user` and `password` or `cookie_file`, after environment overrides
+    pub fn auth(&self) -> Result<Auth> {
+        self.bitcoind.auth()
+    }
+}
+
+impl BitcoindConfig {
+    fn validate(&self) -> Result<()> {
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(anyhow!(
+                "bitcoind.url: expected http://host:port, got {:?}",
+                self.url
+            ));
+        }
+        self.auth()?;
+        if self.timeout == 0 {
+            return Err(anyhow!("bitcoind.timeout must be at least 1 second"));
+        }
+        if self.attempts == 0 {
+            return Err(anyhow!("bitcoind.attempts must be at least 1"));
+        }
+        Ok(())
+    }
+
+    /// Either `user` and `password` or `cookie_file`
+    pub fn auth(&self) -> Result<Auth> {
+        match (&self.user, &self.password, &self.cookie_file) {
+            (Some(user), Some(password), None) => Ok(Auth::UserPass {
+                user: user.clone(),
+                password: password.clone(),
+            }),
+            (None, None, Some(cookie_file)) => Ok(Auth::CookieFile(cookie_file.clone())),
+            (None, None, None) => Err(anyhow!(
+                "bitcoind: set user and password, or cookie_file (or {} and {})",
+                ENV_BITCOIND_USER,
+                ENV_BITCOIND_PASSWORD
+            )),
+            (_, _, Some(_)) => Err(anyhow!(
+                "bitcoind: cookie_file can't be combined with user or password"
+            )),
+            (Some(_), None, None) => Err(anyhow!(
+                "bitcoind.password is missing (or set {})",
+                ENV_BITCOIND_PASSWORD
+            )),
+            (None, Some(_), None) => Err(anyhow!(
+                "bitcoind.user is missing (or set {})",
+                ENV_BITCOIND_USER
+            )),
+        }
+    }
+}
+
+/// Host and port of a `tcp://host:port` zmq endpoint
+pub fn parse_zmq_endpoint(endpoint: &str) -> Result<(String, u16)> {
+    let (host, port) = endpoint
+        .strip_prefix("tcp://")
+        .and_then(|address| address.rsplit_once(':'))
+        .ok_or_else(|| anyhow!("expected tcp://host:port, got {:?}", endpoint))?;
+    let port = port
+        .parse()
+        .with_context(|| format!("invalid port in {:?}", endpoint))?;
+    Ok((host.to_string(), port))
+}
@@ -1,16 +1,32 @@
-use std::{str::FromStr, time::SystemTime, vec};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    str::FromStr,
+    time::{Duration, SystemTime},
+    vec,
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use bitcoin::{
     consensus::{Decodable, Encodable},
-    Amount, BlockHash, FeeRate, Transaction, Txid,
+    hashes::Hash,
+    Amount, BlockHash, FeeRate, OutPoint, Transaction, Txid,
 };
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, OptionalExtension};
+use rusqlite::{params, params_from_iter, types::Value, OpenFlags, OptionalExtension};
+use serde::Deserialize;
 
 use crate::{
     migrations::run_migrations,
-    utils::{get_inputs_hash, prune_large_witnesses},
+    miners::MinerRegistry,
+    rpc::MempoolStatus,
+    utils::{
+        block_subsidy, classify_tx, coinbase_height, compute_fee_rate, count_dust_outputs,
+        count_relay_dust_outputs, get_tx_key_tagged, is_truc, op_return_bytes,
+        prune_large_witnesses, sighash_mask, RbfBump, SighashKind, TxType, TRUC_CHILD_MAX_VSIZE,
+        TRUC_MAX_VSIZE,
+    },
 };
 use log::info;
 
@@ -24,22 +40,754 @@ macro_rules! now {
     };
 }
 
+/// Unix time in milliseconds, the resolution of the tx lifecycle times (`found_at`,
+/// `node_seen_at`, `mined_at`, `pruned_at`) and of the sightings
+#[macro_export]
+macro_rules! now_ms {
+    () => {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+    };
+}
+
 /// Versioning the database, scheme should be backwards compatible
 /// But may not always be forwards compatible
-const MEMPOOL_TRANSACTION_VERSION: u32 = 1;
-const RBF_TRANSACTION_VERSION: u32 = 0;
-const COINBASE_TRANSACTION_VERSION: u32 = 0;
-const MEMPOOL_STATE_VERSION: u32 = 1;
+pub(crate) const MEMPOOL_TRANSACTION_VERSION: u32 = 1;
+pub(crate) const RBF_TRANSACTION_VERSION: u32 = 0;
+pub(crate) const COINBASE_TRANSACTION_VERSION: u32 = 0;
+pub(crate) const MEMPOOL_STATE_VERSION: u32 = 1;
+
+/// Node the observations of single node setups are attributed to
+pub const DEFAULT_NODE_ID: &str = "default";
+
+/// Txids bound per statement when pruning, below SQLite's host parameter limit
+const PRUNE_BATCH_SIZE: usize = 500;
+/// Rows `TransactionIter` decodes per query
+pub(crate) const ITER_BATCH_SIZE: usize = 1_000;
+
+pub(crate) const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+/// Inclusive upper bounds of the OP_RETURN payload size histogram buckets
+pub(crate) const OP_RETURN_HISTOGRAM_BOUNDS: [u64; 7] = [0, 40, 80, 160, 1_000, 10_000, u64::MAX];
+
+/// Aggregate view of a single day of mempool activity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DailySummary {
+    pub day_start: u64,
+    /// Transactions first seen in our mempool during the day
+    pub txs_first_seen: u64,
+    /// Non-coinbase transactions mined during the day
+    pub txs_mined: u64,
+    pub rbf_events: u64,
+    /// Transactions that left the mempool without being mined
+    pub txs_pruned: u64,
+    /// Median seconds between first seen and mined, for txs mined during the day
+    pub median_confirmation_latency: Option<u64>,
+    /// Largest recorded mempool size in bytes
+    pub peak_mempool_size: Option<u64>,
+}
+
+/// OP_RETURN usage over a window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpReturnStats {
+    /// Transactions with at least one OP_RETURN output
+    pub tx_count: u64,
+    pub total_bytes: u64,
+    /// (inclusive upper bound in bytes, tx count), the last bucket is unbounded
+    pub histogram: Vec<(u64, u64)>,
+}
+
+/// How aggressively replacements bump their fees, over events with a known predecessor fee
+#[derive(Debug, Clone, PartialEq)]
+pub struct BumpStats {
+    pub events: u64,
+    pub avg_fee_delta: Option<f64>,
+    pub avg_fee_rate_delta: Option<f64>,
+    pub avg_fee_bump_pct: Option<f64>,
+}
+
+/// Size of the store, for the periodic stats log line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbStats {
+    pub total_rows: u64,
+    pub pending: u64,
+    pub mined: u64,
+    /// Pruned and never mined
+    pub pruned: u64,
+    /// Replacements recorded since the `rbf_since` passed to `Database::db_stats`
+    pub rbf_events: u64,
+    /// On-disk size of the database and its WAL, 0 for in-memory databases
+    pub db_bytes: u64,
+    pub wal_bytes: u64,
+}
+
+/// When a node first announced a tx, relative to the first node that did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropagationDelta {
+    pub txid: Txid,
+    pub node_id: String,
+    /// Unix ms
+    pub first_seen_at: u64,
+    /// Milliseconds after the earliest sighting, 0 for the node(s) that saw it first
+    pub delay: u64,
+}
+
+/// How `related_txid` relates to `txid` in a tx_links row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkDirection {
+    /// Unconfirmed parent, from getmempoolentry's `depends`
+    Parent,
+    /// Unconfirmed child, from getmempoolentry's `spentby`
+    Child,
+}
+
+impl LinkDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkDirection::Parent => "parent",
+            LinkDirection::Child => "child",
+        }
+    }
+}
+
+/// Mempool dwell time of a group of mined transactions, in seconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct DwellStats {
+    pub count: u64,
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    pub mean: Option<f64>,
+    pub median: Option<u64>,
+}
+
+impl DwellStats {
+    pub(crate) fn from_latencies(mut latencies: Vec<u64>) -> Self {
+        latencies.sort_unstable();
+        let count = latencies.len() as u64;
+        let mean = (!latencies.is_empty())
+            .then(|| latencies.iter().sum::<u64>() as f64 / latencies.len() as f64);
+        Self {
+            count,
+            min: latencies.first().copied(),
+            max: latencies.last().copied(),
+            mean,
+            // Lower median, matching `daily_summary`
+            median: latencies
+                .get(latencies.len().saturating_sub(1) / 2)
+                .copied(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CsvKind {
+    Integer,
+    Real,
+    /// Hex strings and labels, quoted when they contain a comma or a quote. Coinbase
+    /// tags are any printable ASCII
+    Text,
+    /// Hex encoded in the file
+    Blob,
+}
+
+/// Columns of the transactions CSV export, in file order. Every column of the table,
+/// a column added by a migration has to be added here too
+const TRANSACTION_CSV_COLUMNS: &[(&str, CsvKind)] = &[
+    ("inputs_hash", CsvKind::Text),
+    ("tx_id", CsvKind::Text),
+    ("tx_data", CsvKind::Text),
+    ("found_at", CsvKind::Integer),
+    ("node_seen_at", CsvKind::Integer),
+    ("mined_at", CsvKind::Integer),
+    ("pruned_at", CsvKind::Integer),
+    ("absolute_fee", CsvKind::Integer),
+    ("fee_rate", CsvKind::Integer),
+    ("fee_known", CsvKind::Integer),
+    ("vsize", CsvKind::Integer),
+    ("effective_fee_rate", CsvKind::Real),
+    ("ancestors_known", CsvKind::Integer),
+    ("seen_in_mempool", CsvKind::Integer),
+    ("out_of_band", CsvKind::Integer),
+    ("tx_type", CsvKind::Text),
+    ("op_return_count", CsvKind::Integer),
+    ("op_return_bytes", CsvKind::Integer),
+    ("dust_output_count", CsvKind::Integer),
+    ("is_truc", CsvKind::Integer),
+    ("truc_topology_ok", CsvKind::Integer),
+    ("sighash_mask", CsvKind::Integer),
+    ("block_height", CsvKind::Integer),
+    ("block_hash", CsvKind::Blob),
+    ("seq", CsvKind::Integer),
+    ("version", CsvKind::Integer),
+    ("removal_reason", CsvKind::Text),
+    ("miner", CsvKind::Text),
+    ("coinbase_tag", CsvKind::Text),
+    ("input_value_total", CsvKind::Integer),
+    ("coinbase_value", CsvKind::Integer),
+    ("matures_at_height", CsvKind::Integer),
+    ("is_large_value", CsvKind::Integer),
+];
+
+/// Many txs evicted in one prune check, typically the node trimming a full mempool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PurgeEvent {
+    /// Unix seconds
+    pub detected_at: u64,
+    pub removed_count: u64,
+    /// Stored vsize of the removed txs, rows without one count as 0
+    pub removed_vbytes: u64,
+    /// Of the last mempool state snapshot before the purge
+    pub mempool_min_fee_before: Option<FeeRate>,
+    pub mempool_min_fee_after: Option<FeeRate>,
+}
+
+/// Marker that every row up to `last_seq` was durably written, for consumers syncing
+/// incrementally to resume from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Unix seconds
+    pub checkpoint_at: u64,
+    pub best_block_hash: BlockHash,
+    pub pending_count: u64,
+    /// Highest `seq` of the transactions table, 0 while it is empty
+    pub last_seq: i64,
+}
+
+/// Block a mined row is inserted for
+struct MinedIn<'a> {
+    height: Option<u64>,
+    hash: &'a [u8],
+    time: u64,
+}
+
+/// Adoption and replacement behavior of TRUC (v3) txs next to v2 txs, over txs found in a
+/// time range
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrucStats {
+    pub truc_count: u64,
+    /// Every other tx, in practice nearly all v2
+    pub v2_count: u64,
+    /// Share of all txs found in the range, 0.0 - 1.0
+    pub truc_share: f64,
+    /// Pending TRUC txs whose observed package broke the 1-parent-1-child topology
+    pub topology_violations: u64,
+    /// Share of txs that were replaced at least once, 0.0 - 1.0
+    pub truc_replacement_rate: f64,
+    pub v2_replacement_rate: f64,
+}
+
+/// Blocks of one miner, `miner` is `None` for coinbases no mapping identified
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinerStats {
+    pub miner: Option<String>,
+    pub blocks: u64,
+    /// Mean of coinbase value minus subsidy
+    pub avg_fees: Amount,
+    /// Share of the miner's block vsize taken by out-of-band txs, 0.0 - 1.0
+    pub out_of_band_share: f64,
+}
+
+/// Txs of one block that never went through our mempool
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutOfBandStats {
+    pub block_height: Option<u64>,
+    pub block_hash: BlockHash,
+    pub tx_count: u64,
+    pub total_fee: Amount,
+    pub vsize: u64,
+    /// Share of the block's vsize, 0.0 - 1.0
+    pub block_share: f64,
+}
+
+/// A tx evicted from the mempool, next to the node's minimum fee at that moment
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvictionContext {
+    pub txid: Txid,
+    /// Unix ms
+    pub pruned_at: u64,
+    /// sat/vB
+    pub fee_rate: f64,
+    /// mempoolminfee of the last snapshot before the eviction, `None` without one
+    pub mempool_min_fee: Option<FeeRate>,
+}
+
+/// Fields of an exported CSV line, unquoting the quoted ones
+fn split_csv_line(line: &str) -> Result<Vec<String>> {
+    let mut fields = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err(anyhow::anyhow!("unterminated quoted field")),
+                }
+            }
+            if !matches!(chars.peek(), None | Some(',')) {
+                return Err(anyhow::anyhow!("unexpected text after a quoted field"));
+            }
+        }
+        while let Some(c) = chars.next_if(|c| *c != ',') {
+            field.push(c);
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+/// Fee rates are stored in the node's sat/kvB so sub-sat/vB floors keep their precision
+pub(crate) fn sat_per_kvb(fee_rate: FeeRate) -> u64 {
+    fee_rate.to_sat_per_kwu() * 4
+}
+
+pub(crate) fn fee_rate_from_sat_per_kvb(sat_per_kvb: u64) -> FeeRate {
+    FeeRate::from_sat_per_kwu((sat_per_kvb + 2) / 4)
+}
+
+/// Lower bounds (sat/vB) of the fee bands of the histogram snapshots. Rates below the
+/// first band are counted in band 0
+pub const FEE_HISTOGRAM_BANDS: &[u64] = &[
+    1, 2, 3, 4, 5, 6, 8, 10, 12, 15, 20, 30, 40, 50, 75, 100, 150, 200, 300, 500, 1000,
+];
+
+/// Pending vbytes of one fee band in one histogram snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeHistogramBand {
+    /// Unix seconds
+    pub snapshot_at: u64,
+    pub band_min_satvb: u64,
+    pub vbytes: u64,
+    pub tx_count: u64,
+}
+
+/// Naive vs ancestor-inclusive fee rate of one tx, both in sat/vB
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeRateComparison {
+    pub txid: Txid,
+    pub naive_fee_rate: f64,
+    pub effective_fee_rate: f64,
+    /// False when an ancestor was unknown and the effective rate fell back to the naive one
+    pub ancestors_known: bool,
+}
+
+/// A new tx spending outpoints already claimed by a different pending tx
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutpointConflict {
+    /// The newly arrived tx
+    pub txid: Txid,
+    /// The pending tx that claimed the outpoints first
+    pub conflicting_txid: Txid,
+    pub outpoints: Vec<OutPoint>,
+}
+
+/// Connected set of unconfirmed transactions, walked from tx_links
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnconfirmedPackage {
+    /// Every reachable unconfirmed tx, including the starting one
+    pub txids: Vec<Txid>,
+    pub total_vsize: u64,
+    pub total_fee: Amount,
+    /// Members we hold no data for (e.g. filtered out), excluded from the totals
+    pub unknown_txids: Vec<Txid>,
+}
+
+/// A stored row of the transactions table
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionInner {
+    pub inputs_hash: String,
+    pub txid: Txid,
+    /// Large witnesses of txs first seen in a block are pruned
+    pub tx: Transaction,
+    /// Lifecycle times are unix ms
+    pub found_at: u64,
+    pub node_seen_at: Option<u64>,
+    pub mined_at: Option<u64>,
+    pub pruned_at: Option<u64>,
+    pub absolute_fee: Amount,
+    pub fee_rate: FeeRate,
+    /// `false` while the fee of a replacement is still being looked up
+    pub fee_known: bool,
+    pub block_height: Option<u64>,
+}
+
+/// A mempool state snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolSnapshot {
+    /// Unix seconds
+    pub created_at: u64,
+    pub tx_count: u64,
+    /// Serialized size of the mempool's txs
+    pub bytes: u64,
+    pub block_height: u64,
+    pub block_hash: BlockHash,
+    /// `None` on snapshots written before these were recorded
+    pub mempool_min_fee: Option<FeeRate>,
+    pub min_relay_tx_fee: Option<FeeRate>,
+    pub max_mempool: Option<u64>,
+    pub usage: Option<u64>,
+    /// `None` on the first poll after a restart and on older snapshots
+    pub churn: Option<MempoolChurn>,
+}
+
+/// Txs that entered and left the mempool between two polls, from diffing their txid sets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MempoolChurn {
+    pub added_since_last: u64,
+    pub removed_since_last: u64,
+}
+
+/// A row of rbf_history, one per replacement of an input set
+#[derive(Debug, Clone, PartialEq)]
+pub struct RbfRecord {
+    pub txid: Txid,
+    /// Unix seconds
+    pub created_at: u64,
+    /// `None` while the replacement's fee is being looked up
+    pub fee: Option<Amount>,
+    /// `None` when the replaced tx's fee was never known
+    pub prev_fee: Option<Amount>,
+    pub fee_bump_pct: Option<f64>,
+    /// No predecessor fee to measure a bump against, see `RbfBump`
+    pub is_initial_observation: bool,
+    pub reject_reason: Option<String>,
+}
+
+/// Lazily decodes the transactions table in inputs hash order, see
+/// `Database::iter_transactions`
+pub struct TransactionIter {
+    conn: r2d2::PooledConnection<PingingConnectionManager>,
+    after_key: String,
+    batch: VecDeque<TransactionInner>,
+    done: bool,
+}
+
+/// Columns `TransactionInner::from_row` reads, in order
+const TRANSACTION_INNER_COLUMNS: &str = "inputs_hash, tx_id, tx_data, found_at, node_seen_at,
+    mined_at, pruned_at, absolute_fee, fee_rate, fee_known, block_height";
+
+impl TransactionInner {
+    /// Decode a row selected with `TRANSACTION_INNER_COLUMNS`
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        let tx_id: String = row.get(1)?;
+        let tx_data: String = row.get(2)?;
+        let bytes = hex::decode(tx_data)?;
+        Ok(Self {
+            inputs_hash: row.get(0)?,
+            txid: Txid::from_str(&tx_id)?,
+            tx: Transaction::consensus_decode(&mut bytes.as_slice())?,
+            found_at: row.get(3)?,
+            node_seen_at: row.get(4)?,
+            mined_at: row.get(5)?,
+            pruned_at: row.get(6)?,
+            absolute_fee: Amount::from_sat(row.get(7)?),
+            fee_rate: FeeRate::from_sat_per_vb_unchecked(row.get(8)?),
+            fee_known: row.get(9)?,
+            block_height: row.get(10)?,
+        })
+    }
+}
+
+impl TransactionIter {
+    fn next_batch(&mut self) -> Result<()> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM transactions WHERE inputs_hash > ?1 ORDER BY inputs_hash LIMIT ?2",
+            TRANSACTION_INNER_COLUMNS
+        ))?;
+        let mut rows = stmt.query(params![self.after_key, ITER_BATCH_SIZE])?;
+        while let Some(row) = rows.next()? {
+            self.batch.push_back(TransactionInner::from_row(row)?);
+        }
+        self.done = self.batch.len() < ITER_BATCH_SIZE;
+        if let Some(last) = self.batch.back() {
+            self.after_key = last.inputs_hash.clone();
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for TransactionIter {
+    type Item = Result<TransactionInner>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.batch.is_empty() && !self.done {
+            if let Err(e) = self.next_batch() {
+                // A failed query or undecodable row ends the iteration
+                self.done = true;
+                self.batch.clear();
+                return Some(Err(e));
+            }
+        }
+        self.batch.pop_front().map(Ok)
+    }
+}
+
+/// `PRAGMA synchronous` of the write connections, the database runs in WAL mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Synchronous {
+    /// No fsync at all. An OS crash or power loss can lose recent commits or corrupt the
+    /// database, an application crash alone loses nothing
+    Off,
+    /// fsync at WAL checkpoints only. The database stays consistent, but commits since the
+    /// last checkpoint can be lost on power loss
+    #[default]
+    Normal,
+    /// fsync on every commit, nothing committed is ever lost
+    Full,
+}
+
+impl Synchronous {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+        }
+    }
+}
+
+impl FromStr for Synchronous {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "off" => Synchronous::Off,
+            "normal" => Synchronous::Normal,
+            "full" => Synchronous::Full,
+            _ => return Err(anyhow::anyhow!("Unknown synchronous mode: {}", s)),
+        })
+    }
+}
+
+/// Pooled connections of `Database::open`, r2d2's default
+pub const DEFAULT_POOL_SIZE: u32 = 10;
+
+/// `SqliteConnectionManager` validating connections with a `SELECT 1` on checkout, so
+/// one that went stale while idle is replaced instead of failing the next query.
+/// `SqliteConnectionManager`'s own check runs an empty batch, which never reaches sqlite
+#[derive(Debug)]
+pub struct PingingConnectionManager(SqliteConnectionManager);
+
+impl PingingConnectionManager {
+    pub fn new(manager: SqliteConnectionManager) -> Self {
+        Self(manager)
+    }
+}
+
+impl r2d2::ManageConnection for PingingConnectionManager {
+    type Connection = rusqlite::Connection;
+    type Error = rusqlite::Error;
+
+    fn connect(&self) -> std::result::Result<Self::Connection, Self::Error> {
+        self.0.connect()
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> std::result::Result<(), Self::Error> {
+        conn.query_row("SELECT 1", [], |_| Ok(()))
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.0.has_broken(conn)
+    }
+}
+
+/// Pool checking every connection out through `PingingConnectionManager`
+fn pinging_pool(
+    manager: SqliteConnectionManager,
+    max_size: u32,
+) -> Result<r2d2::Pool<PingingConnectionManager>> {
+    Ok(r2d2::Pool::builder()
+        .max_size(max_size)
+        .test_on_check_out(true)
+        .build(PingingConnectionManager::new(manager))?)
+}
+
+/// Confirmations before a coinbase's outputs may be spent, the consensus rule of every
+/// Bitcoin network
+pub const COINBASE_MATURITY: u64 = 100;
+
+/// How long finished rows are kept, unset keeps them for good
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retention {
+    /// Mined and pruned txs, with their RBF history, sightings, variants and links
+    pub transactions: Option<Duration>,
+    /// Mempool state, fee histogram, purge event and checkpoint rows
+    pub snapshots: Option<Duration>,
+}
 
 #[derive(Debug, Clone)]
-pub struct Database(r2d2::Pool<SqliteConnectionManager>);
+pub struct Database {
+    pool: r2d2::Pool<PingingConnectionManager>,
+    /// Write methods become no-ops, for dry runs against real data
+    read_only: bool,
+    /// Flat dust limit, `None` applies the relay limit of each output's script type
+    dust_threshold: Option<Amount>,
+    /// Domain tag namespacing the inputs hashes, `None` keeps the untagged keys
+    inputs_hash_tag: Option<String>,
+    /// Labels coinbases with the miner that produced them
+    miners: MinerRegistry,
+    /// Blocks after its own before a coinbase can be spent, see `COINBASE_MATURITY`
+    coinbase_maturity: u64,
+    /// Pending txs paying out more than this are stored with `is_large_value` set
+    large_value_threshold: Option<Amount>,
+    /// Rows older than this are deleted after each checkpoint, see `apply_retention`
+    retention: Retention,
+}
 
 impl Database {
     pub fn new(path: &str) -> Result<Self> {
-        let manager = SqliteConnectionManager::file(path);
-        let pool = r2d2::Pool::new(manager)?;
-        let conn = pool.get()?;
+        Self::open(path, Synchronous::default())
+    }
 
+    /// Open in WAL mode with `synchronous` applied to every pooled connection
+    pub fn open(path: &str, synchronous: Synchronous) -> Result<Self> {
+        Self::open_with_pool_size(path, synchronous, DEFAULT_POOL_SIZE)
+    }
+
+    /// `open` keeping up to `pool_size` connections
+    pub fn open_with_pool_size(
+        path: &str,
+        synchronous: Synchronous,
+        pool_size: u32,
+    ) -> Result<Self> {
+        let manager = SqliteConnectionManager::file(path).with_init(move |conn| {
+            conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+            conn.pragma_update(None, "synchronous", synchronous.as_str())
+        });
+        let pool = pinging_pool(manager, pool_size)?;
+        Self::create_tables(&pool.get()?)?;
+        Ok(Self {
+            pool,
+            read_only: false,
+            dust_threshold: None,
+            inputs_hash_tag: None,
+            miners: MinerRegistry::builtin(),
+            coinbase_maturity: COINBASE_MATURITY,
+            large_value_threshold: None,
+            retention: Retention::default(),
+        })
+    }
+
+    /// Open without ever writing. An existing file is opened with `SQLITE_OPEN_READ_ONLY`,
+    /// otherwise reads run against an empty in-memory schema
+    pub fn open_read_only(path: &str) -> Result<Self> {
+        let pool = if Path::new(path).exists() {
+            let manager = SqliteConnectionManager::file(path).with_flags(
+                OpenFlags::SQLITE_OPEN_READ_ONLY
+                    | OpenFlags::SQLITE_OPEN_URI
+                    | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            );
+            pinging_pool(manager, DEFAULT_POOL_SIZE)?
+        } else {
+            // Every in-memory connection is its own database, keep a single one
+            let pool = pinging_pool(SqliteConnectionManager::memory(), 1)?;
+            let conn = pool.get()?;
+            Self::create_tables(&conn)?;
+            run_migrations(&conn)?;
+            pool
+        };
+        Ok(Self {
+            pool,
+            read_only: true,
+            dust_threshold: None,
+            inputs_hash_tag: None,
+            miners: MinerRegistry::builtin(),
+            coinbase_maturity: COINBASE_MATURITY,
+            large_value_threshold: None,
+            retention: Retention::default(),
+        })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Connection checked out of the pool, for statements there's no method for
+    pub fn connection(&self) -> Result<r2d2::PooledConnection<PingingConnectionManager>> {
+        Ok(self.pool.get()?)
+    }
+
+    /// CREATE statements of every table, then every index, as stored in the database
+    /// file, i.e. including the columns migrations added
+    pub fn dump_schema(&self) -> Result<String> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT sql FROM sqlite_master
+            WHERE type IN ('table', 'index') AND sql IS NOT NULL AND name NOT LIKE 'sqlite_%'
+            ORDER BY type DESC, name",
+        )?;
+        let statements = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(statements
+            .iter()
+            .map(|statement| format!("{};\n", statement))
+            .collect())
+    }
+
+    /// Count outputs below `threshold` as dust regardless of their script type
+    pub fn with_dust_threshold(mut self, threshold: Amount) -> Self {
+        self.dust_threshold = Some(threshold);
+        self
+    }
+
+    /// Namespace the inputs hashes keying the transactions table, for monitors sharing a store
+    pub fn with_inputs_hash_tag(mut self, tag: impl Into<String>) -> Self {
+        self.inputs_hash_tag = Some(tag.into());
+        self
+    }
+
+    /// Identify miners with `miners` instead of only the built-in tags
+    pub fn with_miner_registry(mut self, miners: MinerRegistry) -> Self {
+        self.miners = miners;
+        self
+    }
+
+    /// Record coinbases as maturing `maturity` blocks after their own, e.g. on a test
+    /// network with a patched node
+    pub fn with_coinbase_maturity(mut self, maturity: u64) -> Self {
+        self.coinbase_maturity = maturity;
+        self
+    }
+
+    /// Store the pending txs paying out more than `threshold` with `is_large_value` set,
+    /// e.g. exchange withdrawals
+    pub fn with_large_value_threshold(mut self, threshold: Amount) -> Self {
+        self.large_value_threshold = Some(threshold);
+        self
+    }
+
+    /// Delete finished rows once they're older than `retention`
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    fn tx_key(&self, tx: &Transaction) -> Result<String> {
+        get_tx_key_tagged(tx, self.inputs_hash_tag.as_deref())
+    }
+
+    fn dust_output_count(&self, tx: &Transaction) -> usize {
+        match self.dust_threshold {
+            Some(threshold) => count_dust_outputs(tx, threshold),
+            None => count_relay_dust_outputs(tx),
+        }
+    }
+
+    fn create_tables(conn: &rusqlite::Connection) -> Result<()> {
+        // Times are unix seconds, except the tx lifecycle (found_at, node_seen_at, mined_at,
+        // pruned_at), tx_sightings and txid_history, which `StoreTxTimesInMillis` moved to
+        // milliseconds. Each table below names its unit
         // Create tables if they don't exist
         conn.execute(
             "CREATE TABLE IF NOT EXISTS transactions (
@@ -61,8 +809,21 @@ impl Database {
             "CREATE INDEX IF NOT EXISTS idx_transactions_tx_id ON transactions(tx_id)",
             [],
         )?;
+        // Timestamp indexes used by the aggregate queries
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_transactions_found_at ON transactions(found_at)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_transactions_mined_at ON transactions(mined_at)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_transactions_pruned_at ON transactions(pruned_at)",
+            [],
+        )?;
 
-        // Create the rbf table if it doesn't exist
+        // Create the rbf table if it doesn't exist, created_at in seconds
         conn.execute(
             "CREATE TABLE IF NOT EXISTS rbf (
                 inputs_hash TEXT PRIMARY KEY,
@@ -72,8 +833,38 @@ impl Database {
             )",
             [],
         )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_rbf_created_at ON rbf(created_at)",
+            [],
+        )?;
+
+        // Every observed replacement, the rbf table only keeps the latest per inputs hash
+        // Fee comparisons are NULL when the previous fee was never known, created_at in seconds
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS rbf_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                inputs_hash TEXT NOT NULL,
+                tx_id TEXT NOT NULL,
+                created_at DATETIME NOT NULL,
+                fee_total INTEGER NOT NULL,
+                prev_fee INTEGER,
+                fee_delta INTEGER,
+                fee_rate_delta INTEGER,
+                fee_bump_pct REAL,
+                version INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_rbf_history_inputs_hash ON rbf_history(inputs_hash)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_rbf_history_created_at ON rbf_history(created_at)",
+            [],
+        )?;
 
-        // Create the mempool table if it doesn't exist
+        // Create the mempool table if it doesn't exist, created_at in seconds
         conn.execute(
             "CREATE TABLE IF NOT EXISTS mempool (
                 tx_id TEXT PRIMARY KEY,
@@ -86,281 +877,2431 @@ impl Database {
             )",
             [],
         )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_mempool_created_at ON mempool(created_at)",
+            [],
+        )?;
 
-        // Migrations table tracking what migrations have been applied
+        // Ancestry reported by the node, both directions are stored as seen from `txid`,
+        // observed_at in seconds
         conn.execute(
-            "CREATE TABLE IF NOT EXISTS migrations (
-                id TEXT PRIMARY KEY,
-                applied_at DATETIME NOT NULL
+            "CREATE TABLE IF NOT EXISTS tx_links (
+                txid TEXT NOT NULL,
+                related_txid TEXT NOT NULL,
+                direction TEXT NOT NULL,
+                observed_at DATETIME NOT NULL,
+                PRIMARY KEY (txid, related_txid, direction)
             )",
             [],
         )?;
-        Ok(Self(pool))
-    }
-
-    pub(crate) fn flush(&self) -> Result<()> {
-        let conn = self.0.get()?;
-        conn.cache_flush()?;
-        Ok(())
-    }
-
-    pub(crate) fn record_mempool_state(
-        &self,
-        mempool_size: u64,
-        mempool_tx_count: u64,
-        block_height: u64,
-        block_hash: BlockHash,
-    ) -> Result<()> {
-        let conn = self.0.get()?;
-        let now = now!();
-        let mut writer = vec![];
-        block_hash.consensus_encode(&mut writer)?;
-        let block_hash_str = hex::encode(writer);
         conn.execute(
-            "INSERT OR REPLACE INTO mempool (created_at, size, tx_count, block_height, block_hash, version) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![now, mempool_size, mempool_tx_count, block_height, block_hash_str, MEMPOOL_STATE_VERSION],
+            "CREATE INDEX IF NOT EXISTS idx_tx_links_related_txid ON tx_links(related_txid)",
+            [],
         )?;
-        Ok(())
-    }
-
-    pub(crate) fn record_coinbase_tx(&self, tx: &Transaction) -> Result<()> {
-        let conn = self.0.get()?;
-        if !tx.is_coinbase() {
-            return Ok(());
-        }
 
-        // special case for coinbase tx, key is the txid
-        let tx_id = tx.compute_txid().to_string();
-        let found_at = now!();
-        let mined_at = now!();
-        let mut tx_bytes = vec![];
-        tx.consensus_encode(&mut tx_bytes)?;
-        let tx_str = hex::encode(tx_bytes);
+        // Outpoints claimed by pending txs, rows are dropped once the claimant is mined or pruned,
+        // created_at in seconds
         conn.execute(
-            "INSERT OR REPLACE INTO transactions
-            (inputs_hash, tx_data, tx_id, found_at, mined_at, absolute_fee, fee_rate, version)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![
-                tx_id,
-                tx_str,
-                tx_id,
-                found_at,
-                mined_at,
-                Amount::ZERO.to_sat(),
-                FeeRate::ZERO.to_sat_per_vb_ceil(),
-                COINBASE_TRANSACTION_VERSION
-            ],
+            "CREATE TABLE IF NOT EXISTS spent_outpoints (
+                outpoint TEXT PRIMARY KEY,
+                txid TEXT NOT NULL,
+                inputs_hash TEXT NOT NULL,
+                created_at DATETIME NOT NULL
+            )",
+            [],
         )?;
-
-        Ok(())
-    }
-
-    pub(crate) fn record_mined_tx(&self, tx: &Transaction) -> Result<()> {
-        let mut tx = tx.clone();
-        prune_large_witnesses(&mut tx);
-        let inputs_hash = get_inputs_hash(tx.clone().input)?;
-        let mut tx_bytes = vec![];
-        tx.consensus_encode(&mut tx_bytes)?;
-        let tx_str = hex::encode(tx_bytes);
-        let conn = self.0.get()?;
-        let mined_at = now!();
-
-        let tx_in_mempool: bool = conn.query_row(
-            "SELECT COUNT(*) FROM transactions WHERE inputs_hash = ?1",
-            params![inputs_hash],
-            |row| row.get(0),
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_spent_outpoints_txid ON spent_outpoints(txid)",
+            [],
         )?;
-        if !tx_in_mempool {
-            info!("Received tx that was not in my mempool: {}", inputs_hash);
-        }
+
+        // Double spends among pending txs that don't share the exact same input set.
+        // `outpoints` is a comma separated list of the overlap, detected_at and resolved_at in seconds
         conn.execute(
-            "UPDATE transactions SET mined_at = ?1, tx_data = ?2, seen_in_mempool = ?3 WHERE inputs_hash = ?4",
-            params![mined_at, tx_str, true, inputs_hash],
+            "CREATE TABLE IF NOT EXISTS conflicts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                txid TEXT NOT NULL,
+                conflicting_txid TEXT NOT NULL,
+                outpoints TEXT NOT NULL,
+                detected_at DATETIME NOT NULL,
+                winner_txid TEXT,
+                resolved_at DATETIME
+            )",
+            [],
         )?;
 
-        Ok(())
+        // Blocks already ingested by backfill, makes backfills resumable, completed_at in seconds
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS backfill_coverage (
+                block_height INTEGER PRIMARY KEY,
+                block_hash TEXT NOT NULL,
+                tx_count INTEGER NOT NULL,
+                completed_at DATETIME NOT NULL
+            )",
+            [],
+        )?;
+
+        // Every tracked unconfirmed parent of a tx, keyed by the child's inputs_hash
+        // so the edges survive the child being replaced. Migrated to a row per spent
+        // output, see `AddTxParentOutputs`
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS tx_parents (
+                child_inputs_hash TEXT NOT NULL,
+                parent_txid TEXT NOT NULL,
+                PRIMARY KEY (child_inputs_hash, parent_txid)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_tx_parents_parent_txid ON tx_parents(parent_txid)",
+            [],
+        )?;
+
+        // First zmq announcement of a tx by each monitored node, the transactions row
+        // only has the found_at of whichever node's worker stored it, first_seen_at in milliseconds
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS tx_sightings (
+                txid TEXT NOT NULL,
+                node_id TEXT NOT NULL,
+                first_seen_at DATETIME NOT NULL,
+                PRIMARY KEY (txid, node_id)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_tx_sightings_first_seen_at ON tx_sightings(first_seen_at)",
+            [],
+        )?;
+
+        // Every txid that occupied an inputs_hash slot, replacements update the row in place,
+        // seen_at in milliseconds
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS txid_history (
+                inputs_hash TEXT NOT NULL,
+                tx_id TEXT NOT NULL,
+                seen_at DATETIME NOT NULL,
+                PRIMARY KEY (inputs_hash, tx_id)
+            )",
+            [],
+        )?;
+
+        // Pending vbytes per fee band, one set of rows per mempool state snapshot, snapshot_at
+        // in seconds like the mempool table's created_at
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fee_histogram (
+                snapshot_at DATETIME NOT NULL,
+                band_min_satvb INTEGER NOT NULL,
+                vbytes INTEGER NOT NULL,
+                tx_count INTEGER NOT NULL,
+                PRIMARY KEY (snapshot_at, band_min_satvb)
+            )",
+            [],
+        )?;
+
+        // Mass evictions, a prune check removing a large share of pending txs at once,
+        // detected_at in seconds
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS purge_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                detected_at DATETIME NOT NULL,
+                removed_count INTEGER NOT NULL,
+                removed_vbytes INTEGER NOT NULL,
+                mempoolminfee_before INTEGER,
+                mempoolminfee_after INTEGER
+            )",
+            [],
+        )?;
+
+        // Connected blocks processed from hashblock notifications, block_time (the header's)
+        // and recorded_at in seconds
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS blocks (
+                block_hash BLOB PRIMARY KEY,
+                block_height INTEGER,
+                block_time DATETIME NOT NULL,
+                vsize INTEGER NOT NULL,
+                tx_count INTEGER NOT NULL,
+                recorded_at DATETIME NOT NULL
+            )",
+            [],
+        )?;
+
+        // Periodic markers written after a flush, see `record_checkpoint`, checkpoint_at in seconds
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS checkpoints (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                checkpoint_at DATETIME NOT NULL,
+                best_block_hash TEXT NOT NULL,
+                pending_count INTEGER NOT NULL,
+                last_seq INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Raw tx payloads that could not be decoded, received_at in seconds
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS quarantine (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                payload BLOB NOT NULL,
+                received_at DATETIME NOT NULL,
+                error TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Migrations table tracking what migrations have been applied
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS migrations (
+                id TEXT PRIMARY KEY,
+                applied_at DATETIME NOT NULL
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn flush(&self) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.cache_flush()?;
+        Ok(())
+    }
+
+    pub(crate) fn record_mempool_state(
+        &self,
+        mempool_info: &MempoolStatus,
+        block_height: u64,
+        block_hash: BlockHash,
+        churn: Option<MempoolChurn>,
+    ) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let conn = self.pool.get()?;
+        let now = now!();
+        let mut writer = vec![];
+        block_hash.consensus_encode(&mut writer)?;
+        let block_hash_str = hex::encode(writer);
+        conn.execute(
+            "INSERT OR REPLACE INTO mempool
+            (created_at, size, tx_count, block_height, block_hash, mempool_min_fee, min_relay_tx_fee, max_mempool, usage,
+                added_since_last, removed_since_last, version)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                now,
+                mempool_info.bytes,
+                mempool_info.size,
+                block_height,
+                block_hash_str,
+                sat_per_kvb(mempool_info.mempool_min_fee),
+                sat_per_kvb(mempool_info.min_relay_tx_fee),
+                mempool_info.max_mempool,
+                mempool_info.usage,
+                churn.map(|churn| churn.added_since_last),
+                churn.map(|churn| churn.removed_since_last),
+                MEMPOOL_STATE_VERSION
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Snapshot how many pending vbytes sit in each of `FEE_HISTOGRAM_BANDS`. Aggregated
+    /// in SQL so the cost doesn't grow with memory, rows stored without a vsize or with an
+    /// unknown fee are left out. Returns the number of non-empty bands
+    pub fn record_fee_histogram(&self) -> Result<usize> {
+        if self.read_only {
+            return Ok(0);
+        }
+        let conn = self.pool.get()?;
+        let band = FEE_HISTOGRAM_BANDS
+            .iter()
+            .rev()
+            .map(|min| format!("WHEN CAST(absolute_fee AS REAL) / vsize >= {min} THEN {min}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let bands = conn.execute(
+            &format!(
+                "INSERT OR REPLACE INTO fee_histogram (snapshot_at, band_min_satvb, vbytes, tx_count)
+                SELECT ?1, CASE {band} ELSE 0 END AS band_min_satvb, SUM(vsize), COUNT(*)
+                FROM transactions
+                WHERE mined_at IS NULL AND pruned_at IS NULL AND vsize > 0 AND fee_known = 1
+                GROUP BY band_min_satvb"
+            ),
+            params![now!()],
+        )?;
+        Ok(bands)
+    }
+
+    /// Pending txs per fee rate bucket right now. `buckets` are strictly ascending, finite
+    /// sat/vB lower bounds: bucket i counts rates in `[buckets[i], buckets[i + 1])`, the
+    /// last bucket is unbounded and rates below `buckets[0]` are left out. Rows without a
+    /// vsize or with an unknown fee are skipped
+    pub fn current_fee_rate_histogram(&self, buckets: &[f64]) -> Result<Vec<(f64, u64)>> {
+        if let Some(min) = buckets.iter().find(|min| !min.is_finite()) {
+            return Err(anyhow::anyhow!("Fee rate bucket {} isn't finite", min));
+        }
+        if let Some(pair) = buckets.windows(2).find(|pair| pair[0] >= pair[1]) {
+            return Err(anyhow::anyhow!(
+                "Fee rate buckets must ascend, {} is followed by {}",
+                pair[0],
+                pair[1]
+            ));
+        }
+        if buckets.is_empty() {
+            return Ok(vec![]);
+        }
+        let conn = self.pool.get()?;
+        let bucket = (0..buckets.len())
+            .rev()
+            .map(|i| {
+                format!(
+                    "WHEN CAST(absolute_fee AS REAL) / vsize >= ?{} THEN {i}",
+                    i + 1
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT CASE {bucket} ELSE -1 END AS bucket, COUNT(*)
+            FROM transactions
+            WHERE mined_at IS NULL AND pruned_at IS NULL AND vsize > 0 AND fee_known = 1
+            GROUP BY bucket"
+        ))?;
+        let mut counts = vec![0u64; buckets.len()];
+        let mut rows = stmt.query(params_from_iter(buckets))?;
+        while let Some(row) = rows.next()? {
+            let bucket: i64 = row.get(0)?;
+            if bucket >= 0 {
+                counts[bucket as usize] = row.get(1)?;
+            }
+        }
+        Ok(buckets.iter().copied().zip(counts).collect())
+    }
+
+    pub(crate) fn quarantine_payload(&self, payload: &[u8], error: &str) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let conn = self.pool.get()?;
+        let received_at = now!();
+        conn.execute(
+            "INSERT INTO quarantine (payload, received_at, error) VALUES (?1, ?2, ?3)",
+            params![payload, received_at, error],
+        )?;
+        Ok(())
+    }
+
+    /// Store a coinbase with the miner it identifies, `block_hash` links it to the block
+    /// when it is known. Its output total and BIP34 height are kept for `get_block_fees`
+    pub fn record_coinbase_tx(
+        &self,
+        tx: &Transaction,
+        block_hash: Option<BlockHash>,
+    ) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        if !tx.is_coinbase() {
+            return Ok(());
+        }
+        self.insert_coinbase_row(&self.pool.get()?, tx, block_hash, now_ms!())
+    }
+
+    /// Store a coinbase found and mined at `mined_at` (unix ms), labelled with its miner
+    fn insert_coinbase_row(
+        &self,
+        conn: &rusqlite::Connection,
+        tx: &Transaction,
+        block_hash: Option<BlockHash>,
+        mined_at: u64,
+    ) -> Result<()> {
+        // special case for coinbase tx, key is the txid (see `get_tx_key`)
+        let tx_id = self.tx_key(tx)?;
+        let mut tx_bytes = vec![];
+        tx.consensus_encode(&mut tx_bytes)?;
+        let tx_str = hex::encode(tx_bytes);
+        let (op_return_count, op_return_bytes) = op_return_bytes(tx);
+        let miner = self.miners.identify(tx);
+        let coinbase_value: Amount = tx.output.iter().map(|output| output.value).sum();
+        let block_height = coinbase_height(tx);
+        conn.execute(
+            "INSERT OR REPLACE INTO transactions
+            (inputs_hash, tx_data, tx_id, found_at, mined_at, absolute_fee, fee_rate, tx_type, op_return_count, op_return_bytes, miner, coinbase_tag, block_hash, coinbase_value, block_height, matures_at_height, version, seq)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, (SELECT COALESCE(MAX(seq), 0) + 1 FROM transactions))",
+            params![
+                tx_id,
+                tx_str,
+                tx_id,
+                mined_at,
+                mined_at,
+                Amount::ZERO.to_sat(),
+                FeeRate::ZERO.to_sat_per_vb_ceil(),
+                classify_tx(tx).as_str(),
+                op_return_count,
+                op_return_bytes,
+                miner.label,
+                miner.tag,
+                block_hash.map(|hash| hash.to_byte_array().to_vec()),
+                coinbase_value.to_sat(),
+                block_height,
+                block_height.map(|height| height + self.coinbase_maturity),
+                COINBASE_TRANSACTION_VERSION
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Mark a tracked tx mined, `block_hash` is the confirming block when known
+    pub fn record_mined_tx(&self, tx: &Transaction, block_hash: Option<BlockHash>) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let mut tx = tx.clone();
+        prune_large_witnesses(&mut tx);
+        let inputs_hash = self.tx_key(&tx)?;
+        let mut tx_bytes = vec![];
+        tx.consensus_encode(&mut tx_bytes)?;
+        let tx_str = hex::encode(tx_bytes);
+        let conn = self.pool.get()?;
+        let mined_at = now_ms!();
+
+        let tx_in_mempool: bool = conn.query_row(
+            "SELECT COUNT(*) FROM transactions WHERE inputs_hash = ?1",
+            params![inputs_hash],
+            |row| row.get(0),
+        )?;
+        if !tx_in_mempool {
+            info!("Received tx that was not in my mempool: {}", inputs_hash);
+        }
+        conn.execute(
+            "UPDATE transactions SET mined_at = ?1, tx_data = ?2, seen_in_mempool = ?3,
+                block_hash = COALESCE(?4, block_hash)
+            WHERE inputs_hash = ?5",
+            params![
+                mined_at,
+                tx_str,
+                true,
+                block_hash.map(|hash| hash.to_byte_array().to_vec()),
+                inputs_hash
+            ],
+        )?;
+        let tx_id = tx.compute_txid().to_string();
+        Self::resolve_conflicts(&conn, &tx_id, mined_at)?;
+        Self::refresh_descendant_fee_rates(&conn, &tx_id)?;
+        Self::store_truc_topology(&conn, &tx_id)?;
+        conn.execute(
+            "DELETE FROM spent_outpoints WHERE inputs_hash = ?1",
+            params![inputs_hash],
+        )?;
+
+        Ok(())
+    }
+
+    /// Settle open conflicts involving a tx that just confirmed, the other side can
+    /// never confirm and is marked pruned, releasing the outpoints it still claims.
+    /// `now_ms` is the winner's `mined_at`
+    fn resolve_conflicts(
+        conn: &rusqlite::Connection,
+        winner_txid: &str,
+        now_ms: u64,
+    ) -> Result<()> {
+        let losers =
+            "SELECT conflicting_txid FROM conflicts WHERE txid = ?1 AND resolved_at IS NULL
+            UNION
+            SELECT txid FROM conflicts WHERE conflicting_txid = ?1 AND resolved_at IS NULL";
+        conn.execute(
+            &format!(
+                "UPDATE transactions SET pruned_at = ?2
+                WHERE mined_at IS NULL AND pruned_at IS NULL AND tx_id IN ({losers})"
+            ),
+            params![winner_txid, now_ms],
+        )?;
+        conn.execute(
+            &format!("DELETE FROM spent_outpoints WHERE txid IN ({losers})"),
+            params![winner_txid],
+        )?;
+        conn.execute(
+            "UPDATE conflicts SET winner_txid = ?1, resolved_at = ?2
+            WHERE (txid = ?1 OR conflicting_txid = ?1) AND resolved_at IS NULL",
+            params![winner_txid, now_ms / 1000],
+        )?;
+        Ok(())
+    }
+
+    /// Record conflicts between `tx` and the pending txs already claiming any of its
+    /// outpoints. Must run before `tx` is inserted, which moves the claims over to it
+    pub fn record_conflicts(&self, tx: &Transaction) -> Result<Vec<OutpointConflict>> {
+        let conn = self.pool.get()?;
+        let txid = tx.compute_txid();
+        let mut stmt = conn.prepare("SELECT txid FROM spent_outpoints WHERE outpoint = ?1")?;
+        let mut conflicts: Vec<OutpointConflict> = vec![];
+        for input in &tx.input {
+            let claimant: Option<String> = stmt
+                .query_row(params![input.previous_output.to_string()], |row| row.get(0))
+                .optional()?;
+            let Some(claimant) = claimant else {
+                continue;
+            };
+            let claimant = Txid::from_str(&claimant)?;
+            if claimant == txid {
+                continue;
+            }
+            match conflicts
+                .iter_mut()
+                .find(|conflict| conflict.conflicting_txid == claimant)
+            {
+                Some(conflict) => conflict.outpoints.push(input.previous_output),
+                None => conflicts.push(OutpointConflict {
+                    txid,
+                    conflicting_txid: claimant,
+                    outpoints: vec![input.previous_output],
+                }),
+            }
+        }
+        if self.read_only {
+            return Ok(conflicts);
+        }
+
+        let detected_at = now!();
+        for conflict in &conflicts {
+            let outpoints = conflict
+                .outpoints
+                .iter()
+                .map(|outpoint| outpoint.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            conn.execute(
+                "INSERT INTO conflicts (txid, conflicting_txid, outpoints, detected_at) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    conflict.txid.to_string(),
+                    conflict.conflicting_txid.to_string(),
+                    outpoints,
+                    detected_at
+                ],
+            )?;
+        }
+        Ok(conflicts)
+    }
+
+    pub fn block_is_covered(&self, block_height: u64) -> Result<bool> {
+        let conn = self.pool.get()?;
+        let covered: bool = conn.query_row(
+            "SELECT COUNT(*) FROM backfill_coverage WHERE block_height = ?1",
+            params![block_height],
+            |row| row.get(0),
+        )?;
+        Ok(covered)
+    }
+
+    /// Whether `record_block` stored this block already
+    pub fn block_recorded(&self, block_hash: &BlockHash) -> Result<bool> {
+        let conn = self.pool.get()?;
+        let recorded: bool = conn.query_row(
+            "SELECT COUNT(*) FROM blocks WHERE block_hash = ?1",
+            params![block_hash.to_byte_array().to_vec()],
+            |row| row.get(0),
+        )?;
+        Ok(recorded)
+    }
+
+    /// Height of the highest block `record_block` stored, `None` before the first
+    pub fn last_recorded_block_height(&self) -> Result<Option<u64>> {
+        let conn = self.pool.get()?;
+        Ok(conn.query_row("SELECT MAX(block_height) FROM blocks", [], |row| row.get(0))?)
+    }
+
+    /// Ingest a historical block in one transaction: tracked txs are marked mined,
+    /// unknown ones are inserted as first seen in the block (`found_at` = block time), the
+    /// coinbase as `record_coinbase_tx` stores it. `txs` pairs each of the block's
+    /// transactions with its absolute fee
+    pub fn record_backfilled_block(
+        &self,
+        block_height: u64,
+        block_hash: BlockHash,
+        block_time: u64,
+        txs: &[(Transaction, Amount)],
+    ) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let mut conn = self.pool.get()?;
+        let db_tx = conn.transaction()?;
+        let block_hash_bytes = block_hash.to_byte_array().to_vec();
+        for (tx, fee) in txs {
+            if tx.is_coinbase() {
+                self.insert_coinbase_row(&db_tx, tx, Some(block_hash), block_time * 1000)?;
+                continue;
+            }
+            let inputs_hash = self.tx_key(tx)?;
+            let updated = db_tx.execute(
+                "UPDATE transactions SET mined_at = COALESCE(mined_at, ?1), block_height = ?2, block_hash = ?3
+                WHERE inputs_hash = ?4",
+                params![block_time * 1000, block_height, block_hash_bytes, inputs_hash],
+            )?;
+            if updated > 0 {
+                db_tx.execute(
+                    "DELETE FROM spent_outpoints WHERE inputs_hash = ?1",
+                    params![inputs_hash],
+                )?;
+                continue;
+            }
+
+            let block = MinedIn {
+                height: Some(block_height),
+                hash: &block_hash_bytes,
+                time: block_time,
+            };
+            self.insert_mined_row(&db_tx, tx, *fee, &block, false)?;
+        }
+        db_tx.execute(
+            "INSERT OR REPLACE INTO backfill_coverage (block_height, block_hash, tx_count, completed_at)
+            VALUES (?1, ?2, ?3, ?4)",
+            params![block_height, block_hash.to_string(), txs.len(), now!()],
+        )?;
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    /// Store txs of a freshly connected block that were never in our mempool, found and
+    /// mined at the block time. Already stored txs are left untouched
+    pub fn record_out_of_band_txs(
+        &self,
+        block_height: Option<u64>,
+        block_hash: BlockHash,
+        block_time: u64,
+        txs: &[(Transaction, Amount)],
+    ) -> Result<()> {
+        if self.read_only || txs.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.pool.get()?;
+        let db_tx = conn.transaction()?;
+        let block_hash_bytes = block_hash.to_byte_array().to_vec();
+        let block = MinedIn {
+            height: block_height,
+            hash: &block_hash_bytes,
+            time: block_time,
+        };
+        for (tx, fee) in txs {
+            self.insert_mined_row(&db_tx, tx, *fee, &block, true)?;
+        }
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    /// Size of every connected block we processed, the denominator of block space shares
+    pub fn record_block(
+        &self,
+        block_hash: BlockHash,
+        block_height: Option<u64>,
+        block_time: u64,
+        vsize: u64,
+        tx_count: usize,
+    ) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO blocks (block_hash, block_height, block_time, vsize, tx_count, recorded_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                block_hash.to_byte_array().to_vec(),
+                block_height,
+                block_time,
+                vsize,
+                tx_count,
+                now!()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Insert a tx we only learned about from a block. Large witnesses are pruned from the
+    /// stored tx_data, size and type are taken from the full tx
+    fn insert_mined_row(
+        &self,
+        conn: &rusqlite::Connection,
+        tx: &Transaction,
+        fee: Amount,
+        block: &MinedIn,
+        out_of_band: bool,
+    ) -> Result<()> {
+        let inputs_hash = self.tx_key(tx)?;
+        let mut stored = tx.clone();
+        prune_large_witnesses(&mut stored);
+        let mut tx_bytes = vec![];
+        stored.consensus_encode(&mut tx_bytes)?;
+        let fee_rate = compute_fee_rate(tx, fee)?;
+        let (op_return_count, op_return_bytes) = op_return_bytes(tx);
+        let (version, matures_at_height) = if tx.is_coinbase() {
+            (
+                COINBASE_TRANSACTION_VERSION,
+                block.height.map(|height| height + self.coinbase_maturity),
+            )
+        } else {
+            (MEMPOOL_TRANSACTION_VERSION, None)
+        };
+        conn.execute(
+            "INSERT OR IGNORE INTO transactions
+            (inputs_hash, tx_id, tx_data, found_at, mined_at, seen_in_mempool, out_of_band, absolute_fee, fee_rate, vsize, tx_type, op_return_count, op_return_bytes, dust_output_count, is_truc, sighash_mask, block_height, block_hash, matures_at_height, version, seq)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, (SELECT COALESCE(MAX(seq), 0) + 1 FROM transactions))",
+            params![
+                inputs_hash,
+                tx.compute_txid().to_string(),
+                hex::encode(tx_bytes),
+                block.time * 1000,
+                block.time * 1000,
+                false,
+                out_of_band,
+                fee.to_sat(),
+                fee_rate.to_sat_per_vb_ceil(),
+                tx.vsize(),
+                classify_tx(tx).as_str(),
+                op_return_count,
+                op_return_bytes,
+                self.dust_output_count(tx),
+                is_truc(tx),
+                sighash_mask(tx),
+                block.height,
+                block.hash,
+                matures_at_height,
+                version
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Pending txs the node no longer has in its mempool. The node's txids are loaded into
+    /// a temp table so the difference is computed by SQLite, the transaction is rolled back
+    /// afterwards so nothing outlives the call
+    pub fn txids_of_txs_not_in_list(&self, txids: Vec<Txid>) -> Result<Vec<Txid>> {
+        let mut conn = self.pool.get()?;
+        let db_tx = conn.transaction()?;
+        db_tx.execute(
+            "CREATE TEMP TABLE IF NOT EXISTS node_mempool (tx_id TEXT PRIMARY KEY)",
+            [],
+        )?;
+        {
+            let mut insert =
+                db_tx.prepare("INSERT OR IGNORE INTO temp.node_mempool (tx_id) VALUES (?1)")?;
+            for txid in &txids {
+                insert.execute(params![txid.to_string()])?;
+            }
+        }
+        let missing = {
+            let mut stmt = db_tx.prepare(
+                "SELECT tx_id FROM transactions
+                WHERE mined_at IS NULL AND pruned_at IS NULL
+                AND tx_id NOT IN (SELECT tx_id FROM temp.node_mempool)",
+            )?;
+            let txids = stmt
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            txids
+                .iter()
+                .map(|txid| Ok(Txid::from_str(txid)?))
+                .collect::<Result<Vec<_>>>()?
+        };
+        db_tx.rollback()?;
+        Ok(missing)
+    }
+
+    /// Mark `txids` pruned and release their spent outpoints in a single transaction
+    pub fn record_pruned_txs(&self, txids: Vec<Txid>) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        if txids.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.pool.get()?;
+        let db_tx = conn.transaction()?;
+        let pruned_at = now_ms!();
+        for chunk in txids.chunks(PRUNE_BATCH_SIZE) {
+            let txid_strs: Vec<String> = chunk.iter().map(|txid| txid.to_string()).collect();
+            let placeholders = vec!["?"; chunk.len()].join(",");
+            let mut update_params: Vec<&dyn rusqlite::ToSql> = vec![&pruned_at];
+            update_params.extend(txid_strs.iter().map(|txid| txid as &dyn rusqlite::ToSql));
+            db_tx.execute(
+                &format!(
+                    "UPDATE transactions SET pruned_at = ? WHERE tx_id IN ({})",
+                    placeholders
+                ),
+                update_params.as_slice(),
+            )?;
+            db_tx.execute(
+                &format!(
+                    "DELETE FROM spent_outpoints WHERE txid IN ({})",
+                    placeholders
+                ),
+                params_from_iter(txid_strs.iter()),
+            )?;
+        }
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    /// Record a purge of the already pruned `txids` and tag them with the 'purged' removal reason
+    pub fn record_purge_event(
+        &self,
+        txids: &[Txid],
+        mempool_min_fee_before: Option<FeeRate>,
+        mempool_min_fee_after: Option<FeeRate>,
+    ) -> Result<PurgeEvent> {
+        let txid_list = txids
+            .iter()
+            .map(|txid| format!("'{}'", txid))
+            .collect::<Vec<String>>()
+            .join(",");
+        let conn = self.pool.get()?;
+        let removed_vbytes: u64 = conn.query_row(
+            &format!(
+                "SELECT COALESCE(SUM(vsize), 0) FROM transactions WHERE tx_id IN ({})",
+                txid_list
+            ),
+            [],
+            |row| row.get(0),
+        )?;
+        let event = PurgeEvent {
+            detected_at: now!(),
+            removed_count: txids.len() as u64,
+            removed_vbytes,
+            mempool_min_fee_before,
+            mempool_min_fee_after,
+        };
+        if self.read_only {
+            return Ok(event);
+        }
+        conn.execute(
+            &format!(
+                "UPDATE transactions SET removal_reason = 'purged' WHERE tx_id IN ({})",
+                txid_list
+            ),
+            [],
+        )?;
+        conn.execute(
+            "INSERT INTO purge_events
+            (detected_at, removed_count, removed_vbytes, mempoolminfee_before, mempoolminfee_after)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                event.detected_at,
+                event.removed_count,
+                event.removed_vbytes,
+                event.mempool_min_fee_before.map(sat_per_kvb),
+                event.mempool_min_fee_after.map(sat_per_kvb)
+            ],
+        )?;
+        Ok(event)
+    }
+
+    /// `found_at` is when we observed the tx (defaults to now),
+    /// `node_seen_at` is the node's mempool entry time when known, both in unix ms
+    pub fn insert_mempool_tx(
+        &self,
+        tx: Transaction,
+        found_at: Option<u64>,
+        node_seen_at: Option<u64>,
+        absolute_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let conn = self.pool.get()?;
+        self.insert_pending_row(&conn, &tx, found_at, node_seen_at, absolute_fee, fee_rate)
+    }
+
+    /// `insert_mempool_tx` for many txs in a single sqlite transaction, found now.
+    /// `txs` pairs each tx with the node's mempool entry time, fee and fee rate
+    pub fn insert_mempool_txs(
+        &self,
+        txs: &[(Transaction, Option<u64>, Amount, FeeRate)],
+    ) -> Result<()> {
+        if self.read_only || txs.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.pool.get()?;
+        let db_tx = conn.transaction()?;
+        for (tx, node_seen_at, absolute_fee, fee_rate) in txs {
+            self.insert_pending_row(&db_tx, tx, None, *node_seen_at, *absolute_fee, *fee_rate)?;
+        }
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    /// The large value threshold `tx`'s outputs total more than, `None` below it or
+    /// without one, see `with_large_value_threshold`
+    pub fn exceeded_large_value_threshold(&self, tx: &Transaction) -> Option<Amount> {
+        let total_out_value: Amount = tx.output.iter().map(|output| output.value).sum();
+        self.large_value_threshold
+            .filter(|threshold| total_out_value > *threshold)
+    }
+
+    /// Txs flagged large value found within `[start, end)` (unix seconds), in the order
+    /// they were found
+    pub fn large_value_txids(&self, start: u64, end: u64) -> Result<Vec<Txid>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_id FROM transactions
+            WHERE is_large_value AND found_at >= ?1 * 1000 AND found_at < ?2 * 1000
+            ORDER BY found_at, tx_id",
+        )?;
+        let txids = stmt
+            .query_map(params![start, end], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        txids.iter().map(|txid| Ok(Txid::from_str(txid)?)).collect()
+    }
+
+    fn insert_pending_row(
+        &self,
+        conn: &rusqlite::Connection,
+        tx: &Transaction,
+        found_at: Option<u64>,
+        node_seen_at: Option<u64>,
+        absolute_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<()> {
+        let inputs_hash = self.tx_key(tx)?;
+        let mut tx_bytes = vec![];
+        tx.consensus_encode(&mut tx_bytes)?;
+        let tx_str = hex::encode(tx_bytes);
+
+        let tx_id = tx.compute_txid().to_string();
+        let found_at = found_at.unwrap_or(now_ms!());
+        // Normally routed through the replacement path, but two workers can race on the
+        // variants of a slot. The overwritten one stays in txid_history
+        let occupant: Option<String> = conn
+            .query_row(
+                "SELECT tx_id FROM transactions WHERE inputs_hash = ?1",
+                params![inputs_hash],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(occupant) = occupant.filter(|occupant| *occupant != tx_id) {
+            info!(
+                "{} takes over the inputs hash slot of {}, keeping the latter as a variant",
+                tx_id, occupant
+            );
+        }
+        let tx_type = classify_tx(tx);
+        let (op_return_count, op_return_bytes) = op_return_bytes(tx);
+
+        // An edge per spent output of a tracked unconfirmed parent. When the node reported
+        // the tx's ancestry, inputs outside of it are already confirmed
+        let linked_parents = Self::linked_parents(conn, &tx_id)?;
+        let linked_at = now!();
+        for input in &tx.input {
+            let parent_txid = input.previous_output.txid.to_string();
+            if !linked_parents.is_empty() && !linked_parents.contains(&parent_txid) {
+                continue;
+            }
+            let parent_pending: bool = conn.query_row(
+                "SELECT COUNT(*) FROM transactions WHERE tx_id = ?1 AND mined_at is NULL",
+                params![parent_txid],
+                |row| row.get(0),
+            )?;
+            if parent_pending {
+                conn.execute(
+                    "INSERT OR IGNORE INTO tx_parents (child_inputs_hash, parent_txid, vout, linked_at)
+                    VALUES (?1, ?2, ?3, ?4)",
+                    params![inputs_hash, parent_txid, input.previous_output.vout, linked_at],
+                )?;
+            }
+        }
+
+        conn.execute(
+            "INSERT OR REPLACE INTO transactions
+            (inputs_hash, tx_id, tx_data, found_at, node_seen_at, absolute_fee, fee_rate, vsize, tx_type, op_return_count, op_return_bytes, dust_output_count, is_truc, sighash_mask, input_value_total, is_large_value, version, seq)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, (SELECT COALESCE(MAX(seq), 0) + 1 FROM transactions))",
+            params![
+                inputs_hash,
+                tx_id,
+                tx_str,
+                found_at,
+                node_seen_at,
+                absolute_fee.to_sat(),
+                fee_rate.to_sat_per_vb_ceil(),
+                tx.vsize(),
+                tx_type.as_str(),
+                op_return_count,
+                op_return_bytes,
+                self.dust_output_count(tx),
+                is_truc(tx),
+                sighash_mask(tx),
+                Self::input_value_total(conn, tx)?.map(|total| total.to_sat()),
+                self.exceeded_large_value_threshold(tx).is_some(),
+                MEMPOOL_TRANSACTION_VERSION
+            ],
+        )?;
+        for input in tx.input.iter() {
+            conn.execute(
+                "INSERT OR REPLACE INTO spent_outpoints (outpoint, txid, inputs_hash, created_at)
+                VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    input.previous_output.to_string(),
+                    tx_id,
+                    inputs_hash,
+                    found_at / 1000
+                ],
+            )?;
+        }
+        Self::record_variant(conn, &inputs_hash, &tx_id, &tx_str, found_at)?;
+        Self::store_effective_fee_rate(conn, &tx_id)?;
+        Self::store_truc_topology(conn, &tx_id)?;
+
+        Ok(())
+    }
+
+    pub fn tx_exists(&self, tx: &Transaction) -> Result<bool> {
+        let conn = self.pool.get()?;
+        let inputs_hash = self.tx_key(tx)?;
+
+        let count: i32 = conn.query_row(
+            "SELECT COUNT(*) FROM transactions WHERE inputs_hash = ?1",
+            params![inputs_hash],
+            |row| row.get(0),
+        )?;
+
+        Ok(count > 0)
+    }
+
+    /// Fee and fee rate currently stored for the transaction's slot, `None` while the
+    /// fee of the occupying tx is unknown
+    pub fn get_stored_fee(&self, tx: &Transaction) -> Result<Option<(Amount, FeeRate)>> {
+        let conn = self.pool.get()?;
+        let inputs_hash = self.tx_key(tx)?;
+        let fee: Option<(u64, u64)> = conn
+            .query_row(
+                "SELECT absolute_fee, fee_rate FROM transactions
+                WHERE inputs_hash = ?1 AND fee_known = 1",
+                params![inputs_hash],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+
+        Ok(fee.map(|(fee, fee_rate)| {
+            (
+                Amount::from_sat(fee),
+                FeeRate::from_sat_per_vb_unchecked(fee_rate),
+            )
+        }))
+    }
+
+    /// Currently stored transaction occupying the same slot as `tx`
+    pub fn get_stored_tx(&self, tx: &Transaction) -> Result<Option<Transaction>> {
+        let conn = self.pool.get()?;
+        let inputs_hash = self.tx_key(tx)?;
+        let tx_data: Option<String> = conn
+            .query_row(
+                "SELECT tx_data FROM transactions WHERE inputs_hash = ?1",
+                params![inputs_hash],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(tx_data.map(|data| {
+            let bytes = hex::decode(data).expect("should be valid hex");
+            Transaction::consensus_decode(&mut bytes.as_slice()).expect("Valid transaction")
+        }))
+    }
+
+    /// Record the node's view of a mempool tx's unconfirmed parents and children
+    pub fn record_tx_links(&self, txid: &Txid, depends: &[Txid], spent_by: &[Txid]) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let conn = self.pool.get()?;
+        let observed_at = now!();
+        let links = depends
+            .iter()
+            .map(|related| (related, LinkDirection::Parent))
+            .chain(
+                spent_by
+                    .iter()
+                    .map(|related| (related, LinkDirection::Child)),
+            );
+        for (related, direction) in links {
+            conn.execute(
+                "INSERT OR REPLACE INTO tx_links (txid, related_txid, direction, observed_at)
+                VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    txid.to_string(),
+                    related.to_string(),
+                    direction.as_str(),
+                    observed_at
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Value of the outputs `tx` spends, resolved from the stored parents, so the fee can
+    /// be recomputed locally. `None` as soon as one parent isn't tracked
+    fn input_value_total(conn: &rusqlite::Connection, tx: &Transaction) -> Result<Option<Amount>> {
+        if tx.is_coinbase() {
+            return Ok(None);
+        }
+        let mut total = Amount::ZERO;
+        // Decoded once per parent, however many of its outputs are spent
+        let mut parents: HashMap<Txid, Transaction> = HashMap::new();
+        for input in &tx.input {
+            let parent_txid = input.previous_output.txid;
+            if !parents.contains_key(&parent_txid) {
+                let parent_data: Option<String> = conn
+                    .query_row(
+                        "SELECT tx_data FROM transactions WHERE tx_id = ?1 AND tx_data != '' LIMIT 1",
+                        params![parent_txid.to_string()],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                let Some(parent_data) = parent_data else {
+                    return Ok(None);
+                };
+                let parent =
+                    Transaction::consensus_decode(&mut hex::decode(parent_data)?.as_slice())?;
+                parents.insert(parent_txid, parent);
+            }
+            let Some(output) = parents[&parent_txid]
+                .output
+                .get(input.previous_output.vout as usize)
+            else {
+                return Ok(None);
+            };
+            total += output.value;
+        }
+        Ok(Some(total))
+    }
+
+    /// Parents of `tx_id` known from tx_links, reported either as its depends
+    /// or as another tx's spentby
+    fn linked_parents(conn: &rusqlite::Connection, tx_id: &str) -> Result<Vec<String>> {
+        let mut stmt = conn.prepare(
+            "SELECT related_txid FROM tx_links WHERE txid = ?1 AND direction = ?2
+            UNION
+            SELECT txid FROM tx_links WHERE related_txid = ?1 AND direction = ?3",
+        )?;
+        let parents = stmt
+            .query_map(
+                params![
+                    tx_id,
+                    LinkDirection::Parent.as_str(),
+                    LinkDirection::Child.as_str()
+                ],
+                |row| row.get(0),
+            )?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(parents)
+    }
+
+    /// Unconfirmed parents of a stored tx: the node's reported ancestry when we have it,
+    /// otherwise the tracked parents recorded at insertion (untracked ones are assumed confirmed)
+    fn parent_txids(conn: &rusqlite::Connection, tx_id: &str) -> Result<Vec<String>> {
+        let linked = Self::linked_parents(conn, tx_id)?;
+        if !linked.is_empty() {
+            return Ok(linked);
+        }
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT tx_parents.parent_txid FROM tx_parents
+            JOIN transactions ON transactions.inputs_hash = tx_parents.child_inputs_hash
+            WHERE transactions.tx_id = ?1",
+        )?;
+        let parents = stmt
+            .query_map(params![tx_id], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(parents)
+    }
+
+    fn child_txids(conn: &rusqlite::Connection, tx_id: &str) -> Result<Vec<String>> {
+        let mut stmt = conn.prepare(
+            "SELECT related_txid FROM tx_links WHERE txid = ?1 AND direction = ?2
+            UNION
+            SELECT txid FROM tx_links WHERE related_txid = ?1 AND direction = ?3
+            UNION
+            SELECT transactions.tx_id FROM tx_parents
+            JOIN transactions ON transactions.inputs_hash = tx_parents.child_inputs_hash
+            WHERE tx_parents.parent_txid = ?1",
+        )?;
+        let children = stmt
+            .query_map(
+                params![
+                    tx_id,
+                    LinkDirection::Child.as_str(),
+                    LinkDirection::Parent.as_str()
+                ],
+                |row| row.get(0),
+            )?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(children)
+    }
+
+    /// Unconfirmed parents/children we store of a pending tx, `(tx_id, is_truc, vsize)`
+    fn pending_relatives(
+        conn: &rusqlite::Connection,
+        tx_ids: Vec<String>,
+    ) -> Result<Vec<(String, bool, Option<u64>)>> {
+        let mut stmt = conn.prepare(
+            "SELECT tx_id, is_truc, vsize FROM transactions
+            WHERE tx_id = ?1 AND mined_at IS NULL AND pruned_at IS NULL",
+        )?;
+        let mut relatives = vec![];
+        for tx_id in tx_ids {
+            if let Some(row) = stmt
+                .query_row(params![tx_id], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })
+                .optional()?
+            {
+                relatives.push(row);
+            }
+        }
+        Ok(relatives)
+    }
+
+    /// Whether a pending TRUC tx's package respects BIP431: at most one unconfirmed
+    /// parent, itself TRUC with no other child and no unconfirmed parent of its own,
+    /// and size limits of 10k vB, or 1k vB for the child
+    fn truc_topology_ok(conn: &rusqlite::Connection, tx_id: &str) -> Result<bool> {
+        let vsize: Option<u64> = conn.query_row(
+            "SELECT vsize FROM transactions WHERE tx_id = ?1",
+            params![tx_id],
+            |row| row.get(0),
+        )?;
+        let vsize = vsize.unwrap_or(0);
+        if vsize > TRUC_MAX_VSIZE {
+            return Ok(false);
+        }
+        let parents = Self::pending_relatives(conn, Self::parent_txids(conn, tx_id)?)?;
+        let children = Self::pending_relatives(conn, Self::child_txids(conn, tx_id)?)?;
+        if parents.len() + children.len() > 1 {
+            return Ok(false);
+        }
+        if let Some((parent_txid, parent_is_truc, _)) = parents.first() {
+            let grandparents =
+                Self::pending_relatives(conn, Self::parent_txids(conn, parent_txid)?)?;
+            let siblings = Self::pending_relatives(conn, Self::child_txids(conn, parent_txid)?)?;
+            return Ok(*parent_is_truc
+                && grandparents.is_empty()
+                && siblings.len() <= 1
+                && vsize <= TRUC_CHILD_MAX_VSIZE);
+        }
+        // As the parent, the child has to fit the TRUC child rules
+        Ok(children
+            .first()
+            .is_none_or(|(_, child_is_truc, child_vsize)| {
+                *child_is_truc && child_vsize.unwrap_or(0) <= TRUC_CHILD_MAX_VSIZE
+            }))
+    }
+
+    /// Record the topology check of every pending TRUC tx in the package `tx_id` joined or
+    /// left: itself, its parents and their other children, its children and theirs.
+    /// `tx_id` needn't be TRUC nor pending, e.g. a mined parent frees its descendants
+    fn store_truc_topology(conn: &rusqlite::Connection, tx_id: &str) -> Result<()> {
+        let mut tx_ids = vec![tx_id.to_string()];
+        for parent_txid in Self::parent_txids(conn, tx_id)? {
+            tx_ids.extend(Self::child_txids(conn, &parent_txid)?);
+            tx_ids.push(parent_txid);
+        }
+        for child_txid in Self::child_txids(conn, tx_id)? {
+            tx_ids.extend(Self::child_txids(conn, &child_txid)?);
+            tx_ids.push(child_txid);
+        }
+        tx_ids.sort();
+        tx_ids.dedup();
+        let truc_txids = Self::pending_relatives(conn, tx_ids)?
+            .into_iter()
+            .filter(|(_, is_truc, _)| *is_truc)
+            .map(|(tx_id, _, _)| tx_id);
+        for tx_id in truc_txids {
+            let ok = Self::truc_topology_ok(conn, &tx_id)?;
+            conn.execute(
+                "UPDATE transactions SET truc_topology_ok = ?1 WHERE tx_id = ?2",
+                params![ok, tx_id],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Ancestor-inclusive fee rate of a pending tx, `(rate, ancestors_known)`. Falls back
+    /// to the naive rate when any unconfirmed ancestor's fee or size is unknown.
+    /// `None` if the tx itself isn't stored with a vsize
+    fn compute_effective_fee_rate(
+        conn: &rusqlite::Connection,
+        tx_id: &str,
+    ) -> Result<Option<(f64, bool)>> {
+        let mut stmt = conn.prepare(
+            "SELECT absolute_fee, vsize, mined_at, pruned_at FROM transactions WHERE tx_id = ?1",
+        )?;
+        let mut lookup = |tx_id: &str| -> Result<Option<(u64, Option<u64>, bool, bool)>> {
+            Ok(stmt
+                .query_row(params![tx_id], |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get::<_, Option<u64>>(2)?.is_some(),
+                        row.get::<_, Option<u64>>(3)?.is_some(),
+                    ))
+                })
+                .optional()?)
+        };
+        let Some((fee, Some(vsize), _, _)) = lookup(tx_id)? else {
+            return Ok(None);
+        };
+        if vsize == 0 {
+            return Ok(None);
+        }
+        let naive = fee as f64 / vsize as f64;
+
+        let (mut total_fee, mut total_vsize) = (fee, vsize);
+        let mut seen = HashSet::from([tx_id.to_string()]);
+        let mut queue = VecDeque::from(Self::parent_txids(conn, tx_id)?);
+        while let Some(parent) = queue.pop_front() {
+            if !seen.insert(parent.clone()) {
+                continue;
+            }
+            match lookup(&parent)? {
+                // Confirmed ancestors no longer weigh on the package
+                Some((_, _, true, _)) => {}
+                Some((fee, Some(vsize), false, false)) => {
+                    total_fee += fee;
+                    total_vsize += vsize;
+                    queue.extend(Self::parent_txids(conn, &parent)?);
+                }
+                // Untracked, evicted or stored without a size
+                _ => return Ok(Some((naive, false))),
+            }
+        }
+        Ok(Some((total_fee as f64 / total_vsize as f64, true)))
+    }
+
+    fn store_effective_fee_rate(conn: &rusqlite::Connection, tx_id: &str) -> Result<()> {
+        if let Some((rate, ancestors_known)) = Self::compute_effective_fee_rate(conn, tx_id)? {
+            conn.execute(
+                "UPDATE transactions SET effective_fee_rate = ?1, ancestors_known = ?2 WHERE tx_id = ?3",
+                params![rate, ancestors_known, tx_id],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Recompute the effective fee rate of every pending descendant of `tx_id`,
+    /// after it confirmed or was replaced
+    fn refresh_descendant_fee_rates(conn: &rusqlite::Connection, tx_id: &str) -> Result<()> {
+        let mut seen = HashSet::from([tx_id.to_string()]);
+        let mut queue = VecDeque::from([tx_id.to_string()]);
+        while let Some(current) = queue.pop_front() {
+            for child in Self::child_txids(conn, &current)? {
+                if seen.insert(child.clone()) {
+                    Self::store_effective_fee_rate(conn, &child)?;
+                    queue.push_back(child);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `reject_reason` is why the node refused the original once the replacement arrived
+    pub fn record_rbf(
+        &self,
+        transaction: &Transaction,
+        bump: &RbfBump,
+        reject_reason: Option<&str>,
+    ) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let conn = self.pool.get()?;
+        let inputs_hash = self.tx_key(transaction)?;
+        let tx_id = transaction.compute_txid().to_string();
+        let created_at = now!();
+
+        // If input_hash is not in the database, ignore this
+        if !self.tx_exists(transaction)? {
+            return Ok(());
+        }
+
+        let fee_total = bump.fee.to_sat();
+        conn.execute(
+            "INSERT OR REPLACE INTO rbf (inputs_hash, created_at, fee_total, version) VALUES (?1, ?2, ?3, ?4)",
+            params![inputs_hash, created_at, fee_total, RBF_TRANSACTION_VERSION],
+        )?;
+        conn.execute(
+            "INSERT INTO rbf_history
+            (inputs_hash, tx_id, created_at, fee_total, prev_fee, fee_delta, fee_rate_delta, fee_bump_pct, reject_reason, is_initial_observation, version)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                inputs_hash,
+                tx_id,
+                created_at,
+                fee_total,
+                bump.prev_fee.map(|fee| fee.to_sat()),
+                bump.fee_delta,
+                bump.fee_rate_delta,
+                bump.fee_bump_pct,
+                reject_reason,
+                bump.is_initial_observation,
+                RBF_TRANSACTION_VERSION
+            ],
+        )?;
+        // The replacement's fee becomes the baseline for the next bump
+        conn.execute(
+            "UPDATE transactions SET absolute_fee = ?1, fee_rate = ?2, fee_known = 1
+            WHERE inputs_hash = ?3",
+            params![fee_total, bump.fee_rate.to_sat_per_vb_ceil(), inputs_hash],
+        )?;
+
+        Ok(())
+    }
+
+    /// Store the BIP125 rules the latest recorded replacement by `tx` broke, see
+    /// `check_bip125_rules`
+    pub fn record_bip125_violations(&self, tx: &Transaction, violations: &[&str]) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let conn = self.pool.get()?;
+        conn.execute(
+            "UPDATE rbf_history SET bip125_violations = ?1
+            WHERE id = (SELECT MAX(id) FROM rbf_history WHERE tx_id = ?2)",
+            params![violations.join(","), tx.compute_txid().to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Number of tracked descendants of `txid`, which a replacement of it evicts too
+    pub fn descendant_count(&self, txid: &Txid) -> Result<usize> {
+        let conn = self.pool.get()?;
+        let mut seen = HashSet::from([txid.to_string()]);
+        let mut queue = VecDeque::from([txid.to_string()]);
+        while let Some(current) = queue.pop_front() {
+            for child in Self::child_txids(&conn, &current)? {
+                if seen.insert(child.clone()) {
+                    queue.push_back(child);
+                }
+            }
+        }
+        Ok(seen.len() - 1)
+    }
+
+    /// Record a replacement whose fee could not be looked up, e.g. because it was
+    /// replaced again or evicted before a worker got to it. The history row and the slot
+    /// are marked `fee_known = 0` until `fill_unknown_fee` learns the fee. The slot's fee is
+    /// zeroed, it belonged to the replaced tx and would be read against the replacement's size
+    pub fn record_rbf_unknown_fee(
+        &self,
+        transaction: &Transaction,
+        reject_reason: Option<&str>,
+    ) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        if !self.tx_exists(transaction)? {
+            return Ok(());
+        }
+        let prev_fee = self
+            .get_stored_fee(transaction)?
+            .map(|(fee, _)| fee.to_sat());
+        let conn = self.pool.get()?;
+        let inputs_hash = self.tx_key(transaction)?;
+        let tx_id = transaction.compute_txid().to_string();
+        let created_at = now!();
+
+        conn.execute(
+            "INSERT OR REPLACE INTO rbf (inputs_hash, created_at, fee_total, version) VALUES (?1, ?2, 0, ?3)",
+            params![inputs_hash, created_at, RBF_TRANSACTION_VERSION],
+        )?;
+        conn.execute(
+            "INSERT INTO rbf_history
+            (inputs_hash, tx_id, created_at, fee_total, prev_fee, reject_reason, fee_known, is_initial_observation, version)
+            VALUES (?1, ?2, ?3, 0, ?4, ?5, 0, ?6, ?7)",
+            params![
+                inputs_hash,
+                tx_id,
+                created_at,
+                prev_fee,
+                reject_reason,
+                prev_fee.unwrap_or(0) == 0,
+                RBF_TRANSACTION_VERSION
+            ],
+        )?;
+        conn.execute(
+            "UPDATE transactions SET fee_known = 0, absolute_fee = 0, fee_rate = 0
+            WHERE inputs_hash = ?1",
+            params![inputs_hash],
+        )?;
+
+        Ok(())
+    }
+
+    /// Whether the fee stored for the transaction's slot is known
+    pub fn fee_known(&self, tx: &Transaction) -> Result<bool> {
+        let conn = self.pool.get()?;
+        let inputs_hash = self.tx_key(tx)?;
+        let known: Option<bool> = conn
+            .query_row(
+                "SELECT fee_known FROM transactions WHERE inputs_hash = ?1",
+                params![inputs_hash],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(known.unwrap_or(true))
+    }
+
+    /// Fill in the fee of a replacement recorded by `record_rbf_unknown_fee`. Only applies
+    /// while `tx` still occupies its slot, returns whether anything was filled
+    pub fn fill_unknown_fee(
+        &self,
+        tx: &Transaction,
+        fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<bool> {
+        if self.read_only {
+            return Ok(false);
+        }
+        let mut conn = self.pool.get()?;
+        let inputs_hash = self.tx_key(tx)?;
+        let tx_id = tx.compute_txid().to_string();
+        let db_tx = conn.transaction()?;
+        let filled = db_tx.execute(
+            "UPDATE transactions SET absolute_fee = ?1, fee_rate = ?2, fee_known = 1
+            WHERE inputs_hash = ?3 AND tx_id = ?4 AND fee_known = 0",
+            params![
+                fee.to_sat(),
+                fee_rate.to_sat_per_vb_ceil(),
+                inputs_hash,
+                tx_id
+            ],
+        )?;
+        if filled == 0 {
+            return Ok(false);
+        }
+        db_tx.execute(
+            "UPDATE rbf SET fee_total = ?1 WHERE inputs_hash = ?2",
+            params![fee.to_sat(), inputs_hash],
+        )?;
+        // The previous fee rate is gone by now, only the absolute comparisons are recoverable
+        db_tx.execute(
+            "UPDATE rbf_history SET fee_total = ?1, fee_known = 1,
+                fee_delta = ?1 - prev_fee,
+                fee_bump_pct = CASE WHEN prev_fee > 0 THEN (?1 - prev_fee) * 100.0 / prev_fee END
+            WHERE inputs_hash = ?2 AND tx_id = ?3 AND fee_known = 0",
+            params![fee.to_sat(), inputs_hash, tx_id],
+        )?;
+        Self::store_effective_fee_rate(&db_tx, &tx_id)?;
+        db_tx.commit()?;
+        Ok(true)
+    }
+
+    /// Point the row of a replaced tx at its replacement. The inputs hash stays, the txid,
+    /// raw tx and everything derived from it are refreshed. The node announces the removal
+    /// of the replaced tx (sequence `R`) before the replacement, a slot pruned by it is
+    /// pending again and claims its outpoints back
+    pub fn update_txid_by_inputs_hash(&self, tx: &Transaction) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let conn = self.pool.get()?;
+        let inputs_hash = self.tx_key(tx)?;
+        let tx_id = tx.compute_txid().to_string();
+        let mut tx_bytes = vec![];
+        tx.consensus_encode(&mut tx_bytes)?;
+        let (op_return_count, op_return_bytes) = op_return_bytes(tx);
+        let tx_str = hex::encode(tx_bytes);
+        let replaced: Option<(String, u64, String)> = conn
+            .query_row(
+                "SELECT tx_id, found_at, tx_data FROM transactions WHERE inputs_hash = ?1",
+                params![inputs_hash],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        if let Some((replaced_tx_id, found_at, replaced_tx_data)) = &replaced {
+            // Rows stored before the history was kept get their first txid backfilled
+            Self::record_variant(
+                &conn,
+                &inputs_hash,
+                replaced_tx_id,
+                replaced_tx_data,
+                *found_at,
+            )?;
+            Self::record_variant(&conn, &inputs_hash, &tx_id, &tx_str, now_ms!())?;
+        }
+        let replaced_tx_id = replaced.map(|(replaced_tx_id, _, _)| replaced_tx_id);
+        conn.execute(
+            "UPDATE transactions SET tx_id = ?1, tx_data = ?2, vsize = ?3, tx_type = ?4,
+                op_return_count = ?5, op_return_bytes = ?6, dust_output_count = ?7,
+                is_truc = ?8, truc_topology_ok = NULL, sighash_mask = ?9, is_large_value = ?10,
+                pruned_at = NULL, removal_reason = NULL
+            WHERE inputs_hash = ?11",
+            params![
+                tx_id,
+                tx_str,
+                tx.vsize(),
+                classify_tx(tx).as_str(),
+                op_return_count,
+                op_return_bytes,
+                self.dust_output_count(tx),
+                is_truc(tx),
+                sighash_mask(tx),
+                self.exceeded_large_value_threshold(tx).is_some(),
+                inputs_hash
+            ],
+        )?;
+        for input in tx.input.iter() {
+            conn.execute(
+                "INSERT INTO spent_outpoints (outpoint, txid, inputs_hash, created_at)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (outpoint) DO UPDATE
+                SET txid = excluded.txid, inputs_hash = excluded.inputs_hash",
+                params![
+                    input.previous_output.to_string(),
+                    tx_id,
+                    inputs_hash,
+                    now!()
+                ],
+            )?;
+        }
+        // The replacement's own fee changed, and children of the replaced tx lost their parent
+        Self::store_effective_fee_rate(&conn, &tx_id)?;
+        Self::store_truc_topology(&conn, &tx_id)?;
+        if let Some(replaced_tx_id) = replaced_tx_id.filter(|replaced| *replaced != tx_id) {
+            Self::refresh_descendant_fee_rates(&conn, &replaced_tx_id)?;
+            Self::store_truc_topology(&conn, &replaced_tx_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Keep `tx_id` as a variant of the `inputs_hash` slot. A variant seen before keeps its
+    /// first sighting, its tx is filled in if the row predates `tx_data`
+    fn record_variant(
+        conn: &rusqlite::Connection,
+        inputs_hash: &str,
+        tx_id: &str,
+        tx_data: &str,
+        seen_at: u64,
+    ) -> Result<()> {
+        conn.execute(
+            "INSERT INTO txid_history (inputs_hash, tx_id, seen_at, tx_data) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (inputs_hash, tx_id) DO UPDATE SET tx_data = COALESCE(tx_data, excluded.tx_data)",
+            params![inputs_hash, tx_id, seen_at, tx_data],
+        )?;
+        Ok(())
+    }
+
+    /// Every transaction that occupied the `inputs_hash` slot, oldest first. Variants
+    /// overwritten before their tx was kept are left out, `get_txid_history` still lists them
+    pub fn get_tx_variants(&self, inputs_hash: &str) -> Result<Vec<Transaction>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_data FROM txid_history WHERE inputs_hash = ?1 AND tx_data IS NOT NULL
+            AND tx_data != '' ORDER BY seen_at, rowid",
+        )?;
+        let rows = stmt
+            .query_map(params![inputs_hash], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.iter()
+            .map(|tx_data| {
+                Ok(Transaction::consensus_decode(
+                    &mut hex::decode(tx_data)?.as_slice(),
+                )?)
+            })
+            .collect()
+    }
+
+    /// Every txid that occupied the `inputs_hash` slot with when it was first seen (unix
+    /// ms), oldest first
+    pub fn get_txid_history(&self, inputs_hash: &str) -> Result<Vec<(Txid, u64)>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_id, seen_at FROM txid_history WHERE inputs_hash = ?1 ORDER BY seen_at, rowid",
+        )?;
+        let rows = stmt
+            .query_map(params![inputs_hash], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(txid, seen_at)| Ok((Txid::from_str(&txid)?, seen_at)))
+            .collect()
+    }
+
+    /// The txids of `txids` with no stored row
+    pub fn untracked_txids(&self, txids: &[Txid]) -> Result<Vec<Txid>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT COUNT(*) FROM transactions WHERE tx_id = ?1")?;
+        let mut untracked = vec![];
+        for txid in txids {
+            let count: u64 = stmt.query_row(params![txid.to_string()], |row| row.get(0))?;
+            if count == 0 {
+                untracked.push(*txid);
+            }
+        }
+        Ok(untracked)
+    }
+
+    /// Dust outputs of the stored tx, `None` if it isn't stored
+    pub fn get_dust_count(&self, inputs_hash: &str) -> Result<Option<usize>> {
+        let conn = self.pool.get()?;
+        let count = conn
+            .query_row(
+                "SELECT dust_output_count FROM transactions WHERE inputs_hash = ?1",
+                params![inputs_hash],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(count)
+    }
+
+    /// Whether `txid` is stored and neither mined nor pruned
+    pub fn is_pending(&self, txid: &Txid) -> Result<bool> {
+        let conn = self.pool.get()?;
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM transactions
+            WHERE tx_id = ?1 AND mined_at IS NULL AND pruned_at IS NULL",
+            params![txid.to_string()],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    pub fn pending_tx_count(&self) -> Result<u64> {
+        let conn = self.pool.get()?;
+        let count = conn.query_row(
+            "SELECT COUNT(*) FROM transactions WHERE mined_at IS NULL AND pruned_at IS NULL",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(count)
     }
 
-    pub(crate) fn txids_of_txs_not_in_list(&self, txids: Vec<Txid>) -> Result<Vec<Txid>> {
-        let conn = self.0.get()?;
-        if txids.is_empty() {
-            let mut stmt = conn.prepare(
-                "SELECT tx_id FROM transactions WHERE pruned_at IS NULL AND mined_at IS NULL",
-            )?;
-            return Ok(stmt
-                .query_map([], |row| {
-                    let txid_str: String = row.get(0)?;
-                    let bytes: Vec<u8> = hex::decode(txid_str).expect("should be valid hex");
-                    let txid = Txid::consensus_decode(&mut bytes.as_slice()).expect("Valid txid");
+    /// Record a checkpoint at the current end of the transactions table. Callers flush
+    /// first, so everything the checkpoint covers is already durable. In read-only mode
+    /// the checkpoint is returned without being stored
+    pub fn record_checkpoint(&self, best_block_hash: BlockHash) -> Result<Checkpoint> {
+        let mut conn = self.pool.get()?;
+        let db_tx = conn.transaction()?;
+        let (pending_count, last_seq) = db_tx.query_row(
+            "SELECT COUNT(*) FILTER (WHERE mined_at IS NULL AND pruned_at IS NULL),
+                COALESCE(MAX(seq), 0)
+            FROM transactions",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let checkpoint = Checkpoint {
+            checkpoint_at: now!(),
+            best_block_hash,
+            pending_count,
+            last_seq,
+        };
+        if self.read_only {
+            return Ok(checkpoint);
+        }
+        db_tx.execute(
+            "INSERT INTO checkpoints (checkpoint_at, best_block_hash, pending_count, last_seq)
+            VALUES (?1, ?2, ?3, ?4)",
+            params![
+                checkpoint.checkpoint_at,
+                checkpoint.best_block_hash.to_string(),
+                checkpoint.pending_count,
+                checkpoint.last_seq
+            ],
+        )?;
+        db_tx.commit()?;
+        Ok(checkpoint)
+    }
 
-                    Ok(txid)
-                })?
-                .collect::<Result<Vec<_>, _>>()?);
+    /// Delete the rows `with_retention` no longer keeps, in one transaction. Txs go once
+    /// they've been mined or pruned for longer than the transactions retention, pending
+    /// ones are always kept. Returns the number of deleted txs and snapshot rows
+    pub fn apply_retention(&self) -> Result<(usize, usize)> {
+        if self.read_only {
+            return Ok((0, 0));
+        }
+        let mut conn = self.pool.get()?;
+        let db_tx = conn.transaction()?;
+        let mut deleted_txs = 0;
+        if let Some(keep) = self.retention.transactions {
+            let cutoff_ms = now_ms!().saturating_sub(keep.as_millis() as u64);
+            let expired =
+                "SELECT inputs_hash FROM transactions WHERE COALESCE(mined_at, pruned_at) < ?1";
+            let expired_txids = format!(
+                "SELECT tx_id FROM txid_history WHERE inputs_hash IN ({expired})
+                UNION SELECT tx_id FROM transactions WHERE COALESCE(mined_at, pruned_at) < ?1"
+            );
+            for statement in [
+                format!("DELETE FROM tx_sightings WHERE txid IN ({expired_txids})"),
+                format!("DELETE FROM tx_links WHERE txid IN ({expired_txids})"),
+                format!("DELETE FROM txid_history WHERE inputs_hash IN ({expired})"),
+                format!("DELETE FROM tx_parents WHERE child_inputs_hash IN ({expired})"),
+                format!("DELETE FROM rbf_history WHERE inputs_hash IN ({expired})"),
+                format!("DELETE FROM rbf WHERE inputs_hash IN ({expired})"),
+                format!("DELETE FROM spent_outpoints WHERE inputs_hash IN ({expired})"),
+            ] {
+                db_tx.execute(&statement, params![cutoff_ms])?;
+            }
+            deleted_txs = db_tx.execute(
+                "DELETE FROM transactions WHERE COALESCE(mined_at, pruned_at) < ?1",
+                params![cutoff_ms],
+            )?;
         }
+        let mut deleted_snapshots = 0;
+        if let Some(keep) = self.retention.snapshots {
+            let cutoff = now!().saturating_sub(keep.as_secs());
+            for (table, column) in [
+                ("mempool", "created_at"),
+                ("fee_histogram", "snapshot_at"),
+                ("purge_events", "detected_at"),
+                ("checkpoints", "checkpoint_at"),
+            ] {
+                deleted_snapshots += db_tx.execute(
+                    &format!("DELETE FROM {table} WHERE {column} < ?1"),
+                    params![cutoff],
+                )?;
+            }
+        }
+        db_tx.commit()?;
+        Ok((deleted_txs, deleted_snapshots))
+    }
 
-        let txid_list = txids
-            .iter()
-            .map(|txid| format!("'{}'", txid.to_string()))
-            .collect::<Vec<String>>()
-            .join(",");
+    /// Most recently recorded checkpoint
+    pub fn latest_checkpoint(&self) -> Result<Option<Checkpoint>> {
+        let conn = self.pool.get()?;
+        let row = conn
+            .query_row(
+                "SELECT checkpoint_at, best_block_hash, pending_count, last_seq
+                FROM checkpoints ORDER BY id DESC LIMIT 1",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, u64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, u64>(2)?,
+                        row.get::<_, i64>(3)?,
+                    ))
+                },
+            )
+            .optional()?;
+        row.map(
+            |(checkpoint_at, best_block_hash, pending_count, last_seq)| {
+                Ok(Checkpoint {
+                    checkpoint_at,
+                    best_block_hash: BlockHash::from_str(&best_block_hash)?,
+                    pending_count,
+                    last_seq,
+                })
+            },
+        )
+        .transpose()
+    }
 
-        let query = format!(
-            "SELECT tx_id FROM transactions WHERE tx_id NOT IN ({}) AND pruned_at IS NULL AND mined_at IS NULL",
-            txid_list
-        );
+    /// TRUC adoption among txs found in `[start, end)`, next to the v2 txs found then.
+    /// Mined-only rows are included, the topology check only covers pending ones
+    pub fn truc_stats(&self, start: u64, end: u64) -> Result<TrucStats> {
+        let conn = self.pool.get()?;
+        let (total, truc_count, v2_count, topology_violations, truc_replaced, v2_replaced): (
+            u64,
+            u64,
+            u64,
+            u64,
+            u64,
+            u64,
+        ) = conn.query_row(
+            "SELECT COUNT(*),
+                COUNT(*) FILTER (WHERE is_truc),
+                COUNT(*) FILTER (WHERE NOT is_truc),
+                COUNT(*) FILTER (WHERE is_truc AND truc_topology_ok = FALSE),
+                COUNT(*) FILTER (WHERE is_truc AND replaced),
+                COUNT(*) FILTER (WHERE NOT is_truc AND replaced)
+            FROM (
+                SELECT t.is_truc, t.truc_topology_ok,
+                    EXISTS (SELECT 1 FROM rbf_history r WHERE r.inputs_hash = t.inputs_hash) AS replaced
+                FROM transactions t
+                WHERE t.found_at >= ?1 * 1000 AND t.found_at < ?2 * 1000 AND t.version = ?3
+            )",
+            params![start, end, MEMPOOL_TRANSACTION_VERSION],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            },
+        )?;
+        let share = |count: u64, of: u64| {
+            if of == 0 {
+                0.0
+            } else {
+                count as f64 / of as f64
+            }
+        };
+        Ok(TrucStats {
+            truc_count,
+            v2_count,
+            truc_share: share(truc_count, total),
+            topology_violations,
+            truc_replacement_rate: share(truc_replaced, truc_count),
+            v2_replacement_rate: share(v2_replaced, v2_count),
+        })
+    }
 
-        let mut stmt = conn.prepare(&query)?;
+    /// Recorded coinbases whose outputs a block at `tip_height` may not spend yet,
+    /// oldest first
+    pub fn get_immature_coinbases(&self, tip_height: u64) -> Result<Vec<Txid>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_id FROM transactions
+            WHERE version = ?1 AND matures_at_height > ?2
+            ORDER BY matures_at_height, tx_id",
+        )?;
         let txids = stmt
-            .query_map([], |row| {
-                let txid_str: String = row.get(0)?;
-                let txid = Txid::from_str(&txid_str).expect("Valid txid");
-                Ok(txid)
+            .query_map(params![COINBASE_TRANSACTION_VERSION, tip_height], |row| {
+                row.get::<_, String>(0)
             })?
             .collect::<Result<Vec<_>, _>>()?;
+        txids.iter().map(|txid| Ok(Txid::from_str(txid)?)).collect()
+    }
+
+    /// Txs pruned within `[start, end)` without ever being mined: evicted, replaced or
+    /// expired, in pruning order
+    pub fn get_dropped_txs(&self, start: u64, end: u64) -> Result<Vec<Txid>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_id FROM transactions
+            WHERE pruned_at >= ?1 * 1000 AND pruned_at < ?2 * 1000 AND mined_at IS NULL
+            ORDER BY pruned_at, tx_id",
+        )?;
+        let txids = stmt
+            .query_map(params![start, end], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        txids.iter().map(|txid| Ok(Txid::from_str(txid)?)).collect()
+    }
 
-        Ok(txids)
+    /// Fees collected by the block at `height`: its coinbase's output total minus the
+    /// subsidy, 0 when the miner claimed less than the subsidy. `None` without a stored
+    /// coinbase committing to that height, of competing blocks the last one recorded counts
+    pub fn get_block_fees(&self, height: u64) -> Result<Option<u64>> {
+        let conn = self.pool.get()?;
+        let coinbase_value: Option<u64> = conn
+            .query_row(
+                "SELECT coinbase_value FROM transactions
+                WHERE version = ?1 AND block_height = ?2 AND coinbase_value IS NOT NULL
+                ORDER BY seq DESC LIMIT 1",
+                params![COINBASE_TRANSACTION_VERSION, height],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(coinbase_value.map(|value| {
+            Amount::from_sat(value)
+                .checked_sub(block_subsidy(height))
+                .unwrap_or(Amount::ZERO)
+                .to_sat()
+        }))
     }
 
-    pub(crate) fn record_pruned_txs(&self, txids: Vec<Txid>) -> Result<()> {
-        if txids.is_empty() {
-            return Ok(());
+    /// Per miner totals over blocks recorded with a block time in `[start, end)`, most
+    /// blocks first. Blocks whose coinbase wasn't linked to them are left out
+    pub fn per_miner_stats(&self, start: u64, end: u64) -> Result<Vec<MinerStats>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT coinbase.miner, coinbase.tx_data, blocks.block_height, blocks.vsize,
+                (SELECT COALESCE(SUM(t.vsize), 0) FROM transactions t
+                WHERE t.block_hash = blocks.block_hash AND t.out_of_band = TRUE)
+            FROM blocks
+            JOIN transactions coinbase
+                ON coinbase.block_hash = blocks.block_hash AND coinbase.version = ?3
+            WHERE blocks.block_time >= ?1 AND blocks.block_time < ?2",
+        )?;
+        let rows = stmt
+            .query_map(params![start, end, COINBASE_TRANSACTION_VERSION], |row| {
+                Ok((
+                    row.get::<_, Option<String>>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<u64>>(2)?,
+                    row.get::<_, u64>(3)?,
+                    row.get::<_, u64>(4)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // (blocks, fees, block vsize, out-of-band vsize)
+        let mut totals: HashMap<Option<String>, (u64, Amount, u64, u64)> = HashMap::new();
+        for (miner, tx_data, block_height, block_vsize, out_of_band_vsize) in rows {
+            let coinbase = Transaction::consensus_decode(&mut hex::decode(tx_data)?.as_slice())?;
+            let value: Amount = coinbase.output.iter().map(|output| output.value).sum();
+            let subsidy = block_height.map_or(Amount::ZERO, block_subsidy);
+            let entry = totals.entry(miner).or_default();
+            entry.0 += 1;
+            entry.1 += value.checked_sub(subsidy).unwrap_or(Amount::ZERO);
+            entry.2 += block_vsize;
+            entry.3 += out_of_band_vsize;
         }
-        let conn = self.0.get()?;
-        let pruned_at = now!();
-        let txid_list = txids
+        let mut stats: Vec<MinerStats> = totals
+            .into_iter()
+            .map(
+                |(miner, (blocks, fees, block_vsize, out_of_band_vsize))| MinerStats {
+                    miner,
+                    blocks,
+                    avg_fees: fees / blocks,
+                    out_of_band_share: if block_vsize == 0 {
+                        0.0
+                    } else {
+                        out_of_band_vsize as f64 / block_vsize as f64
+                    },
+                },
+            )
+            .collect();
+        stats.sort_by(|a, b| b.blocks.cmp(&a.blocks).then_with(|| a.miner.cmp(&b.miner)));
+        Ok(stats)
+    }
+
+    /// Number of txs found in `[start, end)` using each sighash type in at least one input.
+    /// Rows stored before sighash types were recorded are not counted
+    pub fn sighash_usage(&self, start: u64, end: u64) -> Result<Vec<(SighashKind, u64)>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT COUNT(*) FROM transactions
+            WHERE found_at >= ?1 * 1000 AND found_at < ?2 * 1000 AND sighash_mask & ?3 != 0",
+        )?;
+        SighashKind::KINDS
             .iter()
-            .map(|txid| {
-                let txid_str = txid.to_string();
-                format!("'{}'", txid_str)
+            .map(|kind| {
+                let count = stmt.query_row(params![start, end, kind.bit()], |row| row.get(0))?;
+                Ok((*kind, count))
             })
-            .collect::<Vec<String>>()
-            .join(",");
-        info!("txid_list: {}", txid_list);
-        let query = format!(
-            "UPDATE transactions SET pruned_at = ?1 WHERE tx_id IN ({})",
-            txid_list
-        );
-        let mut stmt = conn.prepare(&query)?;
-        stmt.execute(params![pruned_at])?;
-        Ok(())
+            .collect()
     }
 
-    pub(crate) fn insert_mempool_tx(
-        &self,
-        tx: Transaction,
-        found_at: Option<u64>,
-        absolute_fee: Amount,
-        fee_rate: FeeRate,
-    ) -> Result<()> {
-        let conn = self.0.get()?;
-        let inputs_hash = get_inputs_hash(tx.clone().input)?;
-        let mut tx_bytes = vec![];
-        tx.consensus_encode(&mut tx_bytes)?;
-        let tx_str = hex::encode(tx_bytes);
+    /// mempoolminfee of the most recent mempool state snapshot that recorded one
+    pub fn last_mempool_min_fee(&self) -> Result<Option<FeeRate>> {
+        let conn = self.pool.get()?;
+        let min_fee: Option<u64> = conn
+            .query_row(
+                "SELECT mempool_min_fee FROM mempool WHERE mempool_min_fee IS NOT NULL
+                ORDER BY created_at DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(min_fee.map(fee_rate_from_sat_per_kvb))
+    }
 
-        let tx_id = tx.compute_txid().to_string();
-        let found_at = found_at.unwrap_or(now!());
+    /// Every stored row, decoded lazily a batch at a time so the table never has to fit
+    /// in memory. The iterator keeps a pooled connection checked out until it is
+    /// dropped, so don't hold on to it across unrelated database work. Rows are read
+    /// in keyset pages, rows written while iterating may or may not be yielded
+    pub fn iter_transactions(&self) -> Result<impl Iterator<Item = Result<TransactionInner>>> {
+        Ok(TransactionIter {
+            conn: self.pool.get()?,
+            after_key: String::new(),
+            batch: VecDeque::new(),
+            done: false,
+        })
+    }
 
-        for input in tx.input.iter() {
-            let prev_txid = input.previous_output.txid;
-            let parent_txid = prev_txid.to_string();
-            // Check if txid is in the database
-            let txid_exists: bool = conn.query_row(
-                "SELECT COUNT(*) FROM transactions WHERE tx_id = ?1 AND mined_at is NULL",
-                params![parent_txid],
-                |row| row.get(0),
-            )?;
-            if txid_exists {
-                // Update with parent txid
-                conn.execute(
-                    "UPDATE transactions SET child_txid = ?1 WHERE tx_id = ?2",
-                    params![tx_id, parent_txid],
-                )?;
+    /// Recompute the columns derived from each stored tx's data, a batch per sqlite
+    /// transaction. Backfills rows written before a classification existed without
+    /// re-ingesting them, returns the number of rows updated
+    pub fn replay_classification(&self) -> Result<usize> {
+        if self.read_only {
+            return Ok(0);
+        }
+        let total: u64 = self.pool.get()?.query_row(
+            "SELECT COUNT(*) FROM transactions WHERE tx_data != ''",
+            [],
+            |row| row.get(0),
+        )?;
+        let mut after_key = String::new();
+        let mut updated = 0;
+        loop {
+            let mut conn = self.pool.get()?;
+            let batch: Vec<(String, String)> = conn
+                .prepare(
+                    "SELECT inputs_hash, tx_data FROM transactions
+                    WHERE inputs_hash > ?1 AND tx_data != '' ORDER BY inputs_hash LIMIT ?2",
+                )?
+                .query_map(params![after_key, ITER_BATCH_SIZE], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect::<Result<_, _>>()?;
+            let Some((last_key, _)) = batch.last() else {
+                break;
+            };
+            after_key = last_key.clone();
+
+            let db_tx = conn.transaction()?;
+            for (inputs_hash, tx_data) in &batch {
+                let bytes = hex::decode(tx_data)?;
+                let tx = Transaction::consensus_decode(&mut bytes.as_slice())?;
+                self.reclassify_row(&db_tx, inputs_hash, &tx)?;
+            }
+            db_tx.commit()?;
+            updated += batch.len();
+            info!(
+                "Replayed classification of {}/{} transactions",
+                updated, total
+            );
+            if batch.len() < ITER_BATCH_SIZE {
+                break;
             }
         }
+        Ok(updated)
+    }
 
+    /// Derived columns of one row, as `insert_pending_row` and `record_coinbase_tx`
+    /// compute them. Lookups that can fail later, e.g. a pruned parent's outputs, keep
+    /// the stored value. A mined row's tx_data lost its witnesses, so its size, type and
+    /// sighash usage are only filled in when missing
+    fn reclassify_row(
+        &self,
+        conn: &rusqlite::Connection,
+        inputs_hash: &str,
+        tx: &Transaction,
+    ) -> Result<()> {
+        let (op_return_count, op_return_bytes) = op_return_bytes(tx);
+        if tx.is_coinbase() {
+            let miner = self.miners.identify(tx);
+            let coinbase_value: Amount = tx.output.iter().map(|output| output.value).sum();
+            conn.execute(
+                "UPDATE transactions SET tx_type = ?1, op_return_count = ?2, op_return_bytes = ?3,
+                    miner = ?4, coinbase_tag = ?5, coinbase_value = ?6,
+                    block_height = COALESCE(?7, block_height),
+                    matures_at_height = COALESCE(?7, block_height) + ?8
+                WHERE inputs_hash = ?9",
+                params![
+                    classify_tx(tx).as_str(),
+                    op_return_count,
+                    op_return_bytes,
+                    miner.label,
+                    miner.tag,
+                    coinbase_value.to_sat(),
+                    coinbase_height(tx),
+                    self.coinbase_maturity,
+                    inputs_hash
+                ],
+            )?;
+            return Ok(());
+        }
         conn.execute(
-            "INSERT OR REPLACE INTO transactions
-            (inputs_hash, tx_id, tx_data, found_at, absolute_fee, fee_rate, version)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            "UPDATE transactions SET
+                vsize = CASE WHEN mined_at IS NULL THEN ?1 ELSE COALESCE(vsize, ?1) END,
+                tx_type = CASE WHEN mined_at IS NULL THEN ?2 ELSE COALESCE(tx_type, ?2) END,
+                op_return_count = ?3, op_return_bytes = ?4, dust_output_count = ?5, is_truc = ?6,
+                sighash_mask =
+                    CASE WHEN mined_at IS NULL THEN ?7 ELSE COALESCE(sighash_mask, ?7) END,
+                input_value_total = COALESCE(?8, input_value_total)
+            WHERE inputs_hash = ?9",
             params![
-                inputs_hash,
-                tx_id,
-                tx_str,
-                found_at,
-                absolute_fee.to_sat(),
-                fee_rate.to_sat_per_vb_ceil(),
-                MEMPOOL_TRANSACTION_VERSION
+                tx.vsize(),
+                classify_tx(tx).as_str(),
+                op_return_count,
+                op_return_bytes,
+                self.dust_output_count(tx),
+                is_truc(tx),
+                sighash_mask(tx),
+                Self::input_value_total(conn, tx)?.map(|total| total.to_sat()),
+                inputs_hash
             ],
         )?;
-
         Ok(())
     }
 
-    pub(crate) fn tx_exists(&self, tx: &Transaction) -> Result<bool> {
-        let conn = self.0.get()?;
-        let inputs_hash = get_inputs_hash(tx.clone().input)?;
-
-        let count: i32 = conn.query_row(
-            "SELECT COUNT(*) FROM transactions WHERE inputs_hash = ?1",
-            params![inputs_hash],
-            |row| row.get(0),
+    /// Next page of txs that are neither pruned nor mined, keyed after `after_key`
+    /// (start with ""). Keyset paging keeps pages stable while rows are updated
+    pub fn pending_txs(&self, after_key: &str, limit: usize) -> Result<Vec<(String, Transaction)>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT inputs_hash, tx_data FROM transactions
+            WHERE mined_at IS NULL AND pruned_at IS NULL AND inputs_hash > ?1
+            ORDER BY inputs_hash LIMIT ?2",
         )?;
-
-        Ok(count > 0)
+        let rows = stmt
+            .query_map(params![after_key, limit], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(key, tx_data)| {
+                let bytes = hex::decode(tx_data)?;
+                Ok((key, Transaction::consensus_decode(&mut bytes.as_slice())?))
+            })
+            .collect()
     }
 
-    pub(crate) fn record_rbf(&self, transaction: &Transaction, fee_total: u64) -> Result<()> {
-        let conn = self.0.get()?;
-        let inputs_hash = get_inputs_hash(transaction.clone().input)?;
-        let created_at = now!();
+    /// Stored row of `txid`
+    pub fn get_tx_record(&self, txid: &Txid) -> Result<Option<TransactionInner>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM transactions WHERE tx_id = ?1",
+            TRANSACTION_INNER_COLUMNS
+        ))?;
+        let mut rows = stmt.query(params![txid.to_string()])?;
+        rows.next()?.map(TransactionInner::from_row).transpose()
+    }
 
-        // If input_hash is not in the database, ignore this
-        if !self.tx_exists(transaction)? {
-            return Ok(());
+    /// Page of the txs that are neither pruned nor mined and were found at or before
+    /// `found_before` (unix seconds, its whole second included), oldest first
+    pub fn pending_tx_records(
+        &self,
+        found_before: u64,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<TransactionInner>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM transactions
+            WHERE mined_at IS NULL AND pruned_at IS NULL AND found_at < (?1 + 1) * 1000
+            ORDER BY found_at, inputs_hash LIMIT ?2 OFFSET ?3",
+            TRANSACTION_INNER_COLUMNS
+        ))?;
+        let mut rows = stmt.query(params![found_before, limit, offset])?;
+        let mut records = vec![];
+        while let Some(row) = rows.next()? {
+            records.push(TransactionInner::from_row(row)?);
         }
+        Ok(records)
+    }
 
-        conn.execute(
-            "INSERT OR REPLACE INTO rbf (inputs_hash, created_at, fee_total, version) VALUES (?1, ?2, ?3, ?4)",
-            params![inputs_hash, created_at, fee_total, RBF_TRANSACTION_VERSION],
+    /// Page of the mempool state snapshots taken in `[start, end)`, oldest first
+    pub fn mempool_snapshots(
+        &self,
+        start: u64,
+        end: u64,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<MempoolSnapshot>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT created_at, size, tx_count, block_height, block_hash, mempool_min_fee,
+                min_relay_tx_fee, max_mempool, usage, added_since_last, removed_since_last
+            FROM mempool WHERE created_at >= ?1 AND created_at < ?2
+            ORDER BY created_at, rowid LIMIT ?3 OFFSET ?4",
         )?;
+        let rows = stmt
+            .query_map(params![start, end, limit, offset], |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, u64>(2)?,
+                    row.get::<_, u64>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, Option<u64>>(5)?,
+                    row.get::<_, Option<u64>>(6)?,
+                    row.get::<_, Option<u64>>(7)?,
+                    row.get::<_, Option<u64>>(8)?,
+                    row.get::<_, Option<u64>>(9)?,
+                    row.get::<_, Option<u64>>(10)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(
+                |(
+                    created_at,
+                    bytes,
+                    tx_count,
+                    block_height,
+                    block_hash,
+                    mempool_min_fee,
+                    min_relay_tx_fee,
+                    max_mempool,
+                    usage,
+                    added_since_last,
+                    removed_since_last,
+                )| {
+                    Ok(MempoolSnapshot {
+                        created_at,
+                        tx_count,
+                        bytes,
+                        block_height,
+                        block_hash: BlockHash::consensus_decode(
+                            &mut hex::decode(block_hash)?.as_slice(),
+                        )?,
+                        mempool_min_fee: mempool_min_fee.map(fee_rate_from_sat_per_kvb),
+                        min_relay_tx_fee: min_relay_tx_fee.map(fee_rate_from_sat_per_kvb),
+                        max_mempool,
+                        usage,
+                        churn: added_since_last.zip(removed_since_last).map(
+                            |(added_since_last, removed_since_last)| MempoolChurn {
+                                added_since_last,
+                                removed_since_last,
+                            },
+                        ),
+                    })
+                },
+            )
+            .collect()
+    }
 
-        Ok(())
+    /// Replacements of the input set `txid` spends, oldest first. `txid` may be any tx
+    /// that occupied the slot, empty when it was never replaced or isn't tracked
+    pub fn rbf_history_of(&self, txid: &Txid) -> Result<Vec<RbfRecord>> {
+        let conn = self.pool.get()?;
+        let txid_hex = txid.to_string();
+        let inputs_hash: Option<String> = conn
+            .query_row(
+                "SELECT inputs_hash FROM transactions WHERE tx_id = ?1
+                UNION ALL SELECT inputs_hash FROM txid_history WHERE tx_id = ?1
+                UNION ALL SELECT inputs_hash FROM rbf_history WHERE tx_id = ?1
+                LIMIT 1",
+                params![txid_hex],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(inputs_hash) = inputs_hash else {
+            return Ok(vec![]);
+        };
+        let mut stmt = conn.prepare(
+            "SELECT tx_id, created_at, fee_total, fee_known, prev_fee, fee_bump_pct, reject_reason,
+                is_initial_observation
+            FROM rbf_history WHERE inputs_hash = ?1 ORDER BY id",
+        )?;
+        let rows = stmt
+            .query_map(params![inputs_hash], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, u64>(2)?,
+                    row.get::<_, bool>(3)?,
+                    row.get::<_, Option<u64>>(4)?,
+                    row.get::<_, Option<f64>>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                    row.get::<_, bool>(7)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(
+                |(
+                    txid,
+                    created_at,
+                    fee,
+                    fee_known,
+                    prev_fee,
+                    fee_bump_pct,
+                    reject_reason,
+                    is_initial_observation,
+                )| {
+                    Ok(RbfRecord {
+                        txid: Txid::from_str(&txid)?,
+                        created_at,
+                        fee: fee_known.then(|| Amount::from_sat(fee)),
+                        prev_fee: prev_fee.map(Amount::from_sat),
+                        fee_bump_pct,
+                        reject_reason,
+                        is_initial_observation,
+                    })
+                },
+            )
+            .collect()
     }
 
-    pub(crate) fn update_txid_by_inputs_hash(&self, tx: &Transaction) -> Result<()> {
-        let conn = self.0.get()?;
-        let inputs_hash = get_inputs_hash(tx.clone().input)?;
-        let tx_id = tx.compute_txid().to_string();
+    /// Note that `node_id` announced `txid` at `seen_at` (unix ms), later announcements by
+    /// the same node are ignored
+    pub fn record_sighting(&self, txid: &Txid, node_id: &str, seen_at: u64) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let conn = self.pool.get()?;
         conn.execute(
-            "UPDATE transactions SET tx_id = ?1 WHERE inputs_hash = ?2",
-            params![tx_id, inputs_hash],
+            "INSERT INTO tx_sightings (txid, node_id, first_seen_at) VALUES (?1, ?2, ?3)
+            ON CONFLICT (txid, node_id) DO UPDATE
+            SET first_seen_at = MIN(first_seen_at, excluded.first_seen_at)",
+            params![txid.to_string(), node_id, seen_at],
         )?;
-
         Ok(())
     }
 
-    /// Remove txs that are neither pruned nor mined
-    /// This should be called when the system if first started
-    /// As the db may include old txs that have been pruned or mined
-    pub(crate) fn remove_stale_txs(&self) -> Result<()> {
-        let conn = self.0.get()?;
-        conn.execute(
-            "DELETE FROM transactions WHERE pruned_at IS NULL AND mined_at IS NULL",
+    /// Every node's sighting of the txs first seen within `[start, end)` (unix seconds) by
+    /// more than one node, with its delay behind the earliest. Ordered by tx, then by delay
+    pub fn propagation_deltas(&self, start: u64, end: u64) -> Result<Vec<PropagationDelta>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "WITH firsts AS (
+                SELECT txid, MIN(first_seen_at) AS first_at FROM tx_sightings
+                GROUP BY txid
+                HAVING COUNT(*) > 1 AND MIN(first_seen_at) >= ?1 * 1000 AND MIN(first_seen_at) < ?2 * 1000
+            )
+            SELECT s.txid, s.node_id, s.first_seen_at, s.first_seen_at - f.first_at
+            FROM tx_sightings s JOIN firsts f ON s.txid = f.txid
+            ORDER BY f.first_at, s.txid, s.first_seen_at, s.node_id",
+        )?;
+        let rows = stmt
+            .query_map(params![start, end], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, u64>(2)?,
+                    row.get::<_, u64>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(txid, node_id, first_seen_at, delay)| {
+                Ok(PropagationDelta {
+                    txid: Txid::from_str(&txid)?,
+                    node_id,
+                    first_seen_at,
+                    delay,
+                })
+            })
+            .collect()
+    }
+
+    /// Row counts of the transactions table, replacements recorded since `rbf_since` and
+    /// the file sizes
+    pub fn db_stats(&self, rbf_since: u64) -> Result<DbStats> {
+        let conn = self.pool.get()?;
+        let (total_rows, pending, mined, pruned) = conn.query_row(
+            "SELECT COUNT(*),
+                COALESCE(SUM(mined_at IS NULL AND pruned_at IS NULL), 0),
+                COALESCE(SUM(mined_at IS NOT NULL), 0),
+                COALESCE(SUM(mined_at IS NULL AND pruned_at IS NOT NULL), 0)
+            FROM transactions",
             [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
         )?;
-        Ok(())
+        let rbf_events = conn.query_row(
+            "SELECT COUNT(*) FROM rbf_history WHERE created_at >= ?1",
+            params![rbf_since],
+            |row| row.get(0),
+        )?;
+        // Empty for in-memory databases
+        let path: String = conn.query_row(
+            "SELECT file FROM pragma_database_list WHERE name = 'main'",
+            [],
+            |row| row.get(0),
+        )?;
+        let file_size = |path: &str| std::fs::metadata(path).map_or(0, |meta| meta.len());
+        let (db_bytes, wal_bytes) = if path.is_empty() {
+            (0, 0)
+        } else {
+            (file_size(&path), file_size(&format!("{}-wal", path)))
+        };
+        Ok(DbStats {
+            total_rows,
+            pending,
+            mined,
+            pruned,
+            rbf_events,
+            db_bytes,
+            wal_bytes,
+        })
     }
 
-    pub(crate) fn run_migrations(&self) -> Result<()> {
-        let conn = self.0.get()?;
+    pub fn run_migrations(&self) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let conn = self.pool.get()?;
         run_migrations(&conn)?;
         Ok(())
     }
 
-    #[allow(dead_code)]
     pub fn get_tx_by_txid(&self, txid: &Txid) -> Result<Option<Transaction>> {
-        let conn = self.0.get()?;
+        let conn = self.pool.get()?;
         let txid_hex = txid.to_string();
         let mut stmt = conn.prepare("SELECT tx_data FROM transactions WHERE tx_id = ?1")?;
         let tx_data: Option<String> = stmt
@@ -372,4 +3313,618 @@ impl Database {
             Transaction::consensus_decode(&mut bytes.as_slice()).expect("Valid transaction")
         }))
     }
+
+    /// Summarize the 24h window starting at `day_start` (unix seconds)
+    pub fn daily_summary(&self, day_start: u64) -> Result<DailySummary> {
+        let conn = self.pool.get()?;
+        let day_end = day_start + SECONDS_PER_DAY;
+
+        let txs_first_seen: u64 = conn.query_row(
+            "SELECT COUNT(*) FROM transactions WHERE found_at >= ?1 * 1000 AND found_at < ?2 * 1000 AND inputs_hash != tx_id",
+            params![day_start, day_end],
+            |row| row.get(0),
+        )?;
+        // Coinbase rows are keyed by txid, exclude them from the mined count
+        let txs_mined: u64 = conn.query_row(
+            "SELECT COUNT(*) FROM transactions WHERE mined_at >= ?1 * 1000 AND mined_at < ?2 * 1000 AND inputs_hash != tx_id",
+            params![day_start, day_end],
+            |row| row.get(0),
+        )?;
+        let txs_pruned: u64 = conn.query_row(
+            "SELECT COUNT(*) FROM transactions WHERE pruned_at >= ?1 * 1000 AND pruned_at < ?2 * 1000 AND mined_at IS NULL",
+            params![day_start, day_end],
+            |row| row.get(0),
+        )?;
+        let rbf_events: u64 = conn.query_row(
+            "SELECT COUNT(*) FROM rbf_history WHERE created_at >= ?1 AND created_at < ?2",
+            params![day_start, day_end],
+            |row| row.get(0),
+        )?;
+
+        // Lower median of the confirmation latencies
+        let median_confirmation_latency: Option<u64> = conn
+            .query_row(
+                "SELECT (mined_at - found_at) / 1000 FROM transactions
+                WHERE mined_at >= ?1 * 1000 AND mined_at < ?2 * 1000 AND inputs_hash != tx_id
+                ORDER BY mined_at - found_at
+                LIMIT 1 OFFSET (?3 - 1) / 2",
+                params![day_start, day_end, txs_mined],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let peak_mempool_size: Option<u64> = conn.query_row(
+            "SELECT MAX(size) FROM mempool WHERE created_at >= ?1 AND created_at < ?2",
+            params![day_start, day_end],
+            |row| row.get(0),
+        )?;
+
+        Ok(DailySummary {
+            day_start,
+            txs_first_seen,
+            txs_mined,
+            rbf_events,
+            txs_pruned,
+            median_confirmation_latency,
+            peak_mempool_size,
+        })
+    }
+
+    /// Average fee increase of replacements created in `[start, end)`.
+    /// Initial observations, without a predecessor fee to compare to, are skipped
+    pub fn average_bump_stats(&self, start: u64, end: u64) -> Result<BumpStats> {
+        let conn = self.pool.get()?;
+        let stats = conn.query_row(
+            "SELECT COUNT(*), AVG(fee_delta), AVG(fee_rate_delta), AVG(fee_bump_pct)
+            FROM rbf_history
+            WHERE created_at >= ?1 AND created_at < ?2 AND is_initial_observation = FALSE",
+            params![start, end],
+            |row| {
+                Ok(BumpStats {
+                    events: row.get(0)?,
+                    avg_fee_delta: row.get(1)?,
+                    avg_fee_rate_delta: row.get(2)?,
+                    avg_fee_bump_pct: row.get(3)?,
+                })
+            },
+        )?;
+        Ok(stats)
+    }
+
+    /// Most replaced input sets of `[start, end)` as `(inputs_hash, bump_count, final_fee)`,
+    /// at most `limit` of them ordered by bump count descending. The final fee is the one
+    /// of the chain's last replacement inside the window, 0 when its fee was never known
+    pub fn rbf_leaderboard(
+        &self,
+        start: u64,
+        end: u64,
+        limit: usize,
+    ) -> Result<Vec<(String, u64, u64)>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT chains.inputs_hash, chains.bumps, last.fee_total
+            FROM (
+                SELECT inputs_hash, COUNT(*) AS bumps, MAX(id) AS last_id
+                FROM rbf_history
+                WHERE created_at >= ?1 AND created_at < ?2
+                GROUP BY inputs_hash
+            ) chains
+            JOIN rbf_history last ON last.id = chains.last_id
+            ORDER BY chains.bumps DESC, chains.inputs_hash
+            LIMIT ?3",
+        )?;
+        let rows = stmt
+            .query_map(params![start, end, limit as i64], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Total extra fee paid by replacements created in `[start, end)`, the sum of each
+    /// bump's fee minus its predecessor's. A chain spanning the window boundary contributes
+    /// only the bumps observed inside the window, each measured against its predecessor
+    /// even if that was seen before `start`. Initial observations, and non-positive
+    /// deltas, add nothing
+    pub fn total_rbf_fee_delta(&self, start: u64, end: u64) -> Result<u64> {
+        let conn = self.pool.get()?;
+        let total: u64 = conn.query_row(
+            "SELECT COALESCE(SUM(fee_delta), 0)
+            FROM rbf_history
+            WHERE created_at >= ?1 AND created_at < ?2 AND fee_delta > 0
+            AND is_initial_observation = FALSE",
+            params![start, end],
+            |row| row.get(0),
+        )?;
+        Ok(total)
+    }
+
+    /// Dwell stats of txs mined in `[start, end)`, grouped by fee rate. `buckets` are
+    /// ascending sat/vB lower bounds: bucket i holds rates in `[buckets[i], buckets[i + 1])`,
+    /// the last bucket is unbounded and rates below `buckets[0]` are left out.
+    /// Dwell time runs from the node's acceptance (our first sighting when unknown) to
+    /// inclusion. Coinbases, backfilled txs we never saw in the mempool and txs with an
+    /// unknown fee are skipped, rows from before vsize was stored fall back to the rounded
+    /// fee_rate
+    pub fn latency_by_fee_bucket(
+        &self,
+        start: u64,
+        end: u64,
+        buckets: &[f64],
+    ) -> Result<Vec<(f64, DwellStats)>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT absolute_fee, vsize, fee_rate, (mined_at - COALESCE(node_seen_at, found_at)) / 1000
+            FROM transactions
+            WHERE mined_at >= ?1 * 1000 AND mined_at < ?2 * 1000 AND inputs_hash != tx_id
+            AND seen_in_mempool = TRUE AND fee_known = 1",
+        )?;
+        let mut latencies: Vec<Vec<u64>> = vec![vec![]; buckets.len()];
+        let mut rows = stmt.query(params![start, end])?;
+        while let Some(row) = rows.next()? {
+            let fee: u64 = row.get(0)?;
+            let vsize: Option<u64> = row.get(1)?;
+            let stored_rate: u64 = row.get(2)?;
+            let latency: i64 = row.get(3)?;
+            let fee_rate = match vsize {
+                Some(vsize) if vsize > 0 => fee as f64 / vsize as f64,
+                _ => stored_rate as f64,
+            };
+            if let Some(bucket) = buckets.iter().rposition(|bound| fee_rate >= *bound) {
+                latencies[bucket].push(latency.max(0) as u64);
+            }
+        }
+
+        Ok(buckets
+            .iter()
+            .copied()
+            .zip(latencies.into_iter().map(DwellStats::from_latencies))
+            .collect())
+    }
+
+    /// Tracked txs spending outputs of `txid` while it was unconfirmed, through the
+    /// node's reported ancestry or our own input scan
+    pub fn get_children(&self, txid: &Txid) -> Result<Vec<Txid>> {
+        let conn = self.pool.get()?;
+        Self::child_txids(&conn, &txid.to_string())?
+            .iter()
+            .map(|child| Ok(Txid::from_str(child)?))
+            .collect()
+    }
+
+    /// Unconfirmed parents of `txid`, see `get_children`
+    pub fn get_parents(&self, txid: &Txid) -> Result<Vec<Txid>> {
+        let conn = self.pool.get()?;
+        Self::parent_txids(&conn, &txid.to_string())?
+            .iter()
+            .map(|parent| Ok(Txid::from_str(parent)?))
+            .collect()
+    }
+
+    /// Write every stored transaction as CSV with a header row, NULLs as empty fields and
+    /// blobs hex encoded. Returns the number of rows written
+    pub fn export_transactions_csv<W: Write>(&self, mut writer: W) -> Result<usize> {
+        let conn = self.pool.get()?;
+        let columns: Vec<&str> = TRANSACTION_CSV_COLUMNS
+            .iter()
+            .map(|(name, _)| *name)
+            .collect();
+        writeln!(writer, "{}", columns.join(","))?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM transactions ORDER BY seq, inputs_hash",
+            columns.join(", ")
+        ))?;
+        let mut rows = stmt.query([])?;
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            let mut fields = Vec::with_capacity(columns.len());
+            for (i, (name, _)) in TRANSACTION_CSV_COLUMNS.iter().enumerate() {
+                let field = match row.get::<_, Value>(i)? {
+                    Value::Null => String::new(),
+                    Value::Integer(n) => n.to_string(),
+                    Value::Real(r) => r.to_string(),
+                    Value::Text(text) => {
+                        if text.contains(['\n', '\r']) {
+                            return Err(anyhow::anyhow!(
+                                "Column {} of row {} can't be written as CSV: {:?}",
+                                name,
+                                count + 1,
+                                text
+                            ));
+                        }
+                        if text.contains([',', '"']) {
+                            format!("\"{}\"", text.replace('"', "\"\""))
+                        } else {
+                            text
+                        }
+                    }
+                    Value::Blob(bytes) => hex::encode(bytes),
+                };
+                fields.push(field);
+            }
+            writeln!(writer, "{}", fields.join(","))?;
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Seed the transactions table from a file written by `export_transactions_csv`, in a
+    /// single sqlite transaction so a malformed file leaves the database untouched.
+    /// Returns the number of rows imported
+    pub fn import_transactions_csv<R: Read>(&self, reader: R) -> Result<usize> {
+        if self.read_only {
+            return Ok(0);
+        }
+        let columns: Vec<&str> = TRANSACTION_CSV_COLUMNS
+            .iter()
+            .map(|(name, _)| *name)
+            .collect();
+        let mut lines = BufReader::new(reader).lines();
+        let header = lines
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("CSV is empty, expected a header row"))??;
+        if header.trim_end() != columns.join(",") {
+            return Err(anyhow::anyhow!(
+                "Invalid CSV header {:?}, expected {:?}",
+                header,
+                columns.join(",")
+            ));
+        }
+
+        let mut conn = self.pool.get()?;
+        let db_tx = conn.transaction()?;
+        let mut count = 0;
+        {
+            let mut stmt = db_tx.prepare(&format!(
+                "INSERT INTO transactions ({}) VALUES ({})",
+                columns.join(", "),
+                vec!["?"; columns.len()].join(", ")
+            ))?;
+            // The header is line 1
+            for (line_number, line) in lines.enumerate().map(|(i, line)| (i + 2, line)) {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let fields = split_csv_line(line.trim_end_matches('\r'))
+                    .map_err(|e| anyhow::anyhow!("Line {}: {}", line_number, e))?;
+                if fields.len() != columns.len() {
+                    return Err(anyhow::anyhow!(
+                        "Line {}: expected {} fields, got {}",
+                        line_number,
+                        columns.len(),
+                        fields.len()
+                    ));
+                }
+                let values = fields
+                    .iter()
+                    .zip(TRANSACTION_CSV_COLUMNS)
+                    .map(|(field, (name, kind))| {
+                        if field.is_empty() {
+                            return Ok(Value::Null);
+                        }
+                        let value = match kind {
+                            CsvKind::Integer => field
+                                .parse()
+                                .map(Value::Integer)
+                                .map_err(anyhow::Error::from),
+                            CsvKind::Real => {
+                                field.parse().map(Value::Real).map_err(anyhow::Error::from)
+                            }
+                            CsvKind::Text => Ok(Value::Text(field.to_string())),
+                            CsvKind::Blob => hex::decode(field)
+                                .map(Value::Blob)
+                                .map_err(anyhow::Error::from),
+                        };
+                        value.map_err(|e| {
+                            anyhow::anyhow!(
+                                "Line {}: invalid {} {:?}: {}",
+                                line_number,
+                                name,
+                                field,
+                                e
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                stmt.execute(params_from_iter(values))
+                    .map_err(|e| anyhow::anyhow!("Line {}: {}", line_number, e))?;
+                count += 1;
+            }
+        }
+        db_tx.commit()?;
+        Ok(count)
+    }
+
+    /// Txs pruned in `[start, end)` with the mempoolminfee in effect when they were, to
+    /// tell congestion evictions apart from other drops (expiry, conflicts)
+    pub fn evictions_with_min_fee(&self, start: u64, end: u64) -> Result<Vec<EvictionContext>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT t.tx_id, t.pruned_at, t.absolute_fee, t.vsize, t.fee_rate,
+                (SELECT m.mempool_min_fee FROM mempool m
+                WHERE m.created_at * 1000 <= t.pruned_at AND m.mempool_min_fee IS NOT NULL
+                ORDER BY m.created_at DESC LIMIT 1)
+            FROM transactions t
+            WHERE t.pruned_at >= ?1 * 1000 AND t.pruned_at < ?2 * 1000
+            ORDER BY t.pruned_at",
+        )?;
+        let rows = stmt
+            .query_map(params![start, end], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, u64>(2)?,
+                    row.get::<_, Option<u64>>(3)?,
+                    row.get::<_, u64>(4)?,
+                    row.get::<_, Option<u64>>(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(txid, pruned_at, fee, vsize, stored_rate, min_fee)| {
+                let fee_rate = match vsize {
+                    Some(vsize) if vsize > 0 => fee as f64 / vsize as f64,
+                    _ => stored_rate as f64,
+                };
+                Ok(EvictionContext {
+                    txid: Txid::from_str(&txid)?,
+                    pruned_at,
+                    fee_rate,
+                    mempool_min_fee: min_fee.map(fee_rate_from_sat_per_kvb),
+                })
+            })
+            .collect()
+    }
+
+    /// Per block out-of-band tx count, fees and block space, for blocks with a time in
+    /// `[start, end)`. Blocks without out-of-band txs are reported with zeroes
+    pub fn out_of_band_stats(&self, start: u64, end: u64) -> Result<Vec<OutOfBandStats>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT blocks.block_height, blocks.block_hash, blocks.vsize, COUNT(t.inputs_hash),
+                COALESCE(SUM(t.absolute_fee), 0), COALESCE(SUM(t.vsize), 0)
+            FROM blocks
+            LEFT JOIN transactions t ON t.block_hash = blocks.block_hash AND t.out_of_band = TRUE
+            WHERE blocks.block_time >= ?1 AND blocks.block_time < ?2
+            GROUP BY blocks.block_hash
+            ORDER BY blocks.block_time, blocks.block_height",
+        )?;
+        let rows = stmt
+            .query_map(params![start, end], |row| {
+                Ok((
+                    row.get::<_, Option<u64>>(0)?,
+                    row.get::<_, Vec<u8>>(1)?,
+                    row.get::<_, u64>(2)?,
+                    row.get::<_, u64>(3)?,
+                    row.get::<_, u64>(4)?,
+                    row.get::<_, u64>(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(
+                |(block_height, block_hash, block_vsize, tx_count, total_fee, vsize)| {
+                    Ok(OutOfBandStats {
+                        block_height,
+                        block_hash: BlockHash::from_slice(&block_hash)?,
+                        tx_count,
+                        total_fee: Amount::from_sat(total_fee),
+                        vsize,
+                        block_share: if block_vsize == 0 {
+                            0.0
+                        } else {
+                            vsize as f64 / block_vsize as f64
+                        },
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Purges detected in `[start, end)`, oldest first
+    pub fn purge_events(&self, start: u64, end: u64) -> Result<Vec<PurgeEvent>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT detected_at, removed_count, removed_vbytes, mempoolminfee_before, mempoolminfee_after
+            FROM purge_events WHERE detected_at >= ?1 AND detected_at < ?2
+            ORDER BY detected_at, id",
+        )?;
+        let events = stmt
+            .query_map(params![start, end], |row| {
+                Ok(PurgeEvent {
+                    detected_at: row.get(0)?,
+                    removed_count: row.get(1)?,
+                    removed_vbytes: row.get(2)?,
+                    mempool_min_fee_before: row
+                        .get::<_, Option<u64>>(3)?
+                        .map(fee_rate_from_sat_per_kvb),
+                    mempool_min_fee_after: row
+                        .get::<_, Option<u64>>(4)?
+                        .map(fee_rate_from_sat_per_kvb),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(events)
+    }
+
+    /// Fee histogram snapshots taken in `[start, end)`, ordered by snapshot then band.
+    /// Bands without pending txs are absent from a snapshot
+    pub fn fee_histogram_series(&self, start: u64, end: u64) -> Result<Vec<FeeHistogramBand>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT snapshot_at, band_min_satvb, vbytes, tx_count FROM fee_histogram
+            WHERE snapshot_at >= ?1 AND snapshot_at < ?2
+            ORDER BY snapshot_at, band_min_satvb",
+        )?;
+        let bands = stmt
+            .query_map(params![start, end], |row| {
+                Ok(FeeHistogramBand {
+                    snapshot_at: row.get(0)?,
+                    band_min_satvb: row.get(1)?,
+                    vbytes: row.get(2)?,
+                    tx_count: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(bands)
+    }
+
+    /// Page of `(seq, txid)` for rows inserted after `seq`, in insertion order.
+    /// Pass the last seq of a page to get the next one, start from 0
+    pub fn get_txs_after_seq(&self, seq: i64, limit: usize) -> Result<Vec<(i64, Txid)>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn
+            .prepare("SELECT seq, tx_id FROM transactions WHERE seq > ?1 ORDER BY seq LIMIT ?2")?;
+        let rows = stmt
+            .query_map(params![seq, limit], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(seq, txid)| Ok((seq, Txid::from_str(&txid)?)))
+            .collect()
+    }
+
+    /// Naive and effective fee rates of txs first seen in `[start, end)`, for measuring
+    /// how far naive fee rate histograms are from what miners actually see. Txs with an
+    /// unknown fee are left out
+    pub fn effective_vs_naive_feerate(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<FeeRateComparison>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_id, CAST(absolute_fee AS REAL) / vsize, effective_fee_rate, ancestors_known
+            FROM transactions
+            WHERE found_at >= ?1 * 1000 AND found_at < ?2 * 1000 AND effective_fee_rate IS NOT NULL AND vsize > 0
+            AND fee_known = 1
+            ORDER BY found_at",
+        )?;
+        let rows = stmt
+            .query_map(params![start, end], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, f64>(1)?,
+                    row.get::<_, f64>(2)?,
+                    row.get::<_, bool>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(
+                |(txid, naive_fee_rate, effective_fee_rate, ancestors_known)| {
+                    Ok(FeeRateComparison {
+                        txid: Txid::from_str(&txid)?,
+                        naive_fee_rate,
+                        effective_fee_rate,
+                        ancestors_known,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Count of transactions first seen in `[start, end)` by spend type
+    pub fn type_distribution(&self, start: u64, end: u64) -> Result<Vec<(TxType, u64)>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_type, COUNT(*) FROM transactions
+            WHERE found_at >= ?1 * 1000 AND found_at < ?2 * 1000 AND tx_type IS NOT NULL
+            GROUP BY tx_type ORDER BY COUNT(*) DESC",
+        )?;
+        let rows = stmt
+            .query_map(params![start, end], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(tx_type, count)| Ok((TxType::from_str(&tx_type)?, count)))
+            .collect()
+    }
+
+    /// OP_RETURN usage of transactions first seen in `[start, end)`
+    pub fn op_return_stats(&self, start: u64, end: u64) -> Result<OpReturnStats> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT op_return_bytes FROM transactions
+            WHERE found_at >= ?1 * 1000 AND found_at < ?2 * 1000 AND op_return_count > 0",
+        )?;
+        let mut histogram: Vec<(u64, u64)> = OP_RETURN_HISTOGRAM_BOUNDS
+            .iter()
+            .map(|bound| (*bound, 0))
+            .collect();
+        let mut tx_count = 0;
+        let mut total_bytes = 0;
+        // Rows are streamed, only the buckets are kept in memory
+        let mut rows = stmt.query(params![start, end])?;
+        while let Some(row) = rows.next()? {
+            let bytes: u64 = row.get(0)?;
+            tx_count += 1;
+            total_bytes += bytes;
+            if let Some(bucket) = histogram.iter_mut().find(|(bound, _)| bytes <= *bound) {
+                bucket.1 += 1;
+            }
+        }
+
+        Ok(OpReturnStats {
+            tx_count,
+            total_bytes,
+            histogram,
+        })
+    }
+
+    /// Walk tx_links in both directions from `txid` to collect its unconfirmed package.
+    /// Mined txs end the walk, their descendants are no longer bound to them
+    pub fn unconfirmed_package(&self, txid: &Txid) -> Result<UnconfirmedPackage> {
+        let conn = self.pool.get()?;
+        let mut links_stmt = conn.prepare(
+            "SELECT related_txid FROM tx_links WHERE txid = ?1
+            UNION
+            SELECT txid FROM tx_links WHERE related_txid = ?1",
+        )?;
+        let mut tx_stmt = conn
+            .prepare("SELECT tx_data, absolute_fee, mined_at FROM transactions WHERE tx_id = ?1")?;
+
+        let mut package = UnconfirmedPackage {
+            txids: vec![],
+            total_vsize: 0,
+            total_fee: Amount::ZERO,
+            unknown_txids: vec![],
+        };
+        let mut seen = HashSet::from([txid.to_string()]);
+        let mut queue = VecDeque::from([txid.to_string()]);
+        while let Some(current) = queue.pop_front() {
+            let stored: Option<(String, u64, Option<u64>)> = tx_stmt
+                .query_row(params![current], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })
+                .optional()?;
+            let current_txid = Txid::from_str(&current)?;
+            match stored {
+                Some((_, _, Some(_))) => continue,
+                Some((tx_data, absolute_fee, None)) => {
+                    let bytes = hex::decode(tx_data)?;
+                    let tx = Transaction::consensus_decode(&mut bytes.as_slice())?;
+                    package.total_vsize += tx.vsize() as u64;
+                    package.total_fee += Amount::from_sat(absolute_fee);
+                }
+                None => package.unknown_txids.push(current_txid),
+            }
+            package.txids.push(current_txid);
+
+            let related = links_stmt
+                .query_map(params![current], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            for related in related {
+                if seen.insert(related.clone()) {
+                    queue.push_back(related);
+                }
+            }
+        }
+        Ok(package)
+    }
 }
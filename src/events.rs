@@ -0,0 +1,261 @@
+use std::future::Future;
+
+use anyhow::Result;
+use log::{error, warn};
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc};
+
+/// Lifecycle events emitted by the workers
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MempoolEvent {
+    New {
+        txid: String,
+        fee: u64,
+        fee_rate: u64,
+        vsize: u64,
+        /// Unix seconds, the stored `found_at` is in milliseconds
+        found_at: u64,
+    },
+    Rbf {
+        txid: String,
+        /// Tx the slot held before, unknown when it wasn't stored
+        replaced_txid: Option<String>,
+        /// Unknown when the replacement left the mempool before its fee was looked up
+        fee: Option<u64>,
+        prev_fee: Option<u64>,
+        /// Fee increase over `prev_fee` in percent, unknown without a prior fee
+        fee_bump_pct: Option<f64>,
+    },
+    Mined {
+        txid: String,
+        /// Unknown when the node didn't report the confirming block
+        block_hash: Option<String>,
+    },
+    Pruned {
+        txid: String,
+    },
+    /// `txid` double spends `outpoints` already claimed by the pending `conflicting_txid`
+    Conflict {
+        txid: String,
+        conflicting_txid: String,
+        outpoints: Vec<String>,
+    },
+    /// An inserted tx pays out more than the configured threshold, both in sats
+    LargeValue {
+        txid: String,
+        total_out_value: u64,
+        threshold: u64,
+    },
+    /// A block fetched in full was recorded, `mined` of its `tx_count` txs (coinbase
+    /// included) were pending
+    Block {
+        hash: String,
+        height: Option<u64>,
+        tx_count: u64,
+        mined: u64,
+    },
+}
+
+impl MempoolEvent {
+    pub fn subject(&self) -> &'static str {
+        match self {
+            MempoolEvent::New { .. } => "mempool.tx.new",
+            MempoolEvent::Rbf { .. } => "mempool.tx.rbf",
+            MempoolEvent::Mined { .. } => "mempool.tx.mined",
+            MempoolEvent::Pruned { .. } => "mempool.tx.pruned",
+            MempoolEvent::Conflict { .. } => "mempool.tx.conflict",
+            MempoolEvent::LargeValue { .. } => "mempool.tx.large_value",
+            MempoolEvent::Block { .. } => "mempool.block",
+        }
+    }
+
+    /// What the event is about, the txid or the block hash. Sinks that partition by key
+    /// keep each transaction's events in order
+    pub fn key(&self) -> &str {
+        match self {
+            MempoolEvent::New { txid, .. }
+            | MempoolEvent::Rbf { txid, .. }
+            | MempoolEvent::Mined { txid, .. }
+            | MempoolEvent::Pruned { txid }
+            | MempoolEvent::Conflict { txid, .. }
+            | MempoolEvent::LargeValue { txid, .. } => txid,
+            MempoolEvent::Block { hash, .. } => hash,
+        }
+    }
+}
+
+/// Destination for serialized events, e.g. a message broker
+pub trait EventSink: Send + 'static {
+    fn send(&mut self, subject: &str, payload: Vec<u8>) -> impl Future<Output = Result<()>> + Send;
+
+    /// Like `send`, with `MempoolEvent::key` for sinks that partition by it
+    fn send_keyed(
+        &mut self,
+        subject: &str,
+        _key: &str,
+        payload: Vec<u8>,
+    ) -> impl Future<Output = Result<()>> + Send {
+        self.send(subject, payload)
+    }
+}
+
+/// Handle the workers publish through. Every event goes to each sink, queued and delivered by
+/// a background task per sink so a slow or unreachable one never blocks the worker or the
+/// other sinks. Events are dropped from a sink whose queue is full
+#[derive(Debug, Clone, Default)]
+pub struct EventPublisher {
+    sinks: Vec<mpsc::Sender<MempoolEvent>>,
+    /// In-process subscribers, e.g. streaming servers
+    broadcast: Option<broadcast::Sender<MempoolEvent>>,
+}
+
+impl EventPublisher {
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Also fan events out to in-process subscribers. A subscriber falling more than
+    /// `capacity` events behind loses the oldest ones. An existing channel is kept, so
+    /// servers that subscribed through a clone keep receiving
+    pub fn with_broadcast(self, capacity: usize) -> Self {
+        if self.broadcast.is_some() {
+            return self;
+        }
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            broadcast: Some(sender),
+            ..self
+        }
+    }
+
+    /// Receiver of every event published from now on, `None` without a broadcast channel
+    pub fn subscribe(&self) -> Option<broadcast::Receiver<MempoolEvent>> {
+        self.broadcast.as_ref().map(|sender| sender.subscribe())
+    }
+
+    pub fn spawn<S: EventSink>(sink: S, buffer: usize) -> Self {
+        Self::default().with_sink(sink, buffer)
+    }
+
+    /// Also deliver every event to `sink`, through a queue of `buffer` events of its own
+    pub fn with_sink<S: EventSink>(mut self, mut sink: S, buffer: usize) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<MempoolEvent>(buffer);
+        tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                let payload = match serde_json::to_vec(&event) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        error!("Error serializing event: {}", e);
+                        continue;
+                    }
+                };
+                if let Err(e) = sink.send_keyed(event.subject(), event.key(), payload).await {
+                    error!("Error publishing event to {}: {}", event.subject(), e);
+                }
+            }
+        });
+        self.sinks.push(sender);
+        self
+    }
+
+    pub fn publish(&self, event: MempoolEvent) {
+        if let Some(broadcast) = &self.broadcast {
+            // Only fails without subscribers
+            let _ = broadcast.send(event.clone());
+        }
+        for sender in &self.sinks {
+            if let Err(e) = sender.try_send(event.clone()) {
+                warn!("Dropping mempool event: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "nats")]
+pub mod nats {
+    use super::EventSink;
+    use anyhow::Result;
+
+    pub struct NatsSink(async_nats::Client);
+
+    impl NatsSink {
+        /// Connect to the NATS server, retrying in the background if it is not up yet
+        pub async fn connect(url: &str) -> Result<Self> {
+            let client = async_nats::ConnectOptions::new()
+                .retry_on_initial_connect()
+                .connect(url)
+                .await?;
+            Ok(Self(client))
+        }
+    }
+
+    impl EventSink for NatsSink {
+        async fn send(&mut self, subject: &str, payload: Vec<u8>) -> Result<()> {
+            self.0.publish(subject.to_string(), payload.into()).await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "kafka")]
+pub mod kafka {
+    use super::EventSink;
+    use anyhow::Result;
+    use log::error;
+    use rdkafka::{
+        producer::{FutureProducer, FutureRecord},
+        ClientConfig,
+    };
+
+    /// Produces events to a single topic, keyed by `MempoolEvent::key`
+    pub struct KafkaSink {
+        producer: FutureProducer,
+        topic: String,
+    }
+
+    impl KafkaSink {
+        /// `brokers` is a comma separated host:port list. Messages wait in the producer's
+        /// queue while the brokers are unreachable
+        pub fn new(brokers: &str, topic: &str) -> Result<Self> {
+            let producer = ClientConfig::new()
+                .set("bootstrap.servers", brokers)
+                .set("message.timeout.ms", "300000")
+                .create()?;
+            Ok(Self {
+                producer,
+                topic: topic.to_string(),
+            })
+        }
+
+        /// Only enqueues, the delivery is awaited in the background so a slow broker never
+        /// holds up the events behind it. Fails when the producer's queue is full
+        fn produce(&self, key: Option<&str>, payload: &[u8]) -> Result<()> {
+            let record = FutureRecord::<str, [u8]>::to(&self.topic).payload(payload);
+            let record = match key {
+                Some(key) => record.key(key),
+                None => record,
+            };
+            let delivery = self.producer.send_result(record).map_err(|(e, _)| e)?;
+            let topic = self.topic.clone();
+            tokio::spawn(async move {
+                match delivery.await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err((e, _))) => error!("Error delivering event to {}: {}", topic, e),
+                    Err(_) => error!("Delivery of an event to {} was cancelled", topic),
+                }
+            });
+            Ok(())
+        }
+    }
+
+    impl EventSink for KafkaSink {
+        async fn send(&mut self, _subject: &str, payload: Vec<u8>) -> Result<()> {
+            self.produce(None, &payload)
+        }
+
+        async fn send_keyed(&mut self, _subject: &str, key: &str, payload: Vec<u8>) -> Result<()> {
+            self.produce(Some(key), &payload)
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use bitcoin::{Address, Amount, FeeRate, ScriptBuf, Transaction};
+
+/// Criteria a new transaction must meet to be stored.
+/// Every configured criterion must match (AND), unset criteria always match
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub min_fee_rate: Option<FeeRate>,
+    /// Transaction must pay to at least one of these
+    pub watched_scripts: Vec<ScriptBuf>,
+    /// Minimum total output value
+    pub min_value: Option<Amount>,
+}
+
+impl Filter {
+    pub fn with_watched_addresses(mut self, addresses: &[Address]) -> Self {
+        self.watched_scripts = addresses
+            .iter()
+            .map(|address| address.script_pubkey())
+            .collect();
+        self
+    }
+
+    pub fn matches(&self, tx: &Transaction, fee_rate: FeeRate) -> bool {
+        if let Some(min_fee_rate) = self.min_fee_rate {
+            if fee_rate < min_fee_rate {
+                return false;
+            }
+        }
+        if !self.watched_scripts.is_empty()
+            && !tx
+                .output
+                .iter()
+                .any(|output| self.watched_scripts.contains(&output.script_pubkey))
+        {
+            return false;
+        }
+        if let Some(min_value) = self.min_value {
+            let total_value: Amount = tx.output.iter().map(|output| output.value).sum();
+            if total_value < min_value {
+                return false;
+            }
+        }
+        true
+    }
+}
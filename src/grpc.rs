@@ -0,0 +1,93 @@
+use std::pin::Pin;
+
+use anyhow::Result;
+use futures_util::{stream, Stream};
+use log::{info, warn};
+use tokio::{net::TcpListener, sync::broadcast::error::RecvError};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::events::{EventPublisher, MempoolEvent};
+
+/// Types generated from `proto/mempool.proto`
+pub mod proto {
+    tonic::include_proto!("mempool");
+}
+
+use proto::{
+    mempool_server::{Mempool, MempoolServer},
+    MempoolTransaction, SubscribeTransactionsRequest,
+};
+
+/// Streams the new transactions published through `EventPublisher`'s broadcast channel
+pub struct MempoolService {
+    events: EventPublisher,
+}
+
+impl MempoolService {
+    pub fn new(events: EventPublisher) -> Self {
+        Self { events }
+    }
+}
+
+type TransactionStream = Pin<Box<dyn Stream<Item = Result<MempoolTransaction, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Mempool for MempoolService {
+    type SubscribeTransactionsStream = TransactionStream;
+
+    async fn subscribe_transactions(
+        &self,
+        _request: Request<SubscribeTransactionsRequest>,
+    ) -> Result<Response<Self::SubscribeTransactionsStream>, Status> {
+        let receiver = self
+            .events
+            .subscribe()
+            .ok_or_else(|| Status::unavailable("event broadcast is disabled"))?;
+        // A subscriber that lagged behind the broadcast buffer gets an error and is dropped
+        // rather than silently missing transactions
+        let transactions = stream::unfold(Some(receiver), |receiver| async move {
+            let mut receiver = receiver?;
+            loop {
+                match receiver.recv().await {
+                    Ok(MempoolEvent::New {
+                        txid,
+                        fee,
+                        vsize,
+                        found_at,
+                        ..
+                    }) => {
+                        let transaction = MempoolTransaction {
+                            txid,
+                            fee,
+                            vsize,
+                            found_at,
+                        };
+                        return Some((Ok(transaction), Some(receiver)));
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Dropping gRPC subscriber lagging {} events behind", skipped);
+                        let status = Status::resource_exhausted(format!(
+                            "subscriber lagged {} events behind",
+                            skipped
+                        ));
+                        return Some((Err(status), None));
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(transactions)))
+    }
+}
+
+/// Serve the gRPC service on a bound listener until the server fails
+pub async fn serve_with_listener(listener: TcpListener, events: EventPublisher) -> Result<()> {
+    info!("gRPC server listening on {}", listener.local_addr()?);
+    Server::builder()
+        .add_service(MempoolServer::new(MempoolService::new(events)))
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await?;
+    Ok(())
+}
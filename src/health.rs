@@ -0,0 +1,182 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use crate::now;
+
+/// Readiness fails while the raw tx queue is at least this full, ingestion is lagging
+pub const QUEUE_LAG_FILL: f64 = 0.8;
+
+/// Liveness and readiness of the tracker, updated by the zmq listener, the workers and
+/// `App::init` as they go so probes only read cached state
+#[derive(Debug, Clone)]
+pub struct Health {
+    /// 0 until the tracker is ready, the silence then counts from readiness until a
+    /// first message arrives
+    last_zmq_message_at: Arc<AtomicU64>,
+    rpc_ok: Arc<AtomicBool>,
+    /// 0 until an RPC round trip succeeds
+    last_rpc_ok_at: Arc<AtomicU64>,
+    db_ok: Arc<AtomicBool>,
+    /// Set once the initial mempool extraction completed
+    ready: Arc<AtomicBool>,
+    /// Raw tx queue depth and capacity last seen by a worker or the zmq listener
+    queue_depth: Arc<AtomicU64>,
+    queue_capacity: Arc<AtomicU64>,
+    /// Total time the zmq listener waited on a full raw tx queue
+    send_blocked_micros: Arc<AtomicU64>,
+    /// Time the last dequeued task spent queued
+    queue_latency_micros: Arc<AtomicU64>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            last_zmq_message_at: Arc::new(AtomicU64::new(0)),
+            rpc_ok: Arc::new(AtomicBool::new(false)),
+            last_rpc_ok_at: Arc::new(AtomicU64::new(0)),
+            db_ok: Arc::new(AtomicBool::new(false)),
+            ready: Arc::new(AtomicBool::new(false)),
+            queue_depth: Arc::new(AtomicU64::new(0)),
+            queue_capacity: Arc::new(AtomicU64::new(0)),
+            send_blocked_micros: Arc::new(AtomicU64::new(0)),
+            queue_latency_micros: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl Health {
+    pub fn record_zmq_message(&self) {
+        self.last_zmq_message_at.store(now!(), Ordering::Relaxed);
+    }
+
+    /// Outcome of the last RPC round trip to the node
+    pub fn record_rpc(&self, ok: bool) {
+        self.rpc_ok.store(ok, Ordering::Relaxed);
+        if ok {
+            self.last_rpc_ok_at.store(now!(), Ordering::Relaxed);
+        }
+    }
+
+    /// Outcome of the last database write, a read-only database never counts as written
+    pub fn record_db_write(&self, ok: bool) {
+        self.db_ok.store(ok, Ordering::Relaxed);
+    }
+
+    /// The zmq listener starts right after, so its silence is counted from here
+    pub fn set_ready(&self) {
+        let _ = self.last_zmq_message_at.compare_exchange(
+            0,
+            now!(),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+        self.ready.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    /// `capacity` is `None` for an unbounded queue, which never counts as lagging
+    pub fn record_queue_depth(&self, depth: usize, capacity: Option<usize>) {
+        self.queue_depth.store(depth as u64, Ordering::Relaxed);
+        self.queue_capacity
+            .store(capacity.unwrap_or(0) as u64, Ordering::Relaxed);
+    }
+
+    pub fn queue_depth(&self) -> u64 {
+        self.queue_depth.load(Ordering::Relaxed)
+    }
+
+    /// Share of the raw tx queue in use, 0 when unbounded
+    pub fn queue_fill(&self) -> f64 {
+        match self.queue_capacity.load(Ordering::Relaxed) {
+            0 => 0.0,
+            capacity => self.queue_depth() as f64 / capacity as f64,
+        }
+    }
+
+    pub fn record_send_blocked(&self, waited: Duration) {
+        self.send_blocked_micros
+            .fetch_add(waited.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn send_blocked(&self) -> Duration {
+        Duration::from_micros(self.send_blocked_micros.load(Ordering::Relaxed))
+    }
+
+    pub fn record_queue_latency(&self, waited: Duration) {
+        self.queue_latency_micros
+            .store(waited.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn queue_latency(&self) -> Duration {
+        Duration::from_micros(self.queue_latency_micros.load(Ordering::Relaxed))
+    }
+
+    /// Seconds since the last zmq message, 0 before the tracker is ready
+    pub fn zmq_silence_secs(&self) -> u64 {
+        match self.last_zmq_message_at.load(Ordering::Relaxed) {
+            0 => 0,
+            at => now!().saturating_sub(at),
+        }
+    }
+
+    /// Seconds since the last successful RPC round trip
+    pub fn rpc_silence_secs(&self) -> u64 {
+        now!().saturating_sub(self.last_rpc_ok_at.load(Ordering::Relaxed))
+    }
+
+    /// Why the pipeline looks wedged, `None` while a zmq message arrived or an RPC round
+    /// trip succeeded within `max_silence`
+    pub fn stall(&self, max_silence: Duration) -> Option<String> {
+        let zmq_silence = self.zmq_silence_secs();
+        let rpc_silence = self.rpc_silence_secs();
+        if zmq_silence <= max_silence.as_secs() || rpc_silence <= max_silence.as_secs() {
+            return None;
+        }
+        Some(format!(
+            "no zmq message for {}s and no RPC success for {}s",
+            zmq_silence, rpc_silence
+        ))
+    }
+
+    /// Failed liveness checks, empty when live. zmq counts as stalled once it was silent
+    /// for longer than `zmq_max_silence`
+    pub fn liveness_failures(&self, zmq_max_silence: Duration) -> Vec<String> {
+        let mut failures = vec![];
+        let silence = self.zmq_silence_secs();
+        if silence > zmq_max_silence.as_secs() {
+            failures.push(format!("no zmq message for {}s", silence));
+        }
+        if !self.rpc_ok.load(Ordering::Relaxed) {
+            failures.push("rpc unreachable".to_string());
+        }
+        if !self.db_ok.load(Ordering::Relaxed) {
+            failures.push("database not writable".to_string());
+        }
+        failures
+    }
+
+    /// Failed readiness checks, empty when ready. Besides the initial mempool extraction,
+    /// a raw tx queue at least `QUEUE_LAG_FILL` full means ingestion is lagging
+    pub fn readiness_failures(&self) -> Vec<String> {
+        let mut failures = vec![];
+        if !self.is_ready() {
+            failures.push("initial mempool extraction in progress".to_string());
+        }
+        if self.queue_fill() >= QUEUE_LAG_FILL {
+            failures.push(format!(
+                "ingestion lagging, {} of {} raw tx queue slots in use",
+                self.queue_depth(),
+                self.queue_capacity.load(Ordering::Relaxed)
+            ));
+        }
+        failures
+    }
+}
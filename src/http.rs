@@ -0,0 +1,60 @@
+use std::{future::Future, time::Duration};
+
+use anyhow::Result;
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use log::info;
+use tokio::net::TcpListener;
+
+use crate::health::Health;
+
+#[derive(Debug, Clone)]
+struct ProbeState {
+    health: Health,
+    zmq_max_silence: Duration,
+}
+
+/// 200 while zmq delivers, the node answers and the database takes writes, 503 with
+/// the failed checks otherwise
+async fn healthz(State(state): State<ProbeState>) -> (StatusCode, String) {
+    let failures = state.health.liveness_failures(state.zmq_max_silence);
+    if failures.is_empty() {
+        (StatusCode::OK, "ok".to_string())
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, failures.join(", "))
+    }
+}
+
+/// 200 with the raw tx queue depth once the initial mempool extraction completed and
+/// ingestion keeps up, 503 with the failed checks otherwise
+async fn readyz(State(state): State<ProbeState>) -> (StatusCode, String) {
+    let failures = state.health.readiness_failures();
+    if failures.is_empty() {
+        (
+            StatusCode::OK,
+            format!("ready, queue depth {}", state.health.queue_depth()),
+        )
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, failures.join(", "))
+    }
+}
+
+/// Serve `/healthz` and `/readyz` on a bound listener until `shutdown` resolves
+pub async fn serve_with_listener(
+    listener: TcpListener,
+    health: Health,
+    zmq_max_silence: Duration,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    info!("Health server listening on {}", listener.local_addr()?);
+    let router = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(ProbeState {
+            health,
+            zmq_max_silence,
+        });
+    axum::serve(listener, router)
+        .with_graceful_shutdown(shutdown)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+
+/// Keeps a second tracker off the same database. Holds an exclusive advisory lock on
+/// `<database>.lock`, which also records the holder's pid, and writes the pid file if
+/// one is configured. The OS drops the lock with the process, so a crashed tracker's
+/// leftovers are taken over. Dropping it releases the lock and removes the pid file
+#[derive(Debug)]
+pub struct InstanceLock {
+    /// Locked while open
+    lock_file: File,
+    pid_file: Option<PathBuf>,
+}
+
+impl InstanceLock {
+    pub fn acquire(database: impl AsRef<Path>, pid_file: Option<&Path>) -> Result<Self> {
+        let lock_path = lock_path(database.as_ref());
+        let mut lock_file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("opening lock file {}", lock_path.display()))?;
+        match lock_file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let holder = read_pid(&mut lock_file)
+                    .map_or_else(|| "unknown pid".to_string(), |pid| format!("pid {}", pid));
+                return Err(anyhow!(
+                    "another instance is running ({}), it holds {}",
+                    holder,
+                    lock_path.display()
+                ));
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("locking {}", lock_path.display()))
+            }
+        }
+
+        let pid = std::process::id();
+        lock_file.set_len(0)?;
+        lock_file.seek(SeekFrom::Start(0))?;
+        writeln!(lock_file, "{}", pid)?;
+        if let Some(path) = pid_file {
+            if let Some(stale) = fs::read_to_string(path).ok().and_then(|s| parse_pid(&s)) {
+                info!(
+                    "Taking over pid file {} of stale pid {}",
+                    path.display(),
+                    stale
+                );
+            }
+            fs::write(path, format!("{}\n", pid))
+                .with_context(|| format!("writing pid file {}", path.display()))?;
+        }
+        Ok(Self {
+            lock_file,
+            pid_file: pid_file.map(Path::to_path_buf),
+        })
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        if let Some(path) = &self.pid_file {
+            if let Err(e) = fs::remove_file(path) {
+                warn!("Failed to remove pid file {}: {}", path.display(), e);
+            }
+        }
+        // The lock file stays, a next instance locks it again
+        let _ = self.lock_file.unlock();
+    }
+}
+
+/// Lock file next to `database`, e.g. mempool-tracker.db.lock
+pub fn lock_path(database: &Path) -> PathBuf {
+    let mut path = database.as_os_str().to_owned();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    parse_pid(&contents)
+}
+
+fn parse_pid(s: &str) -> Option<u32> {
+    s.trim().parse().ok()
+}
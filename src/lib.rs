@@ -1,8 +1,32 @@
 pub mod app;
+pub mod capture;
+pub mod config;
 pub mod database;
+pub mod events;
+pub mod filter;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod health;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod instance;
+pub mod logging;
 pub mod migrations;
+pub mod miners;
+#[cfg(feature = "postgres")]
+pub mod pg_store;
+pub mod rate_limit;
+#[cfg(feature = "http")]
+pub mod rest;
+pub mod rpc;
+pub mod store;
+#[cfg(feature = "sd-notify")]
+pub mod systemd;
+pub mod tip;
 pub mod utils;
 pub mod worker;
+#[cfg(feature = "http")]
+pub mod ws;
 pub mod zmq_factory;
 // Re-export bitcoincore_zmq
 pub use bitcoincore_zmq;
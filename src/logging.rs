@@ -0,0 +1,217 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    panic,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Context, Result};
+use env_logger::{Env, Target};
+use log::error;
+use serde::Deserialize;
+
+use crate::capture::rotate_files;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Where log lines go
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogOutput {
+    #[default]
+    Stderr,
+    File,
+    /// The log file and stderr
+    Both,
+}
+
+impl FromStr for LogOutput {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "stderr" => LogOutput::Stderr,
+            "file" => LogOutput::File,
+            "both" => LogOutput::Both,
+            _ => return Err(anyhow!("Unknown log output: {}", s)),
+        })
+    }
+}
+
+/// How the tracker logs, see `init`
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    pub output: LogOutput,
+    /// Needed unless logging to stderr only
+    pub path: Option<PathBuf>,
+    /// A file is rotated before a line would take it past this size, 0 for no size limit
+    pub max_file_bytes: u64,
+    /// Also rotate at the first line of every UTC day
+    pub daily: bool,
+    /// Files kept including the one being written, at least 1
+    pub max_files: usize,
+    /// env_logger directives, e.g. `info,mempool_tracker::worker=debug`. `RUST_LOG`
+    /// overrides them, without either only errors are logged
+    pub filters: Option<String>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            output: LogOutput::Stderr,
+            path: None,
+            max_file_bytes: 100 * 1024 * 1024,
+            daily: false,
+            max_files: 10,
+            filters: None,
+        }
+    }
+}
+
+/// Installs the logger, once per process. When logging to a file panics are logged too,
+/// then printed by the default hook as before
+pub fn init(config: &LogConfig) -> Result<()> {
+    let env = match &config.filters {
+        Some(filters) => Env::default().default_filter_or(filters.as_str()),
+        None => Env::default(),
+    };
+    let target = match config.output {
+        LogOutput::Stderr => Target::Stderr,
+        LogOutput::File => Target::Pipe(Box::new(RotatingLogFile::from_config(config)?)),
+        LogOutput::Both => {
+            Target::Pipe(Box::new(WithStderr(RotatingLogFile::from_config(config)?)))
+        }
+    };
+    env_logger::Builder::from_env(env)
+        .target(target)
+        .try_init()
+        .context("installing the logger")?;
+    if config.output != LogOutput::Stderr {
+        log_panics();
+    }
+    Ok(())
+}
+
+fn log_panics() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let thread = std::thread::current();
+        error!("thread '{}' {}", thread.name().unwrap_or("<unnamed>"), info);
+        default_hook(info);
+    }));
+}
+
+/// Appends log lines to a file, rotating it to `path.1`, `path.2`, ... by size and, if
+/// asked, by UTC day. Unbuffered so the last lines before a crash make it to the file
+#[derive(Debug)]
+pub struct RotatingLogFile {
+    path: PathBuf,
+    max_file_bytes: u64,
+    daily: bool,
+    max_files: usize,
+    file: File,
+    /// Size of the file being written
+    written: u64,
+    /// UTC day of the file's last line
+    day: u64,
+}
+
+impl RotatingLogFile {
+    /// Keeps appending to an existing log file, which is rotated first if it's from an
+    /// earlier day
+    pub fn open(
+        path: impl AsRef<Path>,
+        max_file_bytes: u64,
+        daily: bool,
+        max_files: usize,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        if max_files == 0 {
+            return Err(anyhow!("Logging keeps at least 1 file"));
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("opening log file {}", path.display()))?;
+        let metadata = file.metadata()?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_file_bytes,
+            daily,
+            max_files,
+            file,
+            written: metadata.len(),
+            day: utc_day(metadata.modified().unwrap_or_else(|_| SystemTime::now())),
+        })
+    }
+
+    fn from_config(config: &LogConfig) -> Result<Self> {
+        let path = config
+            .path
+            .as_ref()
+            .ok_or_else(|| anyhow!("Logging to a file needs its path"))?;
+        Self::open(path, config.max_file_bytes, config.daily, config.max_files)
+    }
+
+    /// Appends `line` as written at `now`
+    pub fn write_at(&mut self, line: &[u8], now: SystemTime) -> io::Result<()> {
+        let day = utc_day(now);
+        let full =
+            self.max_file_bytes > 0 && self.written + line.len() as u64 > self.max_file_bytes;
+        let new_day = self.daily && day != self.day;
+        if self.written > 0 && (full || new_day) {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.written += line.len() as u64;
+        self.day = day;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        rotate_files(&self.path, self.max_files)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_at(buf, SystemTime::now())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Writes every line to the log file and to stderr
+struct WithStderr(RotatingLogFile);
+
+impl Write for WithStderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(buf)?;
+        io::stderr().write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()?;
+        io::stderr().flush()
+    }
+}
+
+fn utc_day(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() / SECONDS_PER_DAY)
+        .unwrap_or(0)
+}
@@ -1,71 +1,692 @@
-use std::time::Duration;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
 use anyhow::Result;
+use bitcoin::{address::NetworkUnchecked, Address, Amount, FeeRate};
 use bitcoind_async_client::Client;
-use clap::Parser;
-use zmq_factory::BitcoinZmqFactory;
-
-mod app;
-mod database;
-mod migrations;
-mod utils;
-mod worker;
-mod zmq_factory;
+use clap::{Parser, Subcommand};
+#[cfg(feature = "grpc")]
+use mempool_tracker::grpc;
+#[cfg(feature = "postgres")]
+use mempool_tracker::pg_store::PgStore;
+use mempool_tracker::{
+    app::{self, AppConfig},
+    capture, config, database,
+    events::{self, EventPublisher},
+    filter::Filter,
+    instance, logging,
+    miners::MinerRegistry,
+    rpc::{self, ReconnectingRpc},
+    store::{DryRunStore, MempoolStore},
+    worker,
+    zmq_factory::{self, BitcoinZmqFactory},
+};
+#[cfg(feature = "http")]
+use mempool_tracker::{http, rest, ws};
 
 // Command line arguments
 #[derive(Clone, Debug, Parser)]
+#[clap(subcommand_negates_reqs = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// TOML config file, see contrib/mempool-tracker.example.toml. The other flags are
+    /// ignored when it is given
     #[clap(long)]
-    bitcoind_user: String,
-    #[clap(long)]
-    bitcoind_password: String,
-    #[clap(long)]
-    bitcoind_host: String,
-    #[clap(long)]
-    bitcoind_rpc_port: u16,
-    #[clap(long)]
-    bitcoind_zmq_port: u16,
-    #[clap(long, default_value_t = 2)]
+    config: Option<PathBuf>,
+    #[clap(long, required_unless_present_any = ["config", "bitcoind_cookie_file"])]
+    bitcoind_user: Option<String>,
+    #[clap(long, required_unless_present_any = ["config", "bitcoind_cookie_file"])]
+    bitcoind_password: Option<String>,
+    /// bitcoind's `.cookie`, instead of --bitcoind-user and --bitcoind-password. It is
+    /// read again on every reconnect
+    #[clap(long, conflicts_with_all = ["bitcoind_user", "bitcoind_password"])]
+    bitcoind_cookie_file: Option<PathBuf>,
+    #[clap(long, required_unless_present = "config")]
+    bitcoind_host: Option<String>,
+    #[clap(long, required_unless_present = "config")]
+    bitcoind_rpc_port: Option<u16>,
+    #[clap(long, required_unless_present = "config")]
+    bitcoind_zmq_port: Option<u16>,
+    /// Concurrent task processors. They overlap RPC calls, database writes still go
+    /// through SQLite one at a time
+    #[clap(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(1..))]
     num_workers: u32,
+    /// Seconds between mempool state snapshots, at least 10
     #[clap(long, default_value_t = 25)]
     mempool_state_check_interval: u64,
+    /// Seconds between prune checks, 0 disables them when the sequence topic reports
+    /// removals (--zmq-topic sequence). Without them lost block announcements aren't
+    /// caught up with and purges aren't detected
     #[clap(long, default_value_t = 120)]
     prune_check_interval: u64,
+    /// Seconds between checkpoints incremental consumers can resume from
+    #[clap(long, default_value_t = 600)]
+    checkpoint_interval: u64,
+    /// Seconds between the database stats log lines
+    #[clap(long, default_value_t = 3600, value_parser = clap::value_parser!(u64).range(1..))]
+    stats_log_interval: u64,
+    /// Only store transactions paying at least this fee rate (sat/vB)
+    #[clap(long)]
+    min_fee_rate: Option<u64>,
+    /// Only store transactions paying to one of these addresses
+    #[clap(long)]
+    watch_address: Vec<Address<NetworkUnchecked>>,
+    /// Only store transactions with at least this total output value (sats)
+    #[clap(long)]
+    min_value: Option<u64>,
+    /// Flag and announce the inserted transactions paying out more than this (sats)
+    #[clap(long)]
+    large_value_threshold: Option<u64>,
+    /// Count outputs below this value (sats) as dust instead of applying the relay
+    /// dust limit of each output's script type
+    #[clap(long)]
+    dust_threshold: Option<u64>,
+    /// Blocks after its own before a coinbase can be spent, for test networks whose
+    /// node was patched
+    #[clap(long, default_value_t = database::COINBASE_MATURITY)]
+    coinbase_maturity: u64,
+    /// Domain tag for the inputs hashes, e.g. the network name, when several monitors
+    /// share a store. Changing it re-keys every transaction
+    #[clap(long)]
+    inputs_hash_tag: Option<String>,
+    /// sqlite fsync policy: off trades crash safety for throughput (an OS crash or power
+    /// loss can lose recent writes or corrupt the file), normal may lose the last commits
+    /// on power loss, full never loses a commit
+    #[clap(long, default_value = "normal")]
+    synchronous: database::Synchronous,
+    /// JSON file of extra coinbase tags and payout addresses identifying miners,
+    /// `{"tags": {"<tag>": "<miner>"}, "addresses": {"<address>": "<miner>"}}`
+    #[clap(long)]
+    miner_mapping: Option<PathBuf>,
+    /// Worker RPC calls per second, 0 is unlimited
+    #[clap(long, default_value_t = 0)]
+    rpc_rate_limit: u32,
+    /// RPC calls allowed back to back before the rate limit applies
+    #[clap(long, default_value_t = 1)]
+    rpc_burst: u32,
+    /// Seconds before a worker RPC call times out
+    #[clap(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    rpc_timeout: u64,
+    /// Tries per worker RPC call, connection errors and timeouts are retried with backoff
+    #[clap(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    rpc_attempts: u32,
+    /// Seconds to wait for a first zmq message at startup when the node can't list its
+    /// zmq publishers, 0 skips the wait
+    #[clap(long, default_value_t = 10)]
+    zmq_probe_timeout: u64,
+    /// Built with the sd-notify feature, seconds without a zmq message or an RPC success
+    /// before the systemd watchdog isn't pinged anymore
+    #[clap(long, default_value_t = 300)]
+    watchdog_max_silence: u64,
+    /// zmq topics to process: rawtx, rawblock, hashblock, sequence. Defaults to rawtx and
+    /// hashblock
+    #[clap(long)]
+    zmq_topic: Vec<zmq_factory::ZmqTopic>,
+    /// Consecutive zmq reconnect failures before giving up
+    #[clap(long, default_value_t = 10)]
+    zmq_max_reconnects: u32,
+    /// Minimum node verification progress required to start (0.0 - 1.0)
+    #[clap(long, default_value_t = 0.9999)]
+    min_verification_progress: f64,
+    /// Raw txs buffered ahead of the workers, lower values apply backpressure sooner.
+    /// Defaults to 50000 per worker
+    #[clap(long)]
+    task_channel_capacity: Option<usize>,
+    /// Warn when no new block was observed for this many seconds
+    #[clap(long, default_value_t = 1800)]
+    tip_stale_after: u64,
+    /// Seconds the workers get to finish the queued tasks on SIGINT/SIGTERM, a second
+    /// signal exits immediately
+    #[clap(long, default_value_t = 30)]
+    shutdown_timeout: u64,
+    /// How a new block's tracked txs are found: full-block, or block-filter to match the
+    /// block's BIP158 filter and fetch only the matching txs (needs -blockfilterindex)
+    #[clap(long, default_value = "full-block")]
+    mined_detection: worker::MinedDetection,
+    /// What a misconfigured node (missing zmq topics or txindex, still syncing) does at
+    /// startup: fail, or warn and start anyway
+    #[clap(long, default_value = "fail")]
+    startup_checks: app::StartupCheckMode,
+    /// Refuse to start unless the node's -mempoolfullrbf matches
+    #[clap(long)]
+    expect_full_rbf: Option<bool>,
+    /// Process everything as usual but never write to the database
+    #[clap(long)]
+    read_only: bool,
+    /// Read the database but drop every write, counting what would have been written and
+    /// logging the counts on shutdown
+    #[clap(long)]
+    dry_run: bool,
+    /// Record into this Postgres server instead of mempool-tracker.db, e.g.
+    /// postgres://tracker@localhost/mempool
+    #[cfg(feature = "postgres")]
+    #[clap(long)]
+    postgres_url: Option<String>,
+    /// Feed the workers from a capture file instead of zmq and stop once it's replayed
+    /// (needs a node with -txindex to look up the replayed txs' prevouts)
+    #[clap(long)]
+    replay_capture: Option<PathBuf>,
+    /// Keep the capture's pace between messages instead of replaying at full speed
+    #[clap(long, requires = "replay_capture")]
+    replay_realtime: bool,
+    /// Append every zmq message received to this file, rotated to <file>.1, <file>.2, ...
+    #[clap(long)]
+    capture: Option<PathBuf>,
+    /// Size a capture file is rotated at, in MiB
+    #[clap(long, default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    capture_max_file_mb: u64,
+    /// Capture files kept, including the one being written
+    #[clap(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    capture_max_files: u64,
+    /// When capture files are synced to disk: never, on-rotate or every-record
+    #[clap(long, default_value = "on-rotate")]
+    capture_fsync: capture::FsyncPolicy,
+    /// Write the tracker's pid to this file, removed on shutdown
+    #[clap(long)]
+    pid_file: Option<PathBuf>,
+    /// Where to log: stderr, file or both
+    #[clap(long, default_value = "stderr")]
+    log_output: logging::LogOutput,
+    /// Log file, rotated to <file>.1, <file>.2, ...
+    #[clap(long)]
+    log_file: Option<PathBuf>,
+    /// Size a log file is rotated at, in MiB, 0 for no size limit
+    #[clap(long, default_value_t = 100)]
+    log_max_file_mb: u64,
+    /// Rotate the log file at the first line of every UTC day too
+    #[clap(long)]
+    log_daily: bool,
+    /// Log files kept, including the one being written
+    #[clap(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    log_max_files: u64,
+    /// Per-module levels, e.g. info,mempool_tracker::worker=debug. RUST_LOG overrides them
+    #[clap(long)]
+    log_filters: Option<String>,
+    /// Ingest mined txs of an inclusive block height range, e.g. 860000:861000. Needs a
+    /// node with -txindex to look up their fees
+    #[clap(long, value_parser = parse_height_range)]
+    backfill: Option<(u64, u64)>,
+    /// Publish mempool events to this NATS server
+    #[cfg(feature = "nats")]
+    #[clap(long)]
+    nats_url: Option<String>,
+    /// Produce mempool events to these Kafka brokers, a comma separated host:port list
+    #[cfg(feature = "kafka")]
+    #[clap(long)]
+    kafka_brokers: Option<String>,
+    /// Topic the events are produced to, keyed by txid
+    #[cfg(feature = "kafka")]
+    #[clap(long, default_value = "mempool-events")]
+    kafka_topic: String,
+    /// Serve the gRPC transaction stream on this address, e.g. 127.0.0.1:50051
+    #[cfg(feature = "grpc")]
+    #[clap(long)]
+    grpc_listen: Option<std::net::SocketAddr>,
+    /// Serve /healthz and /readyz on this address, e.g. 0.0.0.0:8080
+    #[cfg(feature = "http")]
+    #[clap(long)]
+    health_listen: Option<SocketAddr>,
+    /// Seconds without a zmq message before /healthz reports zmq as stalled
+    #[cfg(feature = "http")]
+    #[clap(long, default_value_t = 300)]
+    health_zmq_max_silence: u64,
+    /// Serve the read-only REST API on this address, e.g. 127.0.0.1:8081
+    #[cfg(feature = "http")]
+    #[clap(long)]
+    rest_listen: Option<SocketAddr>,
+    /// Stream live events over a WebSocket at /ws on this address, e.g. 127.0.0.1:8082
+    #[cfg(feature = "http")]
+    #[clap(long)]
+    ws_listen: Option<SocketAddr>,
+    /// Events a feed client may fall behind by before it is dropped
+    #[cfg(feature = "http")]
+    #[clap(long, default_value_t = 10_000, value_parser = clap::value_parser!(u64).range(1..))]
+    ws_buffer: u64,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum Command {
+    /// Print the CREATE statements of the database's tables and indexes, then exit
+    DumpSchema {
+        #[clap(long, default_value = "mempool-tracker.db")]
+        database: String,
+    },
+    /// Recompute the classification columns of every stored tx from its raw data, e.g.
+    /// after an upgrade added new ones, then exit. Honors --dust-threshold and
+    /// --miner-mapping
+    Replay {
+        #[clap(long, default_value = "mempool-tracker.db")]
+        database: String,
+    },
+}
+
+/// Events a gRPC subscriber may fall behind by before it is dropped
+#[cfg(feature = "grpc")]
+const GRPC_BROADCAST_CAPACITY: usize = 10_000;
+
+fn parse_height_range(s: &str) -> Result<(u64, u64), String> {
+    let (from, to) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected FROM:TO, got {}", s))?;
+    let from: u64 = from
+        .parse()
+        .map_err(|e| format!("invalid height {}: {}", from, e))?;
+    let to: u64 = to
+        .parse()
+        .map_err(|e| format!("invalid height {}: {}", to, e))?;
+    if from > to {
+        return Err(format!("range start {} is after its end {}", from, to));
+    }
+    Ok((from, to))
+}
+
+/// Publisher of the configured sinks, broadcasting for the WebSocket feed when
+/// `ws_buffer` is set. Sinks of features not built in were rejected with the config
+#[cfg_attr(
+    not(any(feature = "nats", feature = "kafka", feature = "grpc")),
+    allow(unused_variables)
+)]
+async fn event_publisher(
+    sinks: &config::EventsConfig,
+    ws_buffer: Option<usize>,
+) -> Result<EventPublisher> {
+    // Every configured sink gets each event
+    let events = EventPublisher::disabled();
+    #[cfg(feature = "nats")]
+    let events = match &sinks.nats_url {
+        Some(url) => events.with_sink(events::nats::NatsSink::connect(url).await?, 10_000),
+        None => events,
+    };
+    #[cfg(feature = "kafka")]
+    let events = match &sinks.kafka_brokers {
+        Some(brokers) => events.with_sink(
+            events::kafka::KafkaSink::new(brokers, &sinks.kafka_topic)?,
+            10_000,
+        ),
+        None => events,
+    };
+    let events = match ws_buffer {
+        Some(capacity) => events.with_broadcast(capacity),
+        None => events,
+    };
+    #[cfg(feature = "grpc")]
+    let events = match sinks.grpc_listen {
+        Some(addr) => {
+            let events = events.with_broadcast(GRPC_BROADCAST_CAPACITY);
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            let server_events = events.clone();
+            tokio::spawn(async move {
+                if let Err(e) = grpc::serve_with_listener(listener, server_events).await {
+                    log::error!("gRPC server stopped: {}", e);
+                }
+            });
+            events
+        }
+        None => events,
+    };
+    Ok(events)
+}
+
+fn log_config(args: &Args) -> logging::LogConfig {
+    logging::LogConfig {
+        output: args.log_output,
+        path: args.log_file.clone(),
+        max_file_bytes: args.log_max_file_mb * 1024 * 1024,
+        daily: args.log_daily,
+        max_files: args.log_max_files as usize,
+        filters: args.log_filters.clone(),
+    }
+}
+
+/// HTTP servers to run next to the app, none when all are unset
+#[derive(Debug)]
+struct HttpServers<S> {
+    /// Health probes' address, with the zmq silence allowed
+    health: Option<(SocketAddr, Duration)>,
+    /// REST API's address, with the read-only handle of the app's store it queries
+    rest: Option<(SocketAddr, S)>,
+    /// WebSocket feed's address, the app's events need a broadcast channel
+    ws: Option<SocketAddr>,
+}
+
+/// Init and run the app, serving `servers` meanwhile. The servers stop with the app
+async fn run_app<S: MempoolStore, T: MempoolStore>(
+    mut app: app::App<ReconnectingRpc<Client>, S>,
+    servers: HttpServers<T>,
+) -> Result<()> {
+    #[cfg(feature = "http")]
+    let mut running = vec![];
+    #[cfg(feature = "http")]
+    if let Some((addr, zmq_max_silence)) = servers.health {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(http::serve_with_listener(
+            listener,
+            app.health(),
+            zmq_max_silence,
+            async {
+                let _ = stop_rx.await;
+            },
+        ));
+        running.push(("Health", stop_tx, server));
+    }
+    #[cfg(feature = "http")]
+    if let Some((addr, db)) = servers.rest {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(rest::serve_with_listener(listener, db, async {
+            let _ = stop_rx.await;
+        }));
+        running.push(("REST", stop_tx, server));
+    }
+    #[cfg(feature = "http")]
+    if let Some(addr) = servers.ws {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(ws::serve_with_listener(listener, app.events(), async {
+            let _ = stop_rx.await;
+        }));
+        running.push(("WebSocket", stop_tx, server));
+    }
+    #[cfg(not(feature = "http"))]
+    if servers.health.is_some() || servers.rest.is_some() || servers.ws.is_some() {
+        log::warn!("Built without the http feature, not serving probes, REST or WebSocket");
+    }
+
+    let result = async {
+        app.init().await?;
+        app.run().await
+    }
+    .await;
+
+    #[cfg(feature = "http")]
+    for (name, stop_tx, server) in running {
+        let _ = stop_tx.send(());
+        if let Err(e) = server.await? {
+            log::error!("{} server failed: {}", name, e);
+        }
+    }
+    result
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+    let config = args
+        .config
+        .as_deref()
+        .map(config::Config::load)
+        .transpose()?;
+    match &config {
+        Some(config) => logging::init(&config.logging.log_config())?,
+        None => logging::init(&log_config(&args))?,
+    }
     log::info!("welcome to mempool tracker");
-    env_logger::init();
 
-    let args = Args::parse();
-    let zmq_factory =
-        BitcoinZmqFactory::new(args.bitcoind_host.clone(), args.bitcoind_zmq_port.clone());
-    let db = database::Database::new("mempool-tracker.db")?;
-    let bitcoind_url = format!("http://{}:{}", args.bitcoind_host, args.bitcoind_rpc_port);
+    if let Some(Command::DumpSchema { database }) = &args.command {
+        print!(
+            "{}",
+            database::Database::open_read_only(database)?.dump_schema()?
+        );
+        return Ok(());
+    }
+    if let Some(Command::Replay { database }) = &args.command {
+        let _instance = instance::InstanceLock::acquire(database, None)?;
+        let db = database::Database::open(database, args.synchronous)?
+            .with_coinbase_maturity(args.coinbase_maturity);
+        let db = match args.dust_threshold {
+            Some(threshold) => db.with_dust_threshold(Amount::from_sat(threshold)),
+            None => db,
+        };
+        let db = match &args.miner_mapping {
+            Some(path) => db.with_miner_registry(MinerRegistry::builtin().with_mapping_file(path)?),
+            None => db,
+        };
+        // The columns to fill may come from migrations the tracker hasn't run yet
+        db.run_migrations()?;
+        let updated = db.replay_classification()?;
+        println!("Reclassified {} transactions", updated);
+        return Ok(());
+    }
+    if let Some(config) = config {
+        // Read-only instances can't get in a writer's way
+        let _instance = (!config.database.dry_run)
+            .then(|| {
+                instance::InstanceLock::acquire(&config.database.path, config.pid_file.as_deref())
+            })
+            .transpose()?;
+        let health = config
+            .http
+            .listen
+            .map(|addr| (addr, Duration::from_secs(config.http.zmq_max_silence)));
+        let (rest_listen, ws) = (config.http.rest_listen, config.http.ws_listen);
+        let events = event_publisher(
+            &config.events,
+            config.http.ws_listen.map(|_| config.http.ws_buffer),
+        )
+        .await?;
+        #[cfg(feature = "postgres")]
+        if let Some(url) = config.database.postgres_url.clone() {
+            let db = app::connect_postgres(&config).await?;
+            let rest = match rest_listen {
+                Some(addr) => Some((addr, PgStore::connect_read_only(&url).await?)),
+                None => None,
+            };
+            let servers = HttpServers { health, rest, ws };
+            if config.database.dry_run {
+                let db = DryRunStore::new(db)?;
+                let app = app::App::from_config_with_store(config, db, events)?;
+                return run_app(app, servers).await;
+            }
+            let app = app::App::from_config_with_store(config, db, events)?;
+            return run_app(app, servers).await;
+        }
+        let db = app::open_database(&config)?;
+        // The app opened the file already, queries go through their own read-only pool
+        let rest = match rest_listen {
+            Some(addr) => Some((
+                addr,
+                database::Database::open_read_only(&config.database.path)?,
+            )),
+            None => None,
+        };
+        let servers = HttpServers { health, rest, ws };
+        if config.database.dry_run {
+            let db = DryRunStore::new(db)?;
+            let app = app::App::from_config_with_store(config, db, events)?;
+            return run_app(app, servers).await;
+        }
+        let app = app::App::from_config_with_store(config, db, events)?;
+        return run_app(app, servers).await;
+    }
+
+    // clap requires these without --config
+    let bitcoind_host = args.bitcoind_host.clone().expect("bitcoind host");
+    let bitcoind_rpc_port = args.bitcoind_rpc_port.expect("bitcoind rpc port");
+    let zmq_factory = BitcoinZmqFactory::new(
+        bitcoind_host.clone(),
+        args.bitcoind_zmq_port.expect("bitcoind zmq port"),
+    );
+    let _instance = (!args.read_only && !args.dry_run)
+        .then(|| instance::InstanceLock::acquire("mempool-tracker.db", args.pid_file.as_deref()))
+        .transpose()?;
+    let bitcoind_url = format!("http://{}:{}", bitcoind_host, bitcoind_rpc_port);
 
-    // parse u64 to duration
-    // TODO: add some validation
+    // parse u64 to duration, `App::init` validates them
     let mempool_state_check_interval = Duration::from_secs(args.mempool_state_check_interval);
     let prune_check_interval = Duration::from_secs(args.prune_check_interval);
 
-    let rpc_client = Client::new(
-        bitcoind_url,
-        args.bitcoind_user,
-        args.bitcoind_password,
-        None,
-        None,
+    let auth = match args.bitcoind_cookie_file.clone() {
+        Some(path) => config::Auth::CookieFile(path),
+        None => config::Auth::UserPass {
+            user: args.bitcoind_user.clone().expect("bitcoind user"),
+            password: args.bitcoind_password.clone().expect("bitcoind password"),
+        },
+    };
+    let rpc_client = ReconnectingRpc::new(
+        move || rpc::connect_bitcoind(&bitcoind_url, &auth),
+        Duration::from_secs(1),
     )?;
-    let mut app = app::App::new(
-        rpc_client,
-        zmq_factory,
-        db,
-        args.num_workers as usize,
+    // Every field is set when all the sink features are built in
+    #[allow(clippy::needless_update)]
+    let sinks = config::EventsConfig {
+        #[cfg(feature = "nats")]
+        nats_url: args.nats_url.clone(),
+        #[cfg(feature = "kafka")]
+        kafka_brokers: args.kafka_brokers.clone(),
+        #[cfg(feature = "kafka")]
+        kafka_topic: args.kafka_topic.clone(),
+        #[cfg(feature = "grpc")]
+        grpc_listen: args.grpc_listen,
+        ..Default::default()
+    };
+    #[cfg(feature = "http")]
+    let ws_buffer = args.ws_listen.map(|_| args.ws_buffer as usize);
+    #[cfg(not(feature = "http"))]
+    let ws_buffer = None;
+    let events = event_publisher(&sinks, ws_buffer).await?;
+
+    let watched_addresses: Vec<Address> = args
+        .watch_address
+        .iter()
+        .map(|address| address.clone().assume_checked())
+        .collect();
+    let filter = Filter {
+        min_fee_rate: args.min_fee_rate.map(FeeRate::from_sat_per_vb_unchecked),
+        min_value: args.min_value.map(Amount::from_sat),
+        ..Default::default()
+    }
+    .with_watched_addresses(&watched_addresses);
+
+    let config = AppConfig {
+        num_workers: args.num_workers as usize,
         mempool_state_check_interval,
         prune_check_interval,
+        checkpoint_interval: Duration::from_secs(args.checkpoint_interval),
+        stats_log_interval: Duration::from_secs(args.stats_log_interval),
+        filter,
+        min_verification_progress: args.min_verification_progress,
+        backfill: args.backfill,
+        task_channel_capacity: args.task_channel_capacity,
+        tip_stale_after: Duration::from_secs(args.tip_stale_after),
+        rpc_rate_limit: args.rpc_rate_limit,
+        rpc_burst: args.rpc_burst,
+        rpc_retry: rpc::RpcRetryPolicy {
+            timeout: Duration::from_secs(args.rpc_timeout),
+            attempts: args.rpc_attempts,
+            ..Default::default()
+        },
+        zmq_probe_timeout: Duration::from_secs(args.zmq_probe_timeout),
+        watchdog_max_silence: Duration::from_secs(args.watchdog_max_silence),
+        zmq_topics: if args.zmq_topic.is_empty() {
+            AppConfig::default().zmq_topics
+        } else {
+            args.zmq_topic.clone()
+        },
+        zmq_reconnect: app::ZmqReconnectPolicy {
+            max_failures: args.zmq_max_reconnects,
+            ..Default::default()
+        },
+        shutdown_timeout: Duration::from_secs(args.shutdown_timeout),
+        mined_detection: args.mined_detection,
+        startup_checks: args.startup_checks,
+        expect_full_rbf: args.expect_full_rbf,
+        dry_run: args.dry_run,
+        capture: args.capture.clone().map(|path| capture::CaptureConfig {
+            path,
+            max_file_bytes: args.capture_max_file_mb * 1024 * 1024,
+            max_files: args.capture_max_files as usize,
+            fsync: args.capture_fsync,
+        }),
+        replay: args.replay_capture.clone().map(|path| app::ReplaySource {
+            path,
+            realtime: args.replay_realtime,
+        }),
+        ..Default::default()
+    };
+    #[cfg(feature = "http")]
+    let (health, rest_listen, ws) = (
+        args.health_listen
+            .map(|addr| (addr, Duration::from_secs(args.health_zmq_max_silence))),
+        args.rest_listen,
+        args.ws_listen,
     );
-    app.init().await?;
-    app.run().await?;
+    #[cfg(not(feature = "http"))]
+    let (health, rest_listen, ws) = (None, None::<SocketAddr>, None);
+
+    #[cfg(feature = "postgres")]
+    if let Some(url) = &args.postgres_url {
+        let db = if args.read_only || args.dry_run {
+            PgStore::connect_read_only(url).await?
+        } else {
+            PgStore::connect(url).await?
+        }
+        .with_coinbase_maturity(args.coinbase_maturity);
+        let db = match args.dust_threshold {
+            Some(threshold) => db.with_dust_threshold(Amount::from_sat(threshold)),
+            None => db,
+        };
+        let db = match args.large_value_threshold {
+            Some(threshold) => db.with_large_value_threshold(Amount::from_sat(threshold)),
+            None => db,
+        };
+        let db = match args.inputs_hash_tag.clone() {
+            Some(tag) => db.with_inputs_hash_tag(tag),
+            None => db,
+        };
+        let db = match &args.miner_mapping {
+            Some(path) => db.with_miner_registry(MinerRegistry::builtin().with_mapping_file(path)?),
+            None => db,
+        };
+        let rest = match rest_listen {
+            Some(addr) => Some((addr, PgStore::connect_read_only(url).await?)),
+            None => None,
+        };
+        let servers = HttpServers { health, rest, ws };
+        if args.dry_run {
+            let db = DryRunStore::new(db)?;
+            let app = app::App::new(rpc_client, zmq_factory, db, events, config);
+            return run_app(app, servers).await;
+        }
+        let app = app::App::new(rpc_client, zmq_factory, db, events, config);
+        return run_app(app, servers).await;
+    }
 
-    Ok(())
+    let db = if args.read_only || args.dry_run {
+        database::Database::open_read_only("mempool-tracker.db")?
+    } else {
+        database::Database::open("mempool-tracker.db", args.synchronous)?
+    }
+    .with_coinbase_maturity(args.coinbase_maturity);
+    let db = match args.dust_threshold {
+        Some(threshold) => db.with_dust_threshold(Amount::from_sat(threshold)),
+        None => db,
+    };
+    let db = match args.large_value_threshold {
+        Some(threshold) => db.with_large_value_threshold(Amount::from_sat(threshold)),
+        None => db,
+    };
+    let db = match args.inputs_hash_tag.clone() {
+        Some(tag) => db.with_inputs_hash_tag(tag),
+        None => db,
+    };
+    let db = match &args.miner_mapping {
+        Some(path) => db.with_miner_registry(MinerRegistry::builtin().with_mapping_file(path)?),
+        None => db,
+    };
+    let rest = match rest_listen {
+        Some(addr) => Some((
+            addr,
+            database::Database::open_read_only("mempool-tracker.db")?,
+        )),
+        None => None,
+    };
+    let servers = HttpServers { health, rest, ws };
+    if args.dry_run {
+        let db = DryRunStore::new(db)?;
+        let app = app::App::new(rpc_client, zmq_factory, db, events, config);
+        return run_app(app, servers).await;
+    }
+    let app = app::App::new(rpc_client, zmq_factory, db, events, config);
+    run_app(app, servers).await
 }
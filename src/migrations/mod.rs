@@ -2,6 +2,9 @@ use crate::now;
 use anyhow::Result;
 use std::time::SystemTime;
 
+#[cfg(feature = "postgres")]
+pub(crate) mod postgres;
+
 pub(crate) trait Migration {
     fn migrate(&self, conn: &rusqlite::Connection) -> Result<()>;
     fn id(&self) -> &'static str;
@@ -52,6 +55,698 @@ impl Migration for AddTxNotSeenInMempool {
     }
 }
 
+pub(crate) struct AddTxType;
+
+impl Migration for AddTxType {
+    fn id(&self) -> &'static str {
+        "add_tx_type"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        conn.execute("ALTER TABLE transactions ADD COLUMN tx_type TEXT", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddOpReturnStats;
+
+impl Migration for AddOpReturnStats {
+    fn id(&self) -> &'static str {
+        "add_op_return_stats"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Data carrier usage, counts and payload sizes of OP_RETURN outputs
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN op_return_count INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN op_return_bytes INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddNodeSeenAt;
+
+impl Migration for AddNodeSeenAt {
+    fn id(&self) -> &'static str {
+        "add_node_seen_at"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Time the node itself first saw the tx, found_at is our own observation time
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN node_seen_at DATETIME",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddRbfRejectReason;
+
+impl Migration for AddRbfRejectReason {
+    fn id(&self) -> &'static str {
+        "add_rbf_reject_reason"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Why the node rejected the original when the replacement was verified
+        conn.execute("ALTER TABLE rbf_history ADD COLUMN reject_reason TEXT", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddMinedBlock;
+
+impl Migration for AddMinedBlock {
+    fn id(&self) -> &'static str {
+        "add_mined_block"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Confirming block, set by backfill
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN block_height INTEGER",
+            [],
+        )?;
+        conn.execute("ALTER TABLE transactions ADD COLUMN block_hash BLOB", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddVsize;
+
+impl Migration for AddVsize {
+    fn id(&self) -> &'static str {
+        "add_vsize"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Virtual size, so fee rates can be computed exactly instead of from the rounded fee_rate
+        conn.execute("ALTER TABLE transactions ADD COLUMN vsize INTEGER", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddTxSeq;
+
+impl Migration for AddTxSeq {
+    fn id(&self) -> &'static str {
+        "add_tx_seq"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Monotonic insertion order for cursor pagination, existing rows keep their rowid order
+        conn.execute("ALTER TABLE transactions ADD COLUMN seq INTEGER", [])?;
+        conn.execute("UPDATE transactions SET seq = rowid", [])?;
+        conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_transactions_seq ON transactions(seq)",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddEffectiveFeeRate;
+
+impl Migration for AddEffectiveFeeRate {
+    fn id(&self) -> &'static str {
+        "add_effective_fee_rate"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Ancestor-inclusive fee rate in sat/vB. ancestors_known is false when an unconfirmed
+        // ancestor's fee or size is unknown, effective_fee_rate then holds the naive rate
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN effective_fee_rate REAL",
+            [],
+        )?;
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN ancestors_known BOOLEAN NOT NULL DEFAULT TRUE",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct ReplaceChildTxidWithTxParents;
+
+impl Migration for ReplaceChildTxidWithTxParents {
+    fn id(&self) -> &'static str {
+        "replace_child_txid_with_tx_parents"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // child_txid held a single child per parent, edges now live in tx_parents
+        conn.execute(
+            "INSERT OR IGNORE INTO tx_parents (child_inputs_hash, parent_txid)
+            SELECT child.inputs_hash, parent.tx_id FROM transactions parent
+            JOIN transactions child ON child.tx_id = parent.child_txid",
+            [],
+        )?;
+        conn.execute("ALTER TABLE transactions DROP COLUMN child_txid", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddMempoolFeeLimits;
+
+impl Migration for AddMempoolFeeLimits {
+    fn id(&self) -> &'static str {
+        "add_mempool_fee_limits"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Node fee floors (sat/kvB) and memory limits at each snapshot, NULL for older rows
+        conn.execute("ALTER TABLE mempool ADD COLUMN mempool_min_fee INTEGER", [])?;
+        conn.execute(
+            "ALTER TABLE mempool ADD COLUMN min_relay_tx_fee INTEGER",
+            [],
+        )?;
+        conn.execute("ALTER TABLE mempool ADD COLUMN max_mempool INTEGER", [])?;
+        conn.execute("ALTER TABLE mempool ADD COLUMN usage INTEGER", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddRemovalReason;
+
+impl Migration for AddRemovalReason {
+    fn id(&self) -> &'static str {
+        "add_removal_reason"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Why a tx left the mempool when we know more than that it did, e.g. purged
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN removal_reason TEXT",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddDustOutputCount;
+
+impl Migration for AddDustOutputCount {
+    fn id(&self) -> &'static str {
+        "add_dust_output_count"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Outputs below the dust limit, 0 for rows stored before it was counted
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN dust_output_count INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddOutOfBand;
+
+impl Migration for AddOutOfBand {
+    fn id(&self) -> &'static str {
+        "add_out_of_band"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Mined without ever being seen in our mempool, e.g. submitted straight to a miner
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN out_of_band BOOLEAN NOT NULL DEFAULT FALSE",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddTruc;
+
+impl Migration for AddTruc {
+    fn id(&self) -> &'static str {
+        "add_truc"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Topologically restricted (BIP431) txs, the topology check is only recorded for pending v3 txs
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN is_truc BOOLEAN NOT NULL DEFAULT FALSE",
+            [],
+        )?;
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN truc_topology_ok BOOLEAN",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddSighashMask;
+
+impl Migration for AddSighashMask {
+    fn id(&self) -> &'static str {
+        "add_sighash_mask"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Sighash types used across the inputs, one bit per type (see SighashKind::bit)
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN sighash_mask INTEGER",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddMiner;
+
+impl Migration for AddMiner {
+    fn id(&self) -> &'static str {
+        "add_miner"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Miner resolved from the coinbase, the raw tag is kept for coinbases no mapping matched
+        conn.execute("ALTER TABLE transactions ADD COLUMN miner TEXT", [])?;
+        conn.execute("ALTER TABLE transactions ADD COLUMN coinbase_tag TEXT", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddFeeKnown;
+
+impl Migration for AddFeeKnown {
+    fn id(&self) -> &'static str {
+        "add_fee_known"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Replacements that left the mempool before their fee could be looked up are stored with
+        // fee_known = 0 until a later lookup fills the fee in
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN fee_known INTEGER NOT NULL DEFAULT 1",
+            [],
+        )?;
+        conn.execute(
+            "ALTER TABLE rbf_history ADD COLUMN fee_known INTEGER NOT NULL DEFAULT 1",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddTxParentOutputs;
+
+impl Migration for AddTxParentOutputs {
+    fn id(&self) -> &'static str {
+        "add_tx_parent_outputs"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Edges record which output of the parent the child spends, so one child spending
+        // several outputs of a parent keeps a row per output. Edges from before are left
+        // with an unknown vout
+        conn.execute(
+            "CREATE TABLE tx_parents_new (
+                child_inputs_hash TEXT NOT NULL,
+                parent_txid TEXT NOT NULL,
+                vout INTEGER,
+                linked_at DATETIME,
+                PRIMARY KEY (child_inputs_hash, parent_txid, vout)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "INSERT INTO tx_parents_new (child_inputs_hash, parent_txid)
+            SELECT child_inputs_hash, parent_txid FROM tx_parents",
+            [],
+        )?;
+        conn.execute("DROP TABLE tx_parents", [])?;
+        conn.execute("ALTER TABLE tx_parents_new RENAME TO tx_parents", [])?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_tx_parents_parent_txid ON tx_parents(parent_txid)",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddBip125Violations;
+
+impl Migration for AddBip125Violations {
+    fn id(&self) -> &'static str {
+        "add_bip125_violations"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Comma separated BIP125 rules a replacement broke, empty when it followed them all
+        // and NULL when it couldn't be checked
+        conn.execute(
+            "ALTER TABLE rbf_history ADD COLUMN bip125_violations TEXT",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddTxidHistoryTxData;
+
+impl Migration for AddTxidHistoryTxData {
+    fn id(&self) -> &'static str {
+        "add_txid_history_tx_data"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Full tx of every variant that occupied a slot, the slot row only keeps the latest.
+        // The current occupants are backfilled, earlier variants were already overwritten
+        conn.execute("ALTER TABLE txid_history ADD COLUMN tx_data TEXT", [])?;
+        conn.execute(
+            "UPDATE txid_history SET tx_data = (
+                SELECT tx_data FROM transactions
+                WHERE transactions.inputs_hash = txid_history.inputs_hash AND transactions.tx_id = txid_history.tx_id
+            )
+            WHERE tx_data IS NULL",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddInputValueTotal;
+
+impl Migration for AddInputValueTotal {
+    fn id(&self) -> &'static str {
+        "add_input_value_total"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Sum of the spent outputs' values, NULL unless every parent is tracked
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN input_value_total INTEGER",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddCoinbaseValue;
+
+impl Migration for AddCoinbaseValue {
+    fn id(&self) -> &'static str {
+        "add_coinbase_value"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Output total of coinbases, the block's subsidy plus its fees
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN coinbase_value INTEGER",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddRbfInitialObservation;
+
+impl Migration for AddRbfInitialObservation {
+    fn id(&self) -> &'static str {
+        "add_rbf_initial_observation"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Replacements without a predecessor fee to measure a bump against
+        // Rows from before the flag are backfilled from their baseline
+        conn.execute(
+            "ALTER TABLE rbf_history ADD COLUMN is_initial_observation INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+        conn.execute(
+            "UPDATE rbf_history SET is_initial_observation = 1 WHERE prev_fee IS NULL OR prev_fee = 0",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddMempoolChurn;
+
+impl Migration for AddMempoolChurn {
+    fn id(&self) -> &'static str {
+        "add_mempool_churn"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Churn between two mempool polls, NULL on the first poll after a restart
+        conn.execute(
+            "ALTER TABLE mempool ADD COLUMN added_since_last INTEGER",
+            [],
+        )?;
+        conn.execute(
+            "ALTER TABLE mempool ADD COLUMN removed_since_last INTEGER",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddCoinbaseMaturesAt;
+
+impl Migration for AddCoinbaseMaturesAt {
+    fn id(&self) -> &'static str {
+        "add_coinbase_matures_at"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // First height whose block may spend a coinbase's outputs. Existing coinbases get mainnet's maturity,
+        // a different --coinbase-maturity applies to the ones recorded or replayed afterwards
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN matures_at_height INTEGER",
+            [],
+        )?;
+        conn.execute(
+            "UPDATE transactions SET matures_at_height = block_height + 100 WHERE version = 0 AND block_height IS NOT NULL",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_transactions_matures_at_height ON transactions(matures_at_height)",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct StoreTxTimesInMillis;
+
+impl Migration for StoreTxTimesInMillis {
+    fn id(&self) -> &'static str {
+        "store_tx_times_in_millis"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Lifecycle times and sightings go from unix seconds to milliseconds, many txs arrive
+        // within the same second. Only the per-tx times move: rbf_history, spent_outpoints,
+        // conflicts, snapshots, blocks, backfill_coverage and checkpoints stay in seconds,
+        // they're never compared at sub-second resolution. In one transaction, a rerun would
+        // scale them twice
+        let db_tx = conn.unchecked_transaction()?;
+        db_tx.execute(
+            "UPDATE transactions SET found_at = found_at * 1000, node_seen_at = node_seen_at * 1000,
+                mined_at = mined_at * 1000, pruned_at = pruned_at * 1000",
+            [],
+        )?;
+        db_tx.execute(
+            "UPDATE tx_sightings SET first_seen_at = first_seen_at * 1000",
+            [],
+        )?;
+        db_tx.execute("UPDATE txid_history SET seen_at = seen_at * 1000", [])?;
+
+        let applied_at = now!().to_string();
+        db_tx.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        db_tx.commit()?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddLargeValueFlag;
+
+impl Migration for AddLargeValueFlag {
+    fn id(&self) -> &'static str {
+        "add_large_value_flag"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Txs paying out more than the large value threshold when they were inserted
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN is_large_value BOOLEAN NOT NULL DEFAULT FALSE",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
 fn already_applied(conn: &rusqlite::Connection, migration: &str) -> Result<bool> {
     let mut stmt = conn.prepare("SELECT COUNT(*) FROM migrations WHERE id = ?")?;
     let count: i32 = stmt.query_row([migration], |row| row.get(0))?;
@@ -62,6 +757,33 @@ pub(crate) fn run_migrations(conn: &rusqlite::Connection) -> Result<()> {
     let migrations: Vec<Box<dyn Migration>> = vec![
         Box::new(UpdateChildTxidColName),
         Box::new(AddTxNotSeenInMempool),
+        Box::new(AddTxType),
+        Box::new(AddOpReturnStats),
+        Box::new(AddNodeSeenAt),
+        Box::new(AddRbfRejectReason),
+        Box::new(AddMinedBlock),
+        Box::new(AddVsize),
+        Box::new(AddTxSeq),
+        Box::new(AddEffectiveFeeRate),
+        Box::new(ReplaceChildTxidWithTxParents),
+        Box::new(AddMempoolFeeLimits),
+        Box::new(AddRemovalReason),
+        Box::new(AddDustOutputCount),
+        Box::new(AddOutOfBand),
+        Box::new(AddTruc),
+        Box::new(AddSighashMask),
+        Box::new(AddMiner),
+        Box::new(AddFeeKnown),
+        Box::new(AddTxParentOutputs),
+        Box::new(AddBip125Violations),
+        Box::new(AddTxidHistoryTxData),
+        Box::new(AddInputValueTotal),
+        Box::new(AddCoinbaseValue),
+        Box::new(AddRbfInitialObservation),
+        Box::new(AddMempoolChurn),
+        Box::new(AddCoinbaseMaturesAt),
+        Box::new(StoreTxTimesInMillis),
+        Box::new(AddLargeValueFlag),
     ];
     for migration in migrations {
         if already_applied(conn, migration.id())? {
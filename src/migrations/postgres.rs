@@ -0,0 +1,251 @@
+use anyhow::Result;
+use std::time::SystemTime;
+
+use crate::now;
+
+/// Schema changes of the Postgres store, applied in order. The first entry already has
+/// the columns the sqlite migrations added one by one, later changes go at the end.
+/// Units match the sqlite schema: the tx lifecycle, tx_sightings and txid_history in unix
+/// milliseconds, every other time in seconds. Row ids stand in for sqlite's rowid, `seq`
+/// is drawn from a sequence instead of `MAX(seq) + 1`
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "create_tables",
+        "CREATE TABLE transactions (
+        inputs_hash TEXT PRIMARY KEY,
+        tx_id TEXT NOT NULL,
+        tx_data TEXT NOT NULL,
+        found_at BIGINT NOT NULL,
+        node_seen_at BIGINT,
+        mined_at BIGINT,
+        pruned_at BIGINT,
+        block_hash BYTEA,
+        seen_in_mempool BOOLEAN NOT NULL DEFAULT TRUE,
+        absolute_fee BIGINT NOT NULL,
+        fee_rate BIGINT NOT NULL,
+        fee_known BOOLEAN NOT NULL DEFAULT TRUE,
+        vsize BIGINT,
+        tx_type TEXT,
+        version INTEGER NOT NULL
+    );
+    CREATE INDEX idx_transactions_tx_id ON transactions(tx_id);
+    CREATE INDEX idx_transactions_found_at ON transactions(found_at);
+    CREATE INDEX idx_transactions_mined_at ON transactions(mined_at);
+    CREATE INDEX idx_transactions_pruned_at ON transactions(pruned_at);
+
+    CREATE TABLE rbf (
+        inputs_hash TEXT PRIMARY KEY,
+        created_at BIGINT NOT NULL,
+        fee_total BIGINT NOT NULL,
+        version INTEGER NOT NULL
+    );
+    CREATE INDEX idx_rbf_created_at ON rbf(created_at);
+
+    CREATE TABLE rbf_history (
+        id BIGSERIAL PRIMARY KEY,
+        inputs_hash TEXT NOT NULL,
+        tx_id TEXT NOT NULL,
+        created_at BIGINT NOT NULL,
+        fee_total BIGINT NOT NULL,
+        fee_known BOOLEAN NOT NULL DEFAULT TRUE,
+        prev_fee BIGINT,
+        fee_delta BIGINT,
+        fee_rate_delta BIGINT,
+        fee_bump_pct DOUBLE PRECISION,
+        reject_reason TEXT,
+        is_initial_observation BOOLEAN NOT NULL DEFAULT FALSE,
+        version INTEGER NOT NULL
+    );
+    CREATE INDEX idx_rbf_history_inputs_hash ON rbf_history(inputs_hash);
+    CREATE INDEX idx_rbf_history_created_at ON rbf_history(created_at);
+
+    CREATE TABLE txid_history (
+        inputs_hash TEXT NOT NULL,
+        tx_id TEXT NOT NULL,
+        seen_at BIGINT NOT NULL,
+        tx_data TEXT,
+        PRIMARY KEY (inputs_hash, tx_id)
+    );
+
+    CREATE TABLE spent_outpoints (
+        outpoint TEXT PRIMARY KEY,
+        txid TEXT NOT NULL,
+        inputs_hash TEXT NOT NULL,
+        created_at BIGINT NOT NULL
+    );
+    CREATE INDEX idx_spent_outpoints_txid ON spent_outpoints(txid);",
+    ),
+    (
+        "store_tx_times_in_millis",
+        "UPDATE transactions SET found_at = found_at * 1000, node_seen_at = node_seen_at * 1000,
+            mined_at = mined_at * 1000, pruned_at = pruned_at * 1000;
+        UPDATE txid_history SET seen_at = seen_at * 1000;",
+    ),
+    (
+        "add_classification_and_analytics",
+        "ALTER TABLE transactions
+        ADD COLUMN effective_fee_rate DOUBLE PRECISION,
+        ADD COLUMN ancestors_known BOOLEAN NOT NULL DEFAULT TRUE,
+        ADD COLUMN out_of_band BOOLEAN NOT NULL DEFAULT FALSE,
+        ADD COLUMN op_return_count BIGINT NOT NULL DEFAULT 0,
+        ADD COLUMN op_return_bytes BIGINT NOT NULL DEFAULT 0,
+        ADD COLUMN dust_output_count BIGINT NOT NULL DEFAULT 0,
+        ADD COLUMN is_truc BOOLEAN NOT NULL DEFAULT FALSE,
+        ADD COLUMN truc_topology_ok BOOLEAN,
+        ADD COLUMN sighash_mask BIGINT,
+        ADD COLUMN block_height BIGINT,
+        ADD COLUMN seq BIGINT,
+        ADD COLUMN removal_reason TEXT,
+        ADD COLUMN miner TEXT,
+        ADD COLUMN coinbase_tag TEXT,
+        ADD COLUMN input_value_total BIGINT,
+        ADD COLUMN coinbase_value BIGINT,
+        ADD COLUMN matures_at_height BIGINT,
+        ADD COLUMN is_large_value BOOLEAN NOT NULL DEFAULT FALSE;
+    CREATE SEQUENCE transactions_seq OWNED BY transactions.seq;
+    UPDATE transactions SET seq = nextval('transactions_seq');
+    ALTER TABLE transactions ALTER COLUMN seq SET DEFAULT nextval('transactions_seq');
+    CREATE UNIQUE INDEX idx_transactions_seq ON transactions(seq);
+    CREATE INDEX idx_transactions_matures_at_height ON transactions(matures_at_height);
+
+    ALTER TABLE rbf_history ADD COLUMN bip125_violations TEXT;
+    ALTER TABLE txid_history ADD COLUMN id BIGSERIAL;
+
+    CREATE TABLE mempool (
+        id BIGSERIAL PRIMARY KEY,
+        created_at BIGINT NOT NULL,
+        size BIGINT NOT NULL,
+        tx_count BIGINT NOT NULL,
+        block_height BIGINT NOT NULL,
+        block_hash TEXT NOT NULL,
+        mempool_min_fee BIGINT,
+        min_relay_tx_fee BIGINT,
+        max_mempool BIGINT,
+        usage BIGINT,
+        added_since_last BIGINT,
+        removed_since_last BIGINT,
+        version INTEGER NOT NULL
+    );
+    CREATE INDEX idx_mempool_created_at ON mempool(created_at);
+
+    CREATE TABLE tx_links (
+        txid TEXT NOT NULL,
+        related_txid TEXT NOT NULL,
+        direction TEXT NOT NULL,
+        observed_at BIGINT NOT NULL,
+        PRIMARY KEY (txid, related_txid, direction)
+    );
+    CREATE INDEX idx_tx_links_related_txid ON tx_links(related_txid);
+
+    CREATE TABLE conflicts (
+        id BIGSERIAL PRIMARY KEY,
+        txid TEXT NOT NULL,
+        conflicting_txid TEXT NOT NULL,
+        outpoints TEXT NOT NULL,
+        detected_at BIGINT NOT NULL,
+        winner_txid TEXT,
+        resolved_at BIGINT
+    );
+
+    CREATE TABLE backfill_coverage (
+        block_height BIGINT PRIMARY KEY,
+        block_hash TEXT NOT NULL,
+        tx_count BIGINT NOT NULL,
+        completed_at BIGINT NOT NULL
+    );
+
+    CREATE TABLE tx_parents (
+        child_inputs_hash TEXT NOT NULL,
+        parent_txid TEXT NOT NULL,
+        vout BIGINT NOT NULL,
+        linked_at BIGINT,
+        PRIMARY KEY (child_inputs_hash, parent_txid, vout)
+    );
+    CREATE INDEX idx_tx_parents_parent_txid ON tx_parents(parent_txid);
+
+    CREATE TABLE tx_sightings (
+        txid TEXT NOT NULL,
+        node_id TEXT NOT NULL,
+        first_seen_at BIGINT NOT NULL,
+        PRIMARY KEY (txid, node_id)
+    );
+    CREATE INDEX idx_tx_sightings_first_seen_at ON tx_sightings(first_seen_at);
+
+    CREATE TABLE fee_histogram (
+        snapshot_at BIGINT NOT NULL,
+        band_min_satvb BIGINT NOT NULL,
+        vbytes BIGINT NOT NULL,
+        tx_count BIGINT NOT NULL,
+        PRIMARY KEY (snapshot_at, band_min_satvb)
+    );
+
+    CREATE TABLE purge_events (
+        id BIGSERIAL PRIMARY KEY,
+        detected_at BIGINT NOT NULL,
+        removed_count BIGINT NOT NULL,
+        removed_vbytes BIGINT NOT NULL,
+        mempoolminfee_before BIGINT,
+        mempoolminfee_after BIGINT
+    );
+
+    CREATE TABLE blocks (
+        block_hash BYTEA PRIMARY KEY,
+        block_height BIGINT,
+        block_time BIGINT NOT NULL,
+        vsize BIGINT NOT NULL,
+        tx_count BIGINT NOT NULL,
+        recorded_at BIGINT NOT NULL
+    );
+
+    CREATE TABLE checkpoints (
+        id BIGSERIAL PRIMARY KEY,
+        checkpoint_at BIGINT NOT NULL,
+        best_block_hash TEXT NOT NULL,
+        pending_count BIGINT NOT NULL,
+        last_seq BIGINT NOT NULL
+    );
+
+    CREATE TABLE quarantine (
+        id BIGSERIAL PRIMARY KEY,
+        payload BYTEA NOT NULL,
+        received_at BIGINT NOT NULL,
+        error TEXT NOT NULL
+    );",
+    ),
+];
+
+/// Taken while migrating so instances starting together don't race on the schema
+const MIGRATION_LOCK_ID: i64 = 0x6d656d706f6f6c;
+
+pub(crate) async fn run_migrations(client: &mut deadpool_postgres::Client) -> Result<()> {
+    let db_tx = client.transaction().await?;
+    db_tx
+        .execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK_ID])
+        .await?;
+    db_tx
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS migrations (
+                id TEXT PRIMARY KEY,
+                applied_at BIGINT NOT NULL
+            )",
+        )
+        .await?;
+    for (id, sql) in MIGRATIONS {
+        let applied: i64 = db_tx
+            .query_one("SELECT COUNT(*) FROM migrations WHERE id = $1", &[id])
+            .await?
+            .get(0);
+        if applied > 0 {
+            continue;
+        }
+        db_tx.batch_execute(sql).await?;
+        db_tx
+            .execute(
+                "INSERT INTO migrations (id, applied_at) VALUES ($1, $2)",
+                &[id, &(now!() as i64)],
+            )
+            .await?;
+    }
+    db_tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use std::{collections::HashMap, fs, path::Path, str::FromStr};
+
+use anyhow::{Context, Result};
+use bitcoin::{address::NetworkUnchecked, Address, ScriptBuf, Transaction};
+use serde::Deserialize;
+
+/// Coinbase tags of well-known pools, matched case-insensitively as substrings
+const BUILTIN_TAGS: &[(&str, &str)] = &[
+    ("Foundry USA", "Foundry USA"),
+    ("AntPool", "AntPool"),
+    ("ViaBTC", "ViaBTC"),
+    ("F2Pool", "F2Pool"),
+    ("SpiderPool", "SpiderPool"),
+    ("MARA Pool", "MARA Pool"),
+    ("Binance", "Binance Pool"),
+    ("slush", "Braiins Pool"),
+    ("poolin", "Poolin"),
+    ("BTC.COM", "BTC.com"),
+    ("Luxor", "Luxor"),
+    ("ultimus", "ULTIMUSPOOL"),
+    ("SBICrypto", "SBI Crypto"),
+    ("SecPool", "SECPOOL"),
+    ("OCEAN", "OCEAN"),
+];
+
+/// Shape of a user supplied mapping file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct MappingFile {
+    /// Coinbase tag substring to miner label
+    tags: HashMap<String, String>,
+    /// Payout address to miner label
+    addresses: HashMap<String, String>,
+}
+
+/// Who mined a block, as far as its coinbase tells
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinerId {
+    /// `None` when no tag or payout address is known, `tag` is kept to improve the mapping
+    pub label: Option<String>,
+    pub tag: String,
+}
+
+/// Resolves miner labels from coinbase tags and payout addresses
+#[derive(Debug, Clone)]
+pub struct MinerRegistry {
+    /// Lowercased tag substring and label, user entries first
+    tags: Vec<(String, String)>,
+    addresses: HashMap<ScriptBuf, String>,
+}
+
+impl Default for MinerRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl MinerRegistry {
+    pub fn builtin() -> Self {
+        Self {
+            tags: BUILTIN_TAGS
+                .iter()
+                .map(|(tag, label)| (tag.to_lowercase(), label.to_string()))
+                .collect(),
+            addresses: HashMap::new(),
+        }
+    }
+
+    /// Extend with a JSON file of `{"tags": {tag: label}, "addresses": {address: label}}`.
+    /// Its entries take precedence over the built-in ones
+    pub fn with_mapping_file(mut self, path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Reading miner mapping {}", path.display()))?;
+        let mapping: MappingFile = serde_json::from_str(&contents)
+            .with_context(|| format!("Parsing miner mapping {}", path.display()))?;
+        let mut user_tags: Vec<(String, String)> = mapping
+            .tags
+            .into_iter()
+            .map(|(tag, label)| (tag.to_lowercase(), label))
+            .collect();
+        // Longest tags first so a specific tag wins over a prefix of it
+        user_tags.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+        user_tags.append(&mut self.tags);
+        self.tags = user_tags;
+        for (address, label) in mapping.addresses {
+            let address = Address::<NetworkUnchecked>::from_str(&address)
+                .with_context(|| format!("Invalid payout address {} in miner mapping", address))?
+                .assume_checked();
+            self.addresses.insert(address.script_pubkey(), label);
+        }
+        Ok(self)
+    }
+
+    /// Payout addresses are checked before tags, a pool can't fake paying someone else
+    pub fn identify(&self, coinbase: &Transaction) -> MinerId {
+        let tag = coinbase_tag(coinbase);
+        let by_address = coinbase
+            .output
+            .iter()
+            .find_map(|output| self.addresses.get(&output.script_pubkey));
+        let label = by_address.cloned().or_else(|| {
+            let lowercase = tag.to_lowercase();
+            self.tags
+                .iter()
+                .find(|(pattern, _)| lowercase.contains(pattern.as_str()))
+                .map(|(_, label)| label.clone())
+        });
+        MinerId { label, tag }
+    }
+}
+
+/// Printable ASCII of the coinbase scriptSig after the BIP34 height push, runs of other
+/// bytes collapse to a single space
+pub fn coinbase_tag(coinbase: &Transaction) -> String {
+    let Some(input) = coinbase.input.first() else {
+        return String::new();
+    };
+    let bytes = input.script_sig.as_bytes();
+    // The height is a direct push, or a small-int opcode on young chains
+    let skip = match bytes {
+        [len @ 0x01..=0x4b, ..] => 1 + *len as usize,
+        [0x4c, len, ..] => 2 + *len as usize,
+        [0x00 | 0x4f | 0x51..=0x60, ..] => 1,
+        _ => 0,
+    }
+    .min(bytes.len());
+    let mut tag = String::new();
+    for byte in &bytes[skip..] {
+        if (0x20..0x7f).contains(byte) {
+            tag.push(*byte as char);
+        } else if !tag.ends_with(' ') {
+            tag.push(' ');
+        }
+    }
+    tag.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,90 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+#[derive(Debug)]
+struct Bucket {
+    /// Goes negative while callers are queued for tokens not refilled yet
+    tokens: f64,
+    capacity: f64,
+    per_second: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    /// Take a token, returns how long the caller has to wait for it
+    fn reserve(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.refilled_at = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.per_second)
+        }
+    }
+}
+
+/// Token bucket shared by every worker so the node keeps capacity for its other clients.
+/// Cloning shares the bucket
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Option<Arc<Mutex<Bucket>>>,
+    /// Total time callers spent waiting for a token, in microseconds
+    waited_micros: Arc<AtomicU64>,
+}
+
+impl RateLimiter {
+    /// `per_second` tokens are refilled every second up to `burst`. Zero means unlimited
+    pub fn new(per_second: u32, burst: u32) -> Self {
+        let bucket = (per_second > 0).then(|| {
+            let capacity = burst.max(1) as f64;
+            Arc::new(Mutex::new(Bucket {
+                tokens: capacity,
+                capacity,
+                per_second: per_second as f64,
+                refilled_at: Instant::now(),
+            }))
+        });
+        Self {
+            bucket,
+            waited_micros: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(0, 0)
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.bucket.is_some()
+    }
+
+    /// Wait until a call may go out
+    pub async fn acquire(&self) {
+        let Some(bucket) = &self.bucket else {
+            return;
+        };
+        let wait = bucket.lock().expect("rate limiter lock poisoned").reserve();
+        if wait.is_zero() {
+            return;
+        }
+        self.waited_micros
+            .fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+        tokio::time::sleep(wait).await;
+    }
+
+    /// Total time spent waiting on the limiter. Growing alongside the queue depth means
+    /// the limit is what holds the workers back
+    pub fn waited(&self) -> Duration {
+        Duration::from_micros(self.waited_micros.load(Ordering::Relaxed))
+    }
+}
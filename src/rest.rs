@@ -0,0 +1,340 @@
+use std::{future::Future, str::FromStr, time::SystemTime};
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use bitcoin::{FeeRate, Txid};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+
+use crate::{
+    database::{MempoolSnapshot, RbfRecord, TransactionInner, FEE_HISTOGRAM_BANDS},
+    now,
+    store::MempoolStore,
+    utils::{rfc3339, rfc3339_millis},
+};
+
+/// Page size of the list endpoints when the request sets none, and the largest served
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1_000;
+
+/// Window of `/stats/confirmation-latency` when the request sets none, in seconds
+const DEFAULT_LATENCY_WINDOW: u64 = 86_400;
+
+type ApiError = (StatusCode, String);
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+fn internal_error(e: anyhow::Error) -> ApiError {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+fn parse_txid(txid: &str) -> Result<Txid, ApiError> {
+    Txid::from_str(txid).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("invalid txid {}: {}", txid, e),
+        )
+    })
+}
+
+fn sat_per_vb(fee_rate: FeeRate) -> f64 {
+    fee_rate.to_sat_per_kwu() as f64 / 250.0
+}
+
+/// A page of a list endpoint. `next_offset` is set while more items may follow
+#[derive(Debug, Serialize)]
+struct Page<T> {
+    items: Vec<T>,
+    limit: usize,
+    offset: usize,
+    next_offset: Option<usize>,
+}
+
+impl<T> Page<T> {
+    /// Clamped limit and the offset of a request
+    fn bounds(limit: Option<usize>, offset: Option<usize>) -> (usize, usize) {
+        (
+            limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+            offset.unwrap_or(0),
+        )
+    }
+
+    fn new(items: Vec<T>, limit: usize, offset: usize) -> Self {
+        let next_offset = (items.len() == limit).then_some(offset + limit);
+        Self {
+            items,
+            limit,
+            offset,
+            next_offset,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct TxResponse {
+    txid: String,
+    inputs_hash: String,
+    /// pending, mined or pruned
+    status: &'static str,
+    found_at: String,
+    node_seen_at: Option<String>,
+    mined_at: Option<String>,
+    pruned_at: Option<String>,
+    block_height: Option<u64>,
+    /// Sats, `None` while the fee is being looked up
+    fee: Option<u64>,
+    /// sat/vB, `None` while the fee is being looked up
+    fee_rate: Option<u64>,
+}
+
+impl From<TransactionInner> for TxResponse {
+    fn from(record: TransactionInner) -> Self {
+        let status = match (record.mined_at, record.pruned_at) {
+            (Some(_), _) => "mined",
+            (None, Some(_)) => "pruned",
+            (None, None) => "pending",
+        };
+        Self {
+            txid: record.txid.to_string(),
+            inputs_hash: record.inputs_hash,
+            status,
+            found_at: rfc3339_millis(record.found_at),
+            node_seen_at: record.node_seen_at.map(rfc3339_millis),
+            mined_at: record.mined_at.map(rfc3339_millis),
+            pruned_at: record.pruned_at.map(rfc3339_millis),
+            block_height: record.block_height,
+            fee: record.fee_known.then(|| record.absolute_fee.to_sat()),
+            fee_rate: record
+                .fee_known
+                .then(|| record.fee_rate.to_sat_per_vb_floor()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct MempoolStateResponse {
+    created_at: String,
+    tx_count: u64,
+    bytes: u64,
+    block_height: u64,
+    block_hash: String,
+    /// sat/vB
+    mempool_min_fee: Option<f64>,
+    min_relay_tx_fee: Option<f64>,
+    max_mempool: Option<u64>,
+    usage: Option<u64>,
+    /// Txs that entered and left since the previous snapshot
+    added_since_last: Option<u64>,
+    removed_since_last: Option<u64>,
+}
+
+impl From<MempoolSnapshot> for MempoolStateResponse {
+    fn from(snapshot: MempoolSnapshot) -> Self {
+        Self {
+            created_at: rfc3339(snapshot.created_at),
+            tx_count: snapshot.tx_count,
+            bytes: snapshot.bytes,
+            block_height: snapshot.block_height,
+            block_hash: snapshot.block_hash.to_string(),
+            mempool_min_fee: snapshot.mempool_min_fee.map(sat_per_vb),
+            min_relay_tx_fee: snapshot.min_relay_tx_fee.map(sat_per_vb),
+            max_mempool: snapshot.max_mempool,
+            usage: snapshot.usage,
+            added_since_last: snapshot.churn.map(|churn| churn.added_since_last),
+            removed_since_last: snapshot.churn.map(|churn| churn.removed_since_last),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RbfResponse {
+    txid: String,
+    created_at: String,
+    fee: Option<u64>,
+    prev_fee: Option<u64>,
+    fee_bump_pct: Option<f64>,
+    reject_reason: Option<String>,
+    is_initial_observation: bool,
+}
+
+impl From<RbfRecord> for RbfResponse {
+    fn from(record: RbfRecord) -> Self {
+        Self {
+            txid: record.txid.to_string(),
+            created_at: rfc3339(record.created_at),
+            fee: record.fee.map(|fee| fee.to_sat()),
+            prev_fee: record.prev_fee.map(|fee| fee.to_sat()),
+            fee_bump_pct: record.fee_bump_pct,
+            reject_reason: record.reject_reason,
+            is_initial_observation: record.is_initial_observation,
+        }
+    }
+}
+
+/// Dwell time from the node's acceptance to inclusion, in seconds
+#[derive(Debug, Serialize)]
+struct LatencyResponse {
+    from: String,
+    to: String,
+    count: u64,
+    min: Option<u64>,
+    max: Option<u64>,
+    mean: Option<f64>,
+    median: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PendingParams {
+    /// Seconds since the tx was found, 0 lists every pending tx
+    min_age: Option<u64>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LatencyParams {
+    /// Seconds back from now
+    window: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MempoolStateParams {
+    /// Unix seconds, `[from, to)`
+    from: Option<u64>,
+    to: Option<u64>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+async fn get_tx<S: MempoolStore>(
+    State(db): State<S>,
+    Path(txid): Path<String>,
+) -> ApiResult<TxResponse> {
+    let txid = parse_txid(&txid)?;
+    match db.get_tx_record(&txid).await.map_err(internal_error)? {
+        Some(record) => Ok(Json(record.into())),
+        None => Err((StatusCode::NOT_FOUND, format!("{} is not tracked", txid))),
+    }
+}
+
+async fn get_pending<S: MempoolStore>(
+    State(db): State<S>,
+    Query(params): Query<PendingParams>,
+) -> ApiResult<Page<TxResponse>> {
+    let (limit, offset) = Page::<TxResponse>::bounds(params.limit, params.offset);
+    let found_before = now!().saturating_sub(params.min_age.unwrap_or(0));
+    let records = db
+        .pending_tx_records(found_before, limit, offset)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(Page::new(
+        records.into_iter().map(TxResponse::from).collect(),
+        limit,
+        offset,
+    )))
+}
+
+async fn get_confirmation_latency<S: MempoolStore>(
+    State(db): State<S>,
+    Query(params): Query<LatencyParams>,
+) -> ApiResult<LatencyResponse> {
+    let end = now!() + 1;
+    let start = end.saturating_sub(params.window.unwrap_or(DEFAULT_LATENCY_WINDOW));
+    // A single bucket from 0 sat/vB holds every mined tx
+    let (_, stats) = db
+        .latency_by_fee_bucket(start, end, &[0.0])
+        .await
+        .map_err(internal_error)?
+        .pop()
+        .expect("stats for every bucket");
+    Ok(Json(LatencyResponse {
+        from: rfc3339(start),
+        to: rfc3339(end),
+        count: stats.count,
+        min: stats.min,
+        max: stats.max,
+        mean: stats.mean,
+        median: stats.median,
+    }))
+}
+
+async fn get_mempool_states<S: MempoolStore>(
+    State(db): State<S>,
+    Query(params): Query<MempoolStateParams>,
+) -> ApiResult<Page<MempoolStateResponse>> {
+    let (limit, offset) = Page::<MempoolStateResponse>::bounds(params.limit, params.offset);
+    let start = params.from.unwrap_or(0);
+    let end = params.to.unwrap_or_else(|| now!() + 1);
+    let snapshots = db
+        .mempool_snapshots(start, end, limit, offset)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(Page::new(
+        snapshots
+            .into_iter()
+            .map(MempoolStateResponse::from)
+            .collect(),
+        limit,
+        offset,
+    )))
+}
+
+async fn get_rbf<S: MempoolStore>(
+    State(db): State<S>,
+    Path(txid): Path<String>,
+) -> ApiResult<Vec<RbfResponse>> {
+    let txid = parse_txid(&txid)?;
+    let records = db.rbf_history_of(&txid).await.map_err(internal_error)?;
+    Ok(Json(records.into_iter().map(RbfResponse::from).collect()))
+}
+
+/// Prometheus text exposition of the pending txs per `FEE_HISTOGRAM_BANDS` band, a gauge
+/// per band labelled with its lower bound in sat/vB
+async fn get_metrics<S: MempoolStore>(State(db): State<S>) -> Result<String, ApiError> {
+    let buckets: Vec<f64> = FEE_HISTOGRAM_BANDS.iter().map(|min| *min as f64).collect();
+    let histogram = db
+        .current_fee_rate_histogram(&buckets)
+        .await
+        .map_err(internal_error)?;
+    let mut metrics = String::from(
+        "# HELP mempool_fee_rate_txs Pending txs per fee rate band, by its lower bound in sat/vB\n\
+        # TYPE mempool_fee_rate_txs gauge\n",
+    );
+    for (min, count) in histogram {
+        metrics.push_str(&format!(
+            "mempool_fee_rate_txs{{min_sat_per_vb=\"{}\"}} {}\n",
+            min, count
+        ));
+    }
+    Ok(metrics)
+}
+
+/// Serve the query API over `db` on a bound listener until `shutdown` resolves. The
+/// handle should be read-only, nothing is written through it
+pub async fn serve_with_listener<S: MempoolStore>(
+    listener: TcpListener,
+    db: S,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    info!("REST API listening on {}", listener.local_addr()?);
+    let router = Router::new()
+        .route("/tx/:txid", get(get_tx::<S>))
+        .route("/pending", get(get_pending::<S>))
+        .route(
+            "/stats/confirmation-latency",
+            get(get_confirmation_latency::<S>),
+        )
+        .route("/mempool-state", get(get_mempool_states::<S>))
+        .route("/rbf/:txid", get(get_rbf::<S>))
+        .route("/metrics", get(get_metrics::<S>))
+        .with_state(db);
+    axum::serve(listener, router)
+        .with_graceful_shutdown(shutdown)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,877 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use bitcoin::{bip158::BlockFilter, Amount, Block, BlockHash, FeeRate, Transaction, Txid};
+use bitcoind_async_client::{
+    error::ClientError,
+    traits::{Broadcaster, Reader},
+    Client,
+};
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::{config::Auth, now, rate_limit::RateLimiter};
+
+/// Reconnect attempts after a connection error before the call fails, the backoff
+/// doubles between attempts up to `MAX_RECONNECT_BACKOFF`
+const RECONNECT_ATTEMPTS: u32 = 6;
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Context of the error `ReconnectingRpc` fails with once it gave up reconnecting, so
+/// `RetryingRpc` doesn't start the reconnect attempts all over
+#[derive(Debug)]
+struct ReconnectsExhausted;
+
+impl fmt::Display for ReconnectsExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RPC still unreachable after {} reconnect attempts",
+            RECONNECT_ATTEMPTS
+        )
+    }
+}
+
+/// Node sync state, from getblockchaininfo
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockchainStatus {
+    pub blocks: u64,
+    pub initial_block_download: bool,
+    pub verification_progress: f64,
+}
+
+/// From getmempoolinfo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolStatus {
+    pub loaded: bool,
+    /// Number of transactions
+    pub size: u64,
+    /// Sum of transaction virtual sizes
+    pub bytes: u64,
+    /// Memory used by the mempool, in bytes
+    pub usage: u64,
+    /// Memory limit, the node evicts the cheapest txs past it
+    pub max_mempool: u64,
+    /// Minimum fee rate for a tx to be accepted, raised above `min_relay_tx_fee` once full
+    pub mempool_min_fee: FeeRate,
+    pub min_relay_tx_fee: FeeRate,
+}
+
+impl Default for MempoolStatus {
+    fn default() -> Self {
+        Self {
+            loaded: false,
+            size: 0,
+            bytes: 0,
+            usage: 0,
+            max_mempool: 0,
+            mempool_min_fee: FeeRate::ZERO,
+            min_relay_tx_fee: FeeRate::ZERO,
+        }
+    }
+}
+
+/// A transaction's entry in the node's mempool
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MempoolEntry {
+    /// When the tx entered the node's mempool
+    pub time: u64,
+    /// Base fee, excluding any prioritisetransaction delta
+    pub fee: Amount,
+    pub vsize: u64,
+    /// Unconfirmed parents
+    pub depends: Vec<Txid>,
+    /// Unconfirmed children
+    pub spent_by: Vec<Txid>,
+}
+
+/// Where a transaction stands on chain, from getrawtransaction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TxStatus {
+    /// 0 while unconfirmed
+    pub confirmations: u64,
+    /// Block the tx confirmed in, `None` while unconfirmed
+    pub block_hash: Option<BlockHash>,
+}
+
+impl TxStatus {
+    pub fn is_mined(&self) -> bool {
+        self.confirmations > 0
+    }
+}
+
+/// A block's header fields and size without its txs, from getblock at verbosity 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct BlockSummary {
+    pub height: u64,
+    pub time: u64,
+    pub weight: u64,
+    #[serde(rename = "nTx")]
+    pub tx_count: usize,
+}
+
+/// A zmq publisher the node is configured with, from getzmqnotifications
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ZmqNotification {
+    /// Publisher name, e.g. `pubrawtx`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Endpoint the node binds, e.g. `tcp://0.0.0.0:28332`
+    pub address: String,
+    #[serde(rename = "hwm")]
+    pub high_water_mark: u64,
+}
+
+impl ZmqNotification {
+    /// Port of the endpoint, `None` for non-tcp addresses
+    pub fn port(&self) -> Option<u16> {
+        self.address
+            .strip_prefix("tcp://")?
+            .rsplit_once(':')?
+            .1
+            .parse()
+            .ok()
+    }
+}
+
+/// What the node runs with, from getnetworkinfo, getindexinfo and getmempoolinfo
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodeCapabilities {
+    /// e.g. 270000
+    pub version: u64,
+    /// e.g. `/Satoshi:27.0.0/`
+    pub subversion: String,
+    /// Enabled indexes by name, e.g. `txindex` or `basic block filter index`, and
+    /// whether they caught up with the tip
+    pub indexes: HashMap<String, bool>,
+    /// `-mempoolfullrbf`, `None` on nodes that don't report it
+    pub full_rbf: Option<bool>,
+}
+
+impl NodeCapabilities {
+    pub const TXINDEX: &'static str = "txindex";
+    pub const BLOCK_FILTER_INDEX: &'static str = "basic block filter index";
+
+    pub fn has_index(&self, name: &str) -> bool {
+        self.indexes.contains_key(name)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct NetworkInfoResponse {
+    version: u64,
+    subversion: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct IndexInfoResponse {
+    synced: bool,
+}
+
+/// The fields of getmempoolinfo the async client's response leaves out
+#[derive(Debug, Deserialize)]
+struct MempoolPolicyResponse {
+    fullrbf: Option<bool>,
+}
+
+/// From getblockfilter, the filter header is left out
+#[derive(Debug, Deserialize)]
+struct BlockFilterResponse {
+    /// Hex encoded filter
+    filter: String,
+}
+
+/// The node reports fee rates in BTC/kvB, rounded to the nearest sat/kwu
+fn fee_rate_from_btc_per_kvb(btc_per_kvb: f64) -> FeeRate {
+    FeeRate::from_sat_per_kwu((btc_per_kvb * 100_000_000.0 / 4.0).round() as u64)
+}
+
+/// Subset of the bitcoind RPC the tracker relies on.
+/// Implemented for the async client, tests implement it with a mock node
+pub trait BitcoinRpc: Clone + Send + Sync + 'static {
+    fn get_blockchain_info(&self) -> impl Future<Output = Result<BlockchainStatus>> + Send;
+
+    fn get_mempool_info(&self) -> impl Future<Output = Result<MempoolStatus>> + Send;
+
+    fn get_block_count(&self) -> impl Future<Output = Result<u64>> + Send;
+
+    fn get_block_hash(&self, height: u64) -> impl Future<Output = Result<BlockHash>> + Send;
+
+    fn get_block(&self, hash: &BlockHash) -> impl Future<Output = Result<Block>> + Send;
+
+    /// Height, time, weight and tx count of a block, without transferring its txs
+    fn get_block_summary(
+        &self,
+        hash: &BlockHash,
+    ) -> impl Future<Output = Result<BlockSummary>> + Send;
+
+    /// BIP158 basic filter of a block, needs the node's `-blockfilterindex`
+    fn get_block_filter(
+        &self,
+        hash: &BlockHash,
+    ) -> impl Future<Output = Result<BlockFilter>> + Send;
+
+    fn get_raw_mempool(&self) -> impl Future<Output = Result<Vec<Txid>>> + Send;
+
+    fn get_raw_mempool_verbose(
+        &self,
+    ) -> impl Future<Output = Result<HashMap<Txid, MempoolEntry>>> + Send;
+
+    /// Errors if the tx is not in the mempool. Cheap and works without txindex
+    fn get_mempool_entry(&self, txid: &Txid) -> impl Future<Output = Result<MempoolEntry>> + Send;
+
+    fn get_raw_transaction(&self, txid: &Txid) -> impl Future<Output = Result<Transaction>> + Send;
+
+    /// Confirmations and confirming block of a transaction. Requires txindex for mined txs
+    fn get_tx_status(&self, txid: &Txid) -> impl Future<Output = Result<TxStatus>> + Send;
+
+    /// testmempoolaccept for a single tx, returns the reject reason if the node would refuse it
+    fn test_mempool_accept(
+        &self,
+        tx: &Transaction,
+    ) -> impl Future<Output = Result<Option<String>>> + Send;
+
+    /// zmq publishers the node was started with
+    fn get_zmq_notifications(&self) -> impl Future<Output = Result<Vec<ZmqNotification>>> + Send;
+
+    fn get_node_capabilities(&self) -> impl Future<Output = Result<NodeCapabilities>> + Send;
+
+    /// Client on a connection of its own, e.g. for a restarted worker. Clients without a
+    /// connection to rebuild hand out a clone
+    fn fresh_connection(&self) -> Result<Self> {
+        Ok(self.clone())
+    }
+}
+
+impl BitcoinRpc for Client {
+    async fn get_blockchain_info(&self) -> Result<BlockchainStatus> {
+        let info = Reader::get_blockchain_info(self).await?;
+        Ok(BlockchainStatus {
+            blocks: info.blocks,
+            initial_block_download: info.initial_block_download,
+            verification_progress: info.verification_progress,
+        })
+    }
+
+    async fn get_mempool_info(&self) -> Result<MempoolStatus> {
+        let info = Reader::get_mempool_info(self).await?;
+        Ok(MempoolStatus {
+            loaded: info.loaded,
+            size: info.size as u64,
+            bytes: info.bytes as u64,
+            usage: info.usage as u64,
+            max_mempool: info.max_mempool as u64,
+            mempool_min_fee: fee_rate_from_btc_per_kvb(info.mempool_min_fee),
+            min_relay_tx_fee: fee_rate_from_btc_per_kvb(info.min_relay_tx_fee),
+        })
+    }
+
+    async fn get_block_count(&self) -> Result<u64> {
+        Ok(Reader::get_block_count(self).await?)
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<BlockHash> {
+        Ok(Reader::get_block_hash(self, height).await?)
+    }
+
+    async fn get_block(&self, hash: &BlockHash) -> Result<Block> {
+        Ok(Reader::get_block(self, hash).await?)
+    }
+
+    async fn get_block_summary(&self, hash: &BlockHash) -> Result<BlockSummary> {
+        Ok(self
+            .call("getblock", &[serde_json::to_value(hash)?, 1.into()])
+            .await?)
+    }
+
+    async fn get_block_filter(&self, hash: &BlockHash) -> Result<BlockFilter> {
+        let response: BlockFilterResponse = self
+            .call("getblockfilter", &[serde_json::to_value(hash)?])
+            .await?;
+        Ok(BlockFilter::new(&hex::decode(response.filter)?))
+    }
+
+    async fn get_raw_mempool(&self) -> Result<Vec<Txid>> {
+        Ok(Reader::get_raw_mempool(self).await?)
+    }
+
+    async fn get_raw_mempool_verbose(&self) -> Result<HashMap<Txid, MempoolEntry>> {
+        let mempool = Reader::get_raw_mempool_verbose(self).await?;
+        Ok(mempool
+            .into_iter()
+            .map(|(txid, entry)| {
+                (
+                    txid,
+                    MempoolEntry {
+                        time: entry.time,
+                        fee: entry.fees.base,
+                        vsize: entry.vsize,
+                        depends: entry.depends,
+                        spent_by: entry.spent_by,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    async fn get_mempool_entry(&self, txid: &Txid) -> Result<MempoolEntry> {
+        let entry = Reader::get_mempool_entry(self, txid).await?;
+        Ok(MempoolEntry {
+            time: entry.time,
+            fee: entry.fees.base,
+            vsize: entry.vsize,
+            depends: entry.depends,
+            spent_by: entry.spent_by,
+        })
+    }
+
+    async fn get_raw_transaction(&self, txid: &Txid) -> Result<Transaction> {
+        Ok(self
+            .get_raw_transaction_verbosity_zero(txid)
+            .await?
+            .transaction()?)
+    }
+
+    async fn get_tx_status(&self, txid: &Txid) -> Result<TxStatus> {
+        let tx_info = self.get_raw_transaction_verbosity_one(txid).await?;
+        Ok(TxStatus {
+            confirmations: tx_info.confirmations.unwrap_or(0),
+            block_hash: tx_info.blockhash,
+        })
+    }
+
+    async fn test_mempool_accept(&self, tx: &Transaction) -> Result<Option<String>> {
+        let results = Broadcaster::test_mempool_accept(self, tx).await?;
+        Ok(results
+            .into_iter()
+            .next()
+            .and_then(|result| result.reject_reason))
+    }
+
+    async fn get_zmq_notifications(&self) -> Result<Vec<ZmqNotification>> {
+        Ok(self.call("getzmqnotifications", &[]).await?)
+    }
+
+    async fn get_node_capabilities(&self) -> Result<NodeCapabilities> {
+        let network: NetworkInfoResponse = self.call("getnetworkinfo", &[]).await?;
+        let indexes: HashMap<String, IndexInfoResponse> = self.call("getindexinfo", &[]).await?;
+        let mempool: MempoolPolicyResponse = self.call("getmempoolinfo", &[]).await?;
+        Ok(NodeCapabilities {
+            version: network.version,
+            subversion: network.subversion,
+            indexes: indexes
+                .into_iter()
+                .map(|(name, index)| (name, index.synced))
+                .collect(),
+            full_rbf: mempool.fullrbf,
+        })
+    }
+}
+
+/// Whether an RPC failure means the node doesn't know the tx, neither in its mempool nor
+/// (with txindex) on chain, rather than that the lookup itself failed
+pub fn is_unknown_tx_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .to_string()
+            .contains("No such mempool or blockchain transaction")
+    })
+}
+
+/// Whether an RPC failure means the node is unreachable rather than that it refused the call
+pub fn is_connection_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(client_error) = cause.downcast_ref::<ClientError>() {
+            return matches!(
+                client_error,
+                ClientError::Connection(_)
+                    | ClientError::Network(_)
+                    | ClientError::Timeout
+                    | ClientError::MaxRetriesExceeded(_)
+            );
+        }
+        cause.downcast_ref::<std::io::Error>().is_some_and(|io| {
+            matches!(
+                io.kind(),
+                std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::NotConnected
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::TimedOut
+            )
+        })
+    })
+}
+
+/// A client for the node at `url`. Credentials are resolved on each call, so passing this
+/// as the `ReconnectingRpc` factory picks up a cookie the node rewrote on restart
+pub fn connect_bitcoind(url: &str, auth: &Auth) -> Result<Client> {
+    let (user, password) = auth.credentials()?;
+    Ok(Client::new(url.to_string(), user, password, None, None)?)
+}
+
+/// Rebuilds the client when the node becomes unreachable, e.g. across a bitcoind restart.
+/// On a connection error the client is rebuilt with backoff until the node answers again,
+/// then the failed call is retried once
+#[derive(Clone)]
+pub struct ReconnectingRpc<R: BitcoinRpc> {
+    connect: Arc<dyn Fn() -> Result<R> + Send + Sync>,
+    inner: Arc<Mutex<R>>,
+    /// Set while the node is unreachable
+    disconnected_since: Arc<Mutex<Option<u64>>>,
+    initial_backoff: Duration,
+}
+
+impl<R: BitcoinRpc> fmt::Debug for ReconnectingRpc<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReconnectingRpc")
+            .field("disconnected_since", &self.disconnected_since())
+            .field("initial_backoff", &self.initial_backoff)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: BitcoinRpc> ReconnectingRpc<R> {
+    /// `connect` builds a fresh client, it is called once now and again on every reconnect
+    pub fn new(
+        connect: impl Fn() -> Result<R> + Send + Sync + 'static,
+        initial_backoff: Duration,
+    ) -> Result<Self> {
+        let inner = connect()?;
+        Ok(Self {
+            connect: Arc::new(connect),
+            inner: Arc::new(Mutex::new(inner)),
+            disconnected_since: Arc::new(Mutex::new(None)),
+            initial_backoff,
+        })
+    }
+
+    /// When the node became unreachable, `None` while connected
+    pub fn disconnected_since(&self) -> Option<u64> {
+        *self
+            .disconnected_since
+            .lock()
+            .expect("rpc state lock poisoned")
+    }
+
+    fn client(&self) -> R {
+        self.inner.lock().expect("rpc client lock poisoned").clone()
+    }
+
+    fn mark_connected(&self) {
+        let since = self
+            .disconnected_since
+            .lock()
+            .expect("rpc state lock poisoned")
+            .take();
+        if let Some(since) = since {
+            info!("RPC reconnected after {}s", now!().saturating_sub(since));
+        }
+    }
+
+    fn mark_disconnected(&self) -> u64 {
+        *self
+            .disconnected_since
+            .lock()
+            .expect("rpc state lock poisoned")
+            .get_or_insert(now!())
+    }
+
+    /// Rebuild the client until the node answers, gives up after `RECONNECT_ATTEMPTS`
+    async fn reconnect(&self) -> Result<R> {
+        let mut backoff = self.initial_backoff;
+        let mut last_error = None;
+        for attempt in 1..=RECONNECT_ATTEMPTS {
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+            let client = match (self.connect)() {
+                Ok(client) => client,
+                Err(e) => {
+                    warn!("RPC reconnect attempt {} failed: {:#}", attempt, e);
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            match client.get_block_count().await {
+                Ok(_) => {
+                    *self.inner.lock().expect("rpc client lock poisoned") = client.clone();
+                    return Ok(client);
+                }
+                Err(e) if is_connection_error(&e) => {
+                    warn!("RPC reconnect attempt {} failed: {:#}", attempt, e);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| anyhow::anyhow!("node unreachable"))
+            .context(ReconnectsExhausted))
+    }
+
+    async fn call<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: Fn(R) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let err = match f(self.client()).await {
+            Ok(value) => {
+                self.mark_connected();
+                return Ok(value);
+            }
+            Err(e) if is_connection_error(&e) => e,
+            Err(e) => return Err(e),
+        };
+        let since = self.mark_disconnected();
+        warn!("RPC disconnected since {}: {:#}", since, err);
+        let client = self.reconnect().await?;
+        let result = f(client).await;
+        if result.is_ok() {
+            self.mark_connected();
+        }
+        result
+    }
+}
+
+impl<R: BitcoinRpc> BitcoinRpc for ReconnectingRpc<R> {
+    async fn get_blockchain_info(&self) -> Result<BlockchainStatus> {
+        self.call(|rpc| async move { rpc.get_blockchain_info().await })
+            .await
+    }
+
+    async fn get_mempool_info(&self) -> Result<MempoolStatus> {
+        self.call(|rpc| async move { rpc.get_mempool_info().await })
+            .await
+    }
+
+    async fn get_block_count(&self) -> Result<u64> {
+        self.call(|rpc| async move { rpc.get_block_count().await })
+            .await
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<BlockHash> {
+        self.call(|rpc| async move { rpc.get_block_hash(height).await })
+            .await
+    }
+
+    async fn get_block(&self, hash: &BlockHash) -> Result<Block> {
+        let hash = *hash;
+        self.call(|rpc| async move { rpc.get_block(&hash).await })
+            .await
+    }
+
+    async fn get_block_summary(&self, hash: &BlockHash) -> Result<BlockSummary> {
+        let hash = *hash;
+        self.call(|rpc| async move { rpc.get_block_summary(&hash).await })
+            .await
+    }
+
+    async fn get_block_filter(&self, hash: &BlockHash) -> Result<BlockFilter> {
+        let hash = *hash;
+        self.call(|rpc| async move { rpc.get_block_filter(&hash).await })
+            .await
+    }
+
+    async fn get_raw_mempool(&self) -> Result<Vec<Txid>> {
+        self.call(|rpc| async move { rpc.get_raw_mempool().await })
+            .await
+    }
+
+    async fn get_raw_mempool_verbose(&self) -> Result<HashMap<Txid, MempoolEntry>> {
+        self.call(|rpc| async move { rpc.get_raw_mempool_verbose().await })
+            .await
+    }
+
+    async fn get_mempool_entry(&self, txid: &Txid) -> Result<MempoolEntry> {
+        let txid = *txid;
+        self.call(|rpc| async move { rpc.get_mempool_entry(&txid).await })
+            .await
+    }
+
+    async fn get_raw_transaction(&self, txid: &Txid) -> Result<Transaction> {
+        let txid = *txid;
+        self.call(|rpc| async move { rpc.get_raw_transaction(&txid).await })
+            .await
+    }
+
+    async fn get_tx_status(&self, txid: &Txid) -> Result<TxStatus> {
+        let txid = *txid;
+        self.call(|rpc| async move { rpc.get_tx_status(&txid).await })
+            .await
+    }
+
+    async fn test_mempool_accept(&self, tx: &Transaction) -> Result<Option<String>> {
+        self.call(|rpc| {
+            let tx = tx.clone();
+            async move { rpc.test_mempool_accept(&tx).await }
+        })
+        .await
+    }
+
+    async fn get_zmq_notifications(&self) -> Result<Vec<ZmqNotification>> {
+        self.call(|rpc| async move { rpc.get_zmq_notifications().await })
+            .await
+    }
+
+    async fn get_node_capabilities(&self) -> Result<NodeCapabilities> {
+        self.call(|rpc| async move { rpc.get_node_capabilities().await })
+            .await
+    }
+
+    /// Same node and backoff on a client `connect` builds now, this one's connection and
+    /// disconnect state are left behind
+    fn fresh_connection(&self) -> Result<Self> {
+        Ok(Self {
+            connect: self.connect.clone(),
+            inner: Arc::new(Mutex::new((self.connect)()?)),
+            disconnected_since: Arc::new(Mutex::new(None)),
+            initial_backoff: self.initial_backoff,
+        })
+    }
+}
+
+/// Waits on a shared `RateLimiter` before every call
+#[derive(Debug, Clone)]
+pub struct RateLimitedRpc<R: BitcoinRpc> {
+    inner: R,
+    limiter: RateLimiter,
+}
+
+impl<R: BitcoinRpc> RateLimitedRpc<R> {
+    pub fn new(inner: R, limiter: RateLimiter) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+impl<R: BitcoinRpc> BitcoinRpc for RateLimitedRpc<R> {
+    async fn get_blockchain_info(&self) -> Result<BlockchainStatus> {
+        self.limiter.acquire().await;
+        self.inner.get_blockchain_info().await
+    }
+
+    async fn get_mempool_info(&self) -> Result<MempoolStatus> {
+        self.limiter.acquire().await;
+        self.inner.get_mempool_info().await
+    }
+
+    async fn get_block_count(&self) -> Result<u64> {
+        self.limiter.acquire().await;
+        self.inner.get_block_count().await
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<BlockHash> {
+        self.limiter.acquire().await;
+        self.inner.get_block_hash(height).await
+    }
+
+    async fn get_block(&self, hash: &BlockHash) -> Result<Block> {
+        self.limiter.acquire().await;
+        self.inner.get_block(hash).await
+    }
+
+    async fn get_block_summary(&self, hash: &BlockHash) -> Result<BlockSummary> {
+        self.limiter.acquire().await;
+        self.inner.get_block_summary(hash).await
+    }
+
+    async fn get_block_filter(&self, hash: &BlockHash) -> Result<BlockFilter> {
+        self.limiter.acquire().await;
+        self.inner.get_block_filter(hash).await
+    }
+
+    async fn get_raw_mempool(&self) -> Result<Vec<Txid>> {
+        self.limiter.acquire().await;
+        self.inner.get_raw_mempool().await
+    }
+
+    async fn get_raw_mempool_verbose(&self) -> Result<HashMap<Txid, MempoolEntry>> {
+        self.limiter.acquire().await;
+        self.inner.get_raw_mempool_verbose().await
+    }
+
+    async fn get_mempool_entry(&self, txid: &Txid) -> Result<MempoolEntry> {
+        self.limiter.acquire().await;
+        self.inner.get_mempool_entry(txid).await
+    }
+
+    async fn get_raw_transaction(&self, txid: &Txid) -> Result<Transaction> {
+        self.limiter.acquire().await;
+        self.inner.get_raw_transaction(txid).await
+    }
+
+    async fn get_tx_status(&self, txid: &Txid) -> Result<TxStatus> {
+        self.limiter.acquire().await;
+        self.inner.get_tx_status(txid).await
+    }
+
+    async fn test_mempool_accept(&self, tx: &Transaction) -> Result<Option<String>> {
+        self.limiter.acquire().await;
+        self.inner.test_mempool_accept(tx).await
+    }
+
+    async fn get_zmq_notifications(&self) -> Result<Vec<ZmqNotification>> {
+        self.limiter.acquire().await;
+        self.inner.get_zmq_notifications().await
+    }
+    async fn get_node_capabilities(&self) -> Result<NodeCapabilities> {
+        self.limiter.acquire().await;
+        self.inner.get_node_capabilities().await
+    }
+}
+
+/// Bounds and retries the calls of `RetryingRpc`
+#[derive(Debug, Clone, Copy)]
+pub struct RpcRetryPolicy {
+    /// A call taking longer fails as a timeout
+    pub timeout: Duration,
+    /// Tries per call including the first, only transient errors (connection errors and
+    /// timeouts) are retried
+    pub attempts: u32,
+    /// Wait before the first retry, doubled after every further failure
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RpcRetryPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Times every call out after `RpcRetryPolicy::timeout` and retries transient failures
+/// with backoff, so a momentarily busy node doesn't fail the task. Permanent errors,
+/// e.g. a tx the node doesn't know, are returned right away, and so are those of an
+/// inner `ReconnectingRpc` that already gave up reconnecting
+#[derive(Debug, Clone)]
+pub struct RetryingRpc<R: BitcoinRpc> {
+    inner: R,
+    policy: RpcRetryPolicy,
+    limiter: RateLimiter,
+}
+
+impl<R: BitcoinRpc> RetryingRpc<R> {
+    pub fn new(inner: R, policy: RpcRetryPolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            limiter: RateLimiter::unlimited(),
+        }
+    }
+
+    /// Wait on a shared `limiter` before every attempt. The wait isn't part of the
+    /// attempt's timeout
+    pub fn with_rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    async fn call<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut backoff = self.policy.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            self.limiter.acquire().await;
+            let result = match tokio::time::timeout(self.policy.timeout, f()).await {
+                Ok(result) => result,
+                Err(_) => Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("RPC call timed out after {:?}", self.policy.timeout),
+                )
+                .into()),
+            };
+            match result {
+                Err(e)
+                    if attempt < self.policy.attempts
+                        && is_connection_error(&e)
+                        && e.downcast_ref::<ReconnectsExhausted>().is_none() =>
+                {
+                    warn!(
+                        "RPC call failed (attempt {}/{}), retrying in {:?}: {:#}",
+                        attempt, self.policy.attempts, backoff, e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.policy.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<R: BitcoinRpc> BitcoinRpc for RetryingRpc<R> {
+    async fn get_blockchain_info(&self) -> Result<BlockchainStatus> {
+        self.call(|| self.inner.get_blockchain_info()).await
+    }
+
+    async fn get_mempool_info(&self) -> Result<MempoolStatus> {
+        self.call(|| self.inner.get_mempool_info()).await
+    }
+
+    async fn get_block_count(&self) -> Result<u64> {
+        self.call(|| self.inner.get_block_count()).await
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<BlockHash> {
+        self.call(|| self.inner.get_block_hash(height)).await
+    }
+
+    async fn get_block(&self, hash: &BlockHash) -> Result<Block> {
+        self.call(|| self.inner.get_block(hash)).await
+    }
+
+    async fn get_block_summary(&self, hash: &BlockHash) -> Result<BlockSummary> {
+        self.call(|| self.inner.get_block_summary(hash)).await
+    }
+
+    async fn get_block_filter(&self, hash: &BlockHash) -> Result<BlockFilter> {
+        self.call(|| self.inner.get_block_filter(hash)).await
+    }
+
+    async fn get_raw_mempool(&self) -> Result<Vec<Txid>> {
+        self.call(|| self.inner.get_raw_mempool()).await
+    }
+
+    async fn get_raw_mempool_verbose(&self) -> Result<HashMap<Txid, MempoolEntry>> {
+        self.call(|| self.inner.get_raw_mempool_verbose()).await
+    }
+
+    async fn get_mempool_entry(&self, txid: &Txid) -> Result<MempoolEntry> {
+        self.call(|| self.inner.get_mempool_entry(txid)).await
+    }
+
+    async fn get_raw_transaction(&self, txid: &Txid) -> Result<Transaction> {
+        self.call(|| self.inner.get_raw_transaction(txid)).await
+    }
+
+    async fn get_tx_status(&self, txid: &Txid) -> Result<TxStatus> {
+        self.call(|| self.inner.get_tx_status(txid)).await
+    }
+
+    async fn test_mempool_accept(&self, tx: &Transaction) -> Result<Option<String>> {
+        self.call(|| self.inner.test_mempool_accept(tx)).await
+    }
+
+    async fn get_zmq_notifications(&self) -> Result<Vec<ZmqNotification>> {
+        self.call(|| self.inner.get_zmq_notifications()).await
+    }
+
+    async fn get_node_capabilities(&self) -> Result<NodeCapabilities> {
+        self.call(|| self.inner.get_node_capabilities()).await
+    }
+}
@@ -0,0 +1,1396 @@
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use anyhow::{anyhow, Result};
+use bitcoin::{Amount, BlockHash, FeeRate, Transaction, Txid};
+
+use crate::{
+    database::{
+        BumpStats, Checkpoint, DailySummary, Database, DbStats, DwellStats, EvictionContext,
+        FeeHistogramBand, FeeRateComparison, MempoolChurn, MempoolSnapshot, MinerStats,
+        OpReturnStats, OutOfBandStats, OutpointConflict, PropagationDelta, PurgeEvent, RbfRecord,
+        TransactionInner, TrucStats, UnconfirmedPackage,
+    },
+    rpc::MempoolStatus,
+    utils::{get_tx_key, RbfBump, SighashKind, TxType},
+};
+
+/// Storage the tracker records into and the query API reads from.
+/// Implemented by the sqlite `Database` and, with the `postgres` feature, `PgStore`.
+/// Times and units are those documented on `Database`'s methods
+pub trait MempoolStore: Clone + Send + Sync + 'static {
+    /// Write methods are no-ops, reads still see the stored data
+    fn is_read_only(&self) -> bool;
+
+    fn run_migrations(&self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Make everything written so far durable
+    fn flush(&self) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_mempool_state(
+        &self,
+        mempool_info: &MempoolStatus,
+        block_height: u64,
+        block_hash: BlockHash,
+        churn: Option<MempoolChurn>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_fee_histogram(&self) -> impl Future<Output = Result<usize>> + Send;
+
+    fn current_fee_rate_histogram(
+        &self,
+        buckets: &[f64],
+    ) -> impl Future<Output = Result<Vec<(f64, u64)>>> + Send;
+
+    fn quarantine_payload(
+        &self,
+        payload: &[u8],
+        error: &str,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_coinbase_tx(
+        &self,
+        tx: &Transaction,
+        block_hash: Option<BlockHash>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_mined_tx(
+        &self,
+        tx: &Transaction,
+        block_hash: Option<BlockHash>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_conflicts(
+        &self,
+        tx: &Transaction,
+    ) -> impl Future<Output = Result<Vec<OutpointConflict>>> + Send;
+
+    fn block_is_covered(&self, block_height: u64) -> impl Future<Output = Result<bool>> + Send;
+
+    fn block_recorded(&self, block_hash: &BlockHash) -> impl Future<Output = Result<bool>> + Send;
+
+    fn last_recorded_block_height(&self) -> impl Future<Output = Result<Option<u64>>> + Send;
+
+    fn record_backfilled_block(
+        &self,
+        block_height: u64,
+        block_hash: BlockHash,
+        block_time: u64,
+        txs: &[(Transaction, Amount)],
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_out_of_band_txs(
+        &self,
+        block_height: Option<u64>,
+        block_hash: BlockHash,
+        block_time: u64,
+        txs: &[(Transaction, Amount)],
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_block(
+        &self,
+        block_hash: BlockHash,
+        block_height: Option<u64>,
+        block_time: u64,
+        vsize: u64,
+        tx_count: usize,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Pending txs the node no longer has in its mempool
+    fn txids_of_txs_not_in_list(
+        &self,
+        txids: Vec<Txid>,
+    ) -> impl Future<Output = Result<Vec<Txid>>> + Send;
+
+    fn record_pruned_txs(&self, txids: Vec<Txid>) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_purge_event(
+        &self,
+        txids: &[Txid],
+        mempool_min_fee_before: Option<FeeRate>,
+        mempool_min_fee_after: Option<FeeRate>,
+    ) -> impl Future<Output = Result<PurgeEvent>> + Send;
+
+    fn insert_mempool_tx(
+        &self,
+        tx: Transaction,
+        found_at: Option<u64>,
+        node_seen_at: Option<u64>,
+        absolute_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn insert_mempool_txs(
+        &self,
+        txs: &[(Transaction, Option<u64>, Amount, FeeRate)],
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// No lookup involved, the threshold is configuration
+    fn exceeded_large_value_threshold(&self, tx: &Transaction) -> Option<Amount>;
+
+    fn large_value_txids(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<Vec<Txid>>> + Send;
+
+    fn tx_exists(&self, tx: &Transaction) -> impl Future<Output = Result<bool>> + Send;
+
+    fn get_stored_fee(
+        &self,
+        tx: &Transaction,
+    ) -> impl Future<Output = Result<Option<(Amount, FeeRate)>>> + Send;
+
+    fn get_stored_tx(
+        &self,
+        tx: &Transaction,
+    ) -> impl Future<Output = Result<Option<Transaction>>> + Send;
+
+    fn record_tx_links(
+        &self,
+        txid: &Txid,
+        depends: &[Txid],
+        spent_by: &[Txid],
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_rbf(
+        &self,
+        transaction: &Transaction,
+        bump: &RbfBump,
+        reject_reason: Option<&str>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_bip125_violations(
+        &self,
+        tx: &Transaction,
+        violations: &[&str],
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn descendant_count(&self, txid: &Txid) -> impl Future<Output = Result<usize>> + Send;
+
+    fn record_rbf_unknown_fee(
+        &self,
+        transaction: &Transaction,
+        reject_reason: Option<&str>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn fee_known(&self, tx: &Transaction) -> impl Future<Output = Result<bool>> + Send;
+
+    fn fill_unknown_fee(
+        &self,
+        tx: &Transaction,
+        fee: Amount,
+        fee_rate: FeeRate,
+    ) -> impl Future<Output = Result<bool>> + Send;
+
+    fn update_txid_by_inputs_hash(
+        &self,
+        tx: &Transaction,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn get_tx_variants(
+        &self,
+        inputs_hash: &str,
+    ) -> impl Future<Output = Result<Vec<Transaction>>> + Send;
+
+    fn get_txid_history(
+        &self,
+        inputs_hash: &str,
+    ) -> impl Future<Output = Result<Vec<(Txid, u64)>>> + Send;
+
+    fn untracked_txids(&self, txids: &[Txid]) -> impl Future<Output = Result<Vec<Txid>>> + Send;
+
+    fn get_dust_count(
+        &self,
+        inputs_hash: &str,
+    ) -> impl Future<Output = Result<Option<usize>>> + Send;
+
+    fn is_pending(&self, txid: &Txid) -> impl Future<Output = Result<bool>> + Send;
+
+    fn pending_tx_count(&self) -> impl Future<Output = Result<u64>> + Send;
+
+    fn record_checkpoint(
+        &self,
+        best_block_hash: BlockHash,
+    ) -> impl Future<Output = Result<Checkpoint>> + Send;
+
+    fn apply_retention(&self) -> impl Future<Output = Result<(usize, usize)>> + Send;
+
+    fn latest_checkpoint(&self) -> impl Future<Output = Result<Option<Checkpoint>>> + Send;
+
+    fn truc_stats(&self, start: u64, end: u64) -> impl Future<Output = Result<TrucStats>> + Send;
+
+    fn get_immature_coinbases(
+        &self,
+        tip_height: u64,
+    ) -> impl Future<Output = Result<Vec<Txid>>> + Send;
+
+    fn get_dropped_txs(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<Vec<Txid>>> + Send;
+
+    fn get_block_fees(&self, height: u64) -> impl Future<Output = Result<Option<u64>>> + Send;
+
+    fn per_miner_stats(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<Vec<MinerStats>>> + Send;
+
+    fn sighash_usage(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<Vec<(SighashKind, u64)>>> + Send;
+
+    fn last_mempool_min_fee(&self) -> impl Future<Output = Result<Option<FeeRate>>> + Send;
+
+    /// Recompute the columns derived from each stored tx's data
+    fn replay_classification(&self) -> impl Future<Output = Result<usize>> + Send;
+
+    fn pending_txs(
+        &self,
+        after_key: &str,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<(String, Transaction)>>> + Send;
+
+    fn get_tx_record(
+        &self,
+        txid: &Txid,
+    ) -> impl Future<Output = Result<Option<TransactionInner>>> + Send;
+
+    fn pending_tx_records(
+        &self,
+        found_before: u64,
+        limit: usize,
+        offset: usize,
+    ) -> impl Future<Output = Result<Vec<TransactionInner>>> + Send;
+
+    fn mempool_snapshots(
+        &self,
+        start: u64,
+        end: u64,
+        limit: usize,
+        offset: usize,
+    ) -> impl Future<Output = Result<Vec<MempoolSnapshot>>> + Send;
+
+    fn rbf_history_of(&self, txid: &Txid) -> impl Future<Output = Result<Vec<RbfRecord>>> + Send;
+
+    fn record_sighting(
+        &self,
+        txid: &Txid,
+        node_id: &str,
+        seen_at: u64,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn propagation_deltas(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<Vec<PropagationDelta>>> + Send;
+
+    fn db_stats(&self, rbf_since: u64) -> impl Future<Output = Result<DbStats>> + Send;
+
+    fn get_tx_by_txid(
+        &self,
+        txid: &Txid,
+    ) -> impl Future<Output = Result<Option<Transaction>>> + Send;
+
+    fn daily_summary(&self, day_start: u64) -> impl Future<Output = Result<DailySummary>> + Send;
+
+    fn average_bump_stats(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<BumpStats>> + Send;
+
+    fn rbf_leaderboard(
+        &self,
+        start: u64,
+        end: u64,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<(String, u64, u64)>>> + Send;
+
+    fn total_rbf_fee_delta(&self, start: u64, end: u64)
+        -> impl Future<Output = Result<u64>> + Send;
+
+    fn latency_by_fee_bucket(
+        &self,
+        start: u64,
+        end: u64,
+        buckets: &[f64],
+    ) -> impl Future<Output = Result<Vec<(f64, DwellStats)>>> + Send;
+
+    fn get_children(&self, txid: &Txid) -> impl Future<Output = Result<Vec<Txid>>> + Send;
+
+    fn get_parents(&self, txid: &Txid) -> impl Future<Output = Result<Vec<Txid>>> + Send;
+
+    fn evictions_with_min_fee(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<Vec<EvictionContext>>> + Send;
+
+    fn out_of_band_stats(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<Vec<OutOfBandStats>>> + Send;
+
+    fn purge_events(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<Vec<PurgeEvent>>> + Send;
+
+    fn fee_histogram_series(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<Vec<FeeHistogramBand>>> + Send;
+
+    fn get_txs_after_seq(
+        &self,
+        seq: i64,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<(i64, Txid)>>> + Send;
+
+    fn effective_vs_naive_feerate(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<Vec<FeeRateComparison>>> + Send;
+
+    fn type_distribution(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<Vec<(TxType, u64)>>> + Send;
+
+    fn op_return_stats(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<OpReturnStats>> + Send;
+
+    fn unconfirmed_package(
+        &self,
+        txid: &Txid,
+    ) -> impl Future<Output = Result<UnconfirmedPackage>> + Send;
+}
+
+// sqlite calls are short and synchronous, they run on the calling task
+impl MempoolStore for Database {
+    fn is_read_only(&self) -> bool {
+        Database::is_read_only(self)
+    }
+
+    async fn run_migrations(&self) -> Result<()> {
+        Database::run_migrations(self)
+    }
+
+    async fn flush(&self) -> Result<()> {
+        Database::flush(self)
+    }
+
+    async fn record_mempool_state(
+        &self,
+        mempool_info: &MempoolStatus,
+        block_height: u64,
+        block_hash: BlockHash,
+        churn: Option<MempoolChurn>,
+    ) -> Result<()> {
+        Database::record_mempool_state(self, mempool_info, block_height, block_hash, churn)
+    }
+
+    async fn record_fee_histogram(&self) -> Result<usize> {
+        Database::record_fee_histogram(self)
+    }
+
+    async fn current_fee_rate_histogram(&self, buckets: &[f64]) -> Result<Vec<(f64, u64)>> {
+        Database::current_fee_rate_histogram(self, buckets)
+    }
+
+    async fn quarantine_payload(&self, payload: &[u8], error: &str) -> Result<()> {
+        Database::quarantine_payload(self, payload, error)
+    }
+
+    async fn record_coinbase_tx(
+        &self,
+        tx: &Transaction,
+        block_hash: Option<BlockHash>,
+    ) -> Result<()> {
+        Database::record_coinbase_tx(self, tx, block_hash)
+    }
+
+    async fn record_mined_tx(&self, tx: &Transaction, block_hash: Option<BlockHash>) -> Result<()> {
+        Database::record_mined_tx(self, tx, block_hash)
+    }
+
+    async fn record_conflicts(&self, tx: &Transaction) -> Result<Vec<OutpointConflict>> {
+        Database::record_conflicts(self, tx)
+    }
+
+    async fn block_is_covered(&self, block_height: u64) -> Result<bool> {
+        Database::block_is_covered(self, block_height)
+    }
+
+    async fn block_recorded(&self, block_hash: &BlockHash) -> Result<bool> {
+        Database::block_recorded(self, block_hash)
+    }
+
+    async fn last_recorded_block_height(&self) -> Result<Option<u64>> {
+        Database::last_recorded_block_height(self)
+    }
+
+    async fn record_backfilled_block(
+        &self,
+        block_height: u64,
+        block_hash: BlockHash,
+        block_time: u64,
+        txs: &[(Transaction, Amount)],
+    ) -> Result<()> {
+        Database::record_backfilled_block(self, block_height, block_hash, block_time, txs)
+    }
+
+    async fn record_out_of_band_txs(
+        &self,
+        block_height: Option<u64>,
+        block_hash: BlockHash,
+        block_time: u64,
+        txs: &[(Transaction, Amount)],
+    ) -> Result<()> {
+        Database::record_out_of_band_txs(self, block_height, block_hash, block_time, txs)
+    }
+
+    async fn record_block(
+        &self,
+        block_hash: BlockHash,
+        block_height: Option<u64>,
+        block_time: u64,
+        vsize: u64,
+        tx_count: usize,
+    ) -> Result<()> {
+        Database::record_block(self, block_hash, block_height, block_time, vsize, tx_count)
+    }
+
+    async fn txids_of_txs_not_in_list(&self, txids: Vec<Txid>) -> Result<Vec<Txid>> {
+        Database::txids_of_txs_not_in_list(self, txids)
+    }
+
+    async fn record_pruned_txs(&self, txids: Vec<Txid>) -> Result<()> {
+        Database::record_pruned_txs(self, txids)
+    }
+
+    async fn record_purge_event(
+        &self,
+        txids: &[Txid],
+        mempool_min_fee_before: Option<FeeRate>,
+        mempool_min_fee_after: Option<FeeRate>,
+    ) -> Result<PurgeEvent> {
+        Database::record_purge_event(self, txids, mempool_min_fee_before, mempool_min_fee_after)
+    }
+
+    async fn insert_mempool_tx(
+        &self,
+        tx: Transaction,
+        found_at: Option<u64>,
+        node_seen_at: Option<u64>,
+        absolute_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<()> {
+        Database::insert_mempool_tx(self, tx, found_at, node_seen_at, absolute_fee, fee_rate)
+    }
+
+    async fn insert_mempool_txs(
+        &self,
+        txs: &[(Transaction, Option<u64>, Amount, FeeRate)],
+    ) -> Result<()> {
+        Database::insert_mempool_txs(self, txs)
+    }
+
+    fn exceeded_large_value_threshold(&self, tx: &Transaction) -> Option<Amount> {
+        Database::exceeded_large_value_threshold(self, tx)
+    }
+
+    async fn large_value_txids(&self, start: u64, end: u64) -> Result<Vec<Txid>> {
+        Database::large_value_txids(self, start, end)
+    }
+
+    async fn tx_exists(&self, tx: &Transaction) -> Result<bool> {
+        Database::tx_exists(self, tx)
+    }
+
+    async fn get_stored_fee(&self, tx: &Transaction) -> Result<Option<(Amount, FeeRate)>> {
+        Database::get_stored_fee(self, tx)
+    }
+
+    async fn get_stored_tx(&self, tx: &Transaction) -> Result<Option<Transaction>> {
+        Database::get_stored_tx(self, tx)
+    }
+
+    async fn record_tx_links(
+        &self,
+        txid: &Txid,
+        depends: &[Txid],
+        spent_by: &[Txid],
+    ) -> Result<()> {
+        Database::record_tx_links(self, txid, depends, spent_by)
+    }
+
+    async fn record_rbf(
+        &self,
+        transaction: &Transaction,
+        bump: &RbfBump,
+        reject_reason: Option<&str>,
+    ) -> Result<()> {
+        Database::record_rbf(self, transaction, bump, reject_reason)
+    }
+
+    async fn record_bip125_violations(&self, tx: &Transaction, violations: &[&str]) -> Result<()> {
+        Database::record_bip125_violations(self, tx, violations)
+    }
+
+    async fn descendant_count(&self, txid: &Txid) -> Result<usize> {
+        Database::descendant_count(self, txid)
+    }
+
+    async fn record_rbf_unknown_fee(
+        &self,
+        transaction: &Transaction,
+        reject_reason: Option<&str>,
+    ) -> Result<()> {
+        Database::record_rbf_unknown_fee(self, transaction, reject_reason)
+    }
+
+    async fn fee_known(&self, tx: &Transaction) -> Result<bool> {
+        Database::fee_known(self, tx)
+    }
+
+    async fn fill_unknown_fee(
+        &self,
+        tx: &Transaction,
+        fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<bool> {
+        Database::fill_unknown_fee(self, tx, fee, fee_rate)
+    }
+
+    async fn update_txid_by_inputs_hash(&self, tx: &Transaction) -> Result<()> {
+        Database::update_txid_by_inputs_hash(self, tx)
+    }
+
+    async fn get_tx_variants(&self, inputs_hash: &str) -> Result<Vec<Transaction>> {
+        Database::get_tx_variants(self, inputs_hash)
+    }
+
+    async fn get_txid_history(&self, inputs_hash: &str) -> Result<Vec<(Txid, u64)>> {
+        Database::get_txid_history(self, inputs_hash)
+    }
+
+    async fn untracked_txids(&self, txids: &[Txid]) -> Result<Vec<Txid>> {
+        Database::untracked_txids(self, txids)
+    }
+
+    async fn get_dust_count(&self, inputs_hash: &str) -> Result<Option<usize>> {
+        Database::get_dust_count(self, inputs_hash)
+    }
+
+    async fn is_pending(&self, txid: &Txid) -> Result<bool> {
+        Database::is_pending(self, txid)
+    }
+
+    async fn pending_tx_count(&self) -> Result<u64> {
+        Database::pending_tx_count(self)
+    }
+
+    async fn record_checkpoint(&self, best_block_hash: BlockHash) -> Result<Checkpoint> {
+        Database::record_checkpoint(self, best_block_hash)
+    }
+
+    async fn apply_retention(&self) -> Result<(usize, usize)> {
+        Database::apply_retention(self)
+    }
+
+    async fn latest_checkpoint(&self) -> Result<Option<Checkpoint>> {
+        Database::latest_checkpoint(self)
+    }
+
+    async fn truc_stats(&self, start: u64, end: u64) -> Result<TrucStats> {
+        Database::truc_stats(self, start, end)
+    }
+
+    async fn get_immature_coinbases(&self, tip_height: u64) -> Result<Vec<Txid>> {
+        Database::get_immature_coinbases(self, tip_height)
+    }
+
+    async fn get_dropped_txs(&self, start: u64, end: u64) -> Result<Vec<Txid>> {
+        Database::get_dropped_txs(self, start, end)
+    }
+
+    async fn get_block_fees(&self, height: u64) -> Result<Option<u64>> {
+        Database::get_block_fees(self, height)
+    }
+
+    async fn per_miner_stats(&self, start: u64, end: u64) -> Result<Vec<MinerStats>> {
+        Database::per_miner_stats(self, start, end)
+    }
+
+    async fn sighash_usage(&self, start: u64, end: u64) -> Result<Vec<(SighashKind, u64)>> {
+        Database::sighash_usage(self, start, end)
+    }
+
+    async fn last_mempool_min_fee(&self) -> Result<Option<FeeRate>> {
+        Database::last_mempool_min_fee(self)
+    }
+
+    async fn replay_classification(&self) -> Result<usize> {
+        Database::replay_classification(self)
+    }
+
+    async fn pending_txs(
+        &self,
+        after_key: &str,
+        limit: usize,
+    ) -> Result<Vec<(String, Transaction)>> {
+        Database::pending_txs(self, after_key, limit)
+    }
+
+    async fn get_tx_record(&self, txid: &Txid) -> Result<Option<TransactionInner>> {
+        Database::get_tx_record(self, txid)
+    }
+
+    async fn pending_tx_records(
+        &self,
+        found_before: u64,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<TransactionInner>> {
+        Database::pending_tx_records(self, found_before, limit, offset)
+    }
+
+    async fn mempool_snapshots(
+        &self,
+        start: u64,
+        end: u64,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<MempoolSnapshot>> {
+        Database::mempool_snapshots(self, start, end, limit, offset)
+    }
+
+    async fn rbf_history_of(&self, txid: &Txid) -> Result<Vec<RbfRecord>> {
+        Database::rbf_history_of(self, txid)
+    }
+
+    async fn record_sighting(&self, txid: &Txid, node_id: &str, seen_at: u64) -> Result<()> {
+        Database::record_sighting(self, txid, node_id, seen_at)
+    }
+
+    async fn propagation_deltas(&self, start: u64, end: u64) -> Result<Vec<PropagationDelta>> {
+        Database::propagation_deltas(self, start, end)
+    }
+
+    async fn db_stats(&self, rbf_since: u64) -> Result<DbStats> {
+        Database::db_stats(self, rbf_since)
+    }
+
+    async fn get_tx_by_txid(&self, txid: &Txid) -> Result<Option<Transaction>> {
+        Database::get_tx_by_txid(self, txid)
+    }
+
+    async fn daily_summary(&self, day_start: u64) -> Result<DailySummary> {
+        Database::daily_summary(self, day_start)
+    }
+
+    async fn average_bump_stats(&self, start: u64, end: u64) -> Result<BumpStats> {
+        Database::average_bump_stats(self, start, end)
+    }
+
+    async fn rbf_leaderboard(
+        &self,
+        start: u64,
+        end: u64,
+        limit: usize,
+    ) -> Result<Vec<(String, u64, u64)>> {
+        Database::rbf_leaderboard(self, start, end, limit)
+    }
+
+    async fn total_rbf_fee_delta(&self, start: u64, end: u64) -> Result<u64> {
+        Database::total_rbf_fee_delta(self, start, end)
+    }
+
+    async fn latency_by_fee_bucket(
+        &self,
+        start: u64,
+        end: u64,
+        buckets: &[f64],
+    ) -> Result<Vec<(f64, DwellStats)>> {
+        Database::latency_by_fee_bucket(self, start, end, buckets)
+    }
+
+    async fn get_children(&self, txid: &Txid) -> Result<Vec<Txid>> {
+        Database::get_children(self, txid)
+    }
+
+    async fn get_parents(&self, txid: &Txid) -> Result<Vec<Txid>> {
+        Database::get_parents(self, txid)
+    }
+
+    async fn evictions_with_min_fee(&self, start: u64, end: u64) -> Result<Vec<EvictionContext>> {
+        Database::evictions_with_min_fee(self, start, end)
+    }
+
+    async fn out_of_band_stats(&self, start: u64, end: u64) -> Result<Vec<OutOfBandStats>> {
+        Database::out_of_band_stats(self, start, end)
+    }
+
+    async fn purge_events(&self, start: u64, end: u64) -> Result<Vec<PurgeEvent>> {
+        Database::purge_events(self, start, end)
+    }
+
+    async fn fee_histogram_series(&self, start: u64, end: u64) -> Result<Vec<FeeHistogramBand>> {
+        Database::fee_histogram_series(self, start, end)
+    }
+
+    async fn get_txs_after_seq(&self, seq: i64, limit: usize) -> Result<Vec<(i64, Txid)>> {
+        Database::get_txs_after_seq(self, seq, limit)
+    }
+
+    async fn effective_vs_naive_feerate(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<FeeRateComparison>> {
+        Database::effective_vs_naive_feerate(self, start, end)
+    }
+
+    async fn type_distribution(&self, start: u64, end: u64) -> Result<Vec<(TxType, u64)>> {
+        Database::type_distribution(self, start, end)
+    }
+
+    async fn op_return_stats(&self, start: u64, end: u64) -> Result<OpReturnStats> {
+        Database::op_return_stats(self, start, end)
+    }
+
+    async fn unconfirmed_package(&self, txid: &Txid) -> Result<UnconfirmedPackage> {
+        Database::unconfirmed_package(self, txid)
+    }
+}
+
+/// Store of a dry run: reads go to a read-only store and writes are dropped. The rows the
+/// run would have inserted, replaced, mined or pruned are kept in memory instead, so a tx
+/// first seen during the run is replaced and mined like it would have been and the
+/// outcomes counted are those of a real run. Rescans and the analytics only see the rows
+/// stored before the run
+#[derive(Debug, Clone)]
+pub struct DryRunStore<S> {
+    inner: S,
+    state: Arc<Mutex<DryRunState>>,
+}
+
+/// A tx row as the run left it
+#[derive(Debug, Clone)]
+struct RunRow {
+    txid: Txid,
+    tx: Transaction,
+    /// `None` while a replacement's fee is unknown, like `get_stored_fee`
+    fee: Option<(Amount, FeeRate)>,
+    pending: bool,
+    /// Whether the stored row is pending, to offset the stored pending count
+    pending_in_inner: bool,
+}
+
+#[derive(Debug, Default)]
+struct DryRunState {
+    /// By untagged inputs hash
+    rows: HashMap<String, RunRow>,
+    /// Inputs hash of every txid a row had during the run, a row only answers for its
+    /// current one
+    keys: HashMap<Txid, String>,
+}
+
+impl DryRunState {
+    fn put(&mut self, key: String, row: RunRow) {
+        self.keys.insert(row.txid, key.clone());
+        self.rows.insert(key, row);
+    }
+
+    /// The row whose current txid is `txid`
+    fn row_of(&self, txid: &Txid) -> Option<&RunRow> {
+        self.keys
+            .get(txid)
+            .and_then(|key| self.rows.get(key))
+            .filter(|row| row.txid == *txid)
+    }
+
+    /// The row the run keeps `txid`'s inputs in, `Some(None)` once it was replaced there
+    fn kept(&self, txid: &Txid) -> Option<Option<(String, RunRow)>> {
+        let key = self.keys.get(txid)?;
+        Some(self.row_of(txid).map(|row| (key.clone(), row.clone())))
+    }
+}
+
+impl<S: MempoolStore> DryRunStore<S> {
+    /// Run on top of `inner`, opened read-only so nothing is written, e.g. with
+    /// `Database::open_read_only`
+    pub fn new(inner: S) -> Result<Self> {
+        if !inner.is_read_only() {
+            return Err(anyhow!("A dry run needs a read-only store to read from"));
+        }
+        Ok(Self {
+            inner,
+            state: Arc::default(),
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, DryRunState> {
+        self.state.lock().expect("dry run lock poisoned")
+    }
+
+    /// The row holding `tx`'s inputs, the run's copy or else the stored one
+    async fn row(&self, tx: &Transaction) -> Result<Option<(String, RunRow)>> {
+        let key = get_tx_key(tx)?;
+        let run_row = self.state().rows.get(&key).cloned();
+        if let Some(row) = run_row {
+            return Ok(Some((key, row)));
+        }
+        let Some(stored) = self.inner.get_stored_tx(tx).await? else {
+            return Ok(None);
+        };
+        let txid = stored.compute_txid();
+        let pending = self.inner.is_pending(&txid).await?;
+        let row = RunRow {
+            txid,
+            tx: stored,
+            fee: self.inner.get_stored_fee(tx).await?,
+            pending,
+            pending_in_inner: pending,
+        };
+        Ok(Some((key, row)))
+    }
+
+    async fn insert(&self, tx: Transaction, fee: Amount, fee_rate: FeeRate) -> Result<()> {
+        let pending_in_inner = match self.row(&tx).await? {
+            Some((_, row)) => row.pending_in_inner,
+            None => false,
+        };
+        let key = get_tx_key(&tx)?;
+        self.state().put(
+            key,
+            RunRow {
+                txid: tx.compute_txid(),
+                tx,
+                fee: Some((fee, fee_rate)),
+                pending: true,
+                pending_in_inner,
+            },
+        );
+        Ok(())
+    }
+}
+
+impl<S: MempoolStore> MempoolStore for DryRunStore<S> {
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    async fn insert_mempool_tx(
+        &self,
+        tx: Transaction,
+        _found_at: Option<u64>,
+        _node_seen_at: Option<u64>,
+        absolute_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<()> {
+        self.insert(tx, absolute_fee, fee_rate).await
+    }
+
+    async fn insert_mempool_txs(
+        &self,
+        txs: &[(Transaction, Option<u64>, Amount, FeeRate)],
+    ) -> Result<()> {
+        for (tx, _, absolute_fee, fee_rate) in txs {
+            self.insert(tx.clone(), *absolute_fee, *fee_rate).await?;
+        }
+        Ok(())
+    }
+
+    async fn tx_exists(&self, tx: &Transaction) -> Result<bool> {
+        let key = get_tx_key(tx)?;
+        let kept = self.state().rows.contains_key(&key);
+        if kept {
+            return Ok(true);
+        }
+        self.inner.tx_exists(tx).await
+    }
+
+    async fn get_stored_fee(&self, tx: &Transaction) -> Result<Option<(Amount, FeeRate)>> {
+        Ok(self.row(tx).await?.and_then(|(_, row)| row.fee))
+    }
+
+    async fn get_stored_tx(&self, tx: &Transaction) -> Result<Option<Transaction>> {
+        Ok(self.row(tx).await?.map(|(_, row)| row.tx))
+    }
+
+    async fn fee_known(&self, tx: &Transaction) -> Result<bool> {
+        match self.row(tx).await? {
+            Some((_, row)) => Ok(row.fee.is_some()),
+            None => Ok(true),
+        }
+    }
+
+    async fn fill_unknown_fee(
+        &self,
+        tx: &Transaction,
+        fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<bool> {
+        let Some((key, mut row)) = self.row(tx).await? else {
+            return Ok(false);
+        };
+        if row.fee.is_some() || row.txid != tx.compute_txid() {
+            return Ok(false);
+        }
+        row.fee = Some((fee, fee_rate));
+        self.state().put(key, row);
+        Ok(true)
+    }
+
+    async fn record_rbf(
+        &self,
+        transaction: &Transaction,
+        bump: &RbfBump,
+        _reject_reason: Option<&str>,
+    ) -> Result<()> {
+        if let Some((key, mut row)) = self.row(transaction).await? {
+            row.fee = Some((bump.fee, bump.fee_rate));
+            self.state().put(key, row);
+        }
+        Ok(())
+    }
+
+    async fn record_rbf_unknown_fee(
+        &self,
+        transaction: &Transaction,
+        _reject_reason: Option<&str>,
+    ) -> Result<()> {
+        if let Some((key, mut row)) = self.row(transaction).await? {
+            row.fee = None;
+            self.state().put(key, row);
+        }
+        Ok(())
+    }
+
+    async fn update_txid_by_inputs_hash(&self, tx: &Transaction) -> Result<()> {
+        if let Some((key, mut row)) = self.row(tx).await? {
+            row.txid = tx.compute_txid();
+            row.tx = tx.clone();
+            row.pending = true;
+            self.state().put(key, row);
+        }
+        Ok(())
+    }
+
+    async fn record_mined_tx(
+        &self,
+        tx: &Transaction,
+        _block_hash: Option<BlockHash>,
+    ) -> Result<()> {
+        if let Some((key, mut row)) = self.row(tx).await? {
+            row.pending = false;
+            self.state().put(key, row);
+        }
+        Ok(())
+    }
+
+    async fn record_pruned_txs(&self, txids: Vec<Txid>) -> Result<()> {
+        for txid in txids {
+            let kept = self.state().kept(&txid);
+            let row = match kept {
+                Some(row) => row,
+                None => match self.inner.get_tx_by_txid(&txid).await? {
+                    Some(tx) => self.row(&tx).await?.filter(|(_, row)| row.txid == txid),
+                    None => None,
+                },
+            };
+            if let Some((key, mut row)) = row {
+                row.pending = false;
+                self.state().put(key, row);
+            }
+        }
+        Ok(())
+    }
+
+    async fn is_pending(&self, txid: &Txid) -> Result<bool> {
+        let kept = self.state().kept(txid);
+        match kept {
+            Some(row) => Ok(row.is_some_and(|(_, row)| row.pending)),
+            None => self.inner.is_pending(txid).await,
+        }
+    }
+
+    async fn pending_tx_count(&self) -> Result<u64> {
+        let stored = self.inner.pending_tx_count().await? as i64;
+        let offset: i64 = self
+            .state()
+            .rows
+            .values()
+            .map(|row| row.pending as i64 - row.pending_in_inner as i64)
+            .sum();
+        Ok((stored + offset).max(0) as u64)
+    }
+
+    async fn txids_of_txs_not_in_list(&self, txids: Vec<Txid>) -> Result<Vec<Txid>> {
+        let listed: HashSet<Txid> = txids.iter().copied().collect();
+        let stored = self.inner.txids_of_txs_not_in_list(txids).await?;
+        let state = self.state();
+        let mut gone: Vec<Txid> = stored
+            .into_iter()
+            .filter(|txid| {
+                !state.keys.contains_key(txid) || state.row_of(txid).is_some_and(|row| row.pending)
+            })
+            .collect();
+        let mut seen: HashSet<Txid> = gone.iter().copied().collect();
+        for row in state.rows.values() {
+            if row.pending && !listed.contains(&row.txid) && seen.insert(row.txid) {
+                gone.push(row.txid);
+            }
+        }
+        Ok(gone)
+    }
+
+    async fn untracked_txids(&self, txids: &[Txid]) -> Result<Vec<Txid>> {
+        let untracked = self.inner.untracked_txids(txids).await?;
+        let state = self.state();
+        Ok(untracked
+            .into_iter()
+            .filter(|txid| state.row_of(txid).is_none())
+            .collect())
+    }
+
+    // The rest reads the stored rows as they were, `inner` drops the writes
+
+    async fn run_migrations(&self) -> Result<()> {
+        self.inner.run_migrations().await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    async fn record_mempool_state(
+        &self,
+        mempool_info: &MempoolStatus,
+        block_height: u64,
+        block_hash: BlockHash,
+        churn: Option<MempoolChurn>,
+    ) -> Result<()> {
+        self.inner
+            .record_mempool_state(mempool_info, block_height, block_hash, churn)
+            .await
+    }
+
+    async fn record_fee_histogram(&self) -> Result<usize> {
+        self.inner.record_fee_histogram().await
+    }
+
+    async fn current_fee_rate_histogram(&self, buckets: &[f64]) -> Result<Vec<(f64, u64)>> {
+        self.inner.current_fee_rate_histogram(buckets).await
+    }
+
+    async fn quarantine_payload(&self, payload: &[u8], error: &str) -> Result<()> {
+        self.inner.quarantine_payload(payload, error).await
+    }
+
+    async fn record_coinbase_tx(
+        &self,
+        tx: &Transaction,
+        block_hash: Option<BlockHash>,
+    ) -> Result<()> {
+        self.inner.record_coinbase_tx(tx, block_hash).await
+    }
+
+    async fn record_conflicts(&self, tx: &Transaction) -> Result<Vec<OutpointConflict>> {
+        self.inner.record_conflicts(tx).await
+    }
+
+    async fn block_is_covered(&self, block_height: u64) -> Result<bool> {
+        self.inner.block_is_covered(block_height).await
+    }
+
+    async fn block_recorded(&self, block_hash: &BlockHash) -> Result<bool> {
+        self.inner.block_recorded(block_hash).await
+    }
+
+    async fn last_recorded_block_height(&self) -> Result<Option<u64>> {
+        self.inner.last_recorded_block_height().await
+    }
+
+    async fn record_backfilled_block(
+        &self,
+        block_height: u64,
+        block_hash: BlockHash,
+        block_time: u64,
+        txs: &[(Transaction, Amount)],
+    ) -> Result<()> {
+        self.inner
+            .record_backfilled_block(block_height, block_hash, block_time, txs)
+            .await
+    }
+
+    async fn record_out_of_band_txs(
+        &self,
+        block_height: Option<u64>,
+        block_hash: BlockHash,
+        block_time: u64,
+        txs: &[(Transaction, Amount)],
+    ) -> Result<()> {
+        self.inner
+            .record_out_of_band_txs(block_height, block_hash, block_time, txs)
+            .await
+    }
+
+    async fn record_block(
+        &self,
+        block_hash: BlockHash,
+        block_height: Option<u64>,
+        block_time: u64,
+        vsize: u64,
+        tx_count: usize,
+    ) -> Result<()> {
+        self.inner
+            .record_block(block_hash, block_height, block_time, vsize, tx_count)
+            .await
+    }
+
+    async fn record_purge_event(
+        &self,
+        txids: &[Txid],
+        mempool_min_fee_before: Option<FeeRate>,
+        mempool_min_fee_after: Option<FeeRate>,
+    ) -> Result<PurgeEvent> {
+        self.inner
+            .record_purge_event(txids, mempool_min_fee_before, mempool_min_fee_after)
+            .await
+    }
+
+    fn exceeded_large_value_threshold(&self, tx: &Transaction) -> Option<Amount> {
+        self.inner.exceeded_large_value_threshold(tx)
+    }
+
+    async fn large_value_txids(&self, start: u64, end: u64) -> Result<Vec<Txid>> {
+        self.inner.large_value_txids(start, end).await
+    }
+
+    async fn record_tx_links(
+        &self,
+        txid: &Txid,
+        depends: &[Txid],
+        spent_by: &[Txid],
+    ) -> Result<()> {
+        self.inner.record_tx_links(txid, depends, spent_by).await
+    }
+
+    async fn record_bip125_violations(&self, tx: &Transaction, violations: &[&str]) -> Result<()> {
+        self.inner.record_bip125_violations(tx, violations).await
+    }
+
+    async fn descendant_count(&self, txid: &Txid) -> Result<usize> {
+        self.inner.descendant_count(txid).await
+    }
+
+    async fn get_tx_variants(&self, inputs_hash: &str) -> Result<Vec<Transaction>> {
+        self.inner.get_tx_variants(inputs_hash).await
+    }
+
+    async fn get_txid_history(&self, inputs_hash: &str) -> Result<Vec<(Txid, u64)>> {
+        self.inner.get_txid_history(inputs_hash).await
+    }
+
+    async fn get_dust_count(&self, inputs_hash: &str) -> Result<Option<usize>> {
+        self.inner.get_dust_count(inputs_hash).await
+    }
+
+    async fn record_checkpoint(&self, best_block_hash: BlockHash) -> Result<Checkpoint> {
+        self.inner.record_checkpoint(best_block_hash).await
+    }
+
+    async fn apply_retention(&self) -> Result<(usize, usize)> {
+        self.inner.apply_retention().await
+    }
+
+    async fn latest_checkpoint(&self) -> Result<Option<Checkpoint>> {
+        self.inner.latest_checkpoint().await
+    }
+
+    async fn truc_stats(&self, start: u64, end: u64) -> Result<TrucStats> {
+        self.inner.truc_stats(start, end).await
+    }
+
+    async fn get_immature_coinbases(&self, tip_height: u64) -> Result<Vec<Txid>> {
+        self.inner.get_immature_coinbases(tip_height).await
+    }
+
+    async fn get_dropped_txs(&self, start: u64, end: u64) -> Result<Vec<Txid>> {
+        self.inner.get_dropped_txs(start, end).await
+    }
+
+    async fn get_block_fees(&self, height: u64) -> Result<Option<u64>> {
+        self.inner.get_block_fees(height).await
+    }
+
+    async fn per_miner_stats(&self, start: u64, end: u64) -> Result<Vec<MinerStats>> {
+        self.inner.per_miner_stats(start, end).await
+    }
+
+    async fn sighash_usage(&self, start: u64, end: u64) -> Result<Vec<(SighashKind, u64)>> {
+        self.inner.sighash_usage(start, end).await
+    }
+
+    async fn last_mempool_min_fee(&self) -> Result<Option<FeeRate>> {
+        self.inner.last_mempool_min_fee().await
+    }
+
+    async fn replay_classification(&self) -> Result<usize> {
+        self.inner.replay_classification().await
+    }
+
+    async fn pending_txs(
+        &self,
+        after_key: &str,
+        limit: usize,
+    ) -> Result<Vec<(String, Transaction)>> {
+        self.inner.pending_txs(after_key, limit).await
+    }
+
+    async fn get_tx_record(&self, txid: &Txid) -> Result<Option<TransactionInner>> {
+        self.inner.get_tx_record(txid).await
+    }
+
+    async fn pending_tx_records(
+        &self,
+        found_before: u64,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<TransactionInner>> {
+        self.inner
+            .pending_tx_records(found_before, limit, offset)
+            .await
+    }
+
+    async fn mempool_snapshots(
+        &self,
+        start: u64,
+        end: u64,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<MempoolSnapshot>> {
+        self.inner
+            .mempool_snapshots(start, end, limit, offset)
+            .await
+    }
+
+    async fn rbf_history_of(&self, txid: &Txid) -> Result<Vec<RbfRecord>> {
+        self.inner.rbf_history_of(txid).await
+    }
+
+    async fn record_sighting(&self, txid: &Txid, node_id: &str, seen_at: u64) -> Result<()> {
+        self.inner.record_sighting(txid, node_id, seen_at).await
+    }
+
+    async fn propagation_deltas(&self, start: u64, end: u64) -> Result<Vec<PropagationDelta>> {
+        self.inner.propagation_deltas(start, end).await
+    }
+
+    async fn db_stats(&self, rbf_since: u64) -> Result<DbStats> {
+        self.inner.db_stats(rbf_since).await
+    }
+
+    async fn get_tx_by_txid(&self, txid: &Txid) -> Result<Option<Transaction>> {
+        self.inner.get_tx_by_txid(txid).await
+    }
+
+    async fn daily_summary(&self, day_start: u64) -> Result<DailySummary> {
+        self.inner.daily_summary(day_start).await
+    }
+
+    async fn average_bump_stats(&self, start: u64, end: u64) -> Result<BumpStats> {
+        self.inner.average_bump_stats(start, end).await
+    }
+
+    async fn rbf_leaderboard(
+        &self,
+        start: u64,
+        end: u64,
+        limit: usize,
+    ) -> Result<Vec<(String, u64, u64)>> {
+        self.inner.rbf_leaderboard(start, end, limit).await
+    }
+
+    async fn total_rbf_fee_delta(&self, start: u64, end: u64) -> Result<u64> {
+        self.inner.total_rbf_fee_delta(start, end).await
+    }
+
+    async fn latency_by_fee_bucket(
+        &self,
+        start: u64,
+        end: u64,
+        buckets: &[f64],
+    ) -> Result<Vec<(f64, DwellStats)>> {
+        self.inner.latency_by_fee_bucket(start, end, buckets).await
+    }
+
+    async fn get_children(&self, txid: &Txid) -> Result<Vec<Txid>> {
+        self.inner.get_children(txid).await
+    }
+
+    async fn get_parents(&self, txid: &Txid) -> Result<Vec<Txid>> {
+        self.inner.get_parents(txid).await
+    }
+
+    async fn evictions_with_min_fee(&self, start: u64, end: u64) -> Result<Vec<EvictionContext>> {
+        self.inner.evictions_with_min_fee(start, end).await
+    }
+
+    async fn out_of_band_stats(&self, start: u64, end: u64) -> Result<Vec<OutOfBandStats>> {
+        self.inner.out_of_band_stats(start, end).await
+    }
+
+    async fn purge_events(&self, start: u64, end: u64) -> Result<Vec<PurgeEvent>> {
+        self.inner.purge_events(start, end).await
+    }
+
+    async fn fee_histogram_series(&self, start: u64, end: u64) -> Result<Vec<FeeHistogramBand>> {
+        self.inner.fee_histogram_series(start, end).await
+    }
+
+    async fn get_txs_after_seq(&self, seq: i64, limit: usize) -> Result<Vec<(i64, Txid)>> {
+        self.inner.get_txs_after_seq(seq, limit).await
+    }
+
+    async fn effective_vs_naive_feerate(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<FeeRateComparison>> {
+        self.inner.effective_vs_naive_feerate(start, end).await
+    }
+
+    async fn type_distribution(&self, start: u64, end: u64) -> Result<Vec<(TxType, u64)>> {
+        self.inner.type_distribution(start, end).await
+    }
+
+    async fn op_return_stats(&self, start: u64, end: u64) -> Result<OpReturnStats> {
+        self.inner.op_return_stats(start, end).await
+    }
+
+    async fn unconfirmed_package(&self, txid: &Txid) -> Result<UnconfirmedPackage> {
+        self.inner.unconfirmed_package(txid).await
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use log::{info, warn};
+use sd_notify::NotifyState;
+use tokio::sync::broadcast;
+
+use crate::health::Health;
+
+/// Tells systemd the tracker is up, for `Type=notify` units. Outside systemd there's no
+/// notify socket and nothing is sent
+pub fn notify_ready() {
+    notify(&[NotifyState::Ready], "readiness");
+}
+
+/// Tells systemd the tracker is shutting down, so the stop isn't taken for a failure
+pub fn notify_stopping() {
+    notify(&[NotifyState::Stopping], "shutdown");
+}
+
+fn notify(state: &[NotifyState], what: &str) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        warn!("Failed to notify systemd of the {}: {}", what, e);
+    }
+}
+
+/// How often to ping the watchdog, half the unit's `WatchdogSec`. `None` without one
+pub fn watchdog_interval() -> Option<Duration> {
+    let mut usec = 0;
+    sd_notify::watchdog_enabled(false, &mut usec).then(|| Duration::from_micros(usec / 2))
+}
+
+/// Pings the systemd watchdog every `interval` for as long as `health` shows the pipeline
+/// alive, i.e. a zmq message or an RPC success within `max_silence`. A wedged tracker
+/// stops pinging and systemd restarts it once `WatchdogSec` runs out
+pub async fn run_watchdog(
+    health: Health,
+    max_silence: Duration,
+    interval: Duration,
+    mut shutdown: broadcast::Receiver<()>,
+) {
+    info!("Pinging the systemd watchdog every {:?}", interval);
+    let mut ticks = tokio::time::interval(interval);
+    let mut stalled = false;
+    loop {
+        tokio::select! {
+            _ = shutdown.recv() => {
+                info!("Shutting down systemd watchdog task");
+                break;
+            }
+            _ = ticks.tick() => match health.stall(max_silence) {
+                None => {
+                    if stalled {
+                        info!("Pipeline alive again, pinging the systemd watchdog");
+                        stalled = false;
+                    }
+                    notify(&[NotifyState::Watchdog], "watchdog ping");
+                }
+                Some(reason) => {
+                    // Once per stall, systemd has the last word
+                    if !stalled {
+                        warn!("Not pinging the systemd watchdog: {}", reason);
+                        stalled = true;
+                    }
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use bitcoin::BlockHash;
+
+use crate::now;
+
+/// Chain tip as observed by the `MempoolState` task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObservedTip {
+    pub height: u64,
+    pub hash: BlockHash,
+    /// When this tip was first observed
+    pub observed_at: u64,
+}
+
+/// Last chain tip seen by the workers, shared with the app so a stalled view of the
+/// chain (zmq or RPC stuck) can be alerted on
+#[derive(Debug, Clone)]
+pub struct TipTracker {
+    last: Arc<Mutex<Option<ObservedTip>>>,
+    /// Until a first tip is observed the age counts from startup
+    started_at: u64,
+    stale_after: Duration,
+}
+
+impl TipTracker {
+    pub fn new(stale_after: Duration) -> Self {
+        Self {
+            last: Arc::new(Mutex::new(None)),
+            started_at: now!(),
+            stale_after,
+        }
+    }
+
+    /// Record the node's current tip. The observation time only moves when the tip
+    /// changes, returns whether it did
+    pub fn observe(&self, height: u64, hash: BlockHash) -> bool {
+        let mut last = self.last.lock().expect("tip tracker lock poisoned");
+        if last.is_some_and(|tip| tip.hash == hash) {
+            return false;
+        }
+        *last = Some(ObservedTip {
+            height,
+            hash,
+            observed_at: now!(),
+        });
+        true
+    }
+
+    pub fn last(&self) -> Option<ObservedTip> {
+        *self.last.lock().expect("tip tracker lock poisoned")
+    }
+
+    /// Overwrite the last observation, e.g. with a tip restored from elsewhere
+    pub fn set(&self, tip: ObservedTip) {
+        *self.last.lock().expect("tip tracker lock poisoned") = Some(tip);
+    }
+
+    /// Seconds since the tip last changed
+    pub fn age_secs(&self) -> u64 {
+        let observed_at = self.last().map_or(self.started_at, |tip| tip.observed_at);
+        now!().saturating_sub(observed_at)
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.age_secs() > self.stale_after.as_secs()
+    }
+}
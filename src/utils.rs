@@ -1,6 +1,14 @@
+use std::{fmt, str::FromStr};
+
 use anyhow::Result;
-use bitcoin::{consensus::Encodable, Amount, FeeRate, Transaction, TxIn};
-use bitcoin_hashes::Sha256;
+use bitcoin::{
+    consensus::Encodable,
+    io::{self, Write},
+    opcodes::{Class, ClassifyContext},
+    script::Instruction,
+    Amount, FeeRate, OutPoint, Script, Transaction, TxIn,
+};
+use bitcoin_hashes::{sha256, HashEngine, Sha256};
 
 // Prune tx witness in place
 pub fn prune_large_witnesses(tx: &mut Transaction) {
@@ -9,13 +17,58 @@ pub fn prune_large_witnesses(tx: &mut Transaction) {
     });
 }
 
-pub fn get_inputs_hash(inputs: impl IntoIterator<Item = TxIn>) -> Result<String> {
+/// Feeds consensus encoded bytes straight into the hash engine
+struct HashWriter<'a>(&'a mut sha256::HashEngine);
+
+impl Write for HashWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.input(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash of the inputs in transaction order. A canonical tx has a single input order, so
+/// two txs spending the same outpoints in a different order hash differently, see
+/// `get_inputs_hash_orderless` to collapse them
+pub fn get_inputs_hash(inputs: &[TxIn]) -> Result<String> {
+    get_inputs_hash_tagged(inputs, None)
+}
+
+/// Hash of the spent outpoints sorted by `(txid, vout)`, so a replacement that reorders
+/// the inputs maps to the same hash. Unlike `get_inputs_hash` the scriptSigs and
+/// sequences are left out: a reordered legacy replacement carries new signatures
+pub fn get_inputs_hash_orderless(inputs: &[TxIn]) -> Result<String> {
+    let mut outpoints: Vec<OutPoint> = inputs.iter().map(|input| input.previous_output).collect();
+    outpoints.sort();
+    let mut engine = Sha256::engine();
+    let mut writer = HashWriter(&mut engine);
+    for outpoint in outpoints {
+        outpoint
+            .consensus_encode(&mut writer)
+            .expect("encoding doesn't error");
+    }
+    Ok(hex::encode(Sha256::from_engine(engine).as_byte_array()))
+}
+
+/// Inputs hash namespaced by a domain tag, e.g. the network name, so monitors sharing a
+/// store don't collide. The tag is length-prefixed ahead of the inputs, `None` hashes
+/// exactly like `get_inputs_hash`
+pub fn get_inputs_hash_tagged(inputs: &[TxIn], tag: Option<&str>) -> Result<String> {
     let mut engine = Sha256::engine();
+    let mut writer = HashWriter(&mut engine);
+    if let Some(tag) = tag {
+        tag.as_bytes()
+            .to_vec()
+            .consensus_encode(&mut writer)
+            .expect("encoding doesn't error");
+    }
     for i in inputs {
-        let mut writer = vec![];
         i.consensus_encode(&mut writer)
             .expect("encoding doesn't error");
-        std::io::copy(&mut writer.as_slice(), &mut engine).expect("engine writes don't error");
     }
 
     let hash = Sha256::from_engine(engine);
@@ -23,6 +76,165 @@ pub fn get_inputs_hash(inputs: impl IntoIterator<Item = TxIn>) -> Result<String>
     Ok(hex::encode(hash_bytes))
 }
 
+/// Primary key of a transaction in the `transactions` table.
+/// Regular transactions are keyed by the hash of their inputs so that RBF replacements
+/// land on the same row. A coinbase only has a single null-outpoint input, so it is keyed
+/// by its txid instead, which is unique per block (BIP34 commits the height in the scriptSig).
+///
+/// Outputs are deliberately left out: two txs spending the same inputs can't both confirm,
+/// so they are variants of one slot rather than distinct rows. The row holds the latest
+/// variant, every variant's full tx is kept in `txid_history` (see `get_tx_variants`)
+pub fn get_tx_key(tx: &Transaction) -> Result<String> {
+    get_tx_key_tagged(tx, None)
+}
+
+/// `get_tx_key` with the inputs hash namespaced by `tag`, coinbase keys are txids either way
+pub fn get_tx_key_tagged(tx: &Transaction, tag: Option<&str>) -> Result<String> {
+    if tx.is_coinbase() {
+        return Ok(tx.compute_txid().to_string());
+    }
+    get_inputs_hash_tagged(&tx.input, tag)
+}
+
+/// Fee increase of a replacement relative to the fee we last stored for the same inputs.
+/// Everything but the new fee is `None` when we never knew the original fee
+#[derive(Debug, Clone, PartialEq)]
+pub struct RbfBump {
+    pub fee: Amount,
+    pub fee_rate: FeeRate,
+    pub prev_fee: Option<Amount>,
+    pub fee_delta: Option<i64>,
+    pub fee_rate_delta: Option<i64>,
+    pub fee_bump_pct: Option<f64>,
+    /// The first fee seen for the inputs: the original's was never known, or stored as
+    /// 0. There is no baseline to measure a bump against
+    pub is_initial_observation: bool,
+}
+
+impl RbfBump {
+    pub fn new(prev: Option<(Amount, FeeRate)>, fee: Amount, fee_rate: FeeRate) -> Self {
+        let Some((prev_fee, prev_fee_rate)) = prev else {
+            return Self {
+                fee,
+                fee_rate,
+                prev_fee: None,
+                fee_delta: None,
+                fee_rate_delta: None,
+                fee_bump_pct: None,
+                is_initial_observation: true,
+            };
+        };
+        let fee_delta = fee.to_sat() as i64 - prev_fee.to_sat() as i64;
+        let fee_rate_delta =
+            fee_rate.to_sat_per_vb_ceil() as i64 - prev_fee_rate.to_sat_per_vb_ceil() as i64;
+        // A zero baseline has no meaningful percentage
+        let is_initial_observation = prev_fee == Amount::ZERO;
+        let fee_bump_pct =
+            (!is_initial_observation).then(|| fee_delta as f64 / prev_fee.to_sat() as f64 * 100.0);
+        Self {
+            fee,
+            fee_rate,
+            prev_fee: Some(prev_fee),
+            fee_delta: Some(fee_delta),
+            fee_rate_delta: Some(fee_rate_delta),
+            fee_bump_pct,
+            is_initial_observation,
+        }
+    }
+}
+
+/// BIP125 rule 5, the most txs a single replacement may evict
+pub const BIP125_MAX_REPLACED: usize = 100;
+/// Fee rate a replacement pays for its own relay on top of the original's fee, rule 4
+pub const INCREMENTAL_RELAY_SAT_PER_VB: u64 = 1;
+
+/// Per-rule outcome of a replacement against BIP125 (rule 6 as added by Bitcoin Core).
+/// Violations of a replacement the network relayed point at full-RBF or other policy
+/// differences between nodes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bip125Verdict {
+    /// Rule 1, the original signaled replaceability
+    pub signals_rbf: bool,
+    /// Rule 2, outpoints the original didn't spend. Only unconfirmed ones violate the
+    /// rule, which `retain_unconfirmed_inputs` narrows down to
+    pub new_inputs: Vec<OutPoint>,
+    /// Rule 3, pays at least the original's absolute fee
+    pub higher_absolute_fee: bool,
+    /// Rule 4, the fee increase covers the replacement's vsize at the incremental relay fee
+    pub pays_for_bandwidth: bool,
+    /// Rule 5, the original and its descendants, 1 until `with_replaced_count`
+    pub replaced_count: usize,
+    /// Rule 6, pays a higher fee rate than the original
+    pub higher_fee_rate: bool,
+}
+
+impl Bip125Verdict {
+    /// Drop the new inputs whose outputs are confirmed, they don't violate rule 2
+    pub fn retain_unconfirmed_inputs(mut self, is_unconfirmed: impl Fn(&OutPoint) -> bool) -> Self {
+        self.new_inputs.retain(is_unconfirmed);
+        self
+    }
+
+    pub fn with_replaced_count(mut self, replaced_count: usize) -> Self {
+        self.replaced_count = replaced_count;
+        self
+    }
+
+    /// Names of the violated rules, empty for a valid replacement
+    pub fn violations(&self) -> Vec<&'static str> {
+        [
+            (!self.signals_rbf, "rule1_signaling"),
+            (!self.new_inputs.is_empty(), "rule2_new_unconfirmed_inputs"),
+            (!self.higher_absolute_fee, "rule3_absolute_fee"),
+            (!self.pays_for_bandwidth, "rule4_relay_fee"),
+            (
+                self.replaced_count > BIP125_MAX_REPLACED,
+                "rule5_replaced_count",
+            ),
+            (!self.higher_fee_rate, "rule6_fee_rate"),
+        ]
+        .into_iter()
+        .filter_map(|(violated, rule)| violated.then_some(rule))
+        .collect()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.violations().is_empty()
+    }
+}
+
+/// Check `new` replacing `old` against the BIP125 rules that only need the two txs and
+/// their fees (sats). Every input `old` didn't spend counts against rule 2 until the
+/// caller filters the confirmed ones out, and only `old` itself counts as replaced
+pub fn check_bip125_rules(
+    old: &Transaction,
+    old_fee: u64,
+    new: &Transaction,
+    new_fee: u64,
+) -> Bip125Verdict {
+    let new_inputs = new
+        .input
+        .iter()
+        .map(|input| input.previous_output)
+        .filter(|outpoint| {
+            !old.input
+                .iter()
+                .any(|input| input.previous_output == *outpoint)
+        })
+        .collect();
+    let (old_vsize, new_vsize) = (old.vsize() as u64, new.vsize() as u64);
+    Bip125Verdict {
+        signals_rbf: old.is_explicitly_rbf(),
+        new_inputs,
+        higher_absolute_fee: new_fee >= old_fee,
+        pays_for_bandwidth: new_fee.saturating_sub(old_fee)
+            >= new_vsize * INCREMENTAL_RELAY_SAT_PER_VB,
+        replaced_count: 1,
+        // Compared cross-multiplied to avoid rounding either rate
+        higher_fee_rate: new_fee as u128 * old_vsize as u128 > old_fee as u128 * new_vsize as u128,
+    }
+}
+
 /// Compute the fee rate of a transaction
 pub fn compute_fee_rate(tx: &Transaction, absolute_fee: Amount) -> Result<FeeRate> {
     if tx.is_coinbase() {
@@ -33,3 +245,379 @@ pub fn compute_fee_rate(tx: &Transaction, absolute_fee: Amount) -> Result<FeeRat
         .ok_or(anyhow::anyhow!("Fee rate is 0"))?;
     Ok(fee_rate)
 }
+
+/// Number of OP_RETURN outputs and their summed payload size (script bytes after the opcode)
+pub fn op_return_bytes(tx: &Transaction) -> (u64, u64) {
+    tx.output
+        .iter()
+        .filter(|output| output.script_pubkey.is_op_return())
+        .fold((0, 0), |(count, bytes), output| {
+            (count + 1, bytes + output.script_pubkey.len() as u64 - 1)
+        })
+}
+
+/// Outputs paying less than `dust_threshold`. OP_RETURN outputs are never dust
+pub fn count_dust_outputs(tx: &Transaction, dust_threshold: Amount) -> usize {
+    tx.output
+        .iter()
+        .filter(|output| !output.script_pubkey.is_op_return() && output.value < dust_threshold)
+        .count()
+}
+
+/// Outputs below the default relay dust limit of their script type, e.g. 546 sats for
+/// p2pkh, 294 for p2wpkh and 330 for p2tr
+pub fn count_relay_dust_outputs(tx: &Transaction) -> usize {
+    tx.output
+        .iter()
+        .filter(|output| {
+            !output.script_pubkey.is_op_return()
+                && output.value < output.script_pubkey.minimal_non_dust()
+        })
+        .count()
+}
+
+/// How a transaction spends its inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TxType {
+    Legacy,
+    /// Segwit v0 wrapped in p2sh
+    P2shSegwit,
+    SegwitV0,
+    TaprootKeyPath,
+    TaprootScriptPath,
+    /// Inputs of more than one kind
+    Mixed,
+}
+
+impl TxType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxType::Legacy => "legacy",
+            TxType::P2shSegwit => "p2sh_segwit",
+            TxType::SegwitV0 => "segwit_v0",
+            TxType::TaprootKeyPath => "taproot_keypath",
+            TxType::TaprootScriptPath => "taproot_scriptpath",
+            TxType::Mixed => "mixed",
+        }
+    }
+}
+
+impl fmt::Display for TxType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TxType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "legacy" => TxType::Legacy,
+            "p2sh_segwit" => TxType::P2shSegwit,
+            "segwit_v0" => TxType::SegwitV0,
+            "taproot_keypath" => TxType::TaprootKeyPath,
+            "taproot_scriptpath" => TxType::TaprootScriptPath,
+            "mixed" => TxType::Mixed,
+            _ => return Err(anyhow::anyhow!("Unknown tx type: {}", s)),
+        })
+    }
+}
+
+/// True if the scriptSig is a single push of a v0 witness program (p2sh-p2wpkh / p2sh-p2wsh)
+fn is_nested_segwit_script_sig(script_sig: &Script) -> bool {
+    let bytes = script_sig.as_bytes();
+    match bytes {
+        [0x16, 0x00, 0x14, rest @ ..] => rest.len() == 20,
+        [0x22, 0x00, 0x20, rest @ ..] => rest.len() == 32,
+        _ => false,
+    }
+}
+
+/// Classify a single input from its scriptSig and witness.
+/// Prevouts aren't available so taproot is recognized by witness shape (BIP341)
+fn classify_input(input: &TxIn) -> TxType {
+    if input.witness.is_empty() {
+        return TxType::Legacy;
+    }
+    if !input.script_sig.is_empty() {
+        if is_nested_segwit_script_sig(&input.script_sig) {
+            return TxType::P2shSegwit;
+        }
+        return TxType::Legacy;
+    }
+
+    let mut elements: Vec<&[u8]> = input.witness.iter().collect();
+    // Drop the annex if present
+    if elements.len() >= 2 && elements.last().is_some_and(|e| e.first() == Some(&0x50)) {
+        elements.pop();
+    }
+    match elements.as_slice() {
+        // Single schnorr signature, optionally with a sighash byte
+        [sig] if sig.len() == 64 || sig.len() == 65 => TxType::TaprootKeyPath,
+        // Script inputs, leaf script, control block
+        [.., _script, control]
+            if control.len() >= 33
+                && (control.len() - 33) % 32 == 0
+                && control[0] & 0xfe == 0xc0 =>
+        {
+            TxType::TaprootScriptPath
+        }
+        _ => TxType::SegwitV0,
+    }
+}
+
+/// Sighash type committed to by an input signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SighashKind {
+    /// Taproot signature without a sighash byte
+    Default,
+    All,
+    None,
+    Single,
+    AllAnyoneCanPay,
+    NoneAnyoneCanPay,
+    SingleAnyoneCanPay,
+    /// Non-standard sighash byte, or an input without a signature we can parse
+    Unknown,
+}
+
+impl SighashKind {
+    pub const KINDS: [SighashKind; 8] = [
+        SighashKind::Default,
+        SighashKind::All,
+        SighashKind::None,
+        SighashKind::Single,
+        SighashKind::AllAnyoneCanPay,
+        SighashKind::NoneAnyoneCanPay,
+        SighashKind::SingleAnyoneCanPay,
+        SighashKind::Unknown,
+    ];
+
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            0x01 => SighashKind::All,
+            0x02 => SighashKind::None,
+            0x03 => SighashKind::Single,
+            0x81 => SighashKind::AllAnyoneCanPay,
+            0x82 => SighashKind::NoneAnyoneCanPay,
+            0x83 => SighashKind::SingleAnyoneCanPay,
+            _ => SighashKind::Unknown,
+        }
+    }
+
+    /// Bit of this kind in a sighash mask
+    pub fn bit(&self) -> u32 {
+        1 << Self::KINDS
+            .iter()
+            .position(|kind| kind == self)
+            .expect("every kind is listed")
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SighashKind::Default => "default",
+            SighashKind::All => "all",
+            SighashKind::None => "none",
+            SighashKind::Single => "single",
+            SighashKind::AllAnyoneCanPay => "all_anyonecanpay",
+            SighashKind::NoneAnyoneCanPay => "none_anyonecanpay",
+            SighashKind::SingleAnyoneCanPay => "single_anyonecanpay",
+            SighashKind::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for SighashKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// DER encoded ECDSA signature followed by its sighash byte, checked by shape only
+fn ecdsa_sighash(element: &[u8]) -> Option<SighashKind> {
+    match element {
+        [0x30, der_len, .., sighash]
+            if element.len() >= 9 && *der_len as usize == element.len() - 3 =>
+        {
+            Some(SighashKind::from_byte(*sighash))
+        }
+        _ => None,
+    }
+}
+
+/// BIP341 signature, 64 bytes commits to the default sighash
+fn schnorr_sighash(element: &[u8]) -> Option<SighashKind> {
+    match element.len() {
+        64 => Some(SighashKind::Default),
+        65 => Some(SighashKind::from_byte(element[64])),
+        _ => None,
+    }
+}
+
+/// Sighash types of the signatures found in one input, `[Unknown]` if none parse
+fn input_sighash_kinds(input: &TxIn) -> Vec<SighashKind> {
+    let kinds: Vec<SighashKind> = match classify_input(input) {
+        TxType::Legacy => match input
+            .script_sig
+            .instructions()
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(instructions) => instructions
+                .iter()
+                .filter_map(|instruction| match instruction {
+                    Instruction::PushBytes(push) => ecdsa_sighash(push.as_bytes()),
+                    Instruction::Op(_) => None,
+                })
+                .collect(),
+            Err(_) => vec![],
+        },
+        TxType::P2shSegwit | TxType::SegwitV0 => {
+            input.witness.iter().filter_map(ecdsa_sighash).collect()
+        }
+        TxType::TaprootKeyPath => input
+            .witness
+            .iter()
+            .next()
+            .and_then(schnorr_sighash)
+            .into_iter()
+            .collect(),
+        // Signatures are among the script inputs, ahead of the leaf script and control block
+        TxType::TaprootScriptPath => {
+            let mut elements: Vec<&[u8]> = input.witness.iter().collect();
+            if elements.last().is_some_and(|e| e.first() == Some(&0x50)) {
+                elements.pop();
+            }
+            let script_inputs = elements.len().saturating_sub(2);
+            elements[..script_inputs]
+                .iter()
+                .filter_map(|element| schnorr_sighash(element))
+                .collect()
+        }
+        TxType::Mixed => unreachable!("a single input has a single type"),
+    };
+    if kinds.is_empty() {
+        vec![SighashKind::Unknown]
+    } else {
+        kinds
+    }
+}
+
+/// Bitmask of the sighash types used across a tx's inputs, see `SighashKind::bit`.
+/// A coinbase has no signatures and gets an empty mask
+pub fn sighash_mask(tx: &Transaction) -> u32 {
+    if tx.is_coinbase() {
+        return 0;
+    }
+    tx.input
+        .iter()
+        .flat_map(input_sighash_kinds)
+        .fold(0, |mask, kind| mask | kind.bit())
+}
+
+/// Block subsidy at `height` on the mainnet halving schedule
+pub fn block_subsidy(height: u64) -> Amount {
+    let halvings = height / 210_000;
+    if halvings >= 64 {
+        return Amount::ZERO;
+    }
+    Amount::from_sat(50 * 100_000_000 >> halvings)
+}
+
+/// Height a coinbase commits to as the first push of its scriptSig (BIP34), `None` for
+/// other txs and coinbases predating BIP34
+pub fn coinbase_height(tx: &Transaction) -> Option<u64> {
+    if !tx.is_coinbase() {
+        return None;
+    }
+    let instruction = tx
+        .input
+        .first()?
+        .script_sig
+        .instructions_minimal()
+        .next()?
+        .ok()?;
+    let height = match instruction {
+        // Minimally encoded script number
+        Instruction::PushBytes(push) => push.read_scriptint().ok()?,
+        // Heights up to 16 are pushed as OP_1..OP_16
+        Instruction::Op(op) => match op.classify(ClassifyContext::Legacy) {
+            Class::PushNum(height) => i64::from(height),
+            _ => return None,
+        },
+    };
+    u64::try_from(height).ok()
+}
+
+/// Largest TRUC tx, and largest TRUC child of an unconfirmed TRUC parent (BIP431)
+pub const TRUC_MAX_VSIZE: u64 = 10_000;
+pub const TRUC_CHILD_MAX_VSIZE: u64 = 1_000;
+
+/// Topologically restricted until confirmation (BIP431), nVersion 3. nVersion used to be
+/// serialized as a signed int, it is decoded as one so e.g. 0xfffffffd is never taken
+/// for a TRUC tx
+pub fn is_truc(tx: &Transaction) -> bool {
+    tx.version.0 == 3
+}
+
+/// Classify a transaction by how its inputs are spent.
+/// A coinbase has nothing to spend so it is classified by the outputs it pays to
+pub fn classify_tx(tx: &Transaction) -> TxType {
+    let mut kinds: Vec<TxType> = if tx.is_coinbase() {
+        tx.output
+            .iter()
+            .filter(|output| !output.script_pubkey.is_op_return())
+            .map(|output| {
+                let spk = &output.script_pubkey;
+                if spk.is_p2tr() {
+                    TxType::TaprootKeyPath
+                } else if spk.is_p2wpkh() || spk.is_p2wsh() {
+                    TxType::SegwitV0
+                } else {
+                    TxType::Legacy
+                }
+            })
+            .collect()
+    } else {
+        tx.input.iter().map(classify_input).collect()
+    };
+    kinds.sort_by_key(|kind| kind.as_str());
+    kinds.dedup();
+    match kinds.as_slice() {
+        [] => TxType::Legacy,
+        [kind] => *kind,
+        _ => TxType::Mixed,
+    }
+}
+
+/// Unix milliseconds as an RFC 3339 UTC timestamp, e.g. `2024-01-31T12:00:00.250Z`
+pub fn rfc3339_millis(unix_ms: u64) -> String {
+    let secs = rfc3339(unix_ms / 1000);
+    format!("{}.{:03}Z", secs.trim_end_matches('Z'), unix_ms % 1000)
+}
+
+/// Unix seconds as an RFC 3339 UTC timestamp, e.g. `2024-01-31T12:00:00Z`
+pub fn rfc3339(unix_secs: u64) -> String {
+    let days = (unix_secs / 86_400) as i64;
+    let secs_of_day = unix_secs % 86_400;
+    // civil_from_days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    )
+}
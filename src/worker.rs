@@ -1,46 +1,481 @@
-use crate::{database::Database, utils::compute_fee_rate};
-use anyhow::Result;
+use crate::{
+    database::{Checkpoint, Database, MempoolChurn, DEFAULT_NODE_ID},
+    events::{EventPublisher, MempoolEvent},
+    filter::Filter,
+    health::Health,
+    now, now_ms,
+    rpc::{is_unknown_tx_error, BitcoinRpc, TxStatus},
+    store::MempoolStore,
+    tip::TipTracker,
+    utils::{check_bip125_rules, compute_fee_rate, Bip125Verdict, RbfBump},
+};
+use std::{
+    collections::HashSet,
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, Result};
 use async_channel::Receiver;
-use bitcoin::{consensus::Decodable, Amount, Transaction};
-use bitcoind_async_client::{traits::Reader, Client};
-use log::{debug, error, info};
-
-// Macro to execute a function, if its error, log it and continue
-macro_rules! log_error {
-    ($fn:expr, $arg:expr) => {
-        if let Err(e) = $fn($arg).await {
-            error!("Error: {}", e);
-            continue;
-        }
-    };
-    ($fn:expr, $arg1:expr, $arg2:expr) => {
-        if let Err(e) = $fn($arg1, $arg2) {
-            error!("Error: {}", e);
-            continue;
-        }
-    };
-    ($fn:expr, $arg1:expr, $arg2:expr, $arg3:expr) => {
-        if let Err(e) = $fn($arg1, $arg2, $arg3) {
-            error!("Error: {}", e);
-            continue;
-        }
-    };
+use bitcoin::{
+    block::Header, consensus::Decodable, Amount, Block, BlockHash, Transaction, Txid, Weight,
+};
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use tokio::sync::watch;
+
+/// Pending rows checked per rescan batch, with a pause between batches to spare the node
+const RESCAN_BATCH_SIZE: usize = 100;
+const RESCAN_BATCH_PAUSE: Duration = Duration::from_millis(50);
+
+/// A prune check removing at least this many txs, or this share of the pending ones
+/// (once at least `PURGE_MIN_TXS` are removed), is recorded as a single purge event
+const PURGE_THRESHOLD_TXS: usize = 1_000;
+const PURGE_THRESHOLD_SHARE: f64 = 0.25;
+const PURGE_MIN_TXS: usize = 10;
+
+/// Changes announced on the sequence zmq topic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceEvent {
+    BlockConnected(BlockHash),
+    BlockDisconnected(BlockHash),
+    TxAdded(Txid),
+    /// Left the mempool other than by being mined, e.g. replaced, evicted or expired
+    TxRemoved(Txid),
 }
+
 #[derive(Debug, Clone)]
 pub enum Task {
     RawTx(Vec<u8>),
+    /// A raw tx read back from a capture file, found when it was captured (unix ms)
+    ReplayedRawTx {
+        raw_tx: Vec<u8>,
+        found_at: u64,
+    },
     PruneCheck,
     MempoolState,
+    /// Reconcile every pending row with the node
+    Rescan,
+    /// A block was connected, from the hashblock zmq topic
+    NewBlock(BlockHash),
+    /// A connected block in full, from the rawblock zmq topic
+    RawBlock(Vec<u8>),
+    /// A mempool or chain change, from the sequence zmq topic
+    Sequence(SequenceEvent),
+    /// Ingest the mined txs of an inclusive historical block range. Fees are looked up
+    /// from the prevouts with `getrawtransaction`, so the node needs txindex
+    Backfill {
+        from_height: u64,
+        to_height: u64,
+    },
+    /// Pull in mempool txs we don't track, after zmq notifications were missed
+    ReconcileMempool,
+    /// Flush and record a checkpoint consumers can resume from
+    Checkpoint,
+}
+
+impl Task {
+    /// Short label used in log context
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Task::RawTx(_) => "raw_tx",
+            Task::ReplayedRawTx { .. } => "replayed_raw_tx",
+            Task::PruneCheck => "prune_check",
+            Task::MempoolState => "mempool_state",
+            Task::Rescan => "rescan",
+            Task::NewBlock(_) => "new_block",
+            Task::RawBlock(_) => "raw_block",
+            Task::Sequence(_) => "sequence",
+            Task::Backfill { .. } => "backfill",
+            Task::ReconcileMempool => "reconcile_mempool",
+            Task::Checkpoint => "checkpoint",
+        }
+    }
+
+    /// Block a zmq notification says was connected, whichever topic announced it
+    pub fn connected_block(&self) -> Option<BlockHash> {
+        match self {
+            Task::NewBlock(hash) | Task::Sequence(SequenceEvent::BlockConnected(hash)) => {
+                Some(*hash)
+            }
+            Task::RawBlock(raw_block) => Header::consensus_decode(&mut &raw_block[..])
+                .ok()
+                .map(|header| header.block_hash()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Task::RawTx(raw_tx) => write!(f, "raw tx ({} bytes)", raw_tx.len()),
+            Task::ReplayedRawTx { raw_tx, found_at } => {
+                write!(
+                    f,
+                    "replayed raw tx ({} bytes, found at {})",
+                    raw_tx.len(),
+                    found_at
+                )
+            }
+            Task::PruneCheck => write!(f, "prune check"),
+            Task::MempoolState => write!(f, "mempool state"),
+            Task::Rescan => write!(f, "rescan"),
+            Task::NewBlock(hash) => write!(f, "new block {}", hash),
+            Task::RawBlock(raw_block) => write!(f, "raw block ({} bytes)", raw_block.len()),
+            Task::Sequence(event) => write!(f, "sequence {:?}", event),
+            Task::Backfill {
+                from_height,
+                to_height,
+            } => write!(f, "backfill {}..={}", from_height, to_height),
+            Task::ReconcileMempool => write!(f, "reconcile mempool"),
+            Task::Checkpoint => write!(f, "checkpoint"),
+        }
+    }
+}
+
+/// A task tagged with when it was queued, so workers can tell how long it waited
+#[derive(Debug)]
+pub struct Queued {
+    pub task: Task,
+    pub enqueued_at: Instant,
+}
+
+impl From<Task> for Queued {
+    fn from(task: Task) -> Self {
+        Self {
+            task,
+            enqueued_at: Instant::now(),
+        }
+    }
+}
+
+/// What processing a task did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessOutcome {
+    /// New mempool tx stored
+    Inserted,
+    /// A tracked tx confirmed
+    Mined,
+    /// A tracked tx was replaced
+    Rbf,
+    Coinbase,
+    /// Re-announcement of a tx we already track
+    Duplicate,
+    /// Nothing recorded: filtered out or undecodable
+    Skipped,
+    /// Number of tracked txs that left the mempool unmined
+    Pruned(usize),
+    MempoolStateRecorded,
+    /// Tracked txs confirmed by the block, txs it confirmed that we never saw, then txs
+    /// pruned by the follow-up prune check
+    NewBlock {
+        mined: usize,
+        out_of_band: usize,
+        pruned: usize,
+    },
+    /// Pending rows found mined or gone from the node
+    Rescanned {
+        mined: usize,
+        pruned: usize,
+    },
+    /// Number of blocks ingested, already covered blocks are not counted
+    Backfilled(usize),
+    /// Number of missing mempool txs stored
+    Reconciled(usize),
+    /// Highest transaction seq covered by the checkpoint
+    Checkpointed(i64),
+}
+
+impl fmt::Display for ProcessOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessOutcome::Inserted => write!(f, "inserted"),
+            ProcessOutcome::Mined => write!(f, "mined"),
+            ProcessOutcome::Rbf => write!(f, "rbf"),
+            ProcessOutcome::Coinbase => write!(f, "coinbase"),
+            ProcessOutcome::Duplicate => write!(f, "duplicate"),
+            ProcessOutcome::Skipped => write!(f, "skipped"),
+            ProcessOutcome::Pruned(n) => write!(f, "pruned count={}", n),
+            ProcessOutcome::MempoolStateRecorded => write!(f, "mempool_state_recorded"),
+            ProcessOutcome::NewBlock {
+                mined,
+                out_of_band,
+                pruned,
+            } => write!(
+                f,
+                "new_block mined={} out_of_band={} pruned={}",
+                mined, out_of_band, pruned
+            ),
+            ProcessOutcome::Rescanned { mined, pruned } => {
+                write!(f, "rescanned mined={} pruned={}", mined, pruned)
+            }
+            ProcessOutcome::Backfilled(n) => write!(f, "backfilled count={}", n),
+            ProcessOutcome::Reconciled(n) => write!(f, "reconciled count={}", n),
+            ProcessOutcome::Checkpointed(seq) => write!(f, "checkpointed last_seq={}", seq),
+        }
+    }
+}
+
+/// `key=value` prefix shared by every log line about a task, so a single tx's
+/// journey can be followed with `grep txid=<txid>`
+#[derive(Debug)]
+struct LogContext {
+    task: &'static str,
+    txid: Option<Txid>,
+    started: Instant,
+}
+
+impl LogContext {
+    fn new(task: &Task) -> Self {
+        Self {
+            task: task.kind(),
+            txid: None,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl fmt::Display for LogContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "task={}", self.task)?;
+        if let Some(txid) = self.txid {
+            write!(f, " txid={}", txid)?;
+        }
+        write!(f, " elapsed_ms={}", self.started.elapsed().as_millis())
+    }
+}
+
+/// Outcome of checking a same-inputs arrival against the node's mempool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplacementCheck {
+    /// The stored original is still acceptable, the arrival is a re-announcement
+    Duplicate,
+    /// The original no longer fits in the mempool. `reject_reason` is the node's
+    /// testmempoolaccept reason, `None` if it couldn't be verified
+    Replaced { reject_reason: Option<String> },
+}
+
+/// How a new block's tracked txs are found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MinedDetection {
+    /// Fetch the whole block and look every tx up
+    #[default]
+    FullBlock,
+    /// Test the pending txs' output scripts against the block's BIP158 filter and only
+    /// fetch the block on a match, for pruned or remote nodes. Needs `-blockfilterindex`,
+    /// out-of-band txs are only recorded for the blocks fetched
+    BlockFilter,
+}
+
+impl FromStr for MinedDetection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "full-block" => MinedDetection::FullBlock,
+            "block-filter" => MinedDetection::BlockFilter,
+            _ => return Err(anyhow::anyhow!("Unknown mined detection mode: {}", s)),
+        })
+    }
 }
 
-pub struct TaskContext {
-    bitcoind: Client,
-    db: Database,
-    tasks: Receiver<Task>,
+/// Txids of the last mempool poll, shared by the workers so whichever handles the next
+/// `MempoolState` task diffs against it
+#[derive(Debug, Clone, Default)]
+pub struct MempoolPoll {
+    last: Arc<Mutex<Option<HashSet<Txid>>>>,
+}
+
+impl MempoolPoll {
+    /// Keep `txids` for the next poll and count what entered and left since the previous
+    /// one, `None` on the first poll
+    pub fn diff(&self, txids: Vec<Txid>) -> Option<MempoolChurn> {
+        let current: HashSet<Txid> = txids.into_iter().collect();
+        let mut last = self.last.lock().expect("mempool poll lock poisoned");
+        let churn = last.as_ref().map(|previous| MempoolChurn {
+            added_since_last: current.difference(previous).count() as u64,
+            removed_since_last: previous.difference(&current).count() as u64,
+        });
+        *last = Some(current);
+        churn
+    }
+}
+
+/// Serializes the recording of blocks between the workers sharing it. Without it a block's
+/// `NewBlock` task and a prune check catching up with the same block could both find it
+/// unrecorded and record its txs twice
+#[derive(Debug, Clone, Default)]
+pub struct BlockRecorder {
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl BlockRecorder {
+    async fn lock(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.lock.lock().await
+    }
+}
+
+/// What the workers of a dry run would have written, shared between them. Their
+/// `DryRunStore` drops the writes, these are the outcomes the store never saw
+#[derive(Debug, Clone, Default)]
+pub struct DryRunSummary {
+    inserted: Arc<AtomicU64>,
+    mined: Arc<AtomicU64>,
+    rbf: Arc<AtomicU64>,
+}
+
+impl DryRunSummary {
+    pub fn record(&self, outcome: &ProcessOutcome) {
+        let (inserted, mined, rbf) = match *outcome {
+            ProcessOutcome::Inserted | ProcessOutcome::Coinbase => (1, 0, 0),
+            ProcessOutcome::Reconciled(n) => (n, 0, 0),
+            ProcessOutcome::Mined => (0, 1, 0),
+            ProcessOutcome::NewBlock { mined, .. } | ProcessOutcome::Rescanned { mined, .. } => {
+                (0, mined, 0)
+            }
+            ProcessOutcome::Rbf => (0, 0, 1),
+            _ => return,
+        };
+        self.inserted.fetch_add(inserted as u64, Ordering::Relaxed);
+        self.mined.fetch_add(mined as u64, Ordering::Relaxed);
+        self.rbf.fetch_add(rbf as u64, Ordering::Relaxed);
+    }
+
+    /// Txs that would have been stored
+    pub fn inserted(&self) -> u64 {
+        self.inserted.load(Ordering::Relaxed)
+    }
+
+    /// Tracked txs that would have been marked mined
+    pub fn mined(&self) -> u64 {
+        self.mined.load(Ordering::Relaxed)
+    }
+
+    /// Replacements that would have been recorded
+    pub fn rbf(&self) -> u64 {
+        self.rbf.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Display for DryRunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "would have inserted {} txs, marked {} mined and recorded {} replacements",
+            self.inserted(),
+            self.mined(),
+            self.rbf()
+        )
+    }
+}
+
+/// Throughput and errors of a worker, shared with the app so a wedged or failing worker
+/// shows. Timestamps are unix seconds
+#[derive(Debug, Clone, Default)]
+pub struct WorkerStats {
+    processed: Arc<AtomicU64>,
+    errors: Arc<AtomicU64>,
+    /// 0 until the first task, or error
+    last_task_at: Arc<AtomicU64>,
+    last_error_at: Arc<AtomicU64>,
+}
+
+impl WorkerStats {
+    fn record(&self, ok: bool) {
+        let now = now!();
+        self.processed.fetch_add(1, Ordering::Relaxed);
+        self.last_task_at.store(now, Ordering::Relaxed);
+        if !ok {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+            self.last_error_at.store(now, Ordering::Relaxed);
+        }
+    }
+
+    /// Tasks processed, failed ones included
+    pub fn processed(&self) -> u64 {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn last_task_at(&self) -> Option<u64> {
+        Some(self.last_task_at.load(Ordering::Relaxed)).filter(|at| *at > 0)
+    }
+
+    pub fn last_error_at(&self) -> Option<u64> {
+        Some(self.last_error_at.load(Ordering::Relaxed)).filter(|at| *at > 0)
+    }
+
+    /// Totals of `stats`, with the latest task and error of any of them
+    pub fn aggregate<'a>(stats: impl IntoIterator<Item = &'a WorkerStats>) -> WorkerStats {
+        let total = WorkerStats::default();
+        for stats in stats {
+            total
+                .processed
+                .fetch_add(stats.processed(), Ordering::Relaxed);
+            total.errors.fetch_add(stats.errors(), Ordering::Relaxed);
+            total.last_task_at.fetch_max(
+                stats.last_task_at.load(Ordering::Relaxed),
+                Ordering::Relaxed,
+            );
+            total.last_error_at.fetch_max(
+                stats.last_error_at.load(Ordering::Relaxed),
+                Ordering::Relaxed,
+            );
+        }
+        total
+    }
+}
+
+pub struct TaskContext<R: BitcoinRpc, S: MempoolStore = Database> {
+    bitcoind: R,
+    db: S,
+    events: EventPublisher,
+    filter: Filter,
+    control: Receiver<Queued>,
+    tasks: Receiver<Queued>,
+    tip: TipTracker,
+    health: Health,
+    mined_detection: MinedDetection,
+    /// Node the raw txs of `tasks` are announced by
+    node_id: String,
+    mempool_poll: MempoolPoll,
+    block_recorder: BlockRecorder,
+    /// Turns true once the app shuts down, long tasks stop between steps
+    shutdown: watch::Receiver<bool>,
+    /// Counts the outcomes of a dry run, see `with_dry_run`
+    dry_run: Option<DryRunSummary>,
+    stats: WorkerStats,
+}
+
+fn is_purge(removed: usize, pending: u64) -> bool {
+    removed >= PURGE_THRESHOLD_TXS
+        || (removed >= PURGE_MIN_TXS && removed as f64 >= PURGE_THRESHOLD_SHARE * pending as f64)
+}
+
+/// Next task to process. Control tasks (timers) always take priority over queued raw txs
+/// so a flood of transactions can't delay the periodic checks.
+/// Returns `None` once both channels are closed and drained
+pub async fn next_task(control: &Receiver<Queued>, tasks: &Receiver<Queued>) -> Option<Queued> {
+    tokio::select! {
+        biased;
+        Ok(task) = control.recv() => Some(task),
+        Ok(task) = tasks.recv() => Some(task),
+        else => None,
+    }
 }
 
 /// Return absolute fee of a transaction
-pub async fn get_absolute_fee(tx: &Transaction, rpc_client: &Client) -> Result<Amount> {
+pub async fn get_absolute_fee<R: BitcoinRpc>(tx: &Transaction, rpc_client: &R) -> Result<Amount> {
     if tx.is_coinbase() {
         return Ok(Amount::ZERO);
     }
@@ -51,9 +486,8 @@ pub async fn get_absolute_fee(tx: &Transaction, rpc_client: &Client) -> Result<A
         }
         debug!("Getting input tx: {:?}", vin.previous_output.txid);
         let prev_tx = rpc_client
-            .get_raw_transaction_verbosity_zero(&vin.previous_output.txid)
-            .await?
-            .transaction()?;
+            .get_raw_transaction(&vin.previous_output.txid)
+            .await?;
         let prev_txout = prev_tx.output[vin.previous_output.vout as usize].clone();
         let prev_txout_value = prev_txout.value;
         input_value += prev_txout_value;
@@ -63,100 +497,914 @@ pub async fn get_absolute_fee(tx: &Transaction, rpc_client: &Client) -> Result<A
     Ok(fee)
 }
 
-impl TaskContext {
-    pub fn new(bitcoind: Client, db: Database, tasks: Receiver<Task>) -> Self {
+impl<R: BitcoinRpc, S: MempoolStore> TaskContext<R, S> {
+    pub fn new(
+        bitcoind: R,
+        db: S,
+        events: EventPublisher,
+        filter: Filter,
+        control: Receiver<Queued>,
+        tasks: Receiver<Queued>,
+        tip: TipTracker,
+    ) -> Self {
         Self {
             bitcoind,
             db,
+            events,
+            filter,
+            control,
             tasks,
+            tip,
+            health: Health::default(),
+            mined_detection: MinedDetection::default(),
+            node_id: DEFAULT_NODE_ID.to_string(),
+            mempool_poll: MempoolPoll::default(),
+            block_recorder: BlockRecorder::default(),
+            shutdown: watch::channel(false).1,
+            dry_run: None,
+            stats: WorkerStats::default(),
         }
     }
 
-    async fn check_for_pruned_txs(&self) -> Result<()> {
+    /// Share the previous mempool poll with the other workers, so the churn between two
+    /// `MempoolState` tasks is measured whichever worker handles them
+    pub fn with_mempool_poll(mut self, mempool_poll: MempoolPoll) -> Self {
+        self.mempool_poll = mempool_poll;
+        self
+    }
+
+    /// Record blocks one at a time with the other workers sharing `block_recorder`
+    pub fn with_block_recorder(mut self, block_recorder: BlockRecorder) -> Self {
+        self.block_recorder = block_recorder;
+        self
+    }
+
+    /// Stop a backfill between blocks once `shutdown` turns true
+    pub fn with_shutdown(mut self, shutdown: watch::Receiver<bool>) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Counters of the tasks this worker processed, they keep counting after it's moved
+    /// into its task
+    pub fn stats(&self) -> WorkerStats {
+        self.stats.clone()
+    }
+
+    /// Count what was processed in `summary` and log it as not written. The store has to
+    /// drop its writes for nothing to be written, see `DryRunStore`
+    pub fn with_dry_run(mut self, summary: DryRunSummary) -> Self {
+        self.dry_run = Some(summary);
+        self
+    }
+
+    /// Attribute the sightings of raw txs to `node_id`, see `Database::record_sighting`
+    pub fn with_node_id(mut self, node_id: impl Into<String>) -> Self {
+        self.node_id = node_id.into();
+        self
+    }
+
+    pub fn with_mined_detection(mut self, mined_detection: MinedDetection) -> Self {
+        self.mined_detection = mined_detection;
+        self
+    }
+
+    /// Report the outcome of the periodic mempool state task, the raw tx queue depth
+    /// and how long tasks waited in the queues to `health`
+    pub fn with_health(mut self, health: Health) -> Self {
+        self.health = health;
+        self
+    }
+
+    async fn check_for_pruned_txs(&self) -> Result<usize> {
         info!("Checking for pruned txs");
         let txids = self.bitcoind.get_raw_mempool().await?;
-        let pruned_txids = self.db.txids_of_txs_not_in_list(txids)?;
+        // Txs of a block we haven't processed yet left the mempool too. The snapshot is
+        // taken first, so anything mined after it is still in the list
+        self.catch_up_with_tip().await?;
+        let pending = self.db.pending_tx_count().await?;
+        let pruned_txids = self.db.txids_of_txs_not_in_list(txids).await?;
         info!("Found {} pruned txs", pruned_txids.len());
-        self.db.record_pruned_txs(pruned_txids)?;
-        self.db.flush()?;
+        self.db.record_pruned_txs(pruned_txids.clone()).await?;
+        let pruned = pruned_txids.len();
+        if is_purge(pruned, pending) {
+            let min_fee_before = self.db.last_mempool_min_fee().await?;
+            let min_fee_after = self.bitcoind.get_mempool_info().await?.mempool_min_fee;
+            let event = self
+                .db
+                .record_purge_event(&pruned_txids, min_fee_before, Some(min_fee_after))
+                .await?;
+            warn!(
+                "Mempool purge: {} of {} pending txs ({} vB) removed at once, mempoolminfee {:?} -> {:?}",
+                event.removed_count,
+                pending,
+                event.removed_vbytes,
+                min_fee_before,
+                min_fee_after
+            );
+        }
+        self.db.flush().await?;
+        for txid in pruned_txids {
+            self.events.publish(MempoolEvent::Pruned {
+                txid: txid.to_string(),
+            });
+        }
+        Ok(pruned)
+    }
+
+    /// Verify a suspected replacement by asking the node whether the stored original
+    /// would still be accepted. Any rejection (conflict, insufficient fee, spent inputs)
+    /// confirms the original was displaced
+    async fn verify_replacement(&self, tx: &Transaction) -> Result<ReplacementCheck> {
+        let Some(original) = self.db.get_stored_tx(tx).await? else {
+            return Ok(ReplacementCheck::Replaced {
+                reject_reason: None,
+            });
+        };
+        if original.compute_txid() == tx.compute_txid() {
+            return Ok(ReplacementCheck::Duplicate);
+        }
+
+        match self.bitcoind.test_mempool_accept(&original).await? {
+            Some(reject_reason) => Ok(ReplacementCheck::Replaced {
+                reject_reason: Some(reject_reason),
+            }),
+            None => Ok(ReplacementCheck::Duplicate),
+        }
+    }
+
+    async fn record_mempool_state(&self) -> Result<()> {
+        let node_state = async {
+            let mempool_info = self.bitcoind.get_mempool_info().await?;
+            let block_height = self.bitcoind.get_block_count().await?;
+            let block_hash = self.bitcoind.get_block_hash(block_height).await?;
+            let txids = self.bitcoind.get_raw_mempool().await?;
+            Ok::<_, anyhow::Error>((mempool_info, block_height, block_hash, txids))
+        }
+        .await;
+        self.health.record_rpc(node_state.is_ok());
+        let (mempool_info, block_height, block_hash, txids) = node_state?;
+        let churn = self.mempool_poll.diff(txids);
+        if let Some(churn) = churn {
+            debug!(
+                "Mempool churn since the last poll: +{} -{}",
+                churn.added_since_last, churn.removed_since_last
+            );
+        }
+        let recorded = self
+            .db
+            .record_mempool_state(&mempool_info, block_height, block_hash, churn)
+            .await;
+        self.health
+            .record_db_write(recorded.is_ok() && !self.db.is_read_only());
+        recorded?;
+        let bands = self.db.record_fee_histogram().await?;
+        debug!("Recorded fee histogram with {} bands", bands);
+        if self.tip.observe(block_height, block_hash) {
+            debug!("New tip {} at height {}", block_hash, block_height);
+        } else if self.tip.is_stale() {
+            warn!(
+                "No new block observed for {}s, tip is still {} at height {}. zmq or RPC may be stalled",
+                self.tip.age_secs(),
+                block_hash,
+                block_height
+            );
+        }
         Ok(())
     }
 
-    pub async fn run(&mut self) -> Result<()> {
-        while let Ok(task) = self.tasks.recv().await {
-            match task {
-                Task::MempoolState => {
-                    info!("Mempool state task received");
-                    let mempool_info = self.bitcoind.get_mempool_info().await?;
-                    let block_height = self.bitcoind.get_block_count().await?;
-                    let block_hash = self.bitcoind.get_block_hash(block_height).await?;
-                    if let Err(e) = self.db.record_mempool_state(
-                        mempool_info.bytes as u64,
-                        mempool_info.size as u64,
-                        block_height,
-                        block_hash,
-                    ) {
-                        error!("Error recording mempool state: {}", e);
-                        continue;
+    /// Flush first, then record the checkpoint, so every row it covers is durable by the
+    /// time a consumer can see it
+    async fn checkpoint(&self) -> Result<Checkpoint> {
+        let block_height = self.bitcoind.get_block_count().await?;
+        let best_block_hash = self.bitcoind.get_block_hash(block_height).await?;
+        self.db.flush().await?;
+        let checkpoint = self.db.record_checkpoint(best_block_hash).await?;
+        let (txs, snapshots) = self.db.apply_retention().await?;
+        if txs + snapshots > 0 {
+            info!(
+                "Retention deleted {} txs and {} snapshot rows",
+                txs, snapshots
+            );
+        }
+        Ok(checkpoint)
+    }
+
+    /// Record the blocks no `NewBlock` task has recorded yet, so their txs are marked mined
+    /// instead of pruned. Walks back from the node's tip to the last recorded height, so a
+    /// burst of blocks or a restart is covered, and records what it finds oldest first.
+    /// Without any recorded block only the tip is checked
+    async fn catch_up_with_tip(&self) -> Result<()> {
+        let _recording = self.block_recorder.lock().await;
+        let tip_height = self.bitcoind.get_block_count().await?;
+        let lowest = self
+            .db
+            .last_recorded_block_height()
+            .await?
+            .map_or(tip_height, |height| (height + 1).min(tip_height));
+        let mut unrecorded = vec![];
+        for height in (lowest..=tip_height).rev() {
+            let block_hash = self.bitcoind.get_block_hash(height).await?;
+            if self.db.block_recorded(&block_hash).await? {
+                break;
+            }
+            unrecorded.push((height, block_hash));
+        }
+        if unrecorded.is_empty() {
+            return Ok(());
+        }
+        info!(
+            "{} blocks up to the tip at height {} not processed yet, recording their txs before pruning",
+            unrecorded.len(),
+            tip_height
+        );
+        for (height, block_hash) in unrecorded.into_iter().rev() {
+            let (mined, out_of_band) = self
+                .record_block_txs(&block_hash)
+                .await
+                .with_context(|| format!("recording block {} at height {}", block_hash, height))?;
+            debug!(
+                "Caught up with block {} mined={} out_of_band={}",
+                block_hash, mined, out_of_band
+            );
+        }
+        Ok(())
+    }
+
+    /// Mark the block's tracked txs mined, and only then reconcile the mempool. Txs that
+    /// left the mempool because this block confirmed them must never be recorded as pruned.
+    /// Txs the filter would have stored but we never saw are recorded as out-of-band,
+    /// including any that reached the node while we were down. `block` saves fetching it
+    /// when it came over zmq in full
+    async fn process_new_block(
+        &self,
+        block_hash: &BlockHash,
+        block: Option<Block>,
+    ) -> Result<(usize, usize, usize)> {
+        // A prune check or another topic's announcement may have covered this block already
+        let (mined, out_of_band) = {
+            let _recording = self.block_recorder.lock().await;
+            if self.db.block_recorded(block_hash).await? {
+                (0, 0)
+            } else {
+                match block {
+                    Some(block) => self.record_block(&block).await?,
+                    None => self.record_block_txs(block_hash).await?,
+                }
+            }
+        };
+        let pruned = self.check_for_pruned_txs().await?;
+        Ok((mined, out_of_band, pruned))
+    }
+
+    /// Mark the block's tracked txs mined and store the ones we never saw, returns the
+    /// mined and out-of-band counts
+    async fn record_block_txs(&self, block_hash: &BlockHash) -> Result<(usize, usize)> {
+        if self.mined_detection == MinedDetection::BlockFilter {
+            return self.record_filter_matches(block_hash).await;
+        }
+        let block = self.bitcoind.get_block(block_hash).await?;
+        self.record_block(&block).await
+    }
+
+    /// Test every pending tx's scripts against the block's filter at once. On a match the
+    /// block is fetched and recorded in full, filter false positives just aren't in it,
+    /// otherwise only its summary is recorded. Returns the mined and out-of-band counts
+    async fn record_filter_matches(&self, block_hash: &BlockHash) -> Result<(usize, usize)> {
+        let filter = self.bitcoind.get_block_filter(block_hash).await?;
+        let mut scripts = HashSet::new();
+        let mut after_key = String::new();
+        loop {
+            let batch = self.db.pending_txs(&after_key, RESCAN_BATCH_SIZE).await?;
+            let Some((last_key, _)) = batch.last() else {
+                break;
+            };
+            after_key = last_key.clone();
+            // BIP158 leaves OP_RETURN and empty scripts out
+            scripts.extend(
+                batch
+                    .into_iter()
+                    .flat_map(|(_, tx)| tx.output)
+                    .map(|output| output.script_pubkey)
+                    .filter(|script| !script.is_empty() && !script.is_op_return()),
+            );
+        }
+        if filter.match_any(block_hash, scripts.iter().map(|script| script.as_bytes()))? {
+            let block = self.bitcoind.get_block(block_hash).await?;
+            return self.record_block(&block).await;
+        }
+        let summary = self.bitcoind.get_block_summary(block_hash).await?;
+        self.db
+            .record_block(
+                *block_hash,
+                Some(summary.height),
+                summary.time,
+                Weight::from_wu(summary.weight).to_vbytes_ceil(),
+                summary.tx_count,
+            )
+            .await?;
+        self.events.publish(MempoolEvent::Block {
+            hash: block_hash.to_string(),
+            height: Some(summary.height),
+            tx_count: summary.tx_count as u64,
+            mined: 0,
+        });
+        Ok((0, 0))
+    }
+
+    async fn record_block(&self, block: &Block) -> Result<(usize, usize)> {
+        let block_hash = block.block_hash();
+        let block_height = block.bip34_block_height().ok();
+        let block_time = block.header.time as u64;
+        let mut mined = 0;
+        let mut out_of_band = vec![];
+        for tx in &block.txdata {
+            if tx.is_coinbase() {
+                self.db.record_coinbase_tx(tx, Some(block_hash)).await?;
+                continue;
+            }
+            if self.db.tx_exists(tx).await? {
+                self.db.record_mined_tx(tx, Some(block_hash)).await?;
+                self.events.publish(MempoolEvent::Mined {
+                    txid: tx.compute_txid().to_string(),
+                    block_hash: Some(block_hash.to_string()),
+                });
+                mined += 1;
+                continue;
+            }
+            match get_absolute_fee(tx, &self.bitcoind).await {
+                Ok(fee) => {
+                    if self.filter.matches(tx, compute_fee_rate(tx, fee)?) {
+                        out_of_band.push((tx.clone(), fee));
                     }
                 }
-                Task::PruneCheck => {
-                    info!("Prune check task received");
-                    log_error!(Self::check_for_pruned_txs, self);
+                Err(e) => warn!(
+                    "Skipping out-of-band tx {}, can't get its fee: {:#}",
+                    tx.compute_txid(),
+                    e
+                ),
+            }
+        }
+        self.db
+            .record_block(
+                block_hash,
+                block_height,
+                block_time,
+                block.weight().to_vbytes_ceil(),
+                block.txdata.len(),
+            )
+            .await?;
+        self.db
+            .record_out_of_band_txs(block_height, block_hash, block_time, &out_of_band)
+            .await?;
+        self.db.flush().await?;
+        self.events.publish(MempoolEvent::Block {
+            hash: block_hash.to_string(),
+            height: block_height,
+            tx_count: block.txdata.len() as u64,
+            mined: mined as u64,
+        });
+        Ok((mined, out_of_band.len()))
+    }
+
+    /// Re-check every pending row against the node: still in the mempool stays pending,
+    /// confirmed is marked mined, unknown to the node is marked pruned.
+    /// Updates are committed per batch, so the rescan is idempotent and can be cut short
+    async fn rescan(&self) -> Result<(usize, usize)> {
+        let (mut mined, mut pruned) = (0, 0);
+        let mut after_key = String::new();
+        loop {
+            let batch = self.db.pending_txs(&after_key, RESCAN_BATCH_SIZE).await?;
+            let Some((last_key, _)) = batch.last() else {
+                break;
+            };
+            after_key = last_key.clone();
+            // A lookup error below means "unknown to the node", make sure it isn't
+            // just the node being unreachable before marking anything pruned
+            self.bitcoind.get_block_count().await?;
+
+            let mut pruned_txids = vec![];
+            for (_, tx) in batch {
+                let txid = tx.compute_txid();
+                let fee_known = self.db.fee_known(&tx).await?;
+                if let Ok(entry) = self.bitcoind.get_mempool_entry(&txid).await {
+                    if !fee_known {
+                        self.fill_unknown_fee(&tx, entry.fee).await?;
+                    }
+                    continue;
                 }
-                Task::RawTx(raw_tx) => {
-                    debug!("Received raw tx");
-                    let tx_bytes = raw_tx;
-                    let tx = Transaction::consensus_decode(&mut tx_bytes.as_slice())?;
-                    if tx.is_coinbase() {
-                        info!("Record coinbase tx");
-                        // Record coinbase sperately
-                        self.db.record_coinbase_tx(&tx)?;
-                        continue;
+                match self.bitcoind.get_tx_status(&txid).await {
+                    Ok(status) if !status.is_mined() => {}
+                    Ok(status) => {
+                        if !fee_known {
+                            if let Ok(fee) = get_absolute_fee(&tx, &self.bitcoind).await {
+                                self.fill_unknown_fee(&tx, fee).await?;
+                            }
+                        }
+                        self.db.record_mined_tx(&tx, status.block_hash).await?;
+                        self.events.publish(MempoolEvent::Mined {
+                            txid: txid.to_string(),
+                            block_hash: status.block_hash.map(|hash| hash.to_string()),
+                        });
+                        mined += 1;
                     }
+                    Err(e) if is_unknown_tx_error(&e) => pruned_txids.push(txid),
+                    Err(e) => warn!("Leaving {} pending, looking it up failed: {:#}", txid, e),
+                }
+            }
+            pruned += pruned_txids.len();
+            self.db.record_pruned_txs(pruned_txids.clone()).await?;
+            self.db.flush().await?;
+            for txid in pruned_txids {
+                self.events.publish(MempoolEvent::Pruned {
+                    txid: txid.to_string(),
+                });
+            }
+            tokio::time::sleep(RESCAN_BATCH_PAUSE).await;
+        }
+        Ok((mined, pruned))
+    }
 
-                    let txid = tx.compute_txid();
-                    let tx_info = match self.bitcoind.get_raw_transaction_verbosity_one(&txid).await
-                    {
-                        Ok(tx_info) => tx_info,
-                        Err(e) => {
-                            error!("Error getting transaction info: {}", e);
-                            continue;
-                        }
-                    };
-                    let is_mined = tx_info.confirmations.unwrap_or(0) > 0;
-                    let fee = match get_absolute_fee(&tx, &self.bitcoind).await {
-                        Ok(fee) => fee,
-                        Err(e) => {
-                            error!("Error getting transaction fee: {}", e);
-                            continue;
+    /// Store the fee of a replacement that was recorded without one
+    async fn fill_unknown_fee(&self, tx: &Transaction, fee: Amount) -> Result<()> {
+        let fee_rate = compute_fee_rate(tx, fee).context("computing fee rate")?;
+        if self.db.fill_unknown_fee(tx, fee, fee_rate).await? {
+            debug!(
+                "txid={} filled in unknown fee={}",
+                tx.compute_txid(),
+                fee.to_sat()
+            );
+        }
+        Ok(())
+    }
+
+    /// Ingest blocks `from_height..=to_height`, skipping blocks a previous backfill
+    /// already covered so an interrupted range can simply be re-run. A shutdown stops it
+    /// between blocks. Fees of non-coinbase txs are looked up per input, which requires
+    /// txindex
+    async fn backfill(&self, from_height: u64, to_height: u64) -> Result<usize> {
+        let mut ingested = 0;
+        for (i, height) in (from_height..=to_height).enumerate() {
+            if *self.shutdown.borrow() {
+                info!(
+                    "Backfill stopped by the shutdown at height {}, re-run the range to finish it",
+                    height
+                );
+                break;
+            }
+            if i > 0 && i % 100 == 0 {
+                info!(
+                    "Backfill at height {} ({}/{} blocks)",
+                    height,
+                    i,
+                    to_height - from_height + 1
+                );
+            }
+            if self.db.block_is_covered(height).await? {
+                continue;
+            }
+            let block_hash = self.bitcoind.get_block_hash(height).await?;
+            let block = self.bitcoind.get_block(&block_hash).await?;
+            let mut txs = Vec::with_capacity(block.txdata.len());
+            for tx in block.txdata {
+                let fee = get_absolute_fee(&tx, &self.bitcoind).await?;
+                txs.push((tx, fee));
+            }
+            self.db
+                .record_backfilled_block(height, block_hash, block.header.time as u64, &txs)
+                .await?;
+            ingested += 1;
+        }
+        self.db.flush().await?;
+        Ok(ingested)
+    }
+
+    /// Store the node's mempool txs we don't track in one batch, with the node's entry
+    /// times and fees like the startup sync. Heals our view after notifications were
+    /// lost, e.g. on a reconnect
+    async fn reconcile_mempool(&self) -> Result<usize> {
+        let mempool = self.bitcoind.get_raw_mempool_verbose().await?;
+        let txids: Vec<Txid> = mempool.keys().copied().collect();
+        let missing = self.db.untracked_txids(&txids).await?;
+        info!(
+            "Reconciling {} untracked of {} mempool txs",
+            missing.len(),
+            txids.len()
+        );
+        let mut batch = Vec::with_capacity(missing.len());
+        for txid in missing {
+            let ctx = LogContext {
+                task: "reconcile_mempool",
+                txid: Some(txid),
+                started: Instant::now(),
+            };
+            // Left the mempool since getrawmempool, nothing to reconcile
+            let tx = match self.bitcoind.get_raw_transaction(&txid).await {
+                Ok(tx) => tx,
+                Err(e) => {
+                    debug!("{} skipping: {:#}", ctx, e);
+                    continue;
+                }
+            };
+            let entry = &mempool[&txid];
+            let fee_rate = compute_fee_rate(&tx, entry.fee)?;
+            if !self.filter.matches(&tx, fee_rate) {
+                continue;
+            }
+            self.db
+                .record_tx_links(&txid, &entry.depends, &entry.spent_by)
+                .await?;
+            batch.push((tx, Some(entry.time * 1000), entry.fee, fee_rate));
+        }
+        self.db.insert_mempool_txs(&batch).await?;
+        self.db.flush().await?;
+        Ok(batch.len())
+    }
+
+    /// Mark a tx the node dropped from its mempool pruned right away instead of waiting
+    /// for the next prune check
+    async fn process_removed_tx(&self, txid: &Txid) -> Result<ProcessOutcome> {
+        if !self.db.is_pending(txid).await? {
+            return Ok(ProcessOutcome::Pruned(0));
+        }
+        self.db.record_pruned_txs(vec![*txid]).await?;
+        self.db.flush().await?;
+        self.events.publish(MempoolEvent::Pruned {
+            txid: txid.to_string(),
+        });
+        Ok(ProcessOutcome::Pruned(1))
+    }
+
+    /// Decode a raw tx, undecodable payloads are quarantined for later inspection
+    async fn decode_raw_tx(&self, raw_tx: &[u8]) -> Option<Transaction> {
+        match Transaction::consensus_decode(&mut &raw_tx[..]) {
+            Ok(tx) => Some(tx),
+            Err(e) => {
+                error!("Error decoding raw tx: {}", e);
+                if let Err(e) = self.db.quarantine_payload(raw_tx, &e.to_string()).await {
+                    error!("Error quarantining raw tx: {}", e);
+                }
+                None
+            }
+        }
+    }
+
+    /// Store a mempool `tx` first seen at `found_at` (unix ms), or record what it confirms
+    /// or replaces
+    async fn process_tx(
+        &self,
+        tx: Transaction,
+        found_at: u64,
+        ctx: &LogContext,
+    ) -> Result<ProcessOutcome> {
+        if tx.is_coinbase() {
+            // Record coinbase sperately
+            self.db.record_coinbase_tx(&tx, None).await?;
+            return Ok(ProcessOutcome::Coinbase);
+        }
+
+        let txid = tx.compute_txid();
+        // A tracked slot means this is a mined tx or a replacement, a replacement is
+        // recorded even when its fee can no longer be looked up
+        let tracked = self.db.tx_exists(&tx).await?;
+        // The mempool entry carries the fee and proves the tx is unmined. Only txs missing
+        // from the mempool need the heavier getrawtransaction lookup, which requires txindex
+        let (status, fee, node_seen_at) = match self.bitcoind.get_mempool_entry(&txid).await {
+            Ok(entry) => {
+                if let Err(e) = self
+                    .db
+                    .record_tx_links(&txid, &entry.depends, &entry.spent_by)
+                    .await
+                {
+                    error!("{} error recording tx links: {}", ctx, e);
+                }
+                (
+                    TxStatus::default(),
+                    Some(entry.fee),
+                    Some(entry.time * 1000),
+                )
+            }
+            Err(e) => {
+                debug!("{} not in mempool, checking confirmations: {}", ctx, e);
+                // A replacement that was already replaced again or evicted is unknown to
+                // the node, it still happened
+                let status = match self.bitcoind.get_tx_status(&txid).await {
+                    Ok(status) => status,
+                    Err(e) if tracked => {
+                        debug!("{} unknown to the node: {}", ctx, e);
+                        TxStatus::default()
+                    }
+                    Err(e) => return Err(e.context("getting transaction info")),
+                };
+                let fee = match get_absolute_fee(&tx, &self.bitcoind).await {
+                    Ok(fee) => Some(fee),
+                    Err(e) if tracked => {
+                        debug!("{} fee unknown: {}", ctx, e);
+                        None
+                    }
+                    Err(e) => return Err(e.context("getting transaction fee")),
+                };
+                (status, fee, None)
+            }
+        };
+        let fee_rate = fee
+            .map(|fee| compute_fee_rate(&tx, fee))
+            .transpose()
+            .context("computing fee rate")?;
+        debug!(
+            "{} fee={:?} fee_rate={:?}",
+            ctx,
+            fee.map(|fee| fee.to_sat()),
+            fee_rate.map(|fee_rate| fee_rate.to_sat_per_vb_ceil())
+        );
+        if tracked {
+            let outcome = if status.is_mined() {
+                self.db.record_mined_tx(&tx, status.block_hash).await?;
+                self.events.publish(MempoolEvent::Mined {
+                    txid: txid.to_string(),
+                    block_hash: status.block_hash.map(|hash| hash.to_string()),
+                });
+                ProcessOutcome::Mined
+            } else {
+                let reject_reason = match self.verify_replacement(&tx).await {
+                    Ok(ReplacementCheck::Duplicate) => return Ok(ProcessOutcome::Duplicate),
+                    Ok(ReplacementCheck::Replaced { reject_reason }) => reject_reason,
+                    Err(e) => {
+                        error!("{} error verifying replacement: {}", ctx, e);
+                        None
+                    }
+                };
+                let prev_fee = self.db.get_stored_fee(&tx).await?;
+                let replaced_txid = self
+                    .db
+                    .get_stored_tx(&tx)
+                    .await?
+                    .map(|stored| stored.compute_txid().to_string());
+                let event = match fee.zip(fee_rate) {
+                    Some((fee, fee_rate)) => {
+                        let bump = RbfBump::new(prev_fee, fee, fee_rate);
+                        debug!(
+                            "{} replacing prev_fee={:?} reject_reason={:?}",
+                            ctx,
+                            bump.prev_fee.map(|fee| fee.to_sat()),
+                            reject_reason
+                        );
+                        self.db
+                            .record_rbf(&tx, &bump, reject_reason.as_deref())
+                            .await?;
+                        if let Some(prev_fee) = bump.prev_fee {
+                            match self.bip125_verdict(&tx, prev_fee, fee).await {
+                                Ok(Some(verdict)) => {
+                                    let violations = verdict.violations();
+                                    if !violations.is_empty() {
+                                        info!(
+                                            "{} replacement breaks BIP125 rules={:?}",
+                                            ctx, violations
+                                        );
+                                    }
+                                    self.db.record_bip125_violations(&tx, &violations).await?;
+                                }
+                                Ok(None) => {}
+                                Err(e) => error!("{} error checking BIP125 rules: {}", ctx, e),
+                            }
                         }
-                    };
-                    let fee_rate = match compute_fee_rate(&tx, fee) {
-                        Ok(fee_rate) => fee_rate,
-                        Err(e) => {
-                            error!("Error computing fee rate: {}", e);
-                            continue;
+                        MempoolEvent::Rbf {
+                            txid: txid.to_string(),
+                            replaced_txid,
+                            fee: Some(fee.to_sat()),
+                            prev_fee: bump.prev_fee.map(|fee| fee.to_sat()),
+                            fee_bump_pct: bump.fee_bump_pct,
                         }
-                    };
-                    if self.db.tx_exists(&tx)? {
-                        if is_mined {
-                            self.db.record_mined_tx(&tx)?;
-                            info!("Transaction was mined: {:?}", txid);
-                        } else {
-                            info!("Transaction was RBF'd: {:?}", txid);
-                            self.db.record_rbf(&tx, fee.to_sat())?;
-                            self.db.update_txid_by_inputs_hash(&tx)?;
+                    }
+                    None => {
+                        info!(
+                            "{} replacement left the mempool, recording it without a fee",
+                            ctx
+                        );
+                        self.db
+                            .record_rbf_unknown_fee(&tx, reject_reason.as_deref())
+                            .await?;
+                        MempoolEvent::Rbf {
+                            txid: txid.to_string(),
+                            replaced_txid,
+                            fee: None,
+                            prev_fee: prev_fee.map(|(fee, _)| fee.to_sat()),
+                            fee_bump_pct: None,
                         }
-                        self.db.flush()?;
-                        continue;
                     }
+                };
+                self.db.update_txid_by_inputs_hash(&tx).await?;
+                self.events.publish(event);
+                self.publish_large_value(ctx, &tx);
+                ProcessOutcome::Rbf
+            };
+            self.db.flush().await?;
+            return Ok(outcome);
+        }
+
+        // Fee lookups only fail softly for tracked slots
+        let (fee, fee_rate) = fee.zip(fee_rate).context("fee unknown")?;
+        if !self.filter.matches(&tx, fee_rate) {
+            debug!("{} filtered out", ctx);
+            return Ok(ProcessOutcome::Skipped);
+        }
+
+        for conflict in self.db.record_conflicts(&tx).await? {
+            info!(
+                "{} conflicts with conflicting_txid={} outpoints={:?}",
+                ctx, conflict.conflicting_txid, conflict.outpoints
+            );
+            self.events.publish(MempoolEvent::Conflict {
+                txid: txid.to_string(),
+                conflicting_txid: conflict.conflicting_txid.to_string(),
+                outpoints: conflict
+                    .outpoints
+                    .iter()
+                    .map(|outpoint| outpoint.to_string())
+                    .collect(),
+            });
+        }
+        self.db
+            .insert_mempool_tx(tx.clone(), Some(found_at), node_seen_at, fee, fee_rate)
+            .await?;
+        self.db.flush().await?;
+        self.events.publish(MempoolEvent::New {
+            txid: txid.to_string(),
+            fee: fee.to_sat(),
+            fee_rate: fee_rate.to_sat_per_vb_ceil(),
+            vsize: tx.vsize() as u64,
+            found_at: found_at / 1000,
+        });
+        self.publish_large_value(ctx, &tx);
+        Ok(ProcessOutcome::Inserted)
+    }
+
+    /// Log and announce a tx the database stored flagged large value, see
+    /// `Database::with_large_value_threshold`
+    fn publish_large_value(&self, ctx: &LogContext, tx: &Transaction) {
+        let Some(threshold) = self.db.exceeded_large_value_threshold(tx) else {
+            return;
+        };
+        let total_out_value: Amount = tx.output.iter().map(|output| output.value).sum();
+        info!(
+            "{} large_value total_out_value={} threshold={}",
+            ctx,
+            total_out_value.to_sat(),
+            threshold.to_sat()
+        );
+        self.events.publish(MempoolEvent::LargeValue {
+            txid: tx.compute_txid().to_string(),
+            total_out_value: total_out_value.to_sat(),
+            threshold: threshold.to_sat(),
+        });
+    }
 
-                    self.db.insert_mempool_tx(tx, None, fee, fee_rate)?;
-                    self.db.flush()?;
-                    info!("Transaction inserted: {:?}", txid);
+    /// BIP125 verdict of `tx` replacing the stored tx of its slot, `None` when nothing is
+    /// stored. Call before the slot is pointed at `tx`
+    async fn bip125_verdict(
+        &self,
+        tx: &Transaction,
+        original_fee: Amount,
+        fee: Amount,
+    ) -> Result<Option<Bip125Verdict>> {
+        let Some(original) = self.db.get_stored_tx(tx).await? else {
+            return Ok(None);
+        };
+        let verdict = check_bip125_rules(&original, original_fee.to_sat(), tx, fee.to_sat());
+        // Outputs of txs still in the mempool are the unconfirmed ones
+        let mut unconfirmed = HashSet::new();
+        for outpoint in &verdict.new_inputs {
+            if self
+                .bitcoind
+                .get_mempool_entry(&outpoint.txid)
+                .await
+                .is_ok()
+            {
+                unconfirmed.insert(*outpoint);
+            }
+        }
+        let replaced_count = 1 + self.db.descendant_count(&original.compute_txid()).await?;
+        Ok(Some(
+            verdict
+                .retain_unconfirmed_inputs(|outpoint| unconfirmed.contains(outpoint))
+                .with_replaced_count(replaced_count),
+        ))
+    }
+
+    async fn process_raw_tx(
+        &self,
+        raw_tx: &[u8],
+        found_at: u64,
+        ctx: &mut LogContext,
+    ) -> Result<ProcessOutcome> {
+        let Some(tx) = self.decode_raw_tx(raw_tx).await else {
+            return Ok(ProcessOutcome::Skipped);
+        };
+        let txid = tx.compute_txid();
+        ctx.txid = Some(txid);
+        // Coinbases are announced with their block, there's no propagation to compare
+        if !tx.is_coinbase() {
+            self.db
+                .record_sighting(&txid, &self.node_id, found_at)
+                .await?;
+        }
+        self.process_tx(tx, found_at, ctx).await
+    }
+
+    /// Process a single task. The outcome, or error, is logged with the task's context
+    pub async fn process_task(&self, task: Task) -> Result<ProcessOutcome> {
+        let mut ctx = LogContext::new(&task);
+        let result = match task {
+            Task::MempoolState => self
+                .record_mempool_state()
+                .await
+                .map(|_| ProcessOutcome::MempoolStateRecorded),
+            Task::PruneCheck => self
+                .check_for_pruned_txs()
+                .await
+                .map(ProcessOutcome::Pruned),
+            Task::NewBlock(block_hash)
+            | Task::Sequence(SequenceEvent::BlockConnected(block_hash)) => self
+                .process_new_block(&block_hash, None)
+                .await
+                .map(|(mined, out_of_band, pruned)| ProcessOutcome::NewBlock {
+                    mined,
+                    out_of_band,
+                    pruned,
+                }),
+            Task::RawBlock(raw_block) => match Block::consensus_decode(&mut &raw_block[..]) {
+                Ok(block) => self
+                    .process_new_block(&block.block_hash(), Some(block))
+                    .await
+                    .map(|(mined, out_of_band, pruned)| ProcessOutcome::NewBlock {
+                        mined,
+                        out_of_band,
+                        pruned,
+                    }),
+                Err(e) => {
+                    error!("Error decoding raw block: {}", e);
+                    Ok(ProcessOutcome::Skipped)
                 }
+            },
+            Task::Sequence(SequenceEvent::BlockDisconnected(block_hash)) => {
+                warn!(
+                    "Block {} disconnected, its txs may return to the mempool",
+                    block_hash
+                );
+                Ok(ProcessOutcome::Skipped)
             }
+            // The tx itself arrives on the rawtx topic
+            Task::Sequence(SequenceEvent::TxAdded(_)) => Ok(ProcessOutcome::Skipped),
+            Task::Sequence(SequenceEvent::TxRemoved(txid)) => {
+                ctx.txid = Some(txid);
+                self.process_removed_tx(&txid).await
+            }
+            Task::ReconcileMempool => self
+                .reconcile_mempool()
+                .await
+                .map(ProcessOutcome::Reconciled),
+            Task::Checkpoint => self
+                .checkpoint()
+                .await
+                .map(|checkpoint| ProcessOutcome::Checkpointed(checkpoint.last_seq)),
+            Task::Rescan => self
+                .rescan()
+                .await
+                .map(|(mined, pruned)| ProcessOutcome::Rescanned { mined, pruned }),
+            Task::Backfill {
+                from_height,
+                to_height,
+            } => self
+                .backfill(from_height, to_height)
+                .await
+                .map(ProcessOutcome::Backfilled),
+            Task::RawTx(raw_tx) => self.process_raw_tx(&raw_tx, now_ms!(), &mut ctx).await,
+            Task::ReplayedRawTx { raw_tx, found_at } => {
+                self.process_raw_tx(&raw_tx, found_at, &mut ctx).await
+            }
+        };
+        match (&result, &self.dry_run) {
+            (Ok(outcome), Some(summary)) => {
+                summary.record(outcome);
+                info!("{} outcome={} dry_run=true", ctx, outcome);
+            }
+            (Ok(outcome), None) => info!("{} outcome={}", ctx, outcome),
+            (Err(e), _) => error!("{} outcome=error error=\"{:#}\"", ctx, e),
+        }
+        self.stats.record(result.is_ok());
+        result
+    }
+
+    /// Process tasks until the channels are closed.
+    /// Errors are logged per task and never end the loop
+    pub async fn run(&mut self) -> Result<()> {
+        while let Some(queued) = next_task(&self.control, &self.tasks).await {
+            self.health
+                .record_queue_latency(queued.enqueued_at.elapsed());
+            self.health
+                .record_queue_depth(self.tasks.len(), self.tasks.capacity());
+            // Already logged with its context
+            let _ = self.process_task(queued.task).await;
         }
         info!("Worker shutting down");
         Ok(())
@@ -0,0 +1,233 @@
+use std::future::Future;
+
+use anyhow::Result;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        State, WebSocketUpgrade,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::TcpListener,
+    sync::broadcast::{error::RecvError, Receiver},
+};
+
+use crate::events::{EventPublisher, MempoolEvent};
+
+/// Feed event kinds, the `type` of the JSON messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedKind {
+    TxSeen,
+    TxMined,
+    TxReplaced,
+    TxEvicted,
+    Block,
+}
+
+/// JSON message streamed to `/ws` clients
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeedEvent {
+    TxSeen {
+        txid: String,
+        /// sat/vB
+        fee_rate: u64,
+        vsize: u64,
+    },
+    TxMined {
+        txid: String,
+        block_hash: Option<String>,
+    },
+    TxReplaced {
+        old: Option<String>,
+        new: String,
+        /// Sats over the replaced tx's fee, unknown while either fee is
+        fee_delta: Option<i64>,
+    },
+    TxEvicted {
+        txid: String,
+    },
+    Block {
+        hash: String,
+        height: Option<u64>,
+        /// Share of the block's non-coinbase txs we had pending, `None` for coinbase-only
+        /// blocks
+        coverage: Option<f64>,
+    },
+}
+
+impl FeedEvent {
+    /// The feed's view of a worker event, conflicts and large value alerts aren't streamed
+    pub fn from_event(event: &MempoolEvent) -> Option<Self> {
+        let feed_event = match event {
+            MempoolEvent::New {
+                txid,
+                fee_rate,
+                vsize,
+                ..
+            } => FeedEvent::TxSeen {
+                txid: txid.clone(),
+                fee_rate: *fee_rate,
+                vsize: *vsize,
+            },
+            MempoolEvent::Mined { txid, block_hash } => FeedEvent::TxMined {
+                txid: txid.clone(),
+                block_hash: block_hash.clone(),
+            },
+            MempoolEvent::Rbf {
+                txid,
+                replaced_txid,
+                fee,
+                prev_fee,
+                ..
+            } => FeedEvent::TxReplaced {
+                old: replaced_txid.clone(),
+                new: txid.clone(),
+                fee_delta: fee
+                    .zip(*prev_fee)
+                    .map(|(fee, prev_fee)| fee as i64 - prev_fee as i64),
+            },
+            MempoolEvent::Pruned { txid } => FeedEvent::TxEvicted { txid: txid.clone() },
+            MempoolEvent::Block {
+                hash,
+                height,
+                tx_count,
+                mined,
+            } => FeedEvent::Block {
+                hash: hash.clone(),
+                height: *height,
+                coverage: (*tx_count > 1).then(|| *mined as f64 / (*tx_count - 1) as f64),
+            },
+            MempoolEvent::Conflict { .. } | MempoolEvent::LargeValue { .. } => return None,
+        };
+        Some(feed_event)
+    }
+
+    pub fn kind(&self) -> FeedKind {
+        match self {
+            FeedEvent::TxSeen { .. } => FeedKind::TxSeen,
+            FeedEvent::TxMined { .. } => FeedKind::TxMined,
+            FeedEvent::TxReplaced { .. } => FeedKind::TxReplaced,
+            FeedEvent::TxEvicted { .. } => FeedKind::TxEvicted,
+            FeedEvent::Block { .. } => FeedKind::Block,
+        }
+    }
+}
+
+/// Filter a client sends as a text message, e.g. `{"types": ["block"]}` or
+/// `{"min_fee_rate": 20}`. Each message replaces the previous filter, the default passes
+/// everything
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Subscription {
+    /// Kinds to stream, all of them when unset
+    pub types: Option<Vec<FeedKind>>,
+    /// sat/vB, `tx_seen` events below it are skipped
+    pub min_fee_rate: Option<u64>,
+}
+
+impl Subscription {
+    pub fn matches(&self, event: &FeedEvent) -> bool {
+        if let Some(types) = &self.types {
+            if !types.contains(&event.kind()) {
+                return false;
+            }
+        }
+        match (event, self.min_fee_rate) {
+            (FeedEvent::TxSeen { fee_rate, .. }, Some(min_fee_rate)) => *fee_rate >= min_fee_rate,
+            _ => true,
+        }
+    }
+}
+
+/// Control messages sent alongside the events
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FeedNotice {
+    /// The filter message couldn't be parsed, the previous filter stays
+    Error { message: String },
+    /// The client fell too far behind and is disconnected
+    Lagged { skipped: u64 },
+}
+
+async fn send_json(socket: &mut WebSocket, message: &impl Serialize) -> Result<()> {
+    socket
+        .send(Message::Text(serde_json::to_string(message)?))
+        .await?;
+    Ok(())
+}
+
+/// Stream matching events to one client until it disconnects or lags behind
+async fn feed(mut socket: WebSocket, mut events: Receiver<MempoolEvent>) -> Result<()> {
+    let mut subscription = Subscription::default();
+    loop {
+        tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(filter) => subscription = filter,
+                    Err(e) => {
+                        let message = format!("invalid subscription: {}", e);
+                        send_json(&mut socket, &FeedNotice::Error { message }).await?;
+                    }
+                },
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+            },
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let Some(event) = FeedEvent::from_event(&event) else {
+                        continue;
+                    };
+                    if subscription.matches(&event) {
+                        send_json(&mut socket, &event).await?;
+                    }
+                }
+                // Dropping the client keeps it from backpressuring the workers
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Dropping WebSocket client lagging {} events behind", skipped);
+                    send_json(&mut socket, &FeedNotice::Lagged { skipped }).await?;
+                    return Ok(());
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
+
+async fn ws(upgrade: WebSocketUpgrade, State(events): State<EventPublisher>) -> Response {
+    let Some(receiver) = events.subscribe() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "event broadcast is disabled",
+        )
+            .into_response();
+    };
+    upgrade.on_upgrade(|socket| async move {
+        if let Err(e) = feed(socket, receiver).await {
+            warn!("WebSocket client failed: {}", e);
+        }
+    })
+}
+
+/// Serve the live event feed on `/ws` until `shutdown` resolves. `events` needs a
+/// broadcast channel, see `EventPublisher::with_broadcast`
+pub async fn serve_with_listener(
+    listener: TcpListener,
+    events: EventPublisher,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    info!("WebSocket feed listening on {}", listener.local_addr()?);
+    let router = Router::new().route("/ws", get(ws)).with_state(events);
+    axum::serve(listener, router)
+        .with_graceful_shutdown(shutdown)
+        .await?;
+    Ok(())
+}
@@ -1,10 +1,77 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
 use anyhow::Result;
-use bitcoincore_zmq::MessageStream;
+use bitcoincore_zmq::{Message, MessageStream};
+use serde::Deserialize;
+
+/// A zmq topic bitcoind publishes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ZmqTopic {
+    RawTx,
+    RawBlock,
+    HashBlock,
+    Sequence,
+}
+
+impl ZmqTopic {
+    pub const ALL: [ZmqTopic; 4] = [
+        ZmqTopic::RawTx,
+        ZmqTopic::RawBlock,
+        ZmqTopic::HashBlock,
+        ZmqTopic::Sequence,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ZmqTopic::RawTx => "rawtx",
+            ZmqTopic::RawBlock => "rawblock",
+            ZmqTopic::HashBlock => "hashblock",
+            ZmqTopic::Sequence => "sequence",
+        }
+    }
+
+    /// Name of the topic in `getzmqnotifications`
+    pub fn notification(&self) -> String {
+        format!("pub{}", self.as_str())
+    }
+
+    /// Topic a message was published on, `None` for topics we don't handle
+    pub fn of(message: &Message) -> Option<ZmqTopic> {
+        ZmqTopic::ALL
+            .into_iter()
+            .find(|topic| topic.as_str() == message.topic_str())
+    }
+}
+
+impl fmt::Display for ZmqTopic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ZmqTopic {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ZmqTopic::ALL
+            .into_iter()
+            .find(|topic| topic.as_str() == s.to_ascii_lowercase())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown zmq topic: {}, expected rawtx, rawblock, hashblock or sequence",
+                    s
+                )
+            })
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct BitcoinZmqFactory {
     bitcoind_host: String,
     bitcoind_zmq_port: u16,
+    /// Further endpoints subscribed alongside the main one, e.g. a separate hashblock port
+    extra_endpoints: Vec<(String, u16)>,
 }
 
 impl BitcoinZmqFactory {
@@ -12,14 +79,61 @@ impl BitcoinZmqFactory {
         Self {
             bitcoind_host,
             bitcoind_zmq_port,
+            extra_endpoints: vec![],
+        }
+    }
+
+    /// Also subscribe to `host:port`, a no-op for an endpoint already subscribed
+    pub fn with_endpoint(mut self, host: String, port: u16) -> Self {
+        let endpoint = (host, port);
+        if endpoint != (self.bitcoind_host.clone(), self.bitcoind_zmq_port)
+            && !self.extra_endpoints.contains(&endpoint)
+        {
+            self.extra_endpoints.push(endpoint);
         }
+        self
+    }
+
+    pub fn port(&self) -> u16 {
+        self.bitcoind_zmq_port
+    }
+
+    /// Ports of every subscribed endpoint, the main one first
+    pub fn ports(&self) -> Vec<u16> {
+        std::iter::once(self.bitcoind_zmq_port)
+            .chain(self.extra_endpoints.iter().map(|(_, port)| *port))
+            .collect()
     }
 
     pub fn connect(&self) -> Result<MessageStream> {
-        let zmq = bitcoincore_zmq::subscribe_async(&[&format!(
-            "tcp://{}:{}",
-            self.bitcoind_host, self.bitcoind_zmq_port
-        )])?;
+        let endpoints: Vec<String> =
+            std::iter::once((self.bitcoind_host.as_str(), self.bitcoind_zmq_port))
+                .chain(
+                    self.extra_endpoints
+                        .iter()
+                        .map(|(host, port)| (host.as_str(), *port)),
+                )
+                .map(|(host, port)| format!("tcp://{}:{}", host, port))
+                .collect();
+        let endpoints: Vec<&str> = endpoints.iter().map(String::as_str).collect();
+        let zmq = bitcoincore_zmq::subscribe_async(&endpoints)?;
         Ok(zmq)
     }
 }
+
+/// Tracks the per-topic sequence numbers bitcoind stamps on every zmq message. A jump
+/// means messages were dropped, e.g. by a full high water mark or a reconnect
+#[derive(Debug, Clone, Default)]
+pub struct SequenceGapDetector {
+    last: HashMap<String, u32>,
+}
+
+impl SequenceGapDetector {
+    /// Record a message, returns how many were missed on its topic since the previous one
+    pub fn observe(&mut self, topic: &str, sequence: u32) -> Option<u32> {
+        let previous = self.last.insert(topic.to_string(), sequence)?;
+        let missed = sequence.wrapping_sub(previous).wrapping_sub(1);
+        // A repeated or older number is a restarted publisher, not a gap we can size
+        (missed != 0 && missed < u32::MAX / 2).then_some(missed)
+    }
+}
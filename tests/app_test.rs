@@ -0,0 +1,785 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use async_channel::bounded;
+use bitcoin::{hashes::Hash, Amount, BlockHash};
+use common::{
+    dummy_block, dummy_tx, dummy_txid,
+    mock_rpc::{MockRpc, MOCK_ZMQ_PORT},
+    temp_db,
+};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use mempool_tracker::{
+    app::{
+        listen_zmq, replay_capture, App, AppConfig, DrainReport, QueueSender, ReplaySource,
+        SecondaryNode, StartupCheckMode, ZmqReconnectPolicy,
+    },
+    bitcoincore_zmq::{Message, SequenceMessage},
+    events::EventPublisher,
+    health::Health,
+    rpc::{NodeCapabilities, RpcRetryPolicy, ZmqNotification},
+    tip::ObservedTip,
+    worker::{MinedDetection, Queued, SequenceEvent, Task},
+    zmq_factory::{BitcoinZmqFactory, ZmqTopic},
+};
+
+fn test_app(rpc: MockRpc, config: AppConfig) -> (tempfile::TempDir, App<MockRpc>) {
+    let (dir, db, _conn) = temp_db();
+    let zmq_factory = BitcoinZmqFactory::new("127.0.0.1".to_string(), MOCK_ZMQ_PORT);
+    let app = App::new(rpc, zmq_factory, db, EventPublisher::disabled(), config);
+    (dir, app)
+}
+
+#[tokio::test]
+async fn test_init_fails_health_check_during_ibd() -> Result<()> {
+    let rpc = MockRpc::default();
+    rpc.node().blockchain.initial_block_download = true;
+    let (_dir, mut app) = test_app(rpc.clone(), AppConfig::default());
+
+    let err = app.init().await.unwrap_err();
+    assert!(err.to_string().contains("health check"), "{}", err);
+    assert!(
+        err.to_string().contains("initial block download"),
+        "{}",
+        err
+    );
+    // Nothing past the health check ran
+    assert_eq!(rpc.calls("getrawmempool"), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_init_fails_health_check_below_verification_threshold() -> Result<()> {
+    let rpc = MockRpc::default();
+    rpc.node().blockchain.verification_progress = 0.5;
+    let config = AppConfig {
+        min_verification_progress: 0.99,
+        ..Default::default()
+    };
+    let (_dir, mut app) = test_app(rpc, config);
+
+    let err = app.init().await.unwrap_err();
+    assert!(err.to_string().contains("verification progress"), "{}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_init_passes_health_check_on_synced_node() -> Result<()> {
+    let (_dir, mut app) = test_app(MockRpc::default(), AppConfig::default());
+    app.init().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_init_fails_without_rawtx_notification() -> Result<()> {
+    let rpc = MockRpc::default();
+    rpc.node()
+        .zmq_notifications
+        .retain(|notification| notification.kind != "pubrawtx");
+    let (_dir, mut app) = test_app(rpc.clone(), AppConfig::default());
+
+    let err = app.init().await.unwrap_err();
+    assert!(err.to_string().contains("no pubrawtx"), "{}", err);
+    assert!(err.to_string().contains("-zmqpubrawtx="), "{}", err);
+    assert_eq!(rpc.calls("getrawmempool"), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_init_fails_when_rawtx_is_published_on_another_port() -> Result<()> {
+    let rpc = MockRpc::default();
+    for notification in &mut rpc.node().zmq_notifications {
+        notification.address = "tcp://0.0.0.0:29000".to_string();
+    }
+    let (_dir, mut app) = test_app(rpc, AppConfig::default());
+
+    let err = app.init().await.unwrap_err();
+    assert!(err.to_string().contains("tcp://0.0.0.0:29000"), "{}", err);
+    assert!(err.to_string().contains("--bitcoind-zmq-port"), "{}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_init_checks_node_indexes_and_policy() -> Result<()> {
+    let rpc = MockRpc::default();
+    rpc.node().capabilities.indexes.clear();
+    let (_dir, mut app) = test_app(rpc.clone(), AppConfig::default());
+    let err = app.init().await.unwrap_err();
+    assert!(err.to_string().contains("-txindex"), "{}", err);
+    assert_eq!(rpc.calls("getrawmempool"), 0);
+
+    // Warnings only, the tracker starts on the same node
+    let config = AppConfig {
+        startup_checks: StartupCheckMode::Warn,
+        ..Default::default()
+    };
+    let (_dir, mut app) = test_app(rpc.clone(), config);
+    app.init().await?;
+
+    let rpc = MockRpc::default();
+    rpc.node().capabilities.full_rbf = Some(false);
+    let config = AppConfig {
+        expect_full_rbf: Some(true),
+        ..Default::default()
+    };
+    let (_dir, mut app) = test_app(rpc.clone(), config);
+    let err = app.init().await.unwrap_err();
+    assert!(err.to_string().contains("mempoolfullrbf=0"), "{}", err);
+
+    let config = AppConfig {
+        mined_detection: MinedDetection::BlockFilter,
+        ..Default::default()
+    };
+    let (_dir, mut app) = test_app(rpc.clone(), config.clone());
+    let err = app.init().await.unwrap_err();
+    assert!(err.to_string().contains("-blockfilterindex"), "{}", err);
+    rpc.node()
+        .capabilities
+        .indexes
+        .insert(NodeCapabilities::BLOCK_FILTER_INDEX.to_string(), true);
+    let (_dir, mut app) = test_app(rpc, config);
+    app.init().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_init_fails_when_node_capabilities_are_unavailable() -> Result<()> {
+    let rpc = MockRpc::default();
+    rpc.node()
+        .failing_methods
+        .insert("getnetworkinfo".to_string(), "Method not found".to_string());
+    let (_dir, mut app) = test_app(rpc.clone(), AppConfig::default());
+    let err = app.init().await.unwrap_err();
+    assert!(
+        err.to_string().contains("indexes and mempool policy"),
+        "{}",
+        err
+    );
+    assert!(err.to_string().contains("Method not found"), "{}", err);
+    assert_eq!(rpc.calls("getrawmempool"), 0);
+
+    let config = AppConfig {
+        startup_checks: StartupCheckMode::Warn,
+        ..Default::default()
+    };
+    let (_dir, mut app) = test_app(rpc, config);
+    app.init().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_init_checks_secondary_nodes() -> Result<()> {
+    let rpc = MockRpc::default();
+    let secondary = MockRpc::default();
+    secondary.node().blockchain.initial_block_download = true;
+    let (_dir, app) = test_app(rpc.clone(), AppConfig::default());
+    let mut app = app.with_secondary_node(SecondaryNode {
+        node_id: "backup".to_string(),
+        rpc_client: secondary.clone(),
+        zmq_factory: BitcoinZmqFactory::new("127.0.0.1".to_string(), MOCK_ZMQ_PORT),
+        rpc_retry: RpcRetryPolicy::default(),
+    });
+
+    let err = app.init().await.unwrap_err();
+    assert!(err.to_string().contains("node backup"), "{}", err);
+    assert!(
+        format!("{:#}", err).contains("initial block download"),
+        "{:#}",
+        err
+    );
+    assert_eq!(rpc.calls("getrawmempool"), 0);
+
+    secondary.node().blockchain.initial_block_download = false;
+    secondary
+        .node()
+        .zmq_notifications
+        .retain(|notification| notification.kind != "pubrawtx");
+    let err = app.init().await.unwrap_err();
+    assert!(format!("{:#}", err).contains("no pubrawtx"), "{:#}", err);
+
+    // Named after the setting of the secondary, not the primary's CLI flag
+    secondary.node().zmq_notifications = rpc.node().zmq_notifications.clone();
+    for notification in &mut secondary.node().zmq_notifications {
+        notification.address = "tcp://0.0.0.0:29000".to_string();
+    }
+    let err = format!("{:#}", app.init().await.unwrap_err());
+    assert!(err.contains("zmq.rawtx of [[nodes]] backup"), "{}", err);
+    assert!(!err.contains("--bitcoind-zmq-port"), "{}", err);
+
+    secondary.node().zmq_notifications = rpc.node().zmq_notifications.clone();
+    secondary.node().capabilities.indexes.clear();
+    let err = app.init().await.unwrap_err();
+    assert!(format!("{:#}", err).contains("-txindex"), "{:#}", err);
+    assert_eq!(secondary.calls("getnetworkinfo"), 1);
+    assert_eq!(rpc.calls("getrawmempool"), 0);
+
+    // Only the primary's blocks are processed, so only it needs a block filter index
+    rpc.node()
+        .capabilities
+        .indexes
+        .insert(NodeCapabilities::BLOCK_FILTER_INDEX.to_string(), true);
+    secondary
+        .node()
+        .capabilities
+        .indexes
+        .insert(NodeCapabilities::TXINDEX.to_string(), true);
+    let config = AppConfig {
+        mined_detection: MinedDetection::BlockFilter,
+        ..Default::default()
+    };
+    let (_dir, app) = test_app(rpc, config);
+    let mut app = app.with_secondary_node(SecondaryNode {
+        node_id: "backup".to_string(),
+        rpc_client: secondary,
+        zmq_factory: BitcoinZmqFactory::new("127.0.0.1".to_string(), MOCK_ZMQ_PORT),
+        rpc_retry: RpcRetryPolicy::default(),
+    });
+    app.init().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_init_fails_without_configured_block_topic() -> Result<()> {
+    let rpc = MockRpc::default();
+    rpc.node()
+        .zmq_notifications
+        .retain(|notification| notification.kind != "pubhashblock");
+    let (_dir, mut app) = test_app(rpc, AppConfig::default());
+
+    let err = app.init().await.unwrap_err();
+    assert!(err.to_string().contains("-zmqpubhashblock="), "{}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_init_validates_timer_intervals() -> Result<()> {
+    let config = AppConfig {
+        mempool_state_check_interval: Duration::from_secs(5),
+        ..Default::default()
+    };
+    let (_dir, mut app) = test_app(MockRpc::default(), config);
+    let err = app.init().await.unwrap_err();
+    assert!(err.to_string().contains("mempool state"), "{}", err);
+
+    let config = AppConfig {
+        prune_check_interval: Duration::ZERO,
+        ..Default::default()
+    };
+    let (_dir, mut app) = test_app(MockRpc::default(), config.clone());
+    let err = app.init().await.unwrap_err();
+    assert!(err.to_string().contains("sequence topic"), "{}", err);
+
+    let rpc = MockRpc::default();
+    rpc.node().zmq_notifications.push(ZmqNotification {
+        kind: "pubsequence".to_string(),
+        address: format!("tcp://127.0.0.1:{}", MOCK_ZMQ_PORT),
+        high_water_mark: 1000,
+    });
+    let config = AppConfig {
+        zmq_topics: vec![ZmqTopic::RawTx, ZmqTopic::Sequence],
+        ..config
+    };
+    let (_dir, mut app) = test_app(rpc, config);
+    app.init().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replay_init_leaves_the_node_mempool_alone() -> Result<()> {
+    let rpc = MockRpc::default();
+    let config = AppConfig {
+        replay: Some(ReplaySource {
+            path: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/replay.capture").into(),
+            realtime: false,
+        }),
+        ..Default::default()
+    };
+    let (_dir, mut app) = test_app(rpc.clone(), config);
+    app.init().await?;
+    assert_eq!(rpc.calls("getrawmempool"), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dead_worker_is_replaced() -> Result<()> {
+    let rpc = MockRpc::default();
+    let (_dir, mut app) = test_app(rpc.clone(), AppConfig::default());
+    app.init().await?;
+    assert_eq!(app.metrics().workers, 2);
+    assert_eq!(app.metrics().worker_restarts, 0);
+
+    rpc.node().panic_on = Some("getrawmempool".to_string());
+    app.task_sender().send(Task::PruneCheck.into()).await?;
+    assert!(app.supervise_next_worker_exit().await?);
+    assert_eq!(app.metrics().workers, 2);
+    assert_eq!(app.metrics().worker_restarts, 1);
+
+    // The replacement picks up work
+    let calls = rpc.calls("getrawmempool");
+    app.task_sender().send(Task::PruneCheck.into()).await?;
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while rpc.calls("getrawmempool") == calls {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mempool_sync_continues_past_failed_fetches() -> Result<()> {
+    let rpc = MockRpc::default();
+    let (_dir, db, _conn) = temp_db();
+    let zmq_factory = BitcoinZmqFactory::new("127.0.0.1".to_string(), MOCK_ZMQ_PORT);
+    let app = App::new(
+        rpc.clone(),
+        zmq_factory,
+        db.clone(),
+        EventPublisher::disabled(),
+        AppConfig::default(),
+    );
+    let txs: Vec<_> = (1..=3)
+        .map(|n| dummy_tx(&[(dummy_txid(n), 0)], &[9_000]))
+        .collect();
+    for (n, tx) in txs.iter().enumerate() {
+        rpc.add_to_mempool(tx, 1_700_000_000, Amount::from_sat(1_000 * (n as u64 + 1)));
+    }
+    // Listed in the mempool but gone by the time it's fetched
+    rpc.node().transactions.remove(&txs[2].compute_txid());
+
+    let summary = app.extract_existing_mempool().await?;
+    assert_eq!((summary.synced, summary.failed), (2, 1));
+    // The fee comes from the mempool entry, the prevouts were never looked up
+    let (fee, _) = db.get_stored_fee(&txs[1])?.expect("stored");
+    assert_eq!(fee, Amount::from_sat(2_000));
+    assert!(!db.tx_exists(&txs[2])?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shutdown_drains_queued_tasks() -> Result<()> {
+    let rpc = MockRpc::default();
+    let (_dir, mut app) = test_app(rpc.clone(), AppConfig::default());
+    app.init().await?;
+    let calls = rpc.calls("getrawmempool");
+    for _ in 0..3 {
+        app.task_sender().send(Task::PruneCheck.into()).await?;
+    }
+
+    let report = app.drain_workers(Duration::from_secs(5)).await?;
+    // Our prune checks plus the rescan queued by init
+    assert_eq!(
+        report,
+        DrainReport {
+            drained: 4,
+            dropped: 0
+        }
+    );
+    assert_eq!(rpc.calls("getrawmempool"), calls + 3);
+    assert_eq!(app.worker_stats().processed(), 4);
+    let per_worker = app.per_worker_stats();
+    assert_eq!(per_worker.len(), 2);
+    assert_eq!(
+        per_worker
+            .iter()
+            .map(|stats| stats.processed())
+            .sum::<u64>(),
+        4
+    );
+    assert_eq!(app.metrics().workers, 0);
+    assert!(app.task_sender().is_closed());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shutdown_timeout_drops_queued_and_cut_off_tasks() -> Result<()> {
+    let rpc = MockRpc::default();
+    let config = AppConfig {
+        num_workers: 1,
+        ..Default::default()
+    };
+    let (_dir, mut app) = test_app(rpc.clone(), config);
+    app.init().await?;
+    let calls = rpc.calls("getrawmempool");
+    // Prune checks now hang past the shutdown timeout
+    rpc.node()
+        .delays
+        .insert("getrawmempool".to_string(), Duration::from_secs(10));
+    for _ in 0..2 {
+        app.task_sender().send(Task::PruneCheck.into()).await?;
+    }
+
+    let report = app.drain_workers(Duration::from_millis(200)).await?;
+    // The rescan queued by init goes through, the first prune check is aborted mid-way
+    // and the second never starts
+    assert_eq!(
+        report,
+        DrainReport {
+            drained: 1,
+            dropped: 2
+        }
+    );
+    assert_eq!(rpc.calls("getrawmempool"), calls + 1);
+    assert_eq!(app.metrics().workers, 0);
+    Ok(())
+}
+
+#[test]
+fn test_task_channel_capacity_is_configurable() {
+    let config = AppConfig {
+        task_channel_capacity: Some(3),
+        ..Default::default()
+    };
+    let (_dir, app) = test_app(MockRpc::default(), config);
+    let tasks = app.task_sender();
+    assert_eq!(tasks.capacity(), Some(3));
+    for _ in 0..3 {
+        tasks.try_send(Task::PruneCheck.into()).unwrap();
+    }
+    assert!(tasks.is_full());
+    assert!(tasks.try_send(Task::PruneCheck.into()).is_err());
+}
+
+#[tokio::test]
+async fn test_queue_sender_records_depth_and_blocked_sends() -> Result<()> {
+    let (tx, rx) = bounded(2);
+    let health = Health::default();
+    let mut sender = QueueSender::new(tx, health.clone());
+    sender.send(Task::PruneCheck).await?;
+    assert_eq!(health.queue_depth(), 1);
+    sender.send(Task::PruneCheck).await?;
+    assert_eq!(health.queue_depth(), 2);
+    assert_eq!(health.send_blocked(), Duration::ZERO);
+
+    // The full queue blocks the next send until a task is taken off it
+    let consumer = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        rx.recv().await
+    });
+    sender.send(Task::PruneCheck).await?;
+    consumer.await??;
+    assert!(health.send_blocked() >= Duration::from_millis(40));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_queue_sender_records_a_send_still_blocked() -> Result<()> {
+    let (tx, rx) = bounded(1);
+    let health = Health::default();
+    let mut sender = QueueSender::new(tx, health.clone());
+    sender.send(Task::PruneCheck).await?;
+
+    // The workers stalled, the send never returns on its own
+    let blocked = tokio::spawn(async move { sender.send(Task::PruneCheck).await });
+    tokio::time::sleep(Duration::from_millis(1_500)).await;
+    assert!(!blocked.is_finished());
+    assert!(health.send_blocked() >= Duration::from_secs(1));
+
+    rx.recv().await?;
+    blocked.await??;
+    assert!(health.send_blocked() >= Duration::from_millis(1_400));
+    Ok(())
+}
+
+#[test]
+fn test_task_channel_capacity_scales_with_workers() {
+    let config = AppConfig {
+        num_workers: 6,
+        ..Default::default()
+    };
+    let (_dir, app) = test_app(MockRpc::default(), config);
+    let six = app.task_sender().capacity().unwrap();
+    let (_dir, app) = test_app(MockRpc::default(), AppConfig::default());
+    let two = app.task_sender().capacity().unwrap();
+    assert_eq!(six, two * 3);
+}
+
+#[tokio::test]
+async fn test_dry_run_refuses_a_writable_database() -> Result<()> {
+    let config = AppConfig {
+        dry_run: true,
+        ..Default::default()
+    };
+    let (_dir, mut app) = test_app(MockRpc::default(), config);
+    assert!(app.dry_run_summary().is_some());
+    let err = app.init().await.unwrap_err();
+    assert!(err.to_string().contains("DryRunStore"), "{}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_init_requires_a_worker() -> Result<()> {
+    let config = AppConfig {
+        num_workers: 0,
+        ..Default::default()
+    };
+    let (_dir, mut app) = test_app(MockRpc::default(), config);
+    let err = app.init().await.unwrap_err();
+    assert!(err.to_string().contains("At least one worker"), "{}", err);
+    assert_eq!(app.metrics().workers, 0);
+    Ok(())
+}
+
+#[test]
+fn test_tip_age_counts_from_last_observation() {
+    let (_dir, app) = test_app(MockRpc::default(), AppConfig::default());
+    let tip = app.tip_tracker();
+    assert!(tip.last().is_none());
+    assert!(app.tip_age_secs() < 5);
+
+    let observed_at = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        - 45 * 60;
+    tip.set(ObservedTip {
+        height: 100,
+        hash: BlockHash::all_zeros(),
+        observed_at,
+    });
+    let age = app.tip_age_secs();
+    assert!((45 * 60..45 * 60 + 5).contains(&age), "{}", age);
+    assert!(tip.is_stale());
+}
+
+type MockZmqStream = BoxStream<'static, Result<Message, String>>;
+
+fn fast_reconnects(max_failures: u32) -> ZmqReconnectPolicy {
+    ZmqReconnectPolicy {
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(5),
+        max_failures,
+    }
+}
+
+#[tokio::test]
+async fn test_zmq_listener_reconnects_and_resyncs() -> Result<()> {
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let dropped: MockZmqStream = stream::iter(vec![Err("socket closed".to_string())]).boxed();
+    let resumed = tx.clone();
+    let connect = move || -> Result<MockZmqStream> {
+        Ok(stream::iter(vec![Ok(Message::Tx(resumed.clone(), 0))])
+            .chain(stream::pending())
+            .boxed())
+    };
+    let (control_tx, control_rx) = bounded(10);
+    let (tasks_tx, tasks_rx) = bounded(10);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::broadcast::channel(1);
+    let listener = tokio::spawn(listen_zmq(
+        dropped,
+        connect,
+        control_tx,
+        QueueSender::new(tasks_tx, Health::default()),
+        shutdown_rx,
+        fast_reconnects(3),
+        vec![ZmqTopic::RawTx],
+    ));
+
+    let task = tokio::time::timeout(Duration::from_secs(5), tasks_rx.recv()).await??;
+    assert!(matches!(task.task, Task::RawTx(bytes) if !bytes.is_empty()));
+    // Whatever was published during the gap gets picked up
+    assert!(matches!(
+        control_rx.recv().await?.task,
+        Task::ReconcileMempool
+    ));
+    assert!(matches!(control_rx.recv().await?.task, Task::Rescan));
+
+    shutdown_tx.send(())?;
+    listener.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_zmq_listener_gives_up_after_consecutive_failures() -> Result<()> {
+    let dropped: MockZmqStream = stream::iter(vec![]).boxed();
+    let mut attempts = 0;
+    let connect = move || -> Result<MockZmqStream> {
+        attempts += 1;
+        Err(anyhow::anyhow!("connection refused, attempt {}", attempts))
+    };
+    let (control_tx, control_rx) = bounded(10);
+    let (tasks_tx, _tasks_rx) = bounded(10);
+    let (_shutdown_tx, shutdown_rx) = tokio::sync::broadcast::channel(1);
+
+    let err = tokio::time::timeout(
+        Duration::from_secs(5),
+        listen_zmq(
+            dropped,
+            connect,
+            control_tx,
+            QueueSender::new(tasks_tx, Health::default()),
+            shutdown_rx,
+            fast_reconnects(3),
+            vec![ZmqTopic::RawTx],
+        ),
+    )
+    .await?
+    .unwrap_err();
+    assert!(err.to_string().contains("3 times in a row"), "{}", err);
+    // Never reconnected, so no resync was queued
+    assert!(control_rx.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_zmq_listener_routes_configured_topics() -> Result<()> {
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let block = dummy_block(1_700_000_000, vec![]);
+    let messages: MockZmqStream = stream::iter(vec![
+        Ok(Message::Tx(tx.clone(), 0)),
+        Ok(Message::HashBlock(block.block_hash(), 0)),
+        Ok(Message::Sequence(
+            SequenceMessage::MempoolRemoval {
+                txid: tx.compute_txid(),
+                mempool_sequence: 1,
+            },
+            0,
+        )),
+        // Not subscribed
+        Ok(Message::Block(block.clone(), 0)),
+    ])
+    .chain(stream::pending())
+    .boxed();
+    let (control_tx, control_rx) = bounded(10);
+    let (tasks_tx, tasks_rx) = bounded(10);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::broadcast::channel(1);
+    let listener = tokio::spawn(listen_zmq(
+        messages,
+        || -> Result<MockZmqStream> { Ok(stream::pending().boxed()) },
+        control_tx,
+        QueueSender::new(tasks_tx, Health::default()),
+        shutdown_rx,
+        fast_reconnects(3),
+        vec![ZmqTopic::RawTx, ZmqTopic::HashBlock, ZmqTopic::Sequence],
+    ));
+
+    let recv = |rx: async_channel::Receiver<Queued>| async move {
+        tokio::time::timeout(Duration::from_secs(5), rx.recv()).await
+    };
+    assert!(matches!(
+        recv(tasks_rx.clone()).await??.task,
+        Task::RawTx(_)
+    ));
+    assert!(matches!(
+        recv(tasks_rx.clone()).await??.task,
+        Task::Sequence(SequenceEvent::TxRemoved(txid)) if txid == tx.compute_txid()
+    ));
+    assert!(matches!(
+        recv(control_rx.clone()).await??.task,
+        Task::NewBlock(hash) if hash == block.block_hash()
+    ));
+    assert!(matches!(
+        recv(control_rx.clone()).await??.task,
+        Task::MempoolState
+    ));
+
+    // Let the unsubscribed raw block go through the listener before checking it was dropped
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    shutdown_tx.send(())?;
+    listener.await??;
+    assert!(control_rx.is_empty());
+    assert!(tasks_rx.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_new_block_refreshes_the_mempool_state_once() -> Result<()> {
+    let block = dummy_block(1_700_000_000, vec![]);
+    let next = dummy_block(1_700_000_600, vec![]);
+    // The same block announced on every block topic, then the next one
+    let messages: MockZmqStream = stream::iter(vec![
+        Ok(Message::HashBlock(block.block_hash(), 0)),
+        Ok(Message::Block(block.clone(), 0)),
+        Ok(Message::Sequence(
+            SequenceMessage::BlockConnect {
+                blockhash: block.block_hash(),
+            },
+            0,
+        )),
+        Ok(Message::HashBlock(next.block_hash(), 1)),
+    ])
+    .chain(stream::pending())
+    .boxed();
+    let (control_tx, control_rx) = bounded(10);
+    let (tasks_tx, _tasks_rx) = bounded(10);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::broadcast::channel(1);
+    let listener = tokio::spawn(listen_zmq(
+        messages,
+        || -> Result<MockZmqStream> { Ok(stream::pending().boxed()) },
+        control_tx,
+        QueueSender::new(tasks_tx, Health::default()),
+        shutdown_rx,
+        fast_reconnects(3),
+        ZmqTopic::ALL.to_vec(),
+    ));
+
+    let mut kinds = Vec::new();
+    while kinds.len() < 6 {
+        let queued = tokio::time::timeout(Duration::from_secs(5), control_rx.recv()).await??;
+        kinds.push(queued.task.kind());
+    }
+    assert_eq!(
+        kinds,
+        vec![
+            "new_block",
+            "mempool_state",
+            "raw_block",
+            "sequence",
+            "new_block",
+            "mempool_state"
+        ]
+    );
+    shutdown_tx.send(())?;
+    listener.await??;
+    assert!(control_rx.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_capture_replay_keeps_the_capture_times() -> Result<()> {
+    let (control_tx, control_rx) = bounded(10);
+    let (tasks_tx, tasks_rx) = bounded(10);
+    let (_shutdown_tx, shutdown_rx) = tokio::sync::broadcast::channel(1);
+    let source = ReplaySource {
+        path: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/replay.capture").into(),
+        realtime: false,
+    };
+
+    let replayed = replay_capture(
+        source,
+        control_tx,
+        QueueSender::new(tasks_tx, Health::default()),
+        shutdown_rx,
+    )
+    .await?;
+
+    // The debug record isn't a topic we handle
+    assert_eq!(replayed, 3);
+    let found_at: Vec<u64> = std::iter::from_fn(|| tasks_rx.try_recv().ok())
+        .map(|queued| match queued.task {
+            Task::ReplayedRawTx { raw_tx, found_at } => {
+                assert_eq!(raw_tx.len(), 60);
+                found_at
+            }
+            task => panic!("unexpected {}", task),
+        })
+        .collect();
+    assert_eq!(found_at, vec![1_700_000_000_000, 1_700_000_002_500]);
+    let expected: BlockHash =
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f".parse()?;
+    assert!(matches!(control_rx.try_recv()?.task, Task::NewBlock(hash) if hash == expected));
+    assert!(control_rx.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_zmq_topic_names() -> Result<()> {
+    for topic in ZmqTopic::ALL {
+        assert_eq!(topic.as_str().parse::<ZmqTopic>()?, topic);
+    }
+    assert_eq!(ZmqTopic::HashBlock.notification(), "pubhashblock");
+    assert!("rawfoo".parse::<ZmqTopic>().is_err());
+    Ok(())
+}
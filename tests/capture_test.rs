@@ -0,0 +1,132 @@
+use mempool_tracker::capture::{
+    capture_files, CaptureConfig, CaptureReader, CaptureRecord, CaptureSink, CaptureWriter,
+    FsyncPolicy,
+};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/replay.capture");
+
+#[test]
+fn test_fixture_records_are_read_in_order() -> anyhow::Result<()> {
+    let records = CaptureReader::open(FIXTURE)?.collect::<anyhow::Result<Vec<_>>>()?;
+    let headers: Vec<(u64, &str, usize)> = records
+        .iter()
+        .map(|record| {
+            (
+                record.received_at_ms,
+                record.topic.as_str(),
+                record.payload.len(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        headers,
+        vec![
+            (1_700_000_000_000, "rawtx", 60),
+            (1_700_000_001_000, "debug", 5),
+            (1_700_000_002_500, "rawtx", 60),
+            (1_700_000_003_000, "hashblock", 32),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_records_round_trip_and_truncation_errors() -> anyhow::Result<()> {
+    let records = vec![
+        CaptureRecord {
+            received_at_ms: 1,
+            topic: "rawtx".to_string(),
+            payload: vec![0xde, 0xad],
+        },
+        CaptureRecord {
+            received_at_ms: 2,
+            topic: "sequence".to_string(),
+            payload: vec![],
+        },
+    ];
+    let mut bytes = vec![];
+    for record in &records {
+        record.write_to(&mut bytes)?;
+    }
+    let read = CaptureReader::new(bytes.as_slice()).collect::<anyhow::Result<Vec<_>>>()?;
+    assert_eq!(read, records);
+
+    // The reader stops at the record cut short
+    bytes.pop();
+    let mut reader = CaptureReader::new(bytes.as_slice());
+    assert_eq!(reader.next().transpose()?, Some(records[0].clone()));
+    let err = reader.next().unwrap().unwrap_err();
+    assert!(err.to_string().contains("truncated"), "{}", err);
+    assert!(reader.next().is_none());
+    Ok(())
+}
+
+fn record(n: u8) -> CaptureRecord {
+    CaptureRecord {
+        received_at_ms: n as u64,
+        topic: "rawtx".to_string(),
+        payload: vec![n; 10],
+    }
+}
+
+#[test]
+fn test_capture_rotates_by_size_and_keeps_max_files() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("zmq.capture");
+    // Two records per file
+    let config = CaptureConfig {
+        path: path.clone(),
+        max_file_bytes: 2 * record(0).encoded_len(),
+        max_files: 3,
+        fsync: FsyncPolicy::OnRotate,
+    };
+    let mut writer = CaptureWriter::open(config.clone())?;
+    for n in 0..5 {
+        writer.append(&record(n))?;
+    }
+    drop(writer);
+    // Appending goes on where the file left off
+    let mut writer = CaptureWriter::open(config)?;
+    for n in 5..7 {
+        writer.append(&record(n))?;
+    }
+    drop(writer);
+
+    let files = capture_files(&path);
+    assert_eq!(files.len(), 3);
+    assert!(!dir.path().join("zmq.capture.3").exists());
+    let mut replayed = vec![];
+    for file in files {
+        for record in CaptureReader::open(file)? {
+            replayed.push(record?.received_at_ms);
+        }
+    }
+    // The oldest file, records 0 and 1, was deleted
+    assert_eq!(replayed, vec![2, 3, 4, 5, 6]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_capture_failures_are_counted_not_returned() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("zmq.capture");
+    let writer = CaptureWriter::open(CaptureConfig {
+        path,
+        max_file_bytes: record(0).encoded_len(),
+        max_files: 2,
+        fsync: FsyncPolicy::Never,
+    })?;
+    // The first record fits, the next ones rotate into a directory that is gone
+    std::fs::remove_dir_all(dir.path())?;
+    let sink = CaptureSink::new(writer);
+    for n in 0..3 {
+        sink.append(record(n));
+    }
+    sink.finish().await;
+    assert_eq!(sink.failures(), 2);
+    assert_eq!(sink.dropped(), 0);
+    // Nothing is queued once finished
+    sink.append(record(3));
+    assert_eq!(sink.dropped(), 0);
+    Ok(())
+}
@@ -0,0 +1,336 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use bitcoin::{
+    bip158::BlockFilter, hashes::Hash, Amount, Block, BlockHash, FeeRate, ScriptBuf, Transaction,
+    Txid,
+};
+use mempool_tracker::rpc::{
+    BitcoinRpc, BlockSummary, BlockchainStatus, MempoolEntry, MempoolStatus, NodeCapabilities,
+    TxStatus, ZmqNotification,
+};
+
+/// Port the mock node's zmq notifications are published on
+pub const MOCK_ZMQ_PORT: u16 = 28332;
+
+/// In-memory node state behind `MockRpc`
+#[derive(Debug)]
+pub struct MockNode {
+    pub blockchain: BlockchainStatus,
+    pub mempool_loaded: bool,
+    pub mempool_min_fee: FeeRate,
+    /// Every transaction the node can serve, including prevouts
+    pub transactions: HashMap<Txid, Transaction>,
+    pub mempool: HashMap<Txid, MempoolEntry>,
+    pub confirmations: HashMap<Txid, u64>,
+    /// Block each tx of an added block belongs to
+    pub block_hashes: HashMap<Txid, BlockHash>,
+    pub reject_reasons: HashMap<Txid, String>,
+    /// Blocks by height
+    pub blocks: HashMap<u64, Block>,
+    pub zmq_notifications: Vec<ZmqNotification>,
+    /// txindex on and synced, full RBF
+    pub capabilities: NodeCapabilities,
+    /// RPC method names in call order
+    pub calls: Vec<String>,
+    /// Number of upcoming calls that fail as if the node were down
+    pub unreachable_calls: usize,
+    /// The next call of this RPC method panics, taking its caller down with it
+    pub panic_on: Option<String>,
+    /// RPC methods failing with this error message until removed
+    pub failing_methods: HashMap<String, String>,
+    /// RPC methods answering only after this long
+    pub delays: HashMap<String, Duration>,
+}
+
+impl Default for MockNode {
+    fn default() -> Self {
+        Self {
+            blockchain: BlockchainStatus {
+                blocks: 100,
+                initial_block_download: false,
+                verification_progress: 1.0,
+            },
+            mempool_loaded: true,
+            mempool_min_fee: FeeRate::BROADCAST_MIN,
+            transactions: HashMap::new(),
+            mempool: HashMap::new(),
+            confirmations: HashMap::new(),
+            block_hashes: HashMap::new(),
+            reject_reasons: HashMap::new(),
+            blocks: HashMap::new(),
+            zmq_notifications: ["pubrawtx", "pubhashblock"]
+                .into_iter()
+                .map(|kind| ZmqNotification {
+                    kind: kind.to_string(),
+                    address: format!("tcp://127.0.0.1:{}", MOCK_ZMQ_PORT),
+                    high_water_mark: 1000,
+                })
+                .collect(),
+            capabilities: NodeCapabilities {
+                version: 270000,
+                subversion: "/Satoshi:27.0.0/".to_string(),
+                indexes: HashMap::from([(NodeCapabilities::TXINDEX.to_string(), true)]),
+                full_rbf: Some(true),
+            },
+            calls: vec![],
+            unreachable_calls: 0,
+            panic_on: None,
+            failing_methods: HashMap::new(),
+            delays: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MockRpc(pub Arc<Mutex<MockNode>>);
+
+impl MockRpc {
+    pub fn node(&self) -> std::sync::MutexGuard<'_, MockNode> {
+        self.0.lock().unwrap()
+    }
+
+    /// Make a transaction fetchable without it being in the mempool, e.g. a confirmed parent
+    pub fn add_tx(&self, tx: &Transaction) {
+        self.node()
+            .transactions
+            .insert(tx.compute_txid(), tx.clone());
+    }
+
+    /// Add to the mempool, `depends`/`spentby` are derived from the other mempool txs
+    pub fn add_to_mempool(&self, tx: &Transaction, time: u64, fee: Amount) {
+        self.add_tx(tx);
+        let txid = tx.compute_txid();
+        let mut node = self.node();
+        let depends: Vec<Txid> = tx
+            .input
+            .iter()
+            .map(|input| input.previous_output.txid)
+            .filter(|parent| node.mempool.contains_key(parent))
+            .collect();
+        for parent in &depends {
+            if let Some(entry) = node.mempool.get_mut(parent) {
+                entry.spent_by.push(txid);
+            }
+        }
+        node.mempool.insert(
+            txid,
+            MempoolEntry {
+                time,
+                fee,
+                vsize: tx.vsize() as u64,
+                depends,
+                spent_by: vec![],
+            },
+        );
+    }
+
+    pub fn confirm(&self, txid: &Txid, confirmations: u64) {
+        let mut node = self.node();
+        node.mempool.remove(txid);
+        node.confirmations.insert(*txid, confirmations);
+    }
+
+    /// Add a block at `height`, its transactions become fetchable. A block above the
+    /// current tip becomes the new tip
+    pub fn add_block(&self, height: u64, block: Block) {
+        for tx in &block.txdata {
+            self.add_tx(tx);
+        }
+        let mut node = self.node();
+        let hash = block.block_hash();
+        for tx in &block.txdata {
+            node.block_hashes.insert(tx.compute_txid(), hash);
+        }
+        node.blocks.insert(height, block);
+        node.blockchain.blocks = node.blockchain.blocks.max(height);
+    }
+
+    async fn record(&self, method: &str) -> Result<()> {
+        self.record_call(method)?;
+        let delay = self.node().delays.get(method).copied();
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+        Ok(())
+    }
+
+    fn record_call(&self, method: &str) -> Result<()> {
+        let mut node = self.node();
+        node.calls.push(method.to_string());
+        if node.panic_on.as_deref() == Some(method) {
+            node.panic_on = None;
+            // Release the lock first so the mock stays usable
+            drop(node);
+            panic!("mock {} panicked", method);
+        }
+        if node.unreachable_calls > 0 {
+            node.unreachable_calls -= 1;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionRefused,
+                "connection refused",
+            )
+            .into());
+        }
+        if let Some(message) = node.failing_methods.get(method) {
+            return Err(anyhow!("{}", message));
+        }
+        Ok(())
+    }
+
+    pub fn calls(&self, method: &str) -> usize {
+        self.node().calls.iter().filter(|c| *c == method).count()
+    }
+}
+
+impl BitcoinRpc for MockRpc {
+    async fn get_blockchain_info(&self) -> Result<BlockchainStatus> {
+        self.record("getblockchaininfo").await?;
+        Ok(self.node().blockchain.clone())
+    }
+
+    async fn get_mempool_info(&self) -> Result<MempoolStatus> {
+        self.record("getmempoolinfo").await?;
+        let node = self.node();
+        Ok(MempoolStatus {
+            loaded: node.mempool_loaded,
+            size: node.mempool.len() as u64,
+            bytes: node.mempool.values().map(|entry| entry.vsize).sum(),
+            usage: node.mempool.values().map(|entry| entry.vsize).sum::<u64>() * 4,
+            max_mempool: 300_000_000,
+            mempool_min_fee: node.mempool_min_fee,
+            min_relay_tx_fee: FeeRate::BROADCAST_MIN,
+        })
+    }
+
+    async fn get_block_count(&self) -> Result<u64> {
+        self.record("getblockcount").await?;
+        Ok(self.node().blockchain.blocks)
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<BlockHash> {
+        self.record("getblockhash").await?;
+        if let Some(block) = self.node().blocks.get(&height) {
+            return Ok(block.block_hash());
+        }
+        let mut bytes = [0u8; 32];
+        bytes[..8].copy_from_slice(&height.to_le_bytes());
+        Ok(BlockHash::from_byte_array(bytes))
+    }
+
+    async fn get_block(&self, hash: &BlockHash) -> Result<Block> {
+        self.record("getblock").await?;
+        if let Some(block) = self
+            .node()
+            .blocks
+            .values()
+            .find(|block| block.block_hash() == *hash)
+        {
+            return Ok(block.clone());
+        }
+        // Heights without an added block serve an empty one under their made up hash
+        if hash.as_byte_array()[8..].iter().all(|byte| *byte == 0) {
+            return Ok(super::dummy_block(0, vec![]));
+        }
+        Err(anyhow!("Block not found"))
+    }
+
+    async fn get_block_summary(&self, hash: &BlockHash) -> Result<BlockSummary> {
+        self.record("getblocksummary").await?;
+        let node = self.node();
+        let (height, block) = node
+            .blocks
+            .iter()
+            .find(|(_, block)| block.block_hash() == *hash)
+            .ok_or_else(|| anyhow!("Block not found"))?;
+        Ok(BlockSummary {
+            height: *height,
+            time: block.header.time as u64,
+            weight: block.weight().to_wu(),
+            tx_count: block.txdata.len(),
+        })
+    }
+
+    async fn get_block_filter(&self, hash: &BlockHash) -> Result<BlockFilter> {
+        self.record("getblockfilter").await?;
+        let node = self.node();
+        let block = node
+            .blocks
+            .values()
+            .find(|block| block.block_hash() == *hash)
+            .ok_or_else(|| anyhow!("Block not found"))?;
+        // Prevouts the node doesn't know leave their scripts out of the filter
+        Ok(BlockFilter::new_script_filter(block, |outpoint| {
+            Ok(node
+                .transactions
+                .get(&outpoint.txid)
+                .and_then(|tx| tx.output.get(outpoint.vout as usize))
+                .map(|output| output.script_pubkey.clone())
+                .unwrap_or_else(ScriptBuf::new))
+        })?)
+    }
+
+    async fn get_raw_mempool(&self) -> Result<Vec<Txid>> {
+        self.record("getrawmempool").await?;
+        Ok(self.node().mempool.keys().copied().collect())
+    }
+
+    async fn get_raw_mempool_verbose(&self) -> Result<HashMap<Txid, MempoolEntry>> {
+        self.record("getrawmempool").await?;
+        Ok(self.node().mempool.clone())
+    }
+
+    async fn get_mempool_entry(&self, txid: &Txid) -> Result<MempoolEntry> {
+        self.record("getmempoolentry").await?;
+        self.node()
+            .mempool
+            .get(txid)
+            .cloned()
+            .ok_or_else(|| anyhow!("Transaction not in mempool"))
+    }
+
+    async fn get_raw_transaction(&self, txid: &Txid) -> Result<Transaction> {
+        self.record("getrawtransaction").await?;
+        self.node()
+            .transactions
+            .get(txid)
+            .cloned()
+            .ok_or_else(|| anyhow!("No such mempool or blockchain transaction"))
+    }
+
+    async fn get_tx_status(&self, txid: &Txid) -> Result<TxStatus> {
+        self.record("getrawtransactioninfo").await?;
+        let node = self.node();
+        if !node.transactions.contains_key(txid) {
+            return Err(anyhow!("No such mempool or blockchain transaction"));
+        }
+        let confirmations = node.confirmations.get(txid).copied().unwrap_or(0);
+        Ok(TxStatus {
+            confirmations,
+            block_hash: node
+                .block_hashes
+                .get(txid)
+                .copied()
+                .filter(|_| confirmations > 0),
+        })
+    }
+
+    async fn test_mempool_accept(&self, tx: &Transaction) -> Result<Option<String>> {
+        self.record("testmempoolaccept").await?;
+        Ok(self.node().reject_reasons.get(&tx.compute_txid()).cloned())
+    }
+
+    async fn get_zmq_notifications(&self) -> Result<Vec<ZmqNotification>> {
+        self.record("getzmqnotifications").await?;
+        Ok(self.node().zmq_notifications.clone())
+    }
+
+    async fn get_node_capabilities(&self) -> Result<NodeCapabilities> {
+        self.record("getnetworkinfo").await?;
+        Ok(self.node().capabilities.clone())
+    }
+}
@@ -0,0 +1,83 @@
+#![allow(dead_code)]
+
+pub mod mock_rpc;
+
+use bitcoin::{
+    absolute::LockTime, block, hashes::Hash, transaction::Version, Amount, Block, BlockHash,
+    CompactTarget, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxMerkleNode, TxOut, Txid,
+    Witness,
+};
+use mempool_tracker::database::Database;
+use tempfile::TempDir;
+
+/// Fresh migrated database in a temp directory. Keep the `TempDir` alive for the test's duration
+pub fn temp_db() -> (TempDir, Database, rusqlite::Connection) {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let path = dir.path().join("mempool_tracker_test.db");
+    let db = Database::new(path.to_str().unwrap()).expect("open database");
+    db.run_migrations().expect("migrations");
+    // Raw connection so tests can seed rows with synthetic timestamps
+    let conn = rusqlite::Connection::open(&path).expect("open raw connection");
+    (dir, db, conn)
+}
+
+pub fn dummy_txid(n: u8) -> Txid {
+    Txid::from_byte_array([n; 32])
+}
+
+/// Transaction spending `(txid, vout)` pairs into outputs of the given values
+pub fn dummy_tx(inputs: &[(Txid, u32)], output_values: &[u64]) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: inputs
+            .iter()
+            .map(|(txid, vout)| TxIn {
+                previous_output: OutPoint::new(*txid, *vout),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: output_values
+            .iter()
+            .map(|value| TxOut {
+                value: Amount::from_sat(*value),
+                script_pubkey: ScriptBuf::new(),
+            })
+            .collect(),
+    }
+}
+
+/// Coinbase committing to `height` in its scriptSig, as required by BIP34
+pub fn dummy_coinbase(height: i64, value: u64) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: ScriptBuf::builder().push_int(height).into_script(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: ScriptBuf::new(),
+        }],
+    }
+}
+
+/// Block with the given timestamp and transactions, header fields are not validated
+pub fn dummy_block(time: u32, txdata: Vec<Transaction>) -> Block {
+    Block {
+        header: block::Header {
+            version: block::Version::TWO,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time,
+            bits: CompactTarget::from_consensus(0x207f_ffff),
+            nonce: 0,
+        },
+        txdata,
+    }
+}
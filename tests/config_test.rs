@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use bitcoin::FeeRate;
+use mempool_tracker::{
+    app::StartupCheckMode,
+    capture::FsyncPolicy,
+    config::{Auth, Config, ENV_BITCOIND_COOKIE_FILE, ENV_BITCOIND_PASSWORD, ENV_BITCOIND_USER},
+    database::{Retention, Synchronous, COINBASE_MATURITY},
+    logging::LogOutput,
+};
+
+const MINIMAL: &str = r#"
+[bitcoind]
+url = "http://127.0.0.1:8332"
+user = "file-user"
+password = "file-password"
+
+[zmq]
+rawtx = "tcp://127.0.0.1:28332"
+"#;
+
+fn parse(contents: &str, env: &[(&str, &str)]) -> Result<Config> {
+    let env: HashMap<String, String> = env
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    Config::parse_with_env(contents, |name| env.get(name).cloned())
+}
+
+#[test]
+fn test_example_config_parses() -> Result<()> {
+    let config = parse(include_str!("../contrib/mempool-tracker.example.toml"), &[])?;
+    assert_eq!(config.workers.count, 2);
+    assert_eq!(config.database.synchronous, Synchronous::Normal);
+    assert_eq!(config.intervals.prune_check, 120);
+    assert_eq!(config.startup_checks.mode, StartupCheckMode::Fail);
+    assert_eq!(config.startup_checks.expect_full_rbf, None);
+    assert!(config.capture.capture_config().is_none());
+    assert_eq!(config.logging.log_config().output, LogOutput::Stderr);
+    Ok(())
+}
+
+#[test]
+fn test_storage_filter_and_backfill_settings_are_read() -> Result<()> {
+    let config = parse(
+        &format!(
+            "{}\n[database]\ninputs_hash_tag = \"signet\"\ndust_threshold = 1000\ncoinbase_maturity = 5\n\
+             [workers]\nrpc_rate_limit = 20\nrpc_burst = 5\n\
+             [filter]\nmin_fee_rate = 2\nwatch_addresses = [\"bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq\"]\n\
+             [backfill]\nfrom = 10\nto = 20\n",
+            MINIMAL
+        ),
+        &[],
+    )?;
+    assert_eq!(config.database.inputs_hash_tag.as_deref(), Some("signet"));
+    assert_eq!(config.database.dust_threshold, Some(1000));
+    assert_eq!(config.database.coinbase_maturity, 5);
+    assert_eq!(config.workers.rpc_rate_limit, 20);
+    assert_eq!(config.workers.rpc_burst, 5);
+    let filter = config.filter.filter()?;
+    assert_eq!(
+        filter.min_fee_rate,
+        Some(FeeRate::from_sat_per_vb_unchecked(2))
+    );
+    assert_eq!(filter.watched_scripts.len(), 1);
+    let backfill = config.backfill.expect("backfill");
+    assert_eq!((backfill.from, backfill.to), (10, 20));
+
+    let defaults = parse(MINIMAL, &[])?;
+    assert_eq!(defaults.database.inputs_hash_tag, None);
+    assert_eq!(defaults.database.coinbase_maturity, COINBASE_MATURITY);
+    assert_eq!(defaults.workers.rpc_burst, 1);
+    assert!(defaults.backfill.is_none());
+    assert_eq!(defaults.events.kafka_topic, "mempool-events");
+
+    for (extra, field) in [
+        (
+            "[filter]\nwatch_addresses = [\"nope\"]\n",
+            "filter.watch_addresses",
+        ),
+        ("[backfill]\nfrom = 20\nto = 10\n", "backfill"),
+    ] {
+        let err = parse(&format!("{}\n{}", MINIMAL, extra), &[]).unwrap_err();
+        assert!(format!("{:#}", err).contains(field), "{:#}", err);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_capture_is_configured_by_its_path() -> Result<()> {
+    let config = parse(
+        &format!(
+            "{}\n[capture]\npath = \"zmq.capture\"\nmax_file_mb = 2\nfsync = \"every-record\"\n",
+            MINIMAL
+        ),
+        &[],
+    )?;
+    let capture = config.capture.capture_config().expect("capturing");
+    assert_eq!(capture.path.to_str(), Some("zmq.capture"));
+    assert_eq!(capture.max_file_bytes, 2 * 1024 * 1024);
+    assert_eq!(capture.max_files, 10);
+    assert_eq!(capture.fsync, FsyncPolicy::EveryRecord);
+
+    let err = parse(&format!("{}\n[capture]\nmax_files = 0\n", MINIMAL), &[]).unwrap_err();
+    assert!(err.to_string().contains("capture.max_files"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn test_logging_to_a_file_needs_its_path() -> Result<()> {
+    let config = parse(
+        &format!(
+            "{}\n[logging]\noutput = \"both\"\npath = \"tracker.log\"\ndaily = true\nfilters = \"info,mempool_tracker::worker=debug\"\n",
+            MINIMAL
+        ),
+        &[],
+    )?;
+    let logging = config.logging.log_config();
+    assert_eq!(logging.output, LogOutput::Both);
+    assert_eq!(logging.max_file_bytes, 100 * 1024 * 1024);
+    assert!(logging.daily);
+    assert_eq!(
+        logging.filters.as_deref(),
+        Some("info,mempool_tracker::worker=debug")
+    );
+
+    let err = parse(&format!("{}\n[logging]\noutput = \"file\"\n", MINIMAL), &[]).unwrap_err();
+    assert!(err.to_string().contains("logging.path"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn test_env_overrides_file_credentials() -> Result<()> {
+    let config = parse(MINIMAL, &[])?;
+    assert_eq!(
+        config.auth()?,
+        Auth::UserPass {
+            user: "file-user".to_string(),
+            password: "file-password".to_string()
+        }
+    );
+
+    let config = parse(MINIMAL, &[(ENV_BITCOIND_PASSWORD, "env-password")])?;
+    assert_eq!(
+        config.auth()?,
+        Auth::UserPass {
+            user: "file-user".to_string(),
+            password: "env-password".to_string()
+        }
+    );
+
+    let config = parse(
+        MINIMAL,
+        &[
+            (ENV_BITCOIND_USER, "env-user"),
+            (ENV_BITCOIND_PASSWORD, "env-password"),
+        ],
+    )?;
+    assert_eq!(
+        config.auth()?,
+        Auth::UserPass {
+            user: "env-user".to_string(),
+            password: "env-password".to_string()
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn test_env_supplies_secrets_missing_from_file() -> Result<()> {
+    let without_secrets = r#"
+[bitcoind]
+url = "http://127.0.0.1:8332"
+
+[zmq]
+rawtx = "tcp://127.0.0.1:28332"
+"#;
+    let err = parse(without_secrets, &[]).unwrap_err();
+    assert!(err.to_string().contains("cookie_file"), "{}", err);
+
+    let config = parse(
+        without_secrets,
+        &[(ENV_BITCOIND_COOKIE_FILE, "/tmp/.cookie")],
+    )?;
+    assert_eq!(config.auth()?, Auth::CookieFile("/tmp/.cookie".into()));
+    Ok(())
+}
+
+#[test]
+fn test_invalid_fields_are_named() {
+    let error = |contents: &str| format!("{:#}", parse(contents, &[]).unwrap_err());
+
+    assert!(
+        error(&MINIMAL.replace("tcp://127.0.0.1:28332", "127.0.0.1:28332")).contains("zmq.rawtx")
+    );
+    assert!(error(&format!("{}\n[workers]\ncount = 0\n", MINIMAL)).contains("workers.count"));
+    assert!(error(&format!(
+        "{}\n[database]\nsynchronous = \"sometimes\"\n",
+        MINIMAL
+    ))
+    .contains("synchronous"));
+    // Typos aren't silently ignored
+    assert!(error(&format!("{}\n[workers]\ncuont = 4\n", MINIMAL)).contains("cuont"));
+}
+
+#[test]
+fn test_zmq_endpoints_per_topic() -> Result<()> {
+    let config = parse(MINIMAL, &[])?;
+    assert_eq!(config.zmq.zmq_factory()?.ports(), vec![28332]);
+
+    let config = parse(
+        &format!(
+            "{}hashblock = \"tcp://127.0.0.1:28333\"\nrawblock = \"tcp://127.0.0.1:28334\"\n\
+             sequence = \"tcp://127.0.0.1:28335\"\ntopics = [\"rawtx\", \"rawblock\", \"sequence\"]\n",
+            MINIMAL
+        ),
+        &[],
+    )?;
+    assert_eq!(
+        config.zmq.zmq_factory()?.ports(),
+        vec![28332, 28333, 28334, 28335]
+    );
+
+    // A topic published next to rawtx isn't subscribed to twice
+    let config = parse(
+        &format!("{}sequence = \"tcp://127.0.0.1:28332\"\n", MINIMAL),
+        &[],
+    )?;
+    assert_eq!(config.zmq.zmq_factory()?.ports(), vec![28332]);
+
+    let err = parse(&format!("{}rawblock = \"127.0.0.1:28334\"\n", MINIMAL), &[]).unwrap_err();
+    assert!(format!("{:#}", err).contains("zmq.rawblock"), "{:#}", err);
+    Ok(())
+}
+
+#[test]
+fn test_retention_settings() -> Result<()> {
+    assert_eq!(
+        parse(MINIMAL, &[])?.retention.retention(),
+        Retention::default()
+    );
+
+    let config = parse(
+        &format!(
+            "{}\n[retention]\ntransactions_days = 90\nsnapshots_days = 1\n",
+            MINIMAL
+        ),
+        &[],
+    )?;
+    assert_eq!(
+        config.retention.retention(),
+        Retention {
+            transactions: Some(std::time::Duration::from_secs(90 * 24 * 60 * 60)),
+            snapshots: Some(std::time::Duration::from_secs(24 * 60 * 60)),
+        }
+    );
+
+    let err = parse(
+        &format!("{}\n[retention]\nsnapshots_days = 0\n", MINIMAL),
+        &[],
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string().contains("retention.snapshots_days"),
+        "{}",
+        err
+    );
+    let err = parse(&format!("{}\n[retention]\nkeep_days = 5\n", MINIMAL), &[]).unwrap_err();
+    assert!(format!("{:#}", err).contains("keep_days"), "{:#}", err);
+    Ok(())
+}
+
+#[test]
+fn test_postgres_store_needs_its_feature() -> Result<()> {
+    assert_eq!(parse(MINIMAL, &[])?.database.postgres_url, None);
+
+    let contents = format!(
+        "{}\n[database]\npostgres_url = \"postgres://tracker@localhost/mempool\"\n",
+        MINIMAL
+    );
+    match parse(&contents, &[]) {
+        Ok(config) => {
+            assert!(cfg!(feature = "postgres"));
+            assert_eq!(
+                config.database.postgres_url.as_deref(),
+                Some("postgres://tracker@localhost/mempool")
+            );
+        }
+        Err(err) => {
+            assert!(!cfg!(feature = "postgres"));
+            assert!(err.to_string().contains("database.postgres_url"), "{}", err);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_cookie_file_credentials() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join(".cookie");
+    std::fs::write(&path, "__cookie__:secret\n")?;
+    assert_eq!(
+        Auth::CookieFile(path).credentials()?,
+        ("__cookie__".to_string(), "secret".to_string())
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cookie_file_is_read_again_after_rotation() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join(".cookie");
+    let auth = Auth::CookieFile(path.clone());
+
+    // A missing file names its path
+    let err = auth.credentials().unwrap_err();
+    assert!(
+        err.to_string().contains(&path.display().to_string()),
+        "{}",
+        err
+    );
+
+    std::fs::write(&path, "__cookie__:first\n")?;
+    assert_eq!(auth.credentials()?.1, "first");
+    // The node restarted and wrote a new cookie
+    std::fs::write(&path, "__cookie__:second\n")?;
+    assert_eq!(auth.credentials()?.1, "second");
+    Ok(())
+}
+
+#[test]
+fn test_secondary_nodes_need_distinct_ids() -> Result<()> {
+    let node = |id: &str| {
+        format!(
+            "[[nodes]]\nid = \"{}\"\n[nodes.bitcoind]\nurl = \"http://10.0.2.10:8332\"\n\
+            cookie_file = \"/tmp/.cookie\"\n[nodes.zmq]\nrawtx = \"tcp://10.0.2.10:28332\"\n",
+            id
+        )
+    };
+    let config = parse(&format!("{}{}", MINIMAL, node("dc2")), &[])?;
+    assert_eq!(config.node_id, "default");
+    assert_eq!(config.nodes.len(), 1);
+    assert_eq!(config.nodes[0].id, "dc2");
+    assert_eq!(
+        config.nodes[0].bitcoind.auth()?,
+        Auth::CookieFile("/tmp/.cookie".into())
+    );
+
+    let err = parse(&format!("{}{}", MINIMAL, node("default")), &[]).unwrap_err();
+    assert!(err.to_string().contains("distinct"), "{}", err);
+    let err = parse(
+        &format!("{}{}", MINIMAL, node("dc2").replace("tcp://", "udp://")),
+        &[],
+    )
+    .unwrap_err();
+    assert!(format!("{:#}", err).contains("nodes.dc2"), "{:#}", err);
+    // Only the rawtx endpoint of a secondary is subscribed to
+    let err = parse(
+        &format!(
+            "{}{}sequence = \"tcp://10.0.2.10:28335\"\n",
+            MINIMAL,
+            node("dc2")
+        ),
+        &[],
+    )
+    .unwrap_err();
+    assert!(format!("{:#}", err).contains("nodes.dc2"), "{:#}", err);
+    assert!(format!("{:#}", err).contains("zmq.sequence"), "{:#}", err);
+
+    // The environment overrides are the primary's only
+    let config = parse(
+        &format!("{}{}", MINIMAL, node("dc2")),
+        &[
+            (ENV_BITCOIND_USER, "env-user"),
+            (ENV_BITCOIND_PASSWORD, "env-password"),
+        ],
+    )?;
+    assert_eq!(
+        config.nodes[0].bitcoind.auth()?,
+        Auth::CookieFile("/tmp/.cookie".into())
+    );
+    Ok(())
+}
+
+#[test]
+fn test_prune_check_can_be_disabled_with_sequence_topic() -> Result<()> {
+    let config = parse(
+        &format!(
+            "{}topics = [\"rawtx\", \"sequence\"]\n[intervals]\nprune_check = 0\n",
+            MINIMAL
+        ),
+        &[],
+    )?;
+    assert_eq!(config.intervals.prune_check, 0);
+    Ok(())
+}
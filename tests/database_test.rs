@@ -0,0 +1,1918 @@
+mod common;
+
+use anyhow::Result;
+use bitcoin::{hashes::Hash, Amount, FeeRate, ScriptBuf};
+use common::{dummy_coinbase, dummy_tx, dummy_txid, temp_db};
+use mempool_tracker::{
+    database::{Database, Retention, Synchronous, TransactionInner},
+    utils::{RbfBump, SighashKind},
+};
+use rusqlite::params;
+
+#[test]
+fn test_daily_summary() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let day_start = 1_700_006_400;
+    let day_end = day_start + 24 * 60 * 60;
+
+    let rows: Vec<(&str, u64, Option<u64>, Option<u64>)> = vec![
+        // mined within the day, latencies 100, 300, 600
+        ("a", day_start + 10, Some(day_start + 110), None),
+        ("b", day_start + 20, Some(day_start + 320), None),
+        ("c", day_start + 30, Some(day_start + 630), None),
+        // pruned within the day
+        ("d", day_start + 40, None, Some(day_start + 5_000)),
+        // still pending
+        ("e", day_start + 50, None, None),
+        // seen the day before, mined that day
+        ("f", day_start - 500, Some(day_start + 100), None),
+        // outside the window entirely
+        ("g", day_end + 10, Some(day_end + 20), None),
+    ];
+    for (key, found_at, mined_at, pruned_at) in rows {
+        conn.execute(
+            "INSERT INTO transactions
+            (inputs_hash, tx_id, tx_data, found_at, mined_at, pruned_at, absolute_fee, fee_rate, version)
+            VALUES (?1, ?2, '', ?3 * 1000, ?4 * 1000, ?5 * 1000, 0, 0, 1)",
+            params![key, format!("txid-{}", key), found_at, mined_at, pruned_at],
+        )?;
+    }
+    // Coinbase rows are keyed by txid and must not be counted
+    conn.execute(
+        "INSERT INTO transactions
+        (inputs_hash, tx_id, tx_data, found_at, mined_at, absolute_fee, fee_rate, version)
+        VALUES ('cb', 'cb', '', ?1 * 1000, ?1 * 1000, 0, 0, 0)",
+        params![day_start + 60],
+    )?;
+    for (key, created_at) in [("a", day_start + 50), ("b", day_start + 60), ("z", day_end)] {
+        conn.execute(
+            "INSERT INTO rbf_history (inputs_hash, tx_id, created_at, fee_total, version)
+            VALUES (?1, ?1, ?2, 1000, 0)",
+            params![key, created_at],
+        )?;
+    }
+    for (created_at, size) in [
+        (day_start + 1, 1_000),
+        (day_start + 2, 5_000),
+        (day_end + 1, 9_000),
+    ] {
+        conn.execute(
+            "INSERT INTO mempool (created_at, size, tx_count, block_height, block_hash, version)
+            VALUES (?1, ?2, 1, 1, '', 1)",
+            params![created_at, size],
+        )?;
+    }
+
+    let summary = db.daily_summary(day_start)?;
+    assert_eq!(summary.day_start, day_start);
+    assert_eq!(summary.txs_first_seen, 5);
+    assert_eq!(summary.txs_mined, 4);
+    assert_eq!(summary.rbf_events, 2);
+    assert_eq!(summary.txs_pruned, 1);
+    // latencies are 100, 300, 600, 600 -> lower median is 300
+    assert_eq!(summary.median_confirmation_latency, Some(300));
+    assert_eq!(summary.peak_mempool_size, Some(5_000));
+
+    let empty = db.daily_summary(day_start - 7 * 24 * 60 * 60)?;
+    assert_eq!(empty.txs_mined, 0);
+    assert_eq!(empty.median_confirmation_latency, None);
+    assert_eq!(empty.peak_mempool_size, None);
+    Ok(())
+}
+
+#[test]
+fn test_coinbases_from_different_blocks_are_stored_distinctly() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let coinbase_a = dummy_coinbase(100, 50_000);
+    let coinbase_b = dummy_coinbase(101, 50_000);
+
+    db.record_coinbase_tx(&coinbase_a, None)?;
+    assert!(db.tx_exists(&coinbase_a)?);
+    assert!(!db.tx_exists(&coinbase_b)?);
+
+    db.record_coinbase_tx(&coinbase_b, None)?;
+    assert!(db.tx_exists(&coinbase_b)?);
+
+    let keys: Vec<String> = conn
+        .prepare("SELECT inputs_hash FROM transactions ORDER BY inputs_hash")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let mut expected = vec![
+        coinbase_a.compute_txid().to_string(),
+        coinbase_b.compute_txid().to_string(),
+    ];
+    expected.sort();
+    assert_eq!(keys, expected);
+    Ok(())
+}
+
+#[test]
+fn test_block_fees_are_coinbase_value_minus_subsidy() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    // 3.125 BTC subsidy after the fourth halving
+    db.record_coinbase_tx(&dummy_coinbase(840_000, 312_500_000 + 12_345_678), None)?;
+    // Pushed as OP_5
+    db.record_coinbase_tx(&dummy_coinbase(5, 5_000_000_000 + 1), None)?;
+
+    assert_eq!(db.get_block_fees(840_000)?, Some(12_345_678));
+    assert_eq!(db.get_block_fees(5)?, Some(1));
+    assert_eq!(db.get_block_fees(840_001)?, None);
+    Ok(())
+}
+
+#[test]
+fn test_rbf_records_fee_bump() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let inputs = [(dummy_txid(1), 0)];
+    let original = dummy_tx(&inputs, &[99_000]);
+    let replacement = dummy_tx(&inputs, &[98_500]);
+    let original_rate = FeeRate::from_sat_per_vb_unchecked(10);
+    db.insert_mempool_tx(original, None, None, Amount::from_sat(1_000), original_rate)?;
+
+    let prev = db.get_stored_fee(&replacement)?;
+    assert_eq!(prev, Some((Amount::from_sat(1_000), original_rate)));
+    let bump = RbfBump::new(
+        prev,
+        Amount::from_sat(1_500),
+        FeeRate::from_sat_per_vb_unchecked(15),
+    );
+    db.record_rbf(&replacement, &bump, None)?;
+
+    let (prev_fee, fee_delta, fee_rate_delta, fee_bump_pct): (
+        Option<u64>,
+        Option<i64>,
+        Option<i64>,
+        Option<f64>,
+    ) = conn.query_row(
+        "SELECT prev_fee, fee_delta, fee_rate_delta, fee_bump_pct FROM rbf_history",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+    assert_eq!(prev_fee, Some(1_000));
+    assert_eq!(fee_delta, Some(500));
+    assert_eq!(fee_rate_delta, Some(5));
+    assert_eq!(fee_bump_pct, Some(50.0));
+
+    // The replacement is now the baseline for the next bump
+    assert_eq!(
+        db.get_stored_fee(&replacement)?,
+        Some((
+            Amount::from_sat(1_500),
+            FeeRate::from_sat_per_vb_unchecked(15)
+        ))
+    );
+
+    let stats = db.average_bump_stats(0, u64::MAX / 2)?;
+    assert_eq!(stats.events, 1);
+    assert_eq!(stats.avg_fee_delta, Some(500.0));
+    assert_eq!(stats.avg_fee_bump_pct, Some(50.0));
+    Ok(())
+}
+
+#[test]
+fn test_rbf_unknown_predecessor_fee_is_null() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let inputs = [(dummy_txid(2), 0)];
+    let original = dummy_tx(&inputs, &[99_000]);
+    let original_copy = original.clone();
+    let replacement = dummy_tx(&inputs, &[98_000]);
+    db.insert_mempool_tx(
+        original,
+        None,
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(10),
+    )?;
+
+    let bump = RbfBump::new(
+        None,
+        Amount::from_sat(2_000),
+        FeeRate::from_sat_per_vb_unchecked(20),
+    );
+    db.record_rbf(&replacement, &bump, Some("txn-mempool-conflict"))?;
+
+    let (prev_fee, fee_bump_pct, reject_reason): (Option<u64>, Option<f64>, Option<String>) = conn
+        .query_row(
+            "SELECT prev_fee, fee_bump_pct, reject_reason FROM rbf_history",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+    assert_eq!(prev_fee, None);
+    assert_eq!(fee_bump_pct, None);
+    assert_eq!(reject_reason.as_deref(), Some("txn-mempool-conflict"));
+    assert_eq!(db.get_stored_tx(&replacement)?, Some(original_copy));
+    assert_eq!(db.average_bump_stats(0, u64::MAX / 2)?.events, 0);
+    Ok(())
+}
+
+#[test]
+fn test_rbf_without_baseline_is_initial_observation() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let inputs = [(dummy_txid(3), 0)];
+    let original = dummy_tx(&inputs, &[99_000]);
+    let unknown = dummy_tx(&inputs, &[98_800]);
+    let first_seen = dummy_tx(&inputs, &[98_500]);
+    let bumped = dummy_tx(&inputs, &[98_000]);
+    db.insert_mempool_tx(
+        original.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(10),
+    )?;
+    // The slot's fee is lost until a later replacement is looked up
+    db.record_rbf_unknown_fee(&unknown, None)?;
+    assert_eq!(db.get_stored_fee(&first_seen)?, None);
+
+    let bump = RbfBump::new(
+        db.get_stored_fee(&first_seen)?,
+        Amount::from_sat(1_500),
+        FeeRate::from_sat_per_vb_unchecked(15),
+    );
+    assert!(bump.is_initial_observation);
+    db.record_rbf(&first_seen, &bump, None)?;
+    let bump = RbfBump::new(
+        db.get_stored_fee(&bumped)?,
+        Amount::from_sat(2_000),
+        FeeRate::from_sat_per_vb_unchecked(20),
+    );
+    assert!(!bump.is_initial_observation);
+    db.record_rbf(&bumped, &bump, None)?;
+
+    let history = db.rbf_history_of(&original.compute_txid())?;
+    let flags: Vec<_> = history
+        .iter()
+        .map(|record| record.is_initial_observation)
+        .collect();
+    assert_eq!(flags, vec![false, true, false]);
+    assert_eq!(history[1].fee_bump_pct, None);
+    assert!((history[2].fee_bump_pct.unwrap() - 100.0 / 3.0).abs() < 1e-9);
+    // Only the real bump adds to the total
+    assert_eq!(db.total_rbf_fee_delta(0, u64::MAX / 2)?, 500);
+    Ok(())
+}
+
+#[test]
+fn test_op_return_stats() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+
+    let plain = dummy_tx(&[(dummy_txid(1), 0)], &[1_000]);
+    let mut small = dummy_tx(&[(dummy_txid(2), 0)], &[1_000, 0]);
+    small.output[1].script_pubkey = ScriptBuf::from_bytes(vec![0x6a, 0x04, 1, 2, 3, 4]);
+    let mut large = dummy_tx(&[(dummy_txid(3), 0)], &[0, 0]);
+    let mut payload = vec![0x6a, 0x4c, 100];
+    payload.extend([0xaa; 100]);
+    large.output[0].script_pubkey = ScriptBuf::from_bytes(payload.clone());
+    large.output[1].script_pubkey = ScriptBuf::from_bytes(payload);
+
+    for tx in [plain, small, large] {
+        db.insert_mempool_tx(tx, Some(1_000_000), None, Amount::from_sat(100), fee_rate)?;
+    }
+
+    let stats = db.op_return_stats(0, 2_000)?;
+    assert_eq!(stats.tx_count, 2);
+    assert_eq!(stats.total_bytes, 5 + 2 * 102);
+    let count_at = |bound: u64| {
+        stats
+            .histogram
+            .iter()
+            .find(|(b, _)| *b == bound)
+            .map(|(_, count)| *count)
+    };
+    assert_eq!(count_at(40), Some(1));
+    assert_eq!(count_at(1_000), Some(1));
+    assert_eq!(stats.histogram.iter().map(|(_, c)| c).sum::<u64>(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_node_seen_at_is_stored_alongside_found_at() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let tx = dummy_tx(&[(dummy_txid(4), 0)], &[1_000]);
+    let txid = tx.compute_txid().to_string();
+    db.insert_mempool_tx(
+        tx,
+        Some(1_700_000_005_250),
+        Some(1_700_000_000_000),
+        Amount::from_sat(100),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+
+    let (found_at, node_seen_at): (u64, Option<u64>) = conn.query_row(
+        "SELECT found_at, node_seen_at FROM transactions WHERE tx_id = ?1",
+        params![txid],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    assert_eq!(found_at, 1_700_000_005_250);
+    assert_eq!(node_seen_at, Some(1_700_000_000_000));
+    Ok(())
+}
+
+#[test]
+fn test_unconfirmed_package_walks_tx_links() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let parent = dummy_tx(&[(dummy_txid(1), 0)], &[10_000]);
+    let child = dummy_tx(&[(parent.compute_txid(), 0)], &[9_000]);
+    let grandchild = dummy_tx(&[(child.compute_txid(), 0)], &[8_000]);
+    let unrelated = dummy_tx(&[(dummy_txid(2), 0)], &[5_000]);
+    // Linked by the node but never stored, e.g. filtered out
+    let unknown = dummy_txid(3);
+    for (tx, fee) in [
+        (&parent, 100),
+        (&child, 200),
+        (&grandchild, 300),
+        (&unrelated, 400),
+    ] {
+        db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(fee), fee_rate)?;
+    }
+    db.record_tx_links(&parent.compute_txid(), &[], &[child.compute_txid()])?;
+    db.record_tx_links(
+        &grandchild.compute_txid(),
+        &[child.compute_txid(), unknown],
+        &[],
+    )?;
+
+    let package = db.unconfirmed_package(&child.compute_txid())?;
+    let mut txids = package.txids.clone();
+    txids.sort();
+    let mut expected = vec![
+        parent.compute_txid(),
+        child.compute_txid(),
+        grandchild.compute_txid(),
+        unknown,
+    ];
+    expected.sort();
+    assert_eq!(txids, expected);
+    assert_eq!(package.total_fee, Amount::from_sat(600));
+    assert_eq!(
+        package.total_vsize,
+        (parent.vsize() + child.vsize() + grandchild.vsize()) as u64
+    );
+    assert_eq!(package.unknown_txids, vec![unknown]);
+    Ok(())
+}
+
+#[test]
+fn test_read_only_database_never_writes() -> Result<()> {
+    let (dir, db, conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let existing = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    db.insert_mempool_tx(
+        existing.clone(),
+        None,
+        None,
+        Amount::from_sat(100),
+        fee_rate,
+    )?;
+
+    let path = dir.path().join("mempool_tracker_test.db");
+    let read_only = Database::open_read_only(path.to_str().unwrap())?;
+    assert!(read_only.is_read_only());
+    let tx = dummy_tx(&[(dummy_txid(2), 0)], &[9_000]);
+    read_only.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(100), fee_rate)?;
+    read_only.record_coinbase_tx(&dummy_coinbase(800, 50_000), None)?;
+    read_only.record_tx_links(&tx.compute_txid(), &[existing.compute_txid()], &[])?;
+    read_only.record_rbf(
+        &existing,
+        &RbfBump::new(None, Amount::from_sat(200), fee_rate),
+        None,
+    )?;
+
+    // Reads still see the existing data
+    assert!(read_only.tx_exists(&existing)?);
+    assert!(!read_only.tx_exists(&tx)?);
+    let count = |table: &str| -> Result<u64> {
+        Ok(
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })?,
+        )
+    };
+    assert_eq!(count("transactions")?, 1);
+    assert_eq!(count("tx_links")?, 0);
+    assert_eq!(count("rbf_history")?, 0);
+    Ok(())
+}
+
+#[test]
+fn test_stale_pooled_connection_is_replaced_on_checkout() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("pool.db");
+    let db = Database::open_with_pool_size(path.to_str().unwrap(), Synchronous::Normal, 1)?;
+
+    {
+        let conn = db.connection()?;
+        // Every statement on this connection fails from now on
+        conn.progress_handler(1, Some(|| true));
+        assert!(conn.query_row("SELECT 1", [], |_| Ok(())).is_err());
+    }
+
+    // The only pooled connection is stale, the checkout replaces it
+    assert!(!db.tx_exists(&dummy_tx(&[(dummy_txid(1), 0)], &[1_000]))?);
+    Ok(())
+}
+
+#[test]
+fn test_read_only_database_without_file() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("missing.db");
+    let db = Database::open_read_only(path.to_str().unwrap())?;
+    assert!(!db.tx_exists(&dummy_tx(&[(dummy_txid(1), 0)], &[1_000]))?);
+    assert!(!path.exists());
+    Ok(())
+}
+
+#[test]
+fn test_total_rbf_fee_delta() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    // (inputs_hash, created_at, fee_total, prev_fee)
+    let bumps: Vec<(&str, u64, u64, Option<u64>)> = vec![
+        // chain a starts before the window and bumps twice inside it
+        ("a", 50, 1_100, Some(1_000)),
+        ("a", 150, 1_300, Some(1_100)),
+        ("a", 250, 1_600, Some(1_300)),
+        // chain b: one bump inside, one with an unknown predecessor, one after the window
+        ("b", 120, 2_500, Some(2_000)),
+        ("b", 180, 3_000, None),
+        ("b", 350, 4_000, Some(3_000)),
+    ];
+    for (inputs_hash, created_at, fee_total, prev_fee) in bumps {
+        conn.execute(
+            "INSERT INTO rbf_history (inputs_hash, tx_id, created_at, fee_total, prev_fee, fee_delta, version)
+            VALUES (?1, ?1, ?2, ?3, ?4, ?5, 0)",
+            params![
+                inputs_hash,
+                created_at,
+                fee_total,
+                prev_fee,
+                prev_fee.map(|prev| fee_total as i64 - prev as i64)
+            ],
+        )?;
+    }
+
+    // 200 + 300 from chain a, 500 from chain b
+    assert_eq!(db.total_rbf_fee_delta(100, 300)?, 1_000);
+    assert_eq!(db.total_rbf_fee_delta(0, 1_000)?, 2_100);
+    assert_eq!(db.total_rbf_fee_delta(400, 500)?, 0);
+    Ok(())
+}
+
+#[test]
+fn test_rbf_leaderboard_orders_chains_by_bump_count() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    // (inputs_hash, created_at, fee_total)
+    let bumps = [
+        ("a", 100, 1_100),
+        ("b", 110, 2_100),
+        ("a", 120, 1_200),
+        ("c", 130, 3_100),
+        ("b", 140, 2_200),
+        ("a", 150, 1_300),
+        // outside the window, doesn't count nor set the final fee
+        ("c", 50, 3_000),
+        ("c", 500, 3_900),
+        ("c", 510, 4_000),
+    ];
+    for (inputs_hash, created_at, fee_total) in bumps {
+        conn.execute(
+            "INSERT INTO rbf_history (inputs_hash, tx_id, created_at, fee_total, version)
+            VALUES (?1, ?1, ?2, ?3, 0)",
+            params![inputs_hash, created_at, fee_total],
+        )?;
+    }
+
+    assert_eq!(
+        db.rbf_leaderboard(100, 200, 10)?,
+        vec![
+            ("a".to_string(), 3, 1_300),
+            ("b".to_string(), 2, 2_200),
+            ("c".to_string(), 1, 3_100),
+        ]
+    );
+    assert_eq!(
+        db.rbf_leaderboard(100, 200, 2)?,
+        vec![("a".to_string(), 3, 1_300), ("b".to_string(), 2, 2_200)]
+    );
+    assert!(db.rbf_leaderboard(200, 300, 10)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_partial_overlap_conflicts_are_recorded_and_resolved() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let shared = (dummy_txid(1), 0);
+    let first = dummy_tx(&[shared, (dummy_txid(2), 0)], &[9_000]);
+    // Spends one of the same outpoints plus another, so the inputs hashes differ
+    let second = dummy_tx(&[shared, (dummy_txid(3), 0)], &[9_000]);
+    db.insert_mempool_tx(first.clone(), None, None, Amount::from_sat(1_000), fee_rate)?;
+    assert!(!db.tx_exists(&second)?);
+
+    let conflicts = db.record_conflicts(&second)?;
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].txid, second.compute_txid());
+    assert_eq!(conflicts[0].conflicting_txid, first.compute_txid());
+    assert_eq!(
+        conflicts[0].outpoints,
+        vec![bitcoin::OutPoint::new(shared.0, shared.1)]
+    );
+    db.insert_mempool_tx(
+        second.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        fee_rate,
+    )?;
+
+    // The second tx confirms, the first can no longer make it
+    db.record_mined_tx(&second, None)?;
+    let (winner, resolved_at): (Option<String>, Option<u64>) = conn.query_row(
+        "SELECT winner_txid, resolved_at FROM conflicts",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    assert_eq!(winner, Some(second.compute_txid().to_string()));
+    assert!(resolved_at.is_some());
+    let loser_pruned_at: Option<u64> = conn.query_row(
+        "SELECT pruned_at FROM transactions WHERE tx_id = ?1",
+        [first.compute_txid().to_string()],
+        |row| row.get(0),
+    )?;
+    assert!(loser_pruned_at.is_some());
+
+    // The loser's unshared outpoint is released along with it
+    let claimed: u64 =
+        conn.query_row("SELECT COUNT(*) FROM spent_outpoints", [], |row| row.get(0))?;
+    assert_eq!(claimed, 0);
+    Ok(())
+}
+
+#[test]
+fn test_latency_by_fee_bucket() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    // (key, found_at, node_seen_at, mined_at, absolute_fee, vsize)
+    let rows: Vec<(&str, u64, Option<u64>, u64, u64, u64)> = vec![
+        // 2 and 4 sat/vB, waited 600s and 1200s
+        ("a", 1_000, None, 1_600, 200, 100),
+        ("b", 1_000, None, 2_200, 400, 100),
+        // 10 sat/vB, the node accepted it 40s before we saw it
+        ("c", 1_040, Some(1_000), 1_060, 1_000, 100),
+        // below the lowest bucket
+        ("d", 1_000, None, 1_100, 50, 100),
+        // mined outside the window
+        ("e", 1_000, None, 9_000, 1_000, 100),
+    ];
+    for (key, found_at, node_seen_at, mined_at, fee, vsize) in rows {
+        conn.execute(
+            "INSERT INTO transactions
+            (inputs_hash, tx_id, tx_data, found_at, node_seen_at, mined_at, absolute_fee, fee_rate, vsize, version)
+            VALUES (?1, ?2, '', ?3 * 1000, ?4 * 1000, ?5 * 1000, ?6, 0, ?7, 1)",
+            params![key, format!("txid-{}", key), found_at, node_seen_at, mined_at, fee, vsize],
+        )?;
+    }
+    conn.execute(
+        "INSERT INTO transactions
+        (inputs_hash, tx_id, tx_data, found_at, mined_at, absolute_fee, fee_rate, version)
+        VALUES ('cb', 'cb', '', 1000000, 1500000, 0, 0, 0)",
+        [],
+    )?;
+
+    let buckets = db.latency_by_fee_bucket(0, 5_000, &[1.0, 5.0])?;
+    assert_eq!(buckets.len(), 2);
+    let (low_bound, low) = &buckets[0];
+    assert_eq!(*low_bound, 1.0);
+    assert_eq!(low.count, 2);
+    assert_eq!(low.min, Some(600));
+    assert_eq!(low.max, Some(1_200));
+    assert_eq!(low.mean, Some(900.0));
+    assert_eq!(low.median, Some(600));
+    let (high_bound, high) = &buckets[1];
+    assert_eq!(*high_bound, 5.0);
+    assert_eq!(high.count, 1);
+    assert_eq!(high.median, Some(60));
+    Ok(())
+}
+
+#[test]
+fn test_paginate_txs_by_seq() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let txs: Vec<_> = (1..=5)
+        .map(|n| dummy_tx(&[(dummy_txid(n), 0)], &[1_000]))
+        .collect();
+    // Same found_at for every tx, ordering must not depend on timestamps
+    for tx in &txs {
+        db.insert_mempool_tx(
+            tx.clone(),
+            Some(1_700_000_000_000),
+            None,
+            Amount::from_sat(100),
+            fee_rate,
+        )?;
+    }
+
+    let mut seen = vec![];
+    let mut cursor = 0;
+    loop {
+        let page = db.get_txs_after_seq(cursor, 2)?;
+        if page.is_empty() {
+            break;
+        }
+        assert!(page.len() <= 2);
+        assert!(page.iter().all(|(seq, _)| *seq > cursor));
+        cursor = page.last().unwrap().0;
+        seen.extend(page.into_iter().map(|(_, txid)| txid));
+    }
+    let expected: Vec<_> = txs.iter().map(|tx| tx.compute_txid()).collect();
+    assert_eq!(seen, expected);
+    Ok(())
+}
+
+#[test]
+fn test_effective_fee_rate_includes_unconfirmed_ancestors() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let parent = dummy_tx(&[(dummy_txid(1), 0)], &[10_000]);
+    let child = dummy_tx(&[(parent.compute_txid(), 0)], &[9_000]);
+    // Spends a parent the node reported but we never stored
+    let orphan = dummy_tx(&[(dummy_txid(2), 0)], &[5_000]);
+    let (parent_fee, child_fee) = (parent.vsize() as u64, 20 * child.vsize() as u64);
+    let package_rate = (parent_fee + child_fee) as f64 / (parent.vsize() + child.vsize()) as f64;
+    let child_rate = child_fee as f64 / child.vsize() as f64;
+
+    db.record_tx_links(&child.compute_txid(), &[parent.compute_txid()], &[])?;
+    db.record_tx_links(&orphan.compute_txid(), &[dummy_txid(3)], &[])?;
+    let found_at = 1_700_000_000;
+    for (tx, fee) in [(&parent, parent_fee), (&child, child_fee), (&orphan, 500)] {
+        db.insert_mempool_tx(
+            tx.clone(),
+            Some(found_at * 1000),
+            None,
+            Amount::from_sat(fee),
+            fee_rate,
+        )?;
+    }
+
+    let rates = db.effective_vs_naive_feerate(found_at, found_at + 1)?;
+    assert_eq!(rates.len(), 3);
+    let rate_of = |txid| rates.iter().find(|rate| rate.txid == txid).unwrap().clone();
+    assert_eq!(rate_of(parent.compute_txid()).effective_fee_rate, 1.0);
+    let child_entry = rate_of(child.compute_txid());
+    assert_eq!(child_entry.naive_fee_rate, child_rate);
+    assert_eq!(child_entry.effective_fee_rate, package_rate);
+    assert!(child_entry.ancestors_known);
+    let orphan_entry = rate_of(orphan.compute_txid());
+    assert_eq!(orphan_entry.effective_fee_rate, orphan_entry.naive_fee_rate);
+    assert!(!orphan_entry.ancestors_known);
+
+    // Once the parent confirms the child stands on its own
+    db.record_mined_tx(&parent, None)?;
+    let rates = db.effective_vs_naive_feerate(found_at, found_at + 1)?;
+    let child_entry = rates
+        .iter()
+        .find(|rate| rate.txid == child.compute_txid())
+        .unwrap();
+    assert_eq!(child_entry.effective_fee_rate, child_rate);
+    assert!(child_entry.ancestors_known);
+    Ok(())
+}
+
+#[test]
+fn test_fee_histogram_snapshot_of_pending_txs() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    // (key, absolute_fee, vsize, mined_at, pruned_at)
+    let rows: Vec<(&str, u64, Option<u64>, Option<u64>, Option<u64>)> = vec![
+        // 2.5 and 2 sat/vB share the 2 band
+        ("a", 250, Some(100), None, None),
+        ("b", 400, Some(200), None, None),
+        // 12 sat/vB
+        ("c", 1_200, Some(100), None, None),
+        // below the first band
+        ("d", 50, Some(100), None, None),
+        // no longer pending
+        ("e", 1_000, Some(100), Some(1_500), None),
+        ("f", 1_000, Some(100), None, Some(1_500)),
+        // stored before vsize was recorded
+        ("g", 1_000, None, None, None),
+    ];
+    for (key, fee, vsize, mined_at, pruned_at) in rows {
+        conn.execute(
+            "INSERT INTO transactions
+            (inputs_hash, tx_id, tx_data, found_at, mined_at, pruned_at, absolute_fee, fee_rate, vsize, version)
+            VALUES (?1, ?2, '', 1000, ?3, ?4, ?5, 0, ?6, 1)",
+            params![key, format!("txid-{}", key), mined_at, pruned_at, fee, vsize],
+        )?;
+    }
+
+    assert_eq!(db.record_fee_histogram()?, 3);
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    let series = db.fee_histogram_series(0, now + 1)?;
+    let bands: Vec<_> = series
+        .iter()
+        .map(|band| (band.band_min_satvb, band.vbytes, band.tx_count))
+        .collect();
+    assert_eq!(bands, vec![(0, 100, 1), (2, 300, 2), (12, 100, 1)]);
+    assert!(series
+        .iter()
+        .all(|band| band.snapshot_at == series[0].snapshot_at));
+    assert!(db
+        .fee_histogram_series(0, series[0].snapshot_at)?
+        .is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_fee_histogram_snapshot_skips_replacements_with_an_unknown_fee() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let inputs = [(dummy_txid(1), 0)];
+    let original = dummy_tx(&inputs, &[99_000]);
+    let replacement = dummy_tx(&inputs, &[98_000, 500]);
+    let other = dummy_tx(&[(dummy_txid(2), 0)], &[99_000]);
+    for tx in [&original, &other] {
+        db.insert_mempool_tx(
+            tx.clone(),
+            None,
+            None,
+            Amount::from_sat(1_000),
+            FeeRate::from_sat_per_vb_unchecked(10),
+        )?;
+    }
+    db.record_rbf_unknown_fee(&replacement, None)?;
+    db.update_txid_by_inputs_hash(&replacement)?;
+
+    let (fee, fee_rate): (u64, u64) = conn.query_row(
+        "SELECT absolute_fee, fee_rate FROM transactions WHERE tx_id = ?1",
+        [replacement.compute_txid().to_string()],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    assert_eq!((fee, fee_rate), (0, 0));
+
+    // Only the other tx is counted, the replacement isn't bucketed at all
+    assert_eq!(db.record_fee_histogram()?, 1);
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    let series = db.fee_histogram_series(0, now + 1)?;
+    assert_eq!(series.len(), 1);
+    assert_eq!(series[0].tx_count, 1);
+    assert_eq!(series[0].vbytes, other.vsize() as u64);
+    Ok(())
+}
+
+#[test]
+fn test_current_fee_rate_histogram_counts_pending_txs() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    // (key, absolute_fee, vsize, mined_at, pruned_at, fee_known)
+    let rows: Vec<(&str, u64, Option<u64>, Option<u64>, Option<u64>, bool)> = vec![
+        // 2.5 and 2 sat/vB
+        ("a", 250, Some(100), None, None, true),
+        ("b", 400, Some(200), None, None, true),
+        // 7 sat/vB
+        ("c", 700, Some(100), None, None, true),
+        // 20 sat/vB, in the unbounded last bucket
+        ("d", 2_000, Some(100), None, None, true),
+        // below the first bucket
+        ("e", 50, Some(100), None, None, true),
+        // no longer pending
+        ("f", 700, Some(100), Some(1_500), None, true),
+        ("g", 700, Some(100), None, Some(1_500), true),
+        // no vsize, or a fee that was never looked up
+        ("h", 700, None, None, None, true),
+        ("i", 700, Some(100), None, None, false),
+    ];
+    for (key, fee, vsize, mined_at, pruned_at, fee_known) in rows {
+        conn.execute(
+            "INSERT INTO transactions
+            (inputs_hash, tx_id, tx_data, found_at, mined_at, pruned_at, absolute_fee, fee_rate, vsize, fee_known, version)
+            VALUES (?1, ?2, '', 1000, ?3, ?4, ?5, 0, ?6, ?7, 1)",
+            params![key, format!("txid-{}", key), mined_at, pruned_at, fee, vsize, fee_known],
+        )?;
+    }
+
+    assert_eq!(
+        db.current_fee_rate_histogram(&[1.0, 5.0, 10.0])?,
+        vec![(1.0, 2), (5.0, 1), (10.0, 1)]
+    );
+    assert_eq!(
+        db.current_fee_rate_histogram(&[0.0, 2.5])?,
+        vec![(0.0, 2), (2.5, 3)]
+    );
+    assert!(db.current_fee_rate_histogram(&[])?.is_empty());
+    // Bounds are bound as parameters, only finite ascending ones make buckets
+    for buckets in [
+        &[1.0, f64::NAN][..],
+        &[f64::INFINITY],
+        &[5.0, 1.0],
+        &[1.0, 1.0],
+    ] {
+        assert!(
+            db.current_fee_rate_histogram(buckets).is_err(),
+            "{:?}",
+            buckets
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_tx_with_two_tracked_parents_records_both_edges() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let parent_a = dummy_tx(&[(dummy_txid(1), 0)], &[10_000]);
+    let parent_b = dummy_tx(&[(dummy_txid(2), 0)], &[10_000]);
+    // Consolidation of both parents plus an untracked, presumably confirmed, output
+    let child = dummy_tx(
+        &[
+            (parent_a.compute_txid(), 0),
+            (parent_b.compute_txid(), 0),
+            (dummy_txid(3), 0),
+        ],
+        &[25_000],
+    );
+    for tx in [&parent_a, &parent_b, &child] {
+        db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(500), fee_rate)?;
+    }
+
+    let edges: Vec<(String, String)> = conn
+        .prepare("SELECT child_inputs_hash, parent_txid FROM tx_parents ORDER BY parent_txid")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    let child_key = mempool_tracker::utils::get_tx_key(&child)?;
+    let mut expected = vec![
+        (child_key.clone(), parent_a.compute_txid().to_string()),
+        (child_key, parent_b.compute_txid().to_string()),
+    ];
+    expected.sort();
+    assert_eq!(edges, expected);
+
+    let mut parents = db.get_parents(&child.compute_txid())?;
+    parents.sort();
+    let mut expected_parents = vec![parent_a.compute_txid(), parent_b.compute_txid()];
+    expected_parents.sort();
+    assert_eq!(parents, expected_parents);
+    for parent in [&parent_a, &parent_b] {
+        assert_eq!(
+            db.get_children(&parent.compute_txid())?,
+            vec![child.compute_txid()]
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parent_spent_into_two_children_records_both() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let parent = dummy_tx(&[(dummy_txid(1), 0)], &[10_000, 10_000, 10_000]);
+    let parent_txid = parent.compute_txid();
+    let first_child = dummy_tx(&[(parent_txid, 0)], &[9_000]);
+    let second_child = dummy_tx(&[(parent_txid, 1), (parent_txid, 2)], &[19_000]);
+    for tx in [&parent, &first_child, &second_child] {
+        db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(500), fee_rate)?;
+    }
+
+    let edges: Vec<(String, String, u32)> = conn
+        .prepare("SELECT child_inputs_hash, parent_txid, vout FROM tx_parents ORDER BY vout")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+    let first_key = mempool_tracker::utils::get_tx_key(&first_child)?;
+    let second_key = mempool_tracker::utils::get_tx_key(&second_child)?;
+    assert_eq!(
+        edges,
+        vec![
+            (first_key, parent_txid.to_string(), 0),
+            (second_key.clone(), parent_txid.to_string(), 1),
+            (second_key, parent_txid.to_string(), 2),
+        ]
+    );
+    // Nothing points the other way
+    assert!(db.get_parents(&parent_txid)?.is_empty());
+
+    let mut children = db.get_children(&parent_txid)?;
+    children.sort();
+    let mut expected = vec![first_child.compute_txid(), second_child.compute_txid()];
+    expected.sort();
+    assert_eq!(children, expected);
+    assert_eq!(
+        db.get_parents(&second_child.compute_txid())?,
+        vec![parent_txid]
+    );
+
+    // Replacing one child keeps the other's edge
+    let replacement = dummy_tx(&[(parent_txid, 0)], &[8_000]);
+    db.update_txid_by_inputs_hash(&replacement)?;
+    let mut children = db.get_children(&parent_txid)?;
+    children.sort();
+    let mut expected = vec![replacement.compute_txid(), second_child.compute_txid()];
+    expected.sort();
+    assert_eq!(children, expected);
+    Ok(())
+}
+
+#[test]
+fn test_evictions_carry_the_mempool_min_fee_in_effect() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    // (created_at, mempoolminfee in sat/kvB), the oldest snapshot predates the column
+    for (created_at, min_fee) in [(900, None), (1_000, Some(1_000)), (2_000, Some(5_000))] {
+        conn.execute(
+            "INSERT INTO mempool (created_at, size, tx_count, block_height, block_hash, mempool_min_fee, version)
+            VALUES (?1, 0, 0, 100, '', ?2, 1)",
+            params![created_at, min_fee],
+        )?;
+    }
+    for (n, pruned_at) in [(1u8, 950u64), (2, 1_500), (3, 2_500)] {
+        conn.execute(
+            "INSERT INTO transactions
+            (inputs_hash, tx_id, tx_data, found_at, pruned_at, absolute_fee, fee_rate, vsize, version)
+            VALUES (?1, ?2, '', 500000, ?3 * 1000, 300, 3, 100, 1)",
+            params![format!("key-{}", n), dummy_txid(n).to_string(), pruned_at],
+        )?;
+    }
+
+    let evictions = db.evictions_with_min_fee(0, 3_000)?;
+    let min_fees: Vec<_> = evictions
+        .iter()
+        .map(|eviction| (eviction.txid, eviction.mempool_min_fee))
+        .collect();
+    assert_eq!(
+        min_fees,
+        vec![
+            (dummy_txid(1), None),
+            (dummy_txid(2), Some(FeeRate::from_sat_per_vb_unchecked(1))),
+            (dummy_txid(3), Some(FeeRate::from_sat_per_vb_unchecked(5))),
+        ]
+    );
+    assert!(evictions.iter().all(|eviction| eviction.fee_rate == 3.0));
+    Ok(())
+}
+
+#[test]
+fn test_mempool_min_fee_rounds_to_the_nearest_sat_per_kwu() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    assert_eq!(db.last_mempool_min_fee()?, None);
+    // 1.01 sat/vB is 252.5 sat/kwu
+    conn.execute(
+        "INSERT INTO mempool (created_at, size, tx_count, block_height, block_hash, mempool_min_fee, version)
+        VALUES (1000, 0, 0, 100, '', 1010, 1)",
+        [],
+    )?;
+    assert_eq!(
+        db.last_mempool_min_fee()?,
+        Some(FeeRate::from_sat_per_kwu(253))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_transactions_csv_round_trip() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(2);
+    let pending = dummy_tx(&[(dummy_txid(1), 0)], &[10_000]);
+    let mined = dummy_tx(&[(dummy_txid(2), 0)], &[20_000]);
+    for tx in [&pending, &mined] {
+        db.insert_mempool_tx(
+            tx.clone(),
+            None,
+            Some(1_700_000_000_000),
+            Amount::from_sat(300),
+            fee_rate,
+        )?;
+    }
+    db.record_mined_tx(&mined, None)?;
+    db.record_coinbase_tx(&dummy_coinbase(100, 50_000), None)?;
+    conn.execute(
+        "UPDATE transactions SET block_height = 100, block_hash = X'00ff' WHERE tx_id = ?1",
+        [mined.compute_txid().to_string()],
+    )?;
+    // Coinbase tags can hold any printable ASCII
+    conn.execute(
+        "UPDATE transactions SET coinbase_tag = 'Mined by \"us\", /pool/'
+        WHERE coinbase_value IS NOT NULL",
+        [],
+    )?;
+
+    let mut exported = vec![];
+    assert_eq!(db.export_transactions_csv(&mut exported)?, 3);
+
+    let (_dir_2, imported_db, _conn_2) = temp_db();
+    assert_eq!(imported_db.import_transactions_csv(exported.as_slice())?, 3);
+    let mut reexported = vec![];
+    imported_db.export_transactions_csv(&mut reexported)?;
+    assert_eq!(String::from_utf8(reexported)?, String::from_utf8(exported)?);
+    assert_eq!(
+        imported_db.get_tx_by_txid(&pending.compute_txid())?,
+        db.get_tx_by_txid(&pending.compute_txid())?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_transactions_csv_covers_every_column() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let mut exported = vec![];
+    db.export_transactions_csv(&mut exported)?;
+    let exported = String::from_utf8(exported)?;
+    let mut header: Vec<&str> = exported
+        .lines()
+        .next()
+        .expect("header")
+        .split(',')
+        .collect();
+    header.sort_unstable();
+    let mut columns: Vec<String> = conn
+        .prepare("SELECT name FROM pragma_table_info('transactions')")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    columns.sort_unstable();
+    assert_eq!(header, columns);
+    Ok(())
+}
+
+#[test]
+fn test_transactions_csv_import_rejects_malformed_rows() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    for n in 1..=2 {
+        let tx = dummy_tx(&[(dummy_txid(n), 0)], &[10_000]);
+        db.insert_mempool_tx(tx, None, None, Amount::from_sat(100), fee_rate)?;
+    }
+    let mut exported = vec![];
+    db.export_transactions_csv(&mut exported)?;
+    let exported = String::from_utf8(exported)?;
+
+    let (_dir_2, imported_db, conn_2) = temp_db();
+    let err = imported_db
+        .import_transactions_csv("inputs_hash,tx_id\n".as_bytes())
+        .unwrap_err();
+    assert!(err.to_string().contains("header"), "{}", err);
+
+    // Second data row with a non-numeric found_at
+    let mut lines: Vec<String> = exported.lines().map(str::to_string).collect();
+    let mut fields: Vec<&str> = lines[2].split(',').collect();
+    fields[3] = "yesterday";
+    lines[2] = fields.join(",");
+    let err = imported_db
+        .import_transactions_csv(lines.join("\n").as_bytes())
+        .unwrap_err();
+    assert!(err.to_string().contains("Line 3"), "{}", err);
+    assert!(err.to_string().contains("found_at"), "{}", err);
+    let stored: u64 =
+        conn_2.query_row("SELECT COUNT(*) FROM transactions", [], |row| row.get(0))?;
+    assert_eq!(stored, 0);
+    Ok(())
+}
+
+#[test]
+fn test_dust_output_count_is_stored() -> Result<()> {
+    let (dir, db, _conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    // 100 sats is dust for any script type
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[100, 10_000]);
+    db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(200), fee_rate)?;
+    let key = mempool_tracker::utils::get_tx_key(&tx)?;
+    assert_eq!(db.get_dust_count(&key)?, Some(1));
+    assert_eq!(db.get_dust_count("unknown")?, None);
+
+    let strict_db = Database::new(dir.path().join("mempool_tracker_test.db").to_str().unwrap())?
+        .with_dust_threshold(Amount::from_sat(20_000));
+    strict_db.insert_mempool_tx(tx, None, None, Amount::from_sat(200), fee_rate)?;
+    assert_eq!(strict_db.get_dust_count(&key)?, Some(2));
+    Ok(())
+}
+
+#[test]
+fn test_dropped_txs_were_pruned_without_being_mined() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rows = [
+        (dummy_txid(1), None, Some(1_700_000_500)),
+        (dummy_txid(2), Some(1_700_000_400), None),
+        (dummy_txid(3), None, None),
+        // Dropped outside the window
+        (dummy_txid(4), None, Some(1_700_002_000)),
+    ];
+    for (txid, mined_at, pruned_at) in rows {
+        conn.execute(
+            "INSERT INTO transactions
+            (inputs_hash, tx_id, tx_data, found_at, mined_at, pruned_at, absolute_fee, fee_rate, version)
+            VALUES (?1, ?1, '', 1700000000000, ?2 * 1000, ?3 * 1000, 0, 0, 1)",
+            params![txid.to_string(), mined_at, pruned_at],
+        )?;
+    }
+
+    assert_eq!(
+        db.get_dropped_txs(1_700_000_000, 1_700_001_000)?,
+        vec![dummy_txid(1)]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_record_pruned_txs_marks_whole_batch() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    // More than one statement's worth of txids
+    let txs: Vec<_> = (0..1_100)
+        .map(|vout| dummy_tx(&[(dummy_txid(1), vout)], &[10_000]))
+        .collect();
+    for tx in &txs {
+        db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(200), fee_rate)?;
+    }
+
+    let (kept, pruned) = txs.split_last().unwrap();
+    db.record_pruned_txs(pruned.iter().map(|tx| tx.compute_txid()).collect())?;
+
+    let pruned_count: u64 = conn.query_row(
+        "SELECT COUNT(*) FROM transactions WHERE pruned_at IS NOT NULL",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(pruned_count, pruned.len() as u64);
+    let kept_pruned_at: Option<u64> = conn.query_row(
+        "SELECT pruned_at FROM transactions WHERE tx_id = ?1",
+        [kept.compute_txid().to_string()],
+        |row| row.get(0),
+    )?;
+    assert_eq!(kept_pruned_at, None);
+    Ok(())
+}
+
+#[test]
+fn test_txids_not_in_node_mempool() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let txs: Vec<_> = (0..5)
+        .map(|vout| dummy_tx(&[(dummy_txid(1), vout)], &[10_000]))
+        .collect();
+    for tx in &txs {
+        db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(200), fee_rate)?;
+    }
+    // Mined txs are no longer pending, whether the node has them or not
+    db.record_mined_tx(&txs[4], None)?;
+
+    // The node dropped txs 1 and 3 and has one we never stored
+    let node_mempool = vec![txs[0].compute_txid(), txs[2].compute_txid(), dummy_txid(9)];
+    let mut missing = db.txids_of_txs_not_in_list(node_mempool.clone())?;
+    missing.sort();
+    let mut expected = vec![txs[1].compute_txid(), txs[3].compute_txid()];
+    expected.sort();
+    assert_eq!(missing, expected);
+
+    // Nothing from the previous call lingers
+    assert_eq!(db.txids_of_txs_not_in_list(node_mempool)?.len(), 2);
+    assert_eq!(db.txids_of_txs_not_in_list(vec![])?.len(), 4);
+    Ok(())
+}
+
+#[test]
+fn test_truc_topology_and_stats() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let fee = Amount::from_sat(200);
+    let truc = |inputs: &[(bitcoin::Txid, u32)]| {
+        let mut tx = dummy_tx(inputs, &[10_000, 10_000]);
+        tx.version = bitcoin::transaction::Version(3);
+        tx
+    };
+    let topology_ok = |tx: &bitcoin::Transaction| -> Result<Option<bool>> {
+        Ok(conn.query_row(
+            "SELECT truc_topology_ok FROM transactions WHERE tx_id = ?1",
+            [tx.compute_txid().to_string()],
+            |row| row.get(0),
+        )?)
+    };
+
+    let parent = truc(&[(dummy_txid(1), 0)]);
+    db.insert_mempool_tx(parent.clone(), Some(1_000_000), None, fee, fee_rate)?;
+    let child = truc(&[(parent.compute_txid(), 0)]);
+    db.insert_mempool_tx(child.clone(), Some(1_000_000), None, fee, fee_rate)?;
+    assert_eq!(topology_ok(&parent)?, Some(true));
+    assert_eq!(topology_ok(&child)?, Some(true));
+
+    // A second child breaks the 1-parent-1-child package, for the first child too
+    let sibling = truc(&[(parent.compute_txid(), 1)]);
+    db.insert_mempool_tx(sibling.clone(), Some(1_000_000), None, fee, fee_rate)?;
+    assert_eq!(topology_ok(&parent)?, Some(false));
+    assert_eq!(topology_ok(&child)?, Some(false));
+    assert_eq!(topology_ok(&sibling)?, Some(false));
+
+    // v2 txs, one of them replaced
+    let v2 = dummy_tx(&[(dummy_txid(2), 0)], &[10_000]);
+    db.insert_mempool_tx(v2.clone(), Some(1_000_000), None, fee, fee_rate)?;
+    let other_v2 = dummy_tx(&[(dummy_txid(3), 0)], &[10_000]);
+    db.insert_mempool_tx(other_v2, Some(1_000_000), None, fee, fee_rate)?;
+    let bump = RbfBump::new(
+        Some((fee, fee_rate)),
+        Amount::from_sat(400),
+        FeeRate::from_sat_per_vb_unchecked(2),
+    );
+    db.record_rbf(&v2, &bump, None)?;
+
+    let stats = db.truc_stats(0, 2_000)?;
+    assert_eq!(stats.truc_count, 3);
+    assert_eq!(stats.v2_count, 2);
+    assert!((stats.truc_share - 0.6).abs() < 1e-9);
+    assert_eq!(stats.topology_violations, 3);
+    assert_eq!(stats.truc_replacement_rate, 0.0);
+    assert!((stats.v2_replacement_rate - 0.5).abs() < 1e-9);
+    assert_eq!(db.truc_stats(2_000, 3_000)?.truc_count, 0);
+    Ok(())
+}
+
+#[test]
+fn test_truc_topology_is_rechecked_as_the_package_changes() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let fee = Amount::from_sat(200);
+    let truc = |inputs: &[(bitcoin::Txid, u32)]| {
+        let mut tx = dummy_tx(inputs, &[10_000, 10_000]);
+        tx.version = bitcoin::transaction::Version(3);
+        tx
+    };
+    let topology_ok = |tx: &bitcoin::Transaction| -> Result<Option<bool>> {
+        Ok(conn.query_row(
+            "SELECT truc_topology_ok FROM transactions WHERE tx_id = ?1",
+            [tx.compute_txid().to_string()],
+            |row| row.get(0),
+        )?)
+    };
+
+    // A non-TRUC child breaks its TRUC parent's package, and isn't checked itself
+    let parent = truc(&[(dummy_txid(1), 0)]);
+    db.insert_mempool_tx(parent.clone(), Some(1_000_000), None, fee, fee_rate)?;
+    assert_eq!(topology_ok(&parent)?, Some(true));
+    let v2_child = dummy_tx(&[(parent.compute_txid(), 0)], &[9_000]);
+    db.insert_mempool_tx(v2_child.clone(), Some(1_000_000), None, fee, fee_rate)?;
+    assert_eq!(topology_ok(&parent)?, Some(false));
+    assert_eq!(topology_ok(&v2_child)?, None);
+
+    // A chain of three TRUC txs is too deep until its root is mined
+    let root = truc(&[(dummy_txid(2), 0)]);
+    db.insert_mempool_tx(root.clone(), Some(1_000_000), None, fee, fee_rate)?;
+    let middle = truc(&[(root.compute_txid(), 0)]);
+    db.insert_mempool_tx(middle.clone(), Some(1_000_000), None, fee, fee_rate)?;
+    let leaf = truc(&[(middle.compute_txid(), 0)]);
+    db.insert_mempool_tx(leaf.clone(), Some(1_000_000), None, fee, fee_rate)?;
+    assert_eq!(topology_ok(&middle)?, Some(false));
+    assert_eq!(topology_ok(&leaf)?, Some(false));
+    db.record_mined_tx(&root, None)?;
+    assert_eq!(topology_ok(&middle)?, Some(true));
+    assert_eq!(topology_ok(&leaf)?, Some(true));
+    Ok(())
+}
+
+#[test]
+fn test_update_txid_by_inputs_hash_stores_replacement() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let original = dummy_tx(&[(dummy_txid(1), 0)], &[10_000]);
+    db.insert_mempool_tx(
+        original.clone(),
+        None,
+        None,
+        Amount::from_sat(200),
+        fee_rate,
+    )?;
+
+    // Same inputs, lower output and an extra one
+    let replacement = dummy_tx(&[(dummy_txid(1), 0)], &[8_000, 1_000]);
+    db.update_txid_by_inputs_hash(&replacement)?;
+
+    let (tx_id, vsize): (String, u64) =
+        conn.query_row("SELECT tx_id, vsize FROM transactions", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    assert_eq!(tx_id, replacement.compute_txid().to_string());
+    assert_eq!(vsize, replacement.vsize() as u64);
+    assert_eq!(db.get_stored_tx(&original)?, Some(replacement));
+    Ok(())
+}
+
+#[test]
+fn test_sighash_usage() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let taproot = |vout, sig_len| {
+        let mut tx = dummy_tx(&[(dummy_txid(1), vout)], &[10_000]);
+        let mut sig = vec![0x11; 64];
+        if sig_len == 65 {
+            sig.push(0x83);
+        }
+        tx.input[0].witness = bitcoin::Witness::from_slice(&[sig]);
+        tx
+    };
+    for (vout, sig_len) in [(0, 64), (1, 64), (2, 65)] {
+        db.insert_mempool_tx(
+            taproot(vout, sig_len),
+            Some(1_000_000),
+            None,
+            Amount::from_sat(200),
+            fee_rate,
+        )?;
+    }
+
+    let usage: std::collections::HashMap<_, _> = db.sighash_usage(0, 2_000)?.into_iter().collect();
+    assert_eq!(usage[&SighashKind::Default], 2);
+    assert_eq!(usage[&SighashKind::SingleAnyoneCanPay], 1);
+    assert_eq!(usage[&SighashKind::All], 0);
+    assert_eq!(usage.len(), SighashKind::KINDS.len());
+    Ok(())
+}
+
+#[test]
+fn test_per_miner_stats() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let tagged = |height: i64, value: u64, tag: &[u8]| {
+        let mut coinbase = dummy_coinbase(height, value);
+        let mut script_sig = coinbase.input[0].script_sig.to_bytes();
+        script_sig.extend_from_slice(tag);
+        coinbase.input[0].script_sig = ScriptBuf::from_bytes(script_sig);
+        coinbase
+    };
+    let subsidy = 312_500_000;
+    let blocks = [
+        (840_000, subsidy + 2_000, b"/ViaBTC/".as_slice(), 1),
+        (840_001, subsidy + 4_000, b"/ViaBTC/".as_slice(), 2),
+        (840_002, subsidy + 1_000, b"/mystery/".as_slice(), 3),
+    ];
+    for (height, value, tag, n) in blocks {
+        let hash = bitcoin::BlockHash::from_byte_array([n; 32]);
+        db.record_block(hash, Some(height as u64), 1_000 + n as u64, 1_000, 2)?;
+        db.record_coinbase_tx(&tagged(height, value, tag), Some(hash))?;
+    }
+    // Half of the second ViaBTC block never went through our mempool
+    let mut out_of_band = dummy_tx(&[(dummy_txid(1), 0)], &[10_000]);
+    while out_of_band.vsize() < 500 {
+        out_of_band.output.push(out_of_band.output[0].clone());
+    }
+    let oob_vsize = out_of_band.vsize() as f64;
+    db.record_out_of_band_txs(
+        Some(840_001),
+        bitcoin::BlockHash::from_byte_array([2; 32]),
+        1_002,
+        &[(out_of_band, Amount::from_sat(1_000))],
+    )?;
+
+    let stats = db.per_miner_stats(0, 2_000)?;
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[0].miner.as_deref(), Some("ViaBTC"));
+    assert_eq!(stats[0].blocks, 2);
+    assert_eq!(stats[0].avg_fees, Amount::from_sat(3_000));
+    assert!((stats[0].out_of_band_share - oob_vsize / 2_000.0).abs() < 1e-9);
+    assert_eq!(stats[1].miner, None);
+    assert_eq!(stats[1].avg_fees, Amount::from_sat(1_000));
+    assert_eq!(stats[1].out_of_band_share, 0.0);
+    Ok(())
+}
+
+#[test]
+fn test_txid_history_keeps_every_replacement() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let original = dummy_tx(&[(dummy_txid(1), 0)], &[10_000]);
+    db.insert_mempool_tx(
+        original.clone(),
+        Some(1_000),
+        None,
+        Amount::from_sat(200),
+        fee_rate,
+    )?;
+    let first = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    db.update_txid_by_inputs_hash(&first)?;
+    let second = dummy_tx(&[(dummy_txid(1), 0)], &[8_000]);
+    db.update_txid_by_inputs_hash(&second)?;
+
+    let key = mempool_tracker::utils::get_tx_key(&original)?;
+    let history: Vec<_> = db
+        .get_txid_history(&key)?
+        .into_iter()
+        .map(|(txid, _)| txid)
+        .collect();
+    assert_eq!(
+        history,
+        vec![
+            original.compute_txid(),
+            first.compute_txid(),
+            second.compute_txid()
+        ]
+    );
+    assert_eq!(db.get_txid_history(&key)?[0].1, 1_000);
+    assert!(db.get_txid_history("unknown")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_variants_sharing_inputs_are_all_kept() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let inputs = [(dummy_txid(1), 0), (dummy_txid(2), 1)];
+    let first = dummy_tx(&inputs, &[10_000]);
+    let second = dummy_tx(&inputs, &[4_000, 5_000]);
+    let third = dummy_tx(&inputs, &[8_000]);
+    let key = mempool_tracker::utils::get_tx_key(&first)?;
+    assert_eq!(key, mempool_tracker::utils::get_tx_key(&second)?);
+
+    db.insert_mempool_tx(
+        first.clone(),
+        Some(1_000),
+        None,
+        Amount::from_sat(200),
+        fee_rate,
+    )?;
+    // Replacement path
+    db.update_txid_by_inputs_hash(&second)?;
+    // Two workers inserting variants of an untracked slot
+    db.insert_mempool_tx(third.clone(), None, None, Amount::from_sat(300), fee_rate)?;
+
+    assert_eq!(
+        db.get_tx_variants(&key)?,
+        vec![first.clone(), second.clone(), third.clone()]
+    );
+    // The slot itself holds the latest variant
+    assert_eq!(
+        db.untracked_txids(&[first.compute_txid(), third.compute_txid()])?,
+        vec![first.compute_txid()]
+    );
+    assert!(db.get_tx_variants("unknown")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_synchronous_off_still_writes() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("mempool_tracker_test.db");
+    let db = Database::open(path.to_str().unwrap(), Synchronous::Off)?;
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[10_000]);
+    db.insert_mempool_tx(
+        tx.clone(),
+        None,
+        None,
+        Amount::from_sat(200),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+    assert!(db.tx_exists(&tx)?);
+    assert_eq!(db.get_stored_tx(&tx)?, Some(tx));
+    assert_eq!("off".parse::<Synchronous>()?, Synchronous::Off);
+    assert!("sometimes".parse::<Synchronous>().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_iter_transactions_yields_every_row() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let txs: Vec<_> = (1..=5)
+        .map(|n| dummy_tx(&[(dummy_txid(n), 0)], &[10_000]))
+        .collect();
+    for tx in &txs {
+        db.insert_mempool_tx(
+            tx.clone(),
+            None,
+            Some(1_700_000_000_000),
+            Amount::from_sat(300),
+            FeeRate::from_sat_per_vb_unchecked(2),
+        )?;
+    }
+    db.record_mined_tx(&txs[0], None)?;
+
+    let rows = db.iter_transactions()?.collect::<Result<Vec<_>>>()?;
+    assert_eq!(rows.len(), 5);
+    for tx in &txs {
+        let row = rows
+            .iter()
+            .find(|row| row.txid == tx.compute_txid())
+            .expect("row yielded");
+        assert_eq!(row.tx, *tx);
+        assert_eq!(row.absolute_fee, Amount::from_sat(300));
+        assert_eq!(row.node_seen_at, Some(1_700_000_000_000));
+        assert!(row.fee_known);
+    }
+    assert_eq!(rows.iter().filter(|row| row.mined_at.is_some()).count(), 1);
+    // Keyset order
+    assert!(rows
+        .windows(2)
+        .all(|pair| pair[0].inputs_hash < pair[1].inputs_hash));
+    Ok(())
+}
+
+#[test]
+fn test_dump_schema_lists_tables_and_migrated_columns() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let schema = db.dump_schema()?;
+    assert!(schema.contains("transactions ("), "{}", schema);
+    assert!(schema.contains("rbf ("), "{}", schema);
+    assert!(schema.contains("rbf_history ("), "{}", schema);
+    assert!(schema.contains("idx_transactions_tx_id"), "{}", schema);
+    // Added by a migration rather than create_tables
+    assert!(schema.contains("sighash_mask"), "{}", schema);
+    // Every table comes before the first index
+    let last_table = schema.rfind("CREATE TABLE").expect("tables dumped");
+    let first_index = schema.find("CREATE INDEX").expect("indexes dumped");
+    assert!(last_table < first_index, "{}", schema);
+    Ok(())
+}
+
+#[test]
+fn test_input_value_total_resolves_from_tracked_parents() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let parent = dummy_tx(&[(dummy_txid(1), 0)], &[40_000, 60_000]);
+    db.insert_mempool_tx(
+        parent.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        fee_rate,
+    )?;
+    let parent_txid = parent.compute_txid();
+    let child = dummy_tx(&[(parent_txid, 0), (parent_txid, 1)], &[99_000]);
+    db.insert_mempool_tx(child.clone(), None, None, Amount::from_sat(1_000), fee_rate)?;
+    // One input spends an untracked tx
+    let orphan = dummy_tx(&[(parent_txid, 2), (dummy_txid(9), 0)], &[1_000]);
+    db.insert_mempool_tx(orphan.clone(), None, None, Amount::ZERO, fee_rate)?;
+
+    let input_value_total = |tx: &bitcoin::Transaction| -> Result<Option<u64>> {
+        Ok(conn.query_row(
+            "SELECT input_value_total FROM transactions WHERE tx_id = ?1",
+            params![tx.compute_txid().to_string()],
+            |row| row.get(0),
+        )?)
+    };
+    assert_eq!(input_value_total(&child)?, Some(100_000));
+    // Consistent with the fee the child was stored with
+    let absolute_fee: u64 = conn.query_row(
+        "SELECT absolute_fee FROM transactions WHERE tx_id = ?1",
+        params![child.compute_txid().to_string()],
+        |row| row.get(0),
+    )?;
+    let total_out: u64 = child
+        .output
+        .iter()
+        .map(|output| output.value.to_sat())
+        .sum();
+    assert_eq!(100_000 - total_out, absolute_fee);
+    // The parent's own parent isn't tracked
+    assert_eq!(input_value_total(&parent)?, None);
+    assert_eq!(input_value_total(&orphan)?, None);
+    Ok(())
+}
+
+#[test]
+fn test_pending_tx_records_pages_by_age() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(2);
+    let txs: Vec<_> = (1..=3)
+        .map(|n| dummy_tx(&[(dummy_txid(n), 0)], &[10_000]))
+        .collect();
+    for (tx, found_at) in txs.iter().zip([300_000, 100_000, 200_000]) {
+        db.insert_mempool_tx(
+            tx.clone(),
+            Some(found_at),
+            None,
+            Amount::from_sat(400),
+            fee_rate,
+        )?;
+    }
+    db.record_mined_tx(&txs[1], None)?;
+
+    let txids = |records: Vec<TransactionInner>| {
+        records.iter().map(|record| record.txid).collect::<Vec<_>>()
+    };
+    // Oldest first, the mined one is left out
+    assert_eq!(
+        txids(db.pending_tx_records(u64::MAX / 2, 10, 0)?),
+        vec![txs[2].compute_txid(), txs[0].compute_txid()]
+    );
+    assert_eq!(
+        txids(db.pending_tx_records(u64::MAX / 2, 1, 1)?),
+        vec![txs[0].compute_txid()]
+    );
+    // Found too recently
+    assert_eq!(
+        txids(db.pending_tx_records(250, 10, 0)?),
+        vec![txs[2].compute_txid()]
+    );
+
+    let record = db.get_tx_record(&txs[1].compute_txid())?.unwrap();
+    assert_eq!(record.found_at, 100_000);
+    assert!(record.mined_at.is_some());
+    assert_eq!(db.get_tx_record(&dummy_txid(9))?, None);
+    Ok(())
+}
+
+#[test]
+fn test_db_stats_counts_rows_and_file_sizes() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let txs: Vec<_> = (1..=3)
+        .map(|n| dummy_tx(&[(dummy_txid(n), 0)], &[10_000]))
+        .collect();
+    for tx in &txs {
+        db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(100), fee_rate)?;
+    }
+    db.record_mined_tx(&txs[0], None)?;
+    db.record_pruned_txs(vec![txs[1].compute_txid()])?;
+    for created_at in [50, 150] {
+        conn.execute(
+            "INSERT INTO rbf_history (inputs_hash, tx_id, created_at, fee_total, version)
+            VALUES ('a', 'a', ?1, 1000, 0)",
+            params![created_at],
+        )?;
+    }
+
+    let stats = db.db_stats(100)?;
+    assert_eq!(
+        (stats.total_rows, stats.pending, stats.mined, stats.pruned),
+        (3, 1, 1, 1)
+    );
+    assert_eq!(stats.rbf_events, 1);
+    assert!(stats.db_bytes > 0);
+    Ok(())
+}
+
+#[test]
+fn test_replay_classification_fills_derived_columns() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let mut tx = dummy_tx(&[(dummy_txid(1), 0)], &[100, 0]);
+    tx.output[1].script_pubkey = ScriptBuf::from_bytes(vec![0x6a, 0x02, 1, 2]);
+    db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(100), fee_rate)?;
+    db.record_coinbase_tx(&dummy_coinbase(800_000, 650_000_000), None)?;
+    // Rows as written before the classification columns existed
+    conn.execute(
+        "UPDATE transactions SET tx_type = NULL, vsize = NULL, op_return_count = 0,
+            op_return_bytes = 0, dust_output_count = 0, coinbase_value = NULL",
+        [],
+    )?;
+
+    assert_eq!(db.replay_classification()?, 2);
+    let (tx_type, vsize, op_return_count, op_return_bytes, dust_output_count): (
+        Option<String>,
+        Option<u64>,
+        u64,
+        u64,
+        u64,
+    ) = conn.query_row(
+        "SELECT tx_type, vsize, op_return_count, op_return_bytes, dust_output_count
+        FROM transactions WHERE tx_id = ?1",
+        params![tx.compute_txid().to_string()],
+        |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        },
+    )?;
+    assert!(tx_type.is_some());
+    assert_eq!(vsize, Some(tx.vsize() as u64));
+    assert_eq!((op_return_count, op_return_bytes), (1, 3));
+    assert_eq!(dust_output_count, 1);
+    assert_eq!(db.get_block_fees(800_000)?, Some(650_000_000 - 625_000_000));
+    Ok(())
+}
+
+#[test]
+fn test_replay_classification_keeps_witness_columns_of_mined_rows() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let mut tx = dummy_tx(&[(dummy_txid(1), 0)], &[10_000]);
+    tx.input[0].witness = bitcoin::Witness::from_slice(&[vec![0x11; 65], vec![0x02; 33]]);
+    db.insert_mempool_tx(
+        tx.clone(),
+        None,
+        None,
+        Amount::from_sat(200),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+    // Stores the tx without its witness
+    db.record_mined_tx(&tx, None)?;
+    let columns = |conn: &rusqlite::Connection| -> Result<(u64, String, Option<u64>)> {
+        Ok(conn.query_row(
+            "SELECT vsize, tx_type, sighash_mask FROM transactions WHERE tx_id = ?1",
+            params![tx.compute_txid().to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?)
+    };
+    let before = columns(&conn)?;
+    assert_eq!(before.0, tx.vsize() as u64);
+
+    assert_eq!(db.replay_classification()?, 1);
+    assert_eq!(columns(&conn)?, before);
+    Ok(())
+}
+
+#[test]
+fn test_propagation_deltas_compare_first_sightings() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let (early, late, single) = (dummy_txid(1), dummy_txid(2), dummy_txid(3));
+    db.record_sighting(&early, "dc1", 1_000_000)?;
+    db.record_sighting(&early, "dc2", 1_000_250)?;
+    // Repeated announcements keep the first
+    db.record_sighting(&early, "dc2", 1_010_000)?;
+    db.record_sighting(&late, "dc2", 1_100_000)?;
+    db.record_sighting(&late, "dc1", 1_100_040)?;
+    // Seen by one node only, nothing to compare
+    db.record_sighting(&single, "dc1", 1_050_000)?;
+
+    let deltas: Vec<_> = db
+        .propagation_deltas(0, 2_000)?
+        .into_iter()
+        .map(|delta| (delta.txid, delta.node_id, delta.delay))
+        .collect();
+    assert_eq!(
+        deltas,
+        vec![
+            (early, "dc1".to_string(), 0),
+            (early, "dc2".to_string(), 250),
+            (late, "dc2".to_string(), 0),
+            (late, "dc1".to_string(), 40),
+        ]
+    );
+    // Ranges bound the earliest sighting
+    assert_eq!(db.propagation_deltas(1_001, 2_000)?.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_immature_coinbases_at_tip() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let coinbases: Vec<_> = [800_000, 800_050, 800_099]
+        .into_iter()
+        .map(|height| dummy_coinbase(height, 625_000_000))
+        .collect();
+    for coinbase in &coinbases {
+        db.record_coinbase_tx(coinbase, None)?;
+    }
+    let txids: Vec<_> = coinbases.iter().map(|tx| tx.compute_txid()).collect();
+
+    assert_eq!(db.get_immature_coinbases(800_099)?, txids);
+    // A block at 800_100 may spend the first one
+    assert_eq!(db.get_immature_coinbases(800_100)?, txids[1..]);
+    assert_eq!(db.get_immature_coinbases(800_150)?, txids[2..]);
+    assert!(db.get_immature_coinbases(800_199)?.is_empty());
+
+    let (_dir, db, _conn) = temp_db();
+    let db = db.with_coinbase_maturity(10);
+    db.record_coinbase_tx(&coinbases[0], None)?;
+    assert_eq!(db.get_immature_coinbases(800_009)?, txids[..1]);
+    assert!(db.get_immature_coinbases(800_010)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_found_at_keeps_millis_within_a_second() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let first = dummy_tx(&[(dummy_txid(1), 0)], &[10_000]);
+    let second = dummy_tx(&[(dummy_txid(2), 0)], &[10_000]);
+    db.insert_mempool_tx(
+        first.clone(),
+        Some(1_700_000_000_100),
+        None,
+        Amount::from_sat(200),
+        fee_rate,
+    )?;
+    db.insert_mempool_tx(
+        second.clone(),
+        Some(1_700_000_000_600),
+        None,
+        Amount::from_sat(200),
+        fee_rate,
+    )?;
+
+    let found_at = |tx: &bitcoin::Transaction| -> Result<u64> {
+        Ok(conn.query_row(
+            "SELECT found_at FROM transactions WHERE tx_id = ?1",
+            [tx.compute_txid().to_string()],
+            |row| row.get(0),
+        )?)
+    };
+    assert_eq!(found_at(&first)?, 1_700_000_000_100);
+    assert_eq!(found_at(&second)?, 1_700_000_000_600);
+    // Both fall in the same one second window
+    assert_eq!(db.pending_tx_records(1_700_000_000, 10, 0)?.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_migration_scales_second_times_to_millis() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    conn.execute(
+        "INSERT INTO transactions
+        (inputs_hash, tx_id, tx_data, found_at, node_seen_at, mined_at, absolute_fee, fee_rate, version)
+        VALUES ('a', 'txid-a', '', 1700000000, 1699999990, 1700000600, 0, 0, 1)",
+        [],
+    )?;
+    conn.execute(
+        "INSERT INTO tx_sightings (txid, node_id, first_seen_at) VALUES ('txid-a', 'dc1', 1700000001)",
+        [],
+    )?;
+    // As if the database was written before the migration
+    conn.execute(
+        "DELETE FROM migrations WHERE id = 'store_tx_times_in_millis'",
+        [],
+    )?;
+    db.run_migrations()?;
+
+    let times: (u64, u64, u64, Option<u64>) = conn.query_row(
+        "SELECT found_at, node_seen_at, mined_at, pruned_at FROM transactions WHERE tx_id = 'txid-a'",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+    assert_eq!(
+        times,
+        (
+            1_700_000_000_000,
+            1_699_999_990_000,
+            1_700_000_600_000,
+            None
+        )
+    );
+    let seen: u64 = conn.query_row(
+        "SELECT first_seen_at FROM tx_sightings WHERE txid = 'txid-a'",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(seen, 1_700_000_001_000);
+
+    // Applied once
+    db.run_migrations()?;
+    let found_at: u64 = conn.query_row(
+        "SELECT found_at FROM transactions WHERE tx_id = 'txid-a'",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(found_at, 1_700_000_000_000);
+    Ok(())
+}
+
+#[test]
+fn test_retention_deletes_expired_rows() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    let day = 24 * 60 * 60;
+    // (key, mined_at, pruned_at), in ms
+    let rows: Vec<(&str, Option<u64>, Option<u64>)> = vec![
+        ("old-mined", Some((now - 10 * day) * 1000), None),
+        ("old-pruned", None, Some((now - 10 * day) * 1000)),
+        ("recent-mined", Some((now - day) * 1000), None),
+        // pending for long, still kept
+        ("pending", None, None),
+    ];
+    for (key, mined_at, pruned_at) in rows {
+        conn.execute(
+            "INSERT INTO transactions
+            (inputs_hash, tx_id, tx_data, found_at, mined_at, pruned_at, absolute_fee, fee_rate, version)
+            VALUES (?1, ?2, '', 1000, ?3, ?4, 0, 0, 1)",
+            params![key, format!("txid-{}", key), mined_at, pruned_at],
+        )?;
+        conn.execute(
+            "INSERT INTO tx_sightings (txid, node_id, first_seen_at) VALUES (?1, 'default', 1000)",
+            [format!("txid-{}", key)],
+        )?;
+        conn.execute(
+            "INSERT INTO rbf_history (inputs_hash, tx_id, created_at, fee_total, version)
+            VALUES (?1, ?2, 1, 0, 1)",
+            params![key, format!("txid-{}", key)],
+        )?;
+    }
+    for created_at in [now - 10 * day, now - 1] {
+        conn.execute(
+            "INSERT INTO mempool (created_at, size, tx_count, block_height, block_hash, version)
+            VALUES (?1, 1, 1, 1, '', 1)",
+            [created_at],
+        )?;
+    }
+
+    // Nothing goes without a retention
+    assert_eq!(db.apply_retention()?, (0, 0));
+    let db = db.with_retention(Retention {
+        transactions: Some(std::time::Duration::from_secs(7 * day)),
+        snapshots: Some(std::time::Duration::from_secs(7 * day)),
+    });
+    assert_eq!(db.apply_retention()?, (2, 1));
+
+    let count = |table: &str| -> Result<u64> {
+        Ok(
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })?,
+        )
+    };
+    assert_eq!(count("transactions")?, 2);
+    assert_eq!(count("tx_sightings")?, 2);
+    assert_eq!(count("rbf_history")?, 2);
+    assert_eq!(count("mempool")?, 1);
+    let kept: Vec<String> = conn
+        .prepare("SELECT inputs_hash FROM transactions ORDER BY inputs_hash")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    assert_eq!(kept, vec!["pending", "recent-mined"]);
+    Ok(())
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use async_channel::bounded;
+use bitcoin::{consensus::Encodable, Amount, Transaction};
+use common::{dummy_block, dummy_coinbase, dummy_tx, dummy_txid, mock_rpc::MockRpc, temp_db};
+use mempool_tracker::{
+    database::Database,
+    events::{EventPublisher, EventSink, MempoolEvent},
+    filter::Filter,
+    tip::TipTracker,
+    worker::{ProcessOutcome, Task, TaskContext},
+};
+
+/// Worker publishing to `events` the way the app wires one
+fn worker(rpc: &MockRpc, db: &Database, events: EventPublisher) -> TaskContext<MockRpc> {
+    let (_, control_rx) = bounded(1);
+    let (_, tasks_rx) = bounded(1);
+    TaskContext::new(
+        rpc.clone(),
+        db.clone(),
+        events,
+        Filter::default(),
+        control_rx,
+        tasks_rx,
+        TipTracker::new(Duration::from_secs(1800)),
+    )
+}
+
+fn raw(tx: &Transaction) -> Task {
+    let mut bytes = vec![];
+    tx.consensus_encode(&mut bytes).expect("encode");
+    Task::RawTx(bytes)
+}
+
+#[derive(Clone, Default)]
+struct MockSink(Arc<Mutex<Vec<(String, Vec<u8>)>>>);
+
+impl EventSink for MockSink {
+    async fn send(&mut self, subject: &str, payload: Vec<u8>) -> Result<()> {
+        self.0.lock().unwrap().push((subject.to_string(), payload));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_every_sink_gets_each_event() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let (first, second) = (MockSink::default(), MockSink::default());
+    let events = EventPublisher::spawn(first.clone(), 16).with_sink(second.clone(), 16);
+    let worker = worker(&rpc, &db, events);
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    rpc.add_to_mempool(&tx, 1_700_000_000, Amount::from_sat(1_000));
+    worker.process_task(raw(&tx)).await?;
+
+    for sink in [first, second] {
+        let subject = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some((subject, _)) = sink.0.lock().unwrap().first().cloned() {
+                    return subject;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?;
+        assert_eq!(subject, "mempool.tx.new");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_event_published_on_insert() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let sink = MockSink::default();
+    let worker = worker(&rpc, &db, EventPublisher::spawn(sink.clone(), 16));
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    rpc.add_to_mempool(&tx, 1_700_000_000, Amount::from_sat(1_000));
+
+    assert_eq!(
+        worker.process_task(raw(&tx)).await?,
+        ProcessOutcome::Inserted
+    );
+
+    let published = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(message) = sink.0.lock().unwrap().first().cloned() {
+                return message;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+
+    assert_eq!(published.0, "mempool.tx.new");
+    let payload: serde_json::Value = serde_json::from_slice(&published.1)?;
+    assert_eq!(payload["type"], "new");
+    assert_eq!(payload["txid"], tx.compute_txid().to_string());
+    assert_eq!(payload["fee"], 1_000);
+    Ok(())
+}
+
+/// Records the partition key like a Kafka producer would
+#[derive(Clone, Default)]
+struct KeyedSink(Arc<Mutex<Vec<(String, Vec<u8>)>>>);
+
+impl EventSink for KeyedSink {
+    async fn send(&mut self, _subject: &str, _payload: Vec<u8>) -> Result<()> {
+        panic!("keyed sinks are sent the key");
+    }
+
+    async fn send_keyed(&mut self, _subject: &str, key: &str, payload: Vec<u8>) -> Result<()> {
+        self.0.lock().unwrap().push((key.to_string(), payload));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_events_are_keyed_by_txid() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let sink = KeyedSink::default();
+    let worker = worker(&rpc, &db, EventPublisher::spawn(sink.clone(), 16));
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let txid = tx.compute_txid().to_string();
+    rpc.add_to_mempool(&tx, 1_700_000_000, Amount::from_sat(1_000));
+
+    // Stored as it arrives, then mined by the next block
+    assert_eq!(
+        worker.process_task(raw(&tx)).await?,
+        ProcessOutcome::Inserted
+    );
+    let block = dummy_block(1_700_000_600, vec![dummy_coinbase(101, 50_000), tx.clone()]);
+    rpc.add_block(101, block.clone());
+    worker
+        .process_task(Task::NewBlock(block.block_hash()))
+        .await?;
+
+    let published = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let messages = sink.0.lock().unwrap().clone();
+            if messages.len() >= 3 {
+                return messages;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+
+    let block_hash = block.block_hash().to_string();
+    let keys: Vec<&str> = published.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(
+        keys,
+        vec![txid.as_str(), txid.as_str(), block_hash.as_str()]
+    );
+    let payload: serde_json::Value = serde_json::from_slice(&published[0].1)?;
+    assert_eq!(payload["type"], "new");
+    assert_eq!(payload["txid"], txid);
+    Ok(())
+}
+
+#[test]
+fn test_disabled_publisher_is_a_noop() {
+    EventPublisher::disabled().publish(MempoolEvent::Pruned {
+        txid: "ab".repeat(32),
+    });
+}
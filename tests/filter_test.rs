@@ -0,0 +1,72 @@
+mod common;
+
+use bitcoin::{Address, Amount, FeeRate, Network, PublicKey, ScriptBuf};
+use common::{dummy_tx, dummy_txid};
+use mempool_tracker::filter::Filter;
+use std::str::FromStr;
+
+fn watched_address() -> Address {
+    let pubkey =
+        PublicKey::from_str("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+            .unwrap();
+    Address::p2pkh(pubkey, Network::Regtest)
+}
+
+fn rate(sat_per_vb: u64) -> FeeRate {
+    FeeRate::from_sat_per_vb_unchecked(sat_per_vb)
+}
+
+#[test]
+fn test_empty_filter_matches_everything() {
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[1]);
+    assert!(Filter::default().matches(&tx, rate(0)));
+}
+
+#[test]
+fn test_min_fee_rate() {
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[1_000]);
+    let filter = Filter {
+        min_fee_rate: Some(rate(5)),
+        ..Default::default()
+    };
+    assert!(filter.matches(&tx, rate(5)));
+    assert!(!filter.matches(&tx, rate(4)));
+}
+
+#[test]
+fn test_watched_addresses() {
+    let filter = Filter::default().with_watched_addresses(&[watched_address()]);
+    let mut tx = dummy_tx(&[(dummy_txid(1), 0)], &[1_000, 2_000]);
+    assert!(!filter.matches(&tx, rate(1)));
+
+    tx.output[1].script_pubkey = watched_address().script_pubkey();
+    assert!(filter.matches(&tx, rate(1)));
+}
+
+#[test]
+fn test_min_value() {
+    let filter = Filter {
+        min_value: Some(Amount::from_sat(3_000)),
+        ..Default::default()
+    };
+    assert!(filter.matches(&dummy_tx(&[(dummy_txid(1), 0)], &[1_000, 2_000]), rate(1)));
+    assert!(!filter.matches(&dummy_tx(&[(dummy_txid(1), 0)], &[1_000, 1_999]), rate(1)));
+}
+
+#[test]
+fn test_criteria_are_combined_with_and() {
+    let filter = Filter {
+        min_fee_rate: Some(rate(10)),
+        min_value: Some(Amount::from_sat(1_000)),
+        ..Default::default()
+    }
+    .with_watched_addresses(&[watched_address()]);
+    let mut tx = dummy_tx(&[(dummy_txid(1), 0)], &[5_000]);
+    tx.output[0].script_pubkey = watched_address().script_pubkey();
+    assert!(filter.matches(&tx, rate(10)));
+    // Fails only the fee rate
+    assert!(!filter.matches(&tx, rate(9)));
+    // Fails only the watched address
+    tx.output[0].script_pubkey = ScriptBuf::new();
+    assert!(!filter.matches(&tx, rate(10)));
+}
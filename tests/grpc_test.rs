@@ -0,0 +1,66 @@
+#![cfg(feature = "grpc")]
+
+mod common;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_channel::bounded;
+use bitcoin::{consensus::Encodable, Amount};
+use common::{dummy_tx, dummy_txid, mock_rpc::MockRpc, temp_db};
+use mempool_tracker::{
+    events::EventPublisher,
+    filter::Filter,
+    grpc::{
+        self,
+        proto::{mempool_client::MempoolClient, SubscribeTransactionsRequest},
+    },
+    tip::TipTracker,
+    worker::{ProcessOutcome, Task, TaskContext},
+};
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn test_subscriber_receives_inserted_tx() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let events = EventPublisher::disabled().with_broadcast(16);
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(grpc::serve_with_listener(listener, events.clone()));
+
+    let mut client = MempoolClient::connect(format!("http://{}", addr)).await?;
+    let mut stream = client
+        .subscribe_transactions(SubscribeTransactionsRequest {})
+        .await?
+        .into_inner();
+
+    let (_, control_rx) = bounded(1);
+    let (_, tasks_rx) = bounded(1);
+    let worker = TaskContext::new(
+        rpc.clone(),
+        db.clone(),
+        events,
+        Filter::default(),
+        control_rx,
+        tasks_rx,
+        TipTracker::new(Duration::from_secs(1800)),
+    );
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    rpc.add_to_mempool(&tx, 1_700_000_000, Amount::from_sat(1_000));
+    let mut bytes = vec![];
+    tx.consensus_encode(&mut bytes)?;
+    assert_eq!(
+        worker.process_task(Task::RawTx(bytes)).await?,
+        ProcessOutcome::Inserted
+    );
+
+    let streamed = tokio::time::timeout(Duration::from_secs(5), stream.message())
+        .await??
+        .expect("stream ended");
+    assert_eq!(streamed.txid, tx.compute_txid().to_string());
+    assert_eq!(streamed.fee, 1_000);
+    assert_eq!(streamed.vsize, tx.vsize() as u64);
+    assert!(streamed.found_at > 0);
+    Ok(())
+}
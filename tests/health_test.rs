@@ -0,0 +1,168 @@
+mod common;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use common::{mock_rpc::MockRpc, temp_db};
+use mempool_tracker::{
+    app::{App, AppConfig},
+    database::Database,
+    events::EventPublisher,
+    health::Health,
+    zmq_factory::BitcoinZmqFactory,
+};
+
+const ZMQ_MAX_SILENCE: Duration = Duration::from_secs(300);
+
+#[test]
+fn test_liveness_reports_failed_checks() {
+    let health = Health::default();
+    assert_eq!(
+        health.liveness_failures(ZMQ_MAX_SILENCE),
+        vec!["rpc unreachable", "database not writable"]
+    );
+
+    health.record_rpc(true);
+    health.record_db_write(true);
+    health.record_zmq_message();
+    assert!(health.liveness_failures(ZMQ_MAX_SILENCE).is_empty());
+
+    health.record_rpc(false);
+    assert_eq!(
+        health.liveness_failures(ZMQ_MAX_SILENCE),
+        vec!["rpc unreachable"]
+    );
+}
+
+#[test]
+fn test_stall_needs_both_zmq_and_rpc_silent() {
+    let health = Health::default();
+    // zmq silence only counts once the tracker is ready, e.g. not during a long extraction
+    std::thread::sleep(Duration::from_millis(1_100));
+    assert_eq!(health.zmq_silence_secs(), 0);
+    assert_eq!(health.stall(Duration::ZERO), None);
+
+    health.set_ready();
+    std::thread::sleep(Duration::from_millis(1_100));
+    let reason = health.stall(Duration::ZERO).expect("stalled");
+    assert!(reason.starts_with("no zmq message for "), "{}", reason);
+    assert_eq!(health.stall(ZMQ_MAX_SILENCE), None);
+
+    health.record_rpc(true);
+    assert_eq!(health.stall(Duration::ZERO), None);
+}
+
+#[test]
+fn test_readiness_reflects_ingestion_lag() {
+    let health = Health::default();
+    health.set_ready();
+    health.record_queue_depth(7, Some(10));
+    assert!(health.readiness_failures().is_empty());
+
+    health.record_queue_depth(8, Some(10));
+    assert_eq!(
+        health.readiness_failures(),
+        vec!["ingestion lagging, 8 of 10 raw tx queue slots in use"]
+    );
+
+    // An unbounded queue never fills up
+    health.record_queue_depth(1_000_000, None);
+    assert!(health.readiness_failures().is_empty());
+}
+
+#[tokio::test]
+async fn test_init_marks_app_live_and_ready() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let zmq_factory =
+        BitcoinZmqFactory::new("127.0.0.1".to_string(), common::mock_rpc::MOCK_ZMQ_PORT);
+    let mut app = App::new(
+        MockRpc::default(),
+        zmq_factory,
+        db,
+        EventPublisher::disabled(),
+        AppConfig::default(),
+    );
+    let health = app.health();
+    assert!(!health.is_ready());
+
+    app.init().await?;
+    assert!(health.is_ready());
+    assert!(health.liveness_failures(ZMQ_MAX_SILENCE).is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_read_only_database_is_reported_not_writable() -> Result<()> {
+    let (dir, _db, _conn) = temp_db();
+    let db =
+        Database::open_read_only(dir.path().join("mempool_tracker_test.db").to_str().unwrap())?;
+    let zmq_factory =
+        BitcoinZmqFactory::new("127.0.0.1".to_string(), common::mock_rpc::MOCK_ZMQ_PORT);
+    let mut app = App::new(
+        MockRpc::default(),
+        zmq_factory,
+        db,
+        EventPublisher::disabled(),
+        AppConfig::default(),
+    );
+    app.init().await?;
+    assert_eq!(
+        app.health().liveness_failures(ZMQ_MAX_SILENCE),
+        vec!["database not writable"]
+    );
+    Ok(())
+}
+
+#[cfg(feature = "http")]
+#[tokio::test]
+async fn test_probes_reflect_health() -> Result<()> {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    async fn get(addr: std::net::SocketAddr, path: &str) -> Result<String> {
+        let mut stream = TcpStream::connect(addr).await?;
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        );
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok(response)
+    }
+
+    let health = Health::default();
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(mempool_tracker::http::serve_with_listener(
+        listener,
+        health.clone(),
+        ZMQ_MAX_SILENCE,
+        async {
+            let _ = stop_rx.await;
+        },
+    ));
+
+    let response = get(addr, "/healthz").await?;
+    assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+    assert!(response.contains("rpc unreachable"), "{}", response);
+    assert!(get(addr, "/readyz").await?.starts_with("HTTP/1.1 503"));
+
+    health.record_rpc(true);
+    health.record_db_write(true);
+    health.set_ready();
+    assert!(get(addr, "/healthz").await?.starts_with("HTTP/1.1 200"));
+    assert!(get(addr, "/readyz").await?.starts_with("HTTP/1.1 200"));
+    health.record_queue_depth(9, Some(10));
+    let response = get(addr, "/readyz").await?;
+    assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+    assert!(response.contains("ingestion lagging"), "{}", response);
+
+    // The server stops with the app
+    stop_tx.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), server).await???;
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::fs;
+
+use mempool_tracker::instance::{lock_path, InstanceLock};
+
+#[test]
+fn test_second_instance_is_refused_with_the_holders_pid() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let database = dir.path().join("mempool-tracker.db");
+    let pid_file = dir.path().join("tracker.pid");
+
+    let instance = InstanceLock::acquire(&database, Some(&pid_file))?;
+    let pid = std::process::id().to_string();
+    assert_eq!(fs::read_to_string(&pid_file)?.trim(), pid);
+
+    let err = InstanceLock::acquire(&database, None).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains(&format!("another instance is running (pid {})", pid)),
+        "{}",
+        err
+    );
+
+    // Shutting down removes the pid file and frees the database
+    drop(instance);
+    assert!(!pid_file.exists());
+    drop(InstanceLock::acquire(&database, None)?);
+    Ok(())
+}
+
+#[test]
+fn test_stale_pid_file_is_taken_over() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let database = dir.path().join("mempool-tracker.db");
+    let pid_file = dir.path().join("tracker.pid");
+    // Left behind by a crash, its lock went with the process
+    fs::write(&pid_file, "4194305\n")?;
+    fs::write(lock_path(&database), "4194305\n")?;
+
+    let _instance = InstanceLock::acquire(&database, Some(&pid_file))?;
+    let pid = std::process::id().to_string();
+    assert_eq!(fs::read_to_string(&pid_file)?.trim(), pid);
+    assert_eq!(fs::read_to_string(lock_path(&database))?.trim(), pid);
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use std::{
+    fs,
+    io::Write,
+    time::{Duration, SystemTime},
+};
+
+use mempool_tracker::logging::{LogOutput, RotatingLogFile};
+
+#[test]
+fn test_log_file_rotates_by_size_and_keeps_max_files() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("tracker.log");
+    let mut log = RotatingLogFile::open(&path, 10, false, 2)?;
+    log.write_all(b"first\n")?;
+    log.write_all(b"second\n")?;
+    log.write_all(b"third\n")?;
+
+    assert_eq!(fs::read_to_string(&path)?, "third\n");
+    assert_eq!(
+        fs::read_to_string(dir.path().join("tracker.log.1"))?,
+        "second\n"
+    );
+    assert!(!dir.path().join("tracker.log.2").exists());
+    Ok(())
+}
+
+#[test]
+fn test_log_file_rotates_daily() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("tracker.log");
+    let now = SystemTime::now();
+    let mut log = RotatingLogFile::open(&path, 0, true, 3)?;
+    log.write_at(b"today\n", now)?;
+    log.write_at(b"still today\n", now)?;
+    log.write_at(b"tomorrow\n", now + Duration::from_secs(24 * 60 * 60))?;
+
+    assert_eq!(fs::read_to_string(&path)?, "tomorrow\n");
+    assert_eq!(
+        fs::read_to_string(dir.path().join("tracker.log.1"))?,
+        "today\nstill today\n"
+    );
+
+    // Reopened the same day, the file is appended to
+    drop(log);
+    let mut log = RotatingLogFile::open(&path, 0, true, 3)?;
+    log.write_all(b"restarted\n")?;
+    assert!(fs::read_to_string(&path)?.ends_with("restarted\n"));
+    Ok(())
+}
+
+#[test]
+fn test_log_output_parses() {
+    assert_eq!("both".parse::<LogOutput>().unwrap(), LogOutput::Both);
+    assert!("syslog".parse::<LogOutput>().is_err());
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use std::str::FromStr;
+
+use anyhow::Result;
+use bitcoin::{address::NetworkUnchecked, Address, ScriptBuf};
+use common::dummy_coinbase;
+use mempool_tracker::miners::{coinbase_tag, MinerRegistry};
+
+fn tagged_coinbase(height: i64, tag: &[u8]) -> bitcoin::Transaction {
+    let mut coinbase = dummy_coinbase(height, 50_000);
+    let mut script_sig = coinbase.input[0].script_sig.to_bytes();
+    script_sig.extend_from_slice(tag);
+    coinbase.input[0].script_sig = ScriptBuf::from_bytes(script_sig);
+    coinbase
+}
+
+#[test]
+fn test_coinbase_tag_skips_height_and_binary() {
+    let coinbase = tagged_coinbase(
+        840_000,
+        b"\x01\x02/Foundry USA Pool #dropgold/\xff\xfe\x00abc",
+    );
+    assert_eq!(coinbase_tag(&coinbase), "/Foundry USA Pool #dropgold/ abc");
+    // Small heights are pushed as opcodes
+    assert_eq!(coinbase_tag(&tagged_coinbase(5, b"regtest")), "regtest");
+}
+
+#[test]
+fn test_builtin_tags_identify_miner() {
+    let registry = MinerRegistry::builtin();
+    let id = registry.identify(&tagged_coinbase(840_000, b"Mined by AntPool"));
+    assert_eq!(id.label.as_deref(), Some("AntPool"));
+
+    // Unknown tags are kept raw
+    let id = registry.identify(&tagged_coinbase(840_000, b"/some new pool/"));
+    assert_eq!(id.label, None);
+    assert_eq!(id.tag, "/some new pool/");
+}
+
+#[test]
+fn test_mapping_file_extends_builtin() -> Result<()> {
+    let address = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("miners.json");
+    std::fs::write(
+        &path,
+        format!(
+            r#"{{"tags": {{"/new pool/": "New Pool"}}, "addresses": {{"{}": "Solo Miner"}}}}"#,
+            address
+        ),
+    )?;
+    let registry = MinerRegistry::builtin().with_mapping_file(&path)?;
+
+    let id = registry.identify(&tagged_coinbase(840_000, b"/new pool/"));
+    assert_eq!(id.label.as_deref(), Some("New Pool"));
+    assert_eq!(
+        registry
+            .identify(&tagged_coinbase(840_000, b"/ViaBTC/"))
+            .label
+            .as_deref(),
+        Some("ViaBTC")
+    );
+
+    // A payout address wins over the tag
+    let mut coinbase = tagged_coinbase(840_000, b"/ViaBTC/");
+    coinbase.output[0].script_pubkey = Address::<NetworkUnchecked>::from_str(address)?
+        .assume_checked()
+        .script_pubkey();
+    assert_eq!(
+        registry.identify(&coinbase).label.as_deref(),
+        Some("Solo Miner")
+    );
+
+    std::fs::write(&path, "not json")?;
+    assert!(MinerRegistry::builtin().with_mapping_file(&path).is_err());
+    Ok(())
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use common::mock_rpc::MockRpc;
+use mempool_tracker::{
+    rate_limit::RateLimiter,
+    rpc::{BitcoinRpc, RateLimitedRpc},
+};
+
+#[tokio::test]
+async fn test_unlimited_never_waits() {
+    let limiter = RateLimiter::unlimited();
+    assert!(!limiter.is_limited());
+    for _ in 0..1_000 {
+        limiter.acquire().await;
+    }
+    assert_eq!(limiter.waited(), Duration::ZERO);
+}
+
+#[tokio::test]
+async fn test_calls_beyond_the_burst_wait_for_refills() -> Result<()> {
+    let limiter = RateLimiter::new(50, 2);
+    let mock = MockRpc::default();
+    let rpc = RateLimitedRpc::new(mock.clone(), limiter.clone());
+
+    let started = Instant::now();
+    for _ in 0..6 {
+        rpc.get_block_count().await?;
+    }
+    // The burst goes out at once, the other 4 wait 20ms each
+    assert!(started.elapsed() >= Duration::from_millis(70));
+    assert!(limiter.waited() >= Duration::from_millis(70));
+    assert_eq!(mock.calls("getblockcount"), 6);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_clones_share_the_bucket() {
+    let limiter = RateLimiter::new(10, 1);
+    let other = limiter.clone();
+    limiter.acquire().await;
+    other.acquire().await;
+    assert!(limiter.waited() >= Duration::from_millis(90));
+}
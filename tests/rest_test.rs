@@ -0,0 +1,142 @@
+#![cfg(feature = "http")]
+
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use bitcoin::{Amount, FeeRate};
+use common::{dummy_tx, dummy_txid, temp_db};
+use mempool_tracker::{database::FEE_HISTOGRAM_BANDS, utils::RbfBump};
+use rusqlite::params;
+use serde_json::Value;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Status code and body of a GET
+async fn get_text(addr: std::net::SocketAddr, path: &str) -> Result<(u16, String)> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    let status = response[9..12].parse()?;
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    Ok((status, body.to_string()))
+}
+
+/// Status code and JSON body of a GET
+async fn get(addr: std::net::SocketAddr, path: &str) -> Result<(u16, Value)> {
+    let (status, body) = get_text(addr, path).await?;
+    Ok((status, serde_json::from_str(&body).unwrap_or(Value::Null)))
+}
+
+#[tokio::test]
+async fn test_rest_api_serves_stored_data() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let now_ms = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_millis() as u64;
+    let inputs = [(dummy_txid(1), 0)];
+    let original = dummy_tx(&inputs, &[99_000]);
+    let replacement = dummy_tx(&inputs, &[98_500]);
+    let pending = dummy_tx(&[(dummy_txid(2), 0)], &[50_000]);
+    db.insert_mempool_tx(
+        original.clone(),
+        Some(now_ms - 100_000),
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(10),
+    )?;
+    let bump = RbfBump::new(
+        db.get_stored_fee(&replacement)?,
+        Amount::from_sat(1_500),
+        FeeRate::from_sat_per_vb_unchecked(15),
+    );
+    db.record_rbf(&replacement, &bump, None)?;
+    db.insert_mempool_tx(
+        pending.clone(),
+        Some(now_ms - 10_000),
+        None,
+        Amount::from_sat(200),
+        FeeRate::from_sat_per_vb_unchecked(2),
+    )?;
+    conn.execute(
+        "INSERT INTO mempool (created_at, size, tx_count, block_height, block_hash, version)
+        VALUES (?1, 1000, 2, 800000, ?2, 0)",
+        params![1_700_000_000u64, hex::encode([0u8; 32])],
+    )?;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(mempool_tracker::rest::serve_with_listener(
+        listener,
+        db,
+        async {
+            let _ = stop_rx.await;
+        },
+    ));
+
+    let (status, tx) = get(addr, &format!("/tx/{}", pending.compute_txid())).await?;
+    assert_eq!(status, 200);
+    assert_eq!(tx["txid"], pending.compute_txid().to_string());
+    assert_eq!(tx["status"], "pending");
+    assert_eq!(tx["fee"], 200);
+    assert!(tx["found_at"].as_str().unwrap().ends_with('Z'));
+    assert_eq!(get(addr, &format!("/tx/{}", dummy_txid(9))).await?.0, 404);
+    assert_eq!(get(addr, "/tx/nothex").await?.0, 400);
+
+    // Only the replacement is old enough, its page is full so more may follow
+    let (_, page) = get(addr, "/pending?min_age=50&limit=1").await?;
+    let items = page["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["txid"], replacement.compute_txid().to_string());
+    assert_eq!(page["next_offset"], 1);
+    let (_, page) = get(addr, "/pending?limit=1&offset=1").await?;
+    assert_eq!(page["items"][0]["txid"], pending.compute_txid().to_string());
+
+    let (_, history) = get(addr, &format!("/rbf/{}", original.compute_txid())).await?;
+    let history = history.as_array().unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0]["txid"], replacement.compute_txid().to_string());
+    assert_eq!(history[0]["prev_fee"], 1_000);
+    assert_eq!(history[0]["fee"], 1_500);
+
+    let (_, states) = get(addr, "/mempool-state?from=1600000000&to=1800000000").await?;
+    assert_eq!(states["items"][0]["tx_count"], 2);
+    assert_eq!(states["items"][0]["created_at"], "2023-11-14T22:13:20Z");
+    assert_eq!(states["next_offset"], Value::Null);
+    let (_, states) = get(addr, "/mempool-state?to=1600000000").await?;
+    assert!(states["items"].as_array().unwrap().is_empty());
+
+    let (status, latency) = get(addr, "/stats/confirmation-latency?window=3600").await?;
+    assert_eq!(status, 200);
+    assert_eq!(latency["count"], 0);
+
+    // A gauge per fee band, the pending txs counted in them
+    let (status, metrics) = get_text(addr, "/metrics").await?;
+    assert_eq!(status, 200);
+    assert!(
+        metrics.contains("# TYPE mempool_fee_rate_txs gauge"),
+        "{}",
+        metrics
+    );
+    let gauges: Vec<u64> = metrics
+        .lines()
+        .filter(|line| line.starts_with("mempool_fee_rate_txs{"))
+        .map(|line| line.rsplit(' ').next().unwrap().parse().unwrap())
+        .collect();
+    assert_eq!(gauges.len(), FEE_HISTOGRAM_BANDS.len());
+    assert!(gauges.iter().sum::<u64>() >= 1, "{}", metrics);
+    assert!(metrics.contains("mempool_fee_rate_txs{min_sat_per_vb=\"1\"}"));
+
+    stop_tx.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), server).await???;
+    Ok(())
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use bitcoin::Amount;
+use common::{dummy_tx, dummy_txid, mock_rpc::MockRpc};
+use mempool_tracker::{
+    config::Auth,
+    rate_limit::RateLimiter,
+    rpc::{is_connection_error, BitcoinRpc, ReconnectingRpc, RetryingRpc, RpcRetryPolicy},
+};
+
+/// Wrap `mock` so every reconnect hands out the same node, counting the rebuilds
+fn reconnecting(mock: &MockRpc) -> Result<(ReconnectingRpc<MockRpc>, Arc<AtomicUsize>)> {
+    let connects = Arc::new(AtomicUsize::new(0));
+    let rpc = {
+        let mock = mock.clone();
+        let connects = connects.clone();
+        ReconnectingRpc::new(
+            move || {
+                connects.fetch_add(1, Ordering::SeqCst);
+                Ok(mock.clone())
+            },
+            Duration::from_millis(1),
+        )?
+    };
+    Ok((rpc, connects))
+}
+
+#[tokio::test]
+async fn test_reconnects_and_retries_after_connection_errors() -> Result<()> {
+    let mock = MockRpc::default();
+    let (rpc, connects) = reconnecting(&mock)?;
+    // The failed call plus two failed reconnect probes
+    mock.node().unreachable_calls = 3;
+
+    assert_eq!(rpc.get_block_count().await?, 100);
+    // Initial connect plus one rebuild per reconnect attempt
+    assert_eq!(connects.load(Ordering::SeqCst), 4);
+    assert_eq!(rpc.disconnected_since(), None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_other_errors_do_not_reconnect() -> Result<()> {
+    let mock = MockRpc::default();
+    let (rpc, connects) = reconnecting(&mock)?;
+
+    let err = rpc.get_mempool_entry(&dummy_txid(1)).await.unwrap_err();
+    assert!(!is_connection_error(&err));
+    assert_eq!(connects.load(Ordering::SeqCst), 1);
+    assert_eq!(mock.calls("getmempoolentry"), 1);
+    assert_eq!(rpc.disconnected_since(), None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gives_up_while_node_stays_down() -> Result<()> {
+    let mock = MockRpc::default();
+    let (rpc, _) = reconnecting(&mock)?;
+    mock.node().unreachable_calls = usize::MAX;
+
+    let err = rpc.get_raw_mempool().await.unwrap_err();
+    assert!(is_connection_error(&err));
+    assert!(rpc.disconnected_since().is_some());
+
+    // Once the node is back the next call recovers
+    mock.node().unreachable_calls = 0;
+    assert!(rpc.get_raw_mempool().await?.is_empty());
+    assert_eq!(rpc.disconnected_since(), None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fresh_connection_leaves_the_disconnect_behind() -> Result<()> {
+    let mock = MockRpc::default();
+    let (rpc, connects) = reconnecting(&mock)?;
+    mock.node().unreachable_calls = usize::MAX;
+    assert!(rpc.get_raw_mempool().await.is_err());
+    mock.node().unreachable_calls = 0;
+
+    let connected = connects.load(Ordering::SeqCst);
+    let fresh = rpc.fresh_connection()?;
+    assert_eq!(connects.load(Ordering::SeqCst), connected + 1);
+    assert_eq!(fresh.disconnected_since(), None);
+    assert!(rpc.disconnected_since().is_some());
+    assert_eq!(fresh.get_block_count().await?, 100);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reconnect_rereads_cookie_file() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join(".cookie");
+    std::fs::write(&path, "__cookie__:first\n")?;
+    let mock = MockRpc::default();
+    let passwords = Arc::new(Mutex::new(Vec::new()));
+    let rpc = {
+        let auth = Auth::CookieFile(path.clone());
+        let mock = mock.clone();
+        let passwords = passwords.clone();
+        ReconnectingRpc::new(
+            move || {
+                let (_, password) = auth.credentials()?;
+                passwords.lock().unwrap().push(password);
+                Ok(mock.clone())
+            },
+            Duration::from_millis(1),
+        )?
+    };
+
+    std::fs::write(&path, "__cookie__:second\n")?;
+    mock.node().unreachable_calls = 1;
+    assert_eq!(rpc.get_block_count().await?, 100);
+    assert_eq!(*passwords.lock().unwrap(), ["first", "second"]);
+    Ok(())
+}
+
+fn fast_retries(attempts: u32) -> RpcRetryPolicy {
+    RpcRetryPolicy {
+        attempts,
+        initial_backoff: Duration::from_millis(1),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_slow_calls_time_out_and_are_retried() -> Result<()> {
+    let mock = MockRpc::default();
+    mock.node()
+        .delays
+        .insert("getblockcount".to_string(), Duration::from_millis(500));
+    let policy = RpcRetryPolicy {
+        timeout: Duration::from_millis(50),
+        ..fast_retries(2)
+    };
+    let rpc = RetryingRpc::new(mock.clone(), policy);
+
+    let err = rpc.get_block_count().await.unwrap_err();
+    assert!(is_connection_error(&err));
+    assert!(format!("{:#}", err).contains("timed out"));
+    assert_eq!(mock.calls("getblockcount"), 2);
+
+    // Answering within the timeout
+    mock.node()
+        .delays
+        .insert("getblockcount".to_string(), Duration::from_millis(10));
+    assert_eq!(rpc.get_block_count().await?, 100);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rate_limit_wait_is_not_part_of_the_timeout() -> Result<()> {
+    let mock = MockRpc::default();
+    let policy = RpcRetryPolicy {
+        timeout: Duration::from_millis(50),
+        ..fast_retries(1)
+    };
+    // A token every 200ms after the first
+    let limiter = RateLimiter::new(5, 1);
+    let rpc = RetryingRpc::new(mock.clone(), policy).with_rate_limit(limiter.clone());
+
+    for _ in 0..2 {
+        assert_eq!(rpc.get_block_count().await?, 100);
+    }
+    assert!(limiter.waited() >= Duration::from_millis(100));
+    assert_eq!(mock.calls("getblockcount"), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_exhausted_reconnects_are_not_retried() -> Result<()> {
+    let mock = MockRpc::default();
+    let (reconnecting, _) = reconnecting(&mock)?;
+    let rpc = RetryingRpc::new(reconnecting, fast_retries(3));
+    mock.node().unreachable_calls = usize::MAX;
+
+    let err = rpc.get_raw_mempool().await.unwrap_err();
+    assert!(is_connection_error(&err));
+    // The reconnect attempts probe with getblockcount, the call itself went out once
+    assert_eq!(mock.calls("getrawmempool"), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_transient_errors_are_retried_within_budget() -> Result<()> {
+    let mock = MockRpc::default();
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    mock.add_to_mempool(&tx, 1_700_000_000, Amount::from_sat(1_000));
+    let rpc = RetryingRpc::new(mock.clone(), fast_retries(3));
+    mock.node().unreachable_calls = 2;
+
+    let entry = rpc.get_mempool_entry(&tx.compute_txid()).await?;
+    assert_eq!(entry.fee, Amount::from_sat(1_000));
+    assert_eq!(mock.calls("getmempoolentry"), 3);
+
+    // One failure more than the budget allows surfaces the error
+    mock.node().unreachable_calls = 3;
+    let err = rpc.get_mempool_entry(&tx.compute_txid()).await.unwrap_err();
+    assert!(is_connection_error(&err));
+    assert_eq!(mock.calls("getmempoolentry"), 6);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_permanent_errors_are_not_retried() -> Result<()> {
+    let mock = MockRpc::default();
+    let rpc = RetryingRpc::new(mock.clone(), fast_retries(3));
+
+    let err = rpc.get_mempool_entry(&dummy_txid(1)).await.unwrap_err();
+    assert!(!is_connection_error(&err));
+    assert_eq!(mock.calls("getmempoolentry"), 1);
+    Ok(())
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use std::time::SystemTime;
+
+use anyhow::Result;
+use bitcoin::{hashes::Hash, Amount, BlockHash, FeeRate, Transaction, Txid};
+use common::{dummy_tx, temp_db};
+use mempool_tracker::{
+    now,
+    rpc::MempoolStatus,
+    store::MempoolStore,
+    utils::{get_tx_key, RbfBump},
+};
+
+/// A tx spending a random outpoint, so runs against a shared server don't collide
+fn fresh_tx(output_value: u64) -> Transaction {
+    let prev_txid = Txid::from_byte_array(rand::random());
+    dummy_tx(&[(prev_txid, 0)], &[output_value])
+}
+
+/// The same inserts, replacement, mined and pruned txs and mempool snapshot against any
+/// backend, read back through the query API
+async fn exercise<S: MempoolStore>(store: S) -> Result<()> {
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(5);
+    let original = fresh_tx(10_000);
+    let mined = fresh_tx(20_000);
+    let pruned = fresh_tx(30_000);
+    for tx in [&original, &mined, &pruned] {
+        assert!(!store.tx_exists(tx).await?);
+        store
+            .insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(500), fee_rate)
+            .await?;
+        assert!(store.tx_exists(tx).await?);
+        assert!(store.is_pending(&tx.compute_txid()).await?);
+    }
+    assert_eq!(
+        store.get_stored_fee(&original).await?,
+        Some((Amount::from_sat(500), fee_rate))
+    );
+
+    let mut replacement = original.clone();
+    replacement.output[0].value = Amount::from_sat(9_000);
+    let bump = RbfBump::new(
+        store.get_stored_fee(&original).await?,
+        Amount::from_sat(1_500),
+        FeeRate::from_sat_per_vb_unchecked(15),
+    );
+    store.record_rbf(&replacement, &bump, None).await?;
+    store
+        .insert_mempool_tx(
+            replacement.clone(),
+            None,
+            None,
+            Amount::from_sat(1_500),
+            FeeRate::from_sat_per_vb_unchecked(15),
+        )
+        .await?;
+    assert_eq!(
+        store.get_stored_tx(&original).await?,
+        Some(replacement.clone())
+    );
+    let history = store.rbf_history_of(&original.compute_txid()).await?;
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].txid, replacement.compute_txid());
+    assert_eq!(history[0].prev_fee, Some(Amount::from_sat(500)));
+    let variants = store.get_txid_history(&get_tx_key(&original)?).await?;
+    assert_eq!(variants.len(), 2);
+
+    store.record_mined_tx(&mined, None).await?;
+    store.record_pruned_txs(vec![pruned.compute_txid()]).await?;
+    assert!(!store.is_pending(&mined.compute_txid()).await?);
+    assert!(!store.is_pending(&pruned.compute_txid()).await?);
+    let record = store
+        .get_tx_record(&mined.compute_txid())
+        .await?
+        .expect("mined tx stays tracked");
+    assert!(record.mined_at.is_some());
+    assert_eq!(record.pruned_at, None);
+    let record = store
+        .get_tx_record(&pruned.compute_txid())
+        .await?
+        .expect("pruned tx stays tracked");
+    assert!(record.pruned_at.is_some());
+    assert_eq!(record.mined_at, None);
+
+    let block_hash = BlockHash::from_byte_array(rand::random());
+    let recorded_at = now!();
+    store
+        .record_mempool_state(&MempoolStatus::default(), 850_000, block_hash, None)
+        .await?;
+    let snapshots = store
+        .mempool_snapshots(recorded_at, now!() + 1, 1_000, 0)
+        .await?;
+    assert!(snapshots
+        .iter()
+        .any(|snapshot| snapshot.block_hash == block_hash && snapshot.block_height == 850_000));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sqlite_store() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    exercise(db).await
+}
+
+/// Runs against the server of `MEMPOOL_TRACKER_TEST_POSTGRES_URL`, skipped when it is unset
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn test_postgres_store() -> Result<()> {
+    let Ok(url) = std::env::var("MEMPOOL_TRACKER_TEST_POSTGRES_URL") else {
+        eprintln!("MEMPOOL_TRACKER_TEST_POSTGRES_URL is unset, skipping");
+        return Ok(());
+    };
+    exercise(mempool_tracker::pg_store::PgStore::connect(&url).await?).await
+}
@@ -0,0 +1,401 @@
+mod common;
+
+use bitcoin::{
+    consensus::Encodable, hashes::Hash, Amount, PubkeyHash, ScriptBuf, Sequence, Transaction,
+    TxOut, WPubkeyHash, Witness,
+};
+use bitcoin_hashes::Sha256;
+use common::{dummy_coinbase, dummy_tx, dummy_txid};
+use mempool_tracker::utils::{
+    check_bip125_rules, classify_tx, coinbase_height, count_dust_outputs, count_relay_dust_outputs,
+    get_inputs_hash, get_inputs_hash_orderless, get_inputs_hash_tagged, is_truc, op_return_bytes,
+    rfc3339, rfc3339_millis, sighash_mask, SighashKind, TxType, BIP125_MAX_REPLACED,
+};
+
+/// Buffer-per-input implementation `get_inputs_hash` used to have
+fn buffered_inputs_hash(tx: &bitcoin::Transaction) -> String {
+    let mut engine = Sha256::engine();
+    for i in tx.input.iter() {
+        let mut writer = vec![];
+        i.consensus_encode(&mut writer).unwrap();
+        std::io::copy(&mut writer.as_slice(), &mut engine).unwrap();
+    }
+    hex::encode(Sha256::from_engine(engine).as_byte_array())
+}
+
+#[test]
+fn test_inputs_hash_matches_buffered_implementation() {
+    let tx = dummy_tx(
+        &[(dummy_txid(1), 0), (dummy_txid(2), 3), (dummy_txid(3), 7)],
+        &[1_000, 2_000],
+    );
+    assert_eq!(
+        get_inputs_hash(&tx.input).unwrap(),
+        buffered_inputs_hash(&tx)
+    );
+}
+
+#[test]
+fn test_tagged_inputs_hash_is_namespaced() {
+    let tx = dummy_tx(&[(dummy_txid(1), 0), (dummy_txid(2), 3)], &[1_000]);
+    let untagged = get_inputs_hash(&tx.input).unwrap();
+    let mainnet = get_inputs_hash_tagged(&tx.input, Some("mainnet")).unwrap();
+    let signet = get_inputs_hash_tagged(&tx.input, Some("signet")).unwrap();
+
+    assert_ne!(mainnet, signet);
+    assert_ne!(mainnet, untagged);
+    // No tag keeps the keys of existing databases
+    assert_eq!(get_inputs_hash_tagged(&tx.input, None).unwrap(), untagged);
+    assert_eq!(untagged, buffered_inputs_hash(&tx));
+}
+
+#[test]
+fn test_orderless_inputs_hash_ignores_input_order() {
+    let outpoints = [(dummy_txid(1), 0), (dummy_txid(1), 2), (dummy_txid(2), 1)];
+    let tx = dummy_tx(&outpoints, &[1_000]);
+    let reordered = dummy_tx(&[outpoints[2], outpoints[0], outpoints[1]], &[1_000]);
+
+    assert_eq!(
+        get_inputs_hash_orderless(&tx.input).unwrap(),
+        get_inputs_hash_orderless(&reordered.input).unwrap()
+    );
+    // The default stays order sensitive
+    assert_ne!(
+        get_inputs_hash(&tx.input).unwrap(),
+        get_inputs_hash(&reordered.input).unwrap()
+    );
+    // A legacy replacement signs its reordered inputs anew
+    let mut resigned = reordered.clone();
+    for (n, input) in resigned.input.iter_mut().enumerate() {
+        input.script_sig = ScriptBuf::from_bytes(vec![0x47; 10 + n]);
+        input.sequence = Sequence::ENABLE_RBF_NO_LOCKTIME;
+    }
+    assert_eq!(
+        get_inputs_hash_orderless(&tx.input).unwrap(),
+        get_inputs_hash_orderless(&resigned.input).unwrap()
+    );
+    // Different outpoints still hash differently
+    let other = dummy_tx(&[outpoints[0], outpoints[1]], &[1_000]);
+    assert_ne!(
+        get_inputs_hash_orderless(&tx.input).unwrap(),
+        get_inputs_hash_orderless(&other.input).unwrap()
+    );
+}
+
+fn p2wpkh_witness() -> Witness {
+    Witness::from_slice(&[vec![0x30; 72], vec![0x02; 33]])
+}
+
+fn p2wsh_witness() -> Witness {
+    // 2-of-2 multisig: dummy, two signatures, witness script
+    Witness::from_slice(&[vec![], vec![0x30; 72], vec![0x30; 71], vec![0x52; 71]])
+}
+
+fn taproot_keypath_witness() -> Witness {
+    Witness::from_slice(&[vec![0x11; 64]])
+}
+
+fn taproot_scriptpath_witness() -> Witness {
+    let mut control_block = vec![0xc0];
+    control_block.extend([0x22; 32 + 32]);
+    Witness::from_slice(&[vec![0x11; 64], vec![0x20; 34], control_block])
+}
+
+fn nested_script_sig(program: &[u8]) -> ScriptBuf {
+    let mut bytes = vec![program.len() as u8];
+    bytes.extend_from_slice(program);
+    ScriptBuf::from_bytes(bytes)
+}
+
+/// One input per (scriptSig, witness) pair
+fn tx_with_inputs(inputs: Vec<(ScriptBuf, Witness)>) -> Transaction {
+    let outpoints: Vec<_> = (0..inputs.len() as u32)
+        .map(|i| (dummy_txid(9), i))
+        .collect();
+    let mut tx = dummy_tx(&outpoints, &[1_000]);
+    for (input, (script_sig, witness)) in tx.input.iter_mut().zip(inputs) {
+        input.script_sig = script_sig;
+        input.witness = witness;
+    }
+    tx
+}
+
+#[test]
+fn test_classify_legacy() {
+    let script_sig = ScriptBuf::from_bytes(vec![0x48; 107]);
+    let tx = tx_with_inputs(vec![(script_sig, Witness::new())]);
+    assert_eq!(classify_tx(&tx), TxType::Legacy);
+}
+
+#[test]
+fn test_classify_native_segwit_v0() {
+    let tx = tx_with_inputs(vec![
+        (ScriptBuf::new(), p2wpkh_witness()),
+        (ScriptBuf::new(), p2wsh_witness()),
+    ]);
+    assert_eq!(classify_tx(&tx), TxType::SegwitV0);
+}
+
+#[test]
+fn test_classify_nested_segwit() {
+    let mut p2wpkh_program = vec![0x00, 0x14];
+    p2wpkh_program.extend([0xab; 20]);
+    let mut p2wsh_program = vec![0x00, 0x20];
+    p2wsh_program.extend([0xcd; 32]);
+
+    let tx = tx_with_inputs(vec![
+        (nested_script_sig(&p2wpkh_program), p2wpkh_witness()),
+        (nested_script_sig(&p2wsh_program), p2wsh_witness()),
+    ]);
+    assert_eq!(classify_tx(&tx), TxType::P2shSegwit);
+
+    // A push that isn't a v0 witness program is not nested segwit
+    let mut bogus_program = vec![0x01, 0x14];
+    bogus_program.extend([0xab; 20]);
+    let tx = tx_with_inputs(vec![(nested_script_sig(&bogus_program), p2wpkh_witness())]);
+    assert_eq!(classify_tx(&tx), TxType::Legacy);
+}
+
+#[test]
+fn test_classify_taproot() {
+    let tx = tx_with_inputs(vec![(ScriptBuf::new(), taproot_keypath_witness())]);
+    assert_eq!(classify_tx(&tx), TxType::TaprootKeyPath);
+
+    // Keypath spend with a sighash byte and an annex
+    let tx = tx_with_inputs(vec![(
+        ScriptBuf::new(),
+        Witness::from_slice(&[vec![0x11; 65], vec![0x50, 0x01]]),
+    )]);
+    assert_eq!(classify_tx(&tx), TxType::TaprootKeyPath);
+
+    let tx = tx_with_inputs(vec![(ScriptBuf::new(), taproot_scriptpath_witness())]);
+    assert_eq!(classify_tx(&tx), TxType::TaprootScriptPath);
+}
+
+#[test]
+fn test_classify_mixed() {
+    let tx = tx_with_inputs(vec![
+        (ScriptBuf::new(), p2wpkh_witness()),
+        (ScriptBuf::new(), taproot_keypath_witness()),
+        (ScriptBuf::new(), p2wpkh_witness()),
+    ]);
+    assert_eq!(classify_tx(&tx), TxType::Mixed);
+}
+
+#[test]
+fn test_classify_coinbase_by_outputs() {
+    let mut coinbase = dummy_coinbase(100, 50_000);
+    coinbase.output = vec![TxOut {
+        value: bitcoin::Amount::from_sat(50_000),
+        script_pubkey: ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::from_byte_array([1; 20])),
+    }];
+    assert_eq!(classify_tx(&coinbase), TxType::SegwitV0);
+}
+
+#[test]
+fn test_tx_type_round_trips_through_str() {
+    for tx_type in [
+        TxType::Legacy,
+        TxType::P2shSegwit,
+        TxType::SegwitV0,
+        TxType::TaprootKeyPath,
+        TxType::TaprootScriptPath,
+        TxType::Mixed,
+    ] {
+        assert_eq!(tx_type.as_str().parse::<TxType>().unwrap(), tx_type);
+    }
+}
+
+#[test]
+fn test_op_return_bytes_sums_all_outputs() {
+    let mut tx = dummy_tx(&[(dummy_txid(1), 0)], &[1_000, 0, 0]);
+    assert_eq!(op_return_bytes(&tx), (0, 0));
+
+    // OP_RETURN PUSH4 <4 bytes> and OP_RETURN PUSH2 <2 bytes>
+    tx.output[1].script_pubkey = ScriptBuf::from_bytes(vec![0x6a, 0x04, 1, 2, 3, 4]);
+    tx.output[2].script_pubkey = ScriptBuf::from_bytes(vec![0x6a, 0x02, 5, 6]);
+    assert_eq!(op_return_bytes(&tx), (2, 5 + 3));
+}
+
+/// p2wpkh outputs of 293 and 294 sats, p2pkh of 545 and 546, and an empty OP_RETURN
+fn tx_around_dust_limits() -> Transaction {
+    let mut tx = dummy_tx(&[(dummy_txid(1), 0)], &[293, 294, 545, 546, 0]);
+    let p2wpkh = ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros());
+    let p2pkh = ScriptBuf::new_p2pkh(&PubkeyHash::all_zeros());
+    tx.output[0].script_pubkey = p2wpkh.clone();
+    tx.output[1].script_pubkey = p2wpkh;
+    tx.output[2].script_pubkey = p2pkh.clone();
+    tx.output[3].script_pubkey = p2pkh;
+    tx.output[4].script_pubkey = ScriptBuf::from_bytes(vec![0x6a]);
+    tx
+}
+
+#[test]
+fn test_relay_dust_depends_on_script_type() {
+    // 293 < 294 for p2wpkh, 545 < 546 for p2pkh
+    assert_eq!(count_relay_dust_outputs(&tx_around_dust_limits()), 2);
+}
+
+#[test]
+fn test_flat_dust_threshold() {
+    let tx = tx_around_dust_limits();
+    assert_eq!(count_dust_outputs(&tx, Amount::from_sat(294)), 1);
+    assert_eq!(count_dust_outputs(&tx, Amount::from_sat(1_000)), 4);
+    assert_eq!(count_dust_outputs(&tx, Amount::ZERO), 0);
+}
+
+#[test]
+fn test_is_truc_reads_signed_version() {
+    let mut tx = dummy_tx(&[(dummy_txid(1), 0)], &[1_000]);
+    assert!(!is_truc(&tx));
+    tx.version = bitcoin::transaction::Version(3);
+    assert!(is_truc(&tx));
+
+    // Only the low byte of 0xfffffffd is 3, as a signed int it's -3
+    let mut bytes = vec![];
+    tx.consensus_encode(&mut bytes).unwrap();
+    bytes[..4].copy_from_slice(&[0xfd, 0xff, 0xff, 0xff]);
+    let negative: Transaction = bitcoin::consensus::deserialize(&bytes).unwrap();
+    assert_eq!(negative.version.0, -3);
+    assert!(!is_truc(&negative));
+}
+
+/// DER shaped ECDSA signature with a trailing sighash byte
+fn ecdsa_sig(sighash: u8) -> Vec<u8> {
+    let mut sig = vec![0x30, 0x44];
+    sig.extend([0x02; 68]);
+    sig.push(sighash);
+    sig
+}
+
+fn mask(kinds: &[SighashKind]) -> u32 {
+    kinds.iter().fold(0, |mask, kind| mask | kind.bit())
+}
+
+#[test]
+fn test_sighash_mask_across_input_types() {
+    let mut tx = dummy_tx(
+        &[
+            (dummy_txid(1), 0),
+            (dummy_txid(2), 0),
+            (dummy_txid(3), 0),
+            (dummy_txid(4), 0),
+        ],
+        &[1_000],
+    );
+    // Legacy p2pkh
+    let sig = bitcoin::script::PushBytesBuf::try_from(ecdsa_sig(0x83)).unwrap();
+    tx.input[0].script_sig = bitcoin::script::Builder::new()
+        .push_slice(sig)
+        .push_slice([0x02; 33])
+        .into_script();
+    // Segwit v0 p2wpkh
+    tx.input[1].witness = Witness::from_slice(&[ecdsa_sig(0x01), vec![0x02; 33]]);
+    // Taproot key path without and with a sighash byte
+    tx.input[2].witness = Witness::from_slice(&[vec![0x11; 64]]);
+    let mut schnorr = vec![0x11; 64];
+    schnorr.push(0x82);
+    tx.input[3].witness = Witness::from_slice(&[schnorr]);
+    assert_eq!(
+        sighash_mask(&tx),
+        mask(&[
+            SighashKind::SingleAnyoneCanPay,
+            SighashKind::All,
+            SighashKind::Default,
+            SighashKind::NoneAnyoneCanPay,
+        ])
+    );
+}
+
+#[test]
+fn test_sighash_mask_counts_unparseable_inputs_as_unknown() {
+    let mut tx = dummy_tx(&[(dummy_txid(1), 0), (dummy_txid(2), 0)], &[1_000]);
+    // No signature-shaped element
+    tx.input[0].witness = Witness::from_slice(&[vec![0x01; 10], vec![0x02; 33]]);
+    // Non-standard sighash byte
+    tx.input[1].witness = Witness::from_slice(&[ecdsa_sig(0x04), vec![0x02; 33]]);
+    assert_eq!(sighash_mask(&tx), SighashKind::Unknown.bit());
+    assert_eq!(sighash_mask(&dummy_coinbase(100, 50_000)), 0);
+}
+
+#[test]
+fn test_bip125_valid_replacement() {
+    let original = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let replacement = dummy_tx(&[(dummy_txid(1), 0)], &[8_500]);
+    let verdict = check_bip125_rules(&original, 1_000, &replacement, 1_500);
+    assert!(verdict.signals_rbf);
+    assert!(verdict.new_inputs.is_empty());
+    assert!(verdict.higher_absolute_fee);
+    assert!(verdict.pays_for_bandwidth);
+    assert!(verdict.higher_fee_rate);
+    assert!(verdict.is_valid(), "{:?}", verdict.violations());
+}
+
+#[test]
+fn test_bip125_lower_absolute_fee_breaks_rule_3() {
+    let original = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let replacement = dummy_tx(&[(dummy_txid(1), 0)], &[9_100]);
+    let verdict = check_bip125_rules(&original, 1_000, &replacement, 900);
+    assert!(!verdict.higher_absolute_fee);
+    assert!(!verdict.pays_for_bandwidth);
+    assert_eq!(
+        verdict.violations(),
+        vec!["rule3_absolute_fee", "rule4_relay_fee", "rule6_fee_rate"]
+    );
+}
+
+#[test]
+fn test_bip125_too_many_replaced_breaks_rule_5() {
+    let original = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let replacement = dummy_tx(&[(dummy_txid(1), 0)], &[8_500]);
+    let verdict = check_bip125_rules(&original, 1_000, &replacement, 1_500);
+    assert!(verdict
+        .clone()
+        .with_replaced_count(BIP125_MAX_REPLACED)
+        .is_valid());
+    assert_eq!(
+        verdict
+            .with_replaced_count(BIP125_MAX_REPLACED + 1)
+            .violations(),
+        vec!["rule5_replaced_count"]
+    );
+}
+
+#[test]
+fn test_bip125_new_unconfirmed_input_breaks_rule_2() {
+    let original = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let replacement = dummy_tx(&[(dummy_txid(1), 0), (dummy_txid(2), 0)], &[18_000]);
+    let verdict = check_bip125_rules(&original, 1_000, &replacement, 2_000);
+    assert_eq!(
+        verdict.new_inputs,
+        vec![bitcoin::OutPoint::new(dummy_txid(2), 0)]
+    );
+    assert_eq!(verdict.violations(), vec!["rule2_new_unconfirmed_inputs"]);
+    // A confirmed new input is allowed
+    assert!(verdict.retain_unconfirmed_inputs(|_| false).is_valid());
+}
+
+#[test]
+fn test_rfc3339_formats_utc_timestamps() {
+    assert_eq!(rfc3339(0), "1970-01-01T00:00:00Z");
+    // Leap day
+    assert_eq!(rfc3339(951_782_400), "2000-02-29T00:00:00Z");
+    assert_eq!(rfc3339(1_706_702_399), "2024-01-31T11:59:59Z");
+    assert_eq!(
+        rfc3339_millis(1_706_702_399_005),
+        "2024-01-31T11:59:59.005Z"
+    );
+}
+
+#[test]
+fn test_coinbase_height_follows_bip34() {
+    assert_eq!(coinbase_height(&dummy_coinbase(840_000, 0)), Some(840_000));
+    assert_eq!(coinbase_height(&dummy_coinbase(128, 0)), Some(128));
+    // OP_5 rather than a byte push
+    assert_eq!(coinbase_height(&dummy_coinbase(5, 0)), Some(5));
+    assert_eq!(coinbase_height(&dummy_coinbase(-1, 0)), None);
+    assert_eq!(
+        coinbase_height(&dummy_tx(&[(dummy_txid(1), 0)], &[1_000])),
+        None
+    );
+}
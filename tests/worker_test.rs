@@ -0,0 +1,1785 @@
+mod common;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_channel::bounded;
+use bitcoin::{consensus::Encodable, hashes::Hash, Amount, FeeRate, Transaction};
+use common::{dummy_block, dummy_coinbase, dummy_tx, dummy_txid, mock_rpc::MockRpc, temp_db};
+use mempool_tracker::database::{Database, MempoolChurn, DEFAULT_NODE_ID};
+use mempool_tracker::{
+    events::{EventPublisher, MempoolEvent},
+    filter::Filter,
+    health::Health,
+    rpc::BitcoinRpc,
+    store::{DryRunStore, MempoolStore},
+    tip::TipTracker,
+    worker::{
+        next_task, BlockRecorder, DryRunSummary, MempoolPoll, MinedDetection, ProcessOutcome,
+        Queued, SequenceEvent, Task, TaskContext, WorkerStats,
+    },
+};
+
+#[tokio::test]
+async fn test_control_tasks_are_not_starved_by_raw_txs() -> Result<()> {
+    let (control_tx, control_rx) = bounded(100);
+    let (tasks_tx, tasks_rx) = bounded(100_000);
+    for _ in 0..10_000 {
+        tasks_tx.send(Task::RawTx(vec![]).into()).await?;
+    }
+    control_tx.send(Task::MempoolState.into()).await?;
+
+    let first_tasks: Vec<Task> = {
+        let mut tasks = vec![];
+        for _ in 0..3 {
+            tasks.push(next_task(&control_rx, &tasks_rx).await.unwrap().task);
+        }
+        tasks
+    };
+    assert!(first_tasks
+        .iter()
+        .any(|task| matches!(task, Task::MempoolState)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_next_task_ends_when_channels_close() -> Result<()> {
+    let (control_tx, control_rx) = bounded::<Queued>(1);
+    let (tasks_tx, tasks_rx) = bounded::<Queued>(1);
+    tasks_tx.send(Task::PruneCheck.into()).await?;
+    control_tx.close();
+    tasks_tx.close();
+
+    assert!(matches!(
+        next_task(&control_rx, &tasks_rx).await,
+        Some(Queued {
+            task: Task::PruneCheck,
+            ..
+        })
+    ));
+    assert!(next_task(&control_rx, &tasks_rx).await.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_malformed_raw_tx_does_not_stop_worker() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let (control_tx, control_rx) = bounded(1);
+    let (tasks_tx, tasks_rx) = bounded(10);
+    let mut worker = TaskContext::new(
+        MockRpc::default(),
+        db.clone(),
+        EventPublisher::disabled(),
+        Filter::default(),
+        control_rx,
+        tasks_rx,
+        TipTracker::new(Duration::from_secs(1800)),
+    );
+
+    let coinbase = dummy_coinbase(500, 50_000);
+    let mut coinbase_bytes = vec![];
+    coinbase.consensus_encode(&mut coinbase_bytes)?;
+    tasks_tx
+        .send(Task::RawTx(vec![0xde, 0xad, 0xbe, 0xef]).into())
+        .await?;
+    tasks_tx.send(Task::RawTx(coinbase_bytes).into()).await?;
+    control_tx.close();
+    tasks_tx.close();
+
+    worker.run().await?;
+
+    assert!(db.tx_exists(&coinbase)?);
+    let (payload, error): (Vec<u8>, String) =
+        conn.query_row("SELECT payload, error FROM quarantine", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    assert_eq!(payload, vec![0xde, 0xad, 0xbe, 0xef]);
+    assert!(!error.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_worker_stats_count_tasks_and_errors() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let stats = worker.stats();
+    assert_eq!((stats.processed(), stats.errors()), (0, 0));
+    assert_eq!(stats.last_task_at(), None);
+
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    rpc.add_to_mempool(&tx, 1_700_000_000, Amount::from_sat(1_000));
+    worker.process_task(raw(&tx)).await?;
+    worker.process_task(Task::PruneCheck).await?;
+    assert_eq!((stats.processed(), stats.errors()), (2, 0));
+    assert!(stats.last_task_at().is_some());
+    assert_eq!(stats.last_error_at(), None);
+
+    rpc.node().unreachable_calls = 1;
+    assert!(worker.process_task(Task::PruneCheck).await.is_err());
+    assert_eq!((stats.processed(), stats.errors()), (3, 1));
+    assert!(stats.last_error_at().is_some());
+
+    let other = idle_worker(&rpc, &db);
+    other.process_task(Task::PruneCheck).await?;
+    let total = WorkerStats::aggregate([&stats, &other.stats()]);
+    assert_eq!((total.processed(), total.errors()), (4, 1));
+    assert_eq!(total.last_error_at(), stats.last_error_at());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_worker_records_queue_latency() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let (control_tx, control_rx) = bounded(1);
+    let (tasks_tx, tasks_rx) = bounded(10);
+    let health = Health::default();
+    let mut worker = TaskContext::new(
+        MockRpc::default(),
+        db,
+        EventPublisher::disabled(),
+        Filter::default(),
+        control_rx,
+        tasks_rx,
+        TipTracker::new(Duration::from_secs(1800)),
+    )
+    .with_health(health.clone());
+    tasks_tx.send(Task::RawTx(vec![0xde, 0xad]).into()).await?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    control_tx.close();
+    tasks_tx.close();
+
+    worker.run().await?;
+
+    assert!(health.queue_latency() >= Duration::from_millis(50));
+    assert_eq!(health.queue_depth(), 0);
+    Ok(())
+}
+
+/// Run a worker over `txs` announced as raw txs until the queue drains
+async fn process_raw_txs(rpc: &MockRpc, db: &Database, txs: &[&Transaction]) -> Result<()> {
+    let (control_tx, control_rx) = bounded(1);
+    let (tasks_tx, tasks_rx) = bounded(txs.len().max(1));
+    for tx in txs {
+        let mut bytes = vec![];
+        tx.consensus_encode(&mut bytes)?;
+        tasks_tx.send(Task::RawTx(bytes).into()).await?;
+    }
+    control_tx.close();
+    tasks_tx.close();
+    let mut worker = TaskContext::new(
+        rpc.clone(),
+        db.clone(),
+        EventPublisher::disabled(),
+        Filter::default(),
+        control_rx,
+        tasks_rx,
+        TipTracker::new(Duration::from_secs(1800)),
+    );
+    worker.run().await
+}
+
+#[tokio::test]
+async fn test_unmined_tx_uses_mempool_entry_only() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    rpc.add_to_mempool(&tx, 1_700_000_000, Amount::from_sat(1_000));
+
+    process_raw_txs(&rpc, &db, &[&tx]).await?;
+
+    let (fee, _) = db.get_stored_fee(&tx)?.expect("tx stored");
+    assert_eq!(fee, Amount::from_sat(1_000));
+    let node_seen_at: Option<u64> = conn.query_row(
+        "SELECT node_seen_at FROM transactions WHERE tx_id = ?1",
+        [tx.compute_txid().to_string()],
+        |row| row.get(0),
+    )?;
+    assert_eq!(node_seen_at, Some(1_700_000_000_000));
+    // Neither the confirmation lookup nor the prevout fetches were needed
+    assert_eq!(rpc.calls("getrawtransactioninfo"), 0);
+    assert_eq!(rpc.calls("getrawtransaction"), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mined_tx_falls_back_to_confirmations() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let parent = dummy_tx(&[(dummy_txid(1), 0)], &[10_000]);
+    let tx = dummy_tx(&[(parent.compute_txid(), 0)], &[9_000]);
+    let fee = Amount::from_sat(1_000);
+    db.insert_mempool_tx(
+        tx.clone(),
+        None,
+        None,
+        fee,
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+    rpc.add_tx(&parent);
+    rpc.add_tx(&tx);
+    rpc.confirm(&tx.compute_txid(), 1);
+
+    process_raw_txs(&rpc, &db, &[&tx]).await?;
+
+    let mined_at: Option<u64> = conn.query_row(
+        "SELECT mined_at FROM transactions WHERE tx_id = ?1",
+        [tx.compute_txid().to_string()],
+        |row| row.get(0),
+    )?;
+    assert!(mined_at.is_some());
+    assert_eq!(rpc.calls("getrawtransactioninfo"), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mined_tx_stores_confirming_block_hash() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let parent = dummy_tx(&[(dummy_txid(1), 0)], &[10_000]);
+    let tx = dummy_tx(&[(parent.compute_txid(), 0)], &[9_000]);
+    db.insert_mempool_tx(
+        tx.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+    rpc.add_tx(&parent);
+    let block = dummy_block(
+        1_700_000_000,
+        vec![dummy_coinbase(101, 312_500_000), tx.clone()],
+    );
+    rpc.add_block(101, block.clone());
+    rpc.confirm(&tx.compute_txid(), 1);
+
+    process_raw_txs(&rpc, &db, &[&tx]).await?;
+
+    let block_hash: Option<Vec<u8>> = conn.query_row(
+        "SELECT block_hash FROM transactions WHERE tx_id = ?1",
+        [tx.compute_txid().to_string()],
+        |row| row.get(0),
+    )?;
+    assert_eq!(
+        block_hash,
+        Some(block.block_hash().to_byte_array().to_vec())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replacement_uses_mempool_entry_fee() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let original = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let replacement = dummy_tx(&[(dummy_txid(1), 0)], &[8_500]);
+    db.insert_mempool_tx(
+        original.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+    rpc.add_to_mempool(&replacement, 1_700_000_000, Amount::from_sat(1_500));
+    rpc.node()
+        .reject_reasons
+        .insert(original.compute_txid(), "txn-mempool-conflict".to_string());
+
+    process_raw_txs(&rpc, &db, &[&replacement]).await?;
+
+    let (fee_total, prev_fee, reject_reason): (u64, Option<u64>, Option<String>) = conn.query_row(
+        "SELECT fee_total, prev_fee, reject_reason FROM rbf_history WHERE tx_id = ?1",
+        [replacement.compute_txid().to_string()],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    assert_eq!(fee_total, 1_500);
+    assert_eq!(prev_fee, Some(1_000));
+    assert_eq!(reject_reason.as_deref(), Some("txn-mempool-conflict"));
+    assert_eq!(rpc.calls("getrawtransactioninfo"), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replacement_records_bip125_violations() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let original = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let valid = dummy_tx(&[(dummy_txid(1), 0)], &[8_500]);
+    // Pays less than the replacement it replaces, only a full-RBF node would take it
+    let underpaying = dummy_tx(&[(dummy_txid(1), 0)], &[8_800]);
+    rpc.add_to_mempool(&original, 1_700_000_000, Amount::from_sat(1_000));
+    process_raw_txs(&rpc, &db, &[&original]).await?;
+
+    rpc.node().mempool.remove(&original.compute_txid());
+    rpc.add_to_mempool(&valid, 1_700_000_100, Amount::from_sat(1_500));
+    rpc.node()
+        .reject_reasons
+        .insert(original.compute_txid(), "txn-mempool-conflict".to_string());
+    process_raw_txs(&rpc, &db, &[&valid]).await?;
+
+    rpc.node().mempool.remove(&valid.compute_txid());
+    rpc.add_to_mempool(&underpaying, 1_700_000_200, Amount::from_sat(1_200));
+    rpc.node()
+        .reject_reasons
+        .insert(valid.compute_txid(), "txn-mempool-conflict".to_string());
+    process_raw_txs(&rpc, &db, &[&underpaying]).await?;
+
+    let violations = |tx: &Transaction| -> Result<Option<String>> {
+        Ok(conn.query_row(
+            "SELECT bip125_violations FROM rbf_history WHERE tx_id = ?1",
+            [tx.compute_txid().to_string()],
+            |row| row.get(0),
+        )?)
+    };
+    assert_eq!(violations(&valid)?.as_deref(), Some(""));
+    assert_eq!(
+        violations(&underpaying)?.as_deref(),
+        Some("rule3_absolute_fee,rule4_relay_fee,rule6_fee_rate")
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mempool_entry_ancestry_is_recorded() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let parent = dummy_tx(&[(dummy_txid(1), 0)], &[10_000]);
+    let child = dummy_tx(&[(parent.compute_txid(), 0)], &[9_000]);
+    rpc.add_to_mempool(&parent, 1_700_000_000, Amount::from_sat(1_000));
+    rpc.add_to_mempool(&child, 1_700_000_001, Amount::from_sat(1_000));
+
+    process_raw_txs(&rpc, &db, &[&parent, &child]).await?;
+
+    let links: Vec<(String, String, String)> = conn
+        .prepare("SELECT txid, related_txid, direction FROM tx_links ORDER BY direction")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+    let parent_txid = parent.compute_txid().to_string();
+    let child_txid = child.compute_txid().to_string();
+    assert!(links.contains(&(
+        child_txid.clone(),
+        parent_txid.clone(),
+        "parent".to_string()
+    )));
+    let parent_edges: Vec<String> = conn
+        .prepare("SELECT parent_txid FROM tx_parents")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    assert_eq!(parent_edges, vec![parent_txid]);
+    assert_eq!(
+        db.get_children(&parent.compute_txid())?,
+        vec![child.compute_txid()]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replacement_records_fee_bump_pct() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let original = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let replacement = dummy_tx(&[(dummy_txid(1), 0)], &[8_500]);
+    rpc.add_to_mempool(&original, 1_700_000_000, Amount::from_sat(1_000));
+    process_raw_txs(&rpc, &db, &[&original]).await?;
+
+    rpc.node().mempool.remove(&original.compute_txid());
+    rpc.add_to_mempool(&replacement, 1_700_000_100, Amount::from_sat(1_500));
+    rpc.node()
+        .reject_reasons
+        .insert(original.compute_txid(), "txn-mempool-conflict".to_string());
+    process_raw_txs(&rpc, &db, &[&replacement]).await?;
+
+    let fee_bump_pct: Option<f64> = conn.query_row(
+        "SELECT fee_bump_pct FROM rbf_history WHERE tx_id = ?1",
+        [replacement.compute_txid().to_string()],
+        |row| row.get(0),
+    )?;
+    assert_eq!(fee_bump_pct, Some(50.0));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replacement_gone_from_node_is_recorded_without_fee() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let original = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let replacement = dummy_tx(&[(dummy_txid(1), 0)], &[8_500]);
+    db.insert_mempool_tx(
+        original.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+
+    // Replaced again or evicted before the worker ran, the node knows neither the
+    // replacement nor its prevouts
+    assert_eq!(
+        worker.process_task(raw(&replacement)).await?,
+        ProcessOutcome::Rbf
+    );
+    let replacement_txid = replacement.compute_txid().to_string();
+    let history = |conn: &rusqlite::Connection| -> Result<(u64, Option<u64>, bool)> {
+        Ok(conn.query_row(
+            "SELECT fee_total, prev_fee, fee_known FROM rbf_history WHERE tx_id = ?1",
+            [&replacement_txid],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?)
+    };
+    assert_eq!(history(&conn)?, (0, Some(1_000), false));
+    let stored_txid: String = conn.query_row(
+        "SELECT tx_id FROM transactions WHERE inputs_hash IN (SELECT inputs_hash FROM rbf_history)",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(stored_txid, replacement_txid);
+    assert!(!db.fee_known(&replacement)?);
+    assert_eq!(db.get_stored_fee(&replacement)?, None);
+
+    // Back in the mempool by the next rescan, which fills the fee in
+    rpc.add_to_mempool(&replacement, 1_700_000_100, Amount::from_sat(1_500));
+    worker.process_task(Task::Rescan).await?;
+    assert_eq!(history(&conn)?, (1_500, Some(1_000), true));
+    let fee_bump_pct: Option<f64> = conn.query_row(
+        "SELECT fee_bump_pct FROM rbf_history WHERE tx_id = ?1",
+        [&replacement_txid],
+        |row| row.get(0),
+    )?;
+    assert_eq!(fee_bump_pct, Some(50.0));
+    assert_eq!(
+        db.get_stored_fee(&replacement)?.map(|(fee, _)| fee),
+        Some(Amount::from_sat(1_500))
+    );
+    Ok(())
+}
+
+/// Worker with idle channels, for driving `process_task` directly
+fn idle_worker<S: MempoolStore>(rpc: &MockRpc, db: &S) -> TaskContext<MockRpc, S> {
+    let (_, control_rx) = bounded(1);
+    let (_, tasks_rx) = bounded(1);
+    TaskContext::new(
+        rpc.clone(),
+        db.clone(),
+        EventPublisher::disabled(),
+        Filter::default(),
+        control_rx,
+        tasks_rx,
+        TipTracker::new(Duration::from_secs(1800)),
+    )
+}
+
+fn raw(tx: &Transaction) -> Task {
+    let mut bytes = vec![];
+    tx.consensus_encode(&mut bytes).expect("encode");
+    Task::RawTx(bytes)
+}
+
+#[tokio::test]
+async fn test_dry_run_counts_what_would_be_written() -> Result<()> {
+    let (dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let earlier = dummy_tx(&[(dummy_txid(3), 0)], &[9_000]);
+    let original = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let replacement = dummy_tx(&[(dummy_txid(1), 0)], &[8_500]);
+    let fresh = dummy_tx(&[(dummy_txid(2), 0)], &[9_000]);
+    db.insert_mempool_tx(
+        earlier.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+    for tx in [&original, &fresh] {
+        rpc.add_to_mempool(tx, 1_700_000_000, Amount::from_sat(1_000));
+    }
+
+    let store = DryRunStore::new(Database::open_read_only(
+        dir.path().join("mempool_tracker_test.db").to_str().unwrap(),
+    )?)?;
+    assert!(store.tx_exists(&earlier).await?);
+    let summary = DryRunSummary::default();
+    let worker = idle_worker(&rpc, &store).with_dry_run(summary.clone());
+    for tx in [&original, &fresh] {
+        assert_eq!(
+            worker.process_task(raw(tx)).await?,
+            ProcessOutcome::Inserted
+        );
+    }
+    // Both the replaced and the mined tx were first seen during the run
+    rpc.add_to_mempool(&replacement, 1_700_000_100, Amount::from_sat(1_500));
+    rpc.node()
+        .reject_reasons
+        .insert(original.compute_txid(), "txn-mempool-conflict".to_string());
+    assert_eq!(
+        worker.process_task(raw(&replacement)).await?,
+        ProcessOutcome::Rbf
+    );
+    let block = dummy_block(
+        1_700_000_600,
+        vec![dummy_coinbase(900, 50_000), fresh.clone()],
+    );
+    rpc.add_block(900, block.clone());
+    let ProcessOutcome::NewBlock { mined, .. } = worker
+        .process_task(Task::NewBlock(block.block_hash()))
+        .await?
+    else {
+        panic!("not a new block outcome");
+    };
+    assert_eq!(mined, 1);
+
+    assert_eq!(
+        (summary.inserted(), summary.mined(), summary.rbf()),
+        (2, 1, 1)
+    );
+    // The store keeps what the run did
+    assert!(store.tx_exists(&fresh).await?);
+    assert!(!store.is_pending(&fresh.compute_txid()).await?);
+    assert_eq!(
+        store.get_stored_tx(&original).await?,
+        Some(replacement.clone())
+    );
+    // The file only has what was there before the run
+    let stored: u64 = conn.query_row("SELECT COUNT(*) FROM transactions", [], |row| row.get(0))?;
+    assert_eq!(stored, 1);
+    let replacements: u64 =
+        conn.query_row("SELECT COUNT(*) FROM rbf_history", [], |row| row.get(0))?;
+    assert_eq!(replacements, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_read_only_worker_leaves_the_file_untouched() -> Result<()> {
+    let (dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let original = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let replacement = dummy_tx(&[(dummy_txid(1), 0)], &[8_500]);
+    let fresh = dummy_tx(&[(dummy_txid(2), 0)], &[9_000]);
+    for tx in [&original, &fresh] {
+        rpc.add_to_mempool(tx, 1_700_000_000, Amount::from_sat(1_000));
+    }
+    assert_eq!(
+        idle_worker(&rpc, &db).process_task(raw(&original)).await?,
+        ProcessOutcome::Inserted
+    );
+
+    let read_only =
+        Database::open_read_only(dir.path().join("mempool_tracker_test.db").to_str().unwrap())?;
+    let worker = idle_worker(&rpc, &read_only);
+    // A new tx, a replacement and a block mining the stored tx
+    assert_eq!(
+        worker.process_task(raw(&fresh)).await?,
+        ProcessOutcome::Inserted
+    );
+    rpc.add_to_mempool(&replacement, 1_700_000_100, Amount::from_sat(1_500));
+    rpc.node()
+        .reject_reasons
+        .insert(original.compute_txid(), "txn-mempool-conflict".to_string());
+    assert_eq!(
+        worker.process_task(raw(&replacement)).await?,
+        ProcessOutcome::Rbf
+    );
+    let block = dummy_block(
+        1_700_000_600,
+        vec![dummy_coinbase(101, 50_000), original.clone()],
+    );
+    rpc.add_block(101, block.clone());
+    let ProcessOutcome::NewBlock { mined, .. } = worker
+        .process_task(Task::NewBlock(block.block_hash()))
+        .await?
+    else {
+        panic!("not a new block outcome");
+    };
+    assert_eq!(mined, 1);
+
+    let count = |table: &str| -> Result<u64> {
+        Ok(
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })?,
+        )
+    };
+    assert_eq!(count("transactions")?, 1);
+    assert_eq!(count("rbf_history")?, 0);
+    assert_eq!(count("blocks")?, 0);
+    let (tx_id, mined_at): (String, Option<u64>) =
+        conn.query_row("SELECT tx_id, mined_at FROM transactions", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    assert_eq!(tx_id, original.compute_txid().to_string());
+    assert_eq!(mined_at, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replayed_raw_tx_is_found_when_captured() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    rpc.add_to_mempool(&tx, 1_700_000_000, Amount::from_sat(1_000));
+    let Task::RawTx(raw_tx) = raw(&tx) else {
+        unreachable!()
+    };
+
+    let outcome = idle_worker(&rpc, &db)
+        .process_task(Task::ReplayedRawTx {
+            raw_tx,
+            found_at: 1_600_000_000_250,
+        })
+        .await?;
+    assert_eq!(outcome, ProcessOutcome::Inserted);
+    let found_at: u64 = conn.query_row(
+        "SELECT found_at FROM transactions WHERE tx_id = ?1",
+        [tx.compute_txid().to_string()],
+        |row| row.get(0),
+    )?;
+    assert_eq!(found_at, 1_600_000_000_250);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_raw_txs_are_attributed_to_the_announcing_node() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    rpc.add_to_mempool(&tx, 1_700_000_000, Amount::from_sat(1_000));
+
+    idle_worker(&rpc, &db).process_task(raw(&tx)).await?;
+    idle_worker(&rpc, &db)
+        .with_node_id("dc2")
+        .process_task(raw(&tx))
+        .await?;
+    idle_worker(&rpc, &db)
+        .process_task(raw(&dummy_coinbase(500, 50_000)))
+        .await?;
+
+    let mut stmt = conn.prepare("SELECT txid, node_id FROM tx_sightings ORDER BY node_id")?;
+    let sightings = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let txid = tx.compute_txid().to_string();
+    assert_eq!(
+        sightings,
+        vec![
+            (txid.clone(), "dc2".to_string()),
+            (txid, DEFAULT_NODE_ID.to_string())
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_process_task_outcomes_for_raw_txs() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    // Confirmed parent, fetched for fees once the spend leaves the mempool
+    let parent = dummy_tx(&[(dummy_txid(2), 0)], &[10_000]);
+    rpc.add_tx(&parent);
+
+    let tx = dummy_tx(&[(parent.compute_txid(), 0)], &[9_000]);
+    rpc.add_to_mempool(&tx, 1_700_000_000, Amount::from_sat(1_000));
+    assert_eq!(
+        worker.process_task(raw(&tx)).await?,
+        ProcessOutcome::Inserted
+    );
+    // Re-announcement of the same tx
+    assert_eq!(
+        worker.process_task(raw(&tx)).await?,
+        ProcessOutcome::Duplicate
+    );
+
+    let replacement = dummy_tx(&[(parent.compute_txid(), 0)], &[8_000]);
+    rpc.node().mempool.remove(&tx.compute_txid());
+    rpc.add_to_mempool(&replacement, 1_700_000_100, Amount::from_sat(2_000));
+    rpc.node()
+        .reject_reasons
+        .insert(tx.compute_txid(), "txn-mempool-conflict".to_string());
+    assert_eq!(
+        worker.process_task(raw(&replacement)).await?,
+        ProcessOutcome::Rbf
+    );
+
+    rpc.confirm(&replacement.compute_txid(), 1);
+    assert_eq!(
+        worker.process_task(raw(&replacement)).await?,
+        ProcessOutcome::Mined
+    );
+
+    assert_eq!(
+        worker
+            .process_task(raw(&dummy_coinbase(700, 50_000)))
+            .await?,
+        ProcessOutcome::Coinbase
+    );
+    assert_eq!(
+        worker.process_task(Task::RawTx(vec![0x00])).await?,
+        ProcessOutcome::Skipped
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_process_task_outcomes_for_control_tasks() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+
+    let kept = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let dropped = dummy_tx(&[(dummy_txid(2), 0)], &[9_000]);
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    for tx in [&kept, &dropped] {
+        db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(1_000), fee_rate)?;
+    }
+    rpc.add_to_mempool(&kept, 1_700_000_000, Amount::from_sat(1_000));
+
+    assert_eq!(
+        worker.process_task(Task::PruneCheck).await?,
+        ProcessOutcome::Pruned(1)
+    );
+    assert_eq!(
+        worker.process_task(Task::MempoolState).await?,
+        ProcessOutcome::MempoolStateRecorded
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_backfill_ingests_blocks_and_resumes() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+
+    let funding = dummy_tx(&[(dummy_txid(1), 0)], &[20_000]);
+    rpc.add_tx(&funding);
+    // Seen in our mempool before the monitor went down
+    let tracked = dummy_tx(&[(funding.compute_txid(), 0)], &[19_000]);
+    db.insert_mempool_tx(
+        tracked.clone(),
+        Some(1_600_000_000),
+        None,
+        Amount::from_sat(1_000),
+        fee_rate,
+    )?;
+    let funding_2 = dummy_tx(&[(dummy_txid(2), 0)], &[5_000]);
+    rpc.add_tx(&funding_2);
+    let unseen = dummy_tx(&[(funding_2.compute_txid(), 0)], &[4_000]);
+    let block_10 = dummy_block(
+        1_700_000_000,
+        vec![dummy_coinbase(10, 50_000), tracked.clone()],
+    );
+    let block_11 = dummy_block(
+        1_700_000_600,
+        vec![dummy_coinbase(11, 50_000), unseen.clone()],
+    );
+    rpc.add_block(10, block_10.clone());
+    rpc.add_block(11, block_11.clone());
+
+    let backfill = Task::Backfill {
+        from_height: 10,
+        to_height: 11,
+    };
+    assert_eq!(
+        worker.process_task(backfill.clone()).await?,
+        ProcessOutcome::Backfilled(2)
+    );
+
+    let row = |tx: &Transaction| -> Result<(u64, Option<u64>, u64, Vec<u8>, bool, u64)> {
+        Ok(conn.query_row(
+            "SELECT found_at, mined_at, block_height, block_hash, seen_in_mempool, absolute_fee
+            FROM transactions WHERE tx_id = ?1",
+            [tx.compute_txid().to_string()],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            },
+        )?)
+    };
+    let tracked_row = row(&tracked)?;
+    assert_eq!(tracked_row.0, 1_600_000_000);
+    assert_eq!(tracked_row.1, Some(1_700_000_000_000));
+    assert_eq!(tracked_row.2, 10);
+    assert_eq!(
+        tracked_row.3,
+        block_10.block_hash().to_byte_array().to_vec()
+    );
+    let unseen_row = row(&unseen)?;
+    assert_eq!(
+        unseen_row,
+        (
+            1_700_000_600_000,
+            Some(1_700_000_600_000),
+            11,
+            block_11.block_hash().to_byte_array().to_vec(),
+            false,
+            1_000
+        )
+    );
+    assert!(db.tx_exists(&block_11.txdata[0])?);
+    // Coinbases are stored as a live block's are, at the block time
+    let coinbase: (Option<u64>, Option<u64>, Option<u64>) = conn.query_row(
+        "SELECT mined_at, coinbase_value, matures_at_height FROM transactions WHERE tx_id = ?1",
+        [block_11.txdata[0].compute_txid().to_string()],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    assert_eq!(coinbase, (Some(1_700_000_600_000), Some(50_000), Some(111)));
+
+    // Re-running the range skips the covered blocks
+    assert_eq!(
+        worker.process_task(backfill).await?,
+        ProcessOutcome::Backfilled(0)
+    );
+    assert_eq!(rpc.calls("getblock"), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_backfill_stops_on_shutdown() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let worker = idle_worker(&rpc, &db).with_shutdown(shutdown_rx);
+    shutdown_tx.send_replace(true);
+
+    assert_eq!(
+        worker
+            .process_task(Task::Backfill {
+                from_height: 10,
+                to_height: 11,
+            })
+            .await?,
+        ProcessOutcome::Backfilled(0)
+    );
+    assert_eq!(rpc.calls("getblockhash"), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rescan_reconciles_pending_rows() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+
+    let still_pending = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let mined_while_down = dummy_tx(&[(dummy_txid(2), 0)], &[9_000]);
+    let evicted_while_down = dummy_tx(&[(dummy_txid(3), 0)], &[9_000]);
+    for tx in [&still_pending, &mined_while_down, &evicted_while_down] {
+        db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(1_000), fee_rate)?;
+    }
+    rpc.add_to_mempool(&still_pending, 1_700_000_000, Amount::from_sat(1_000));
+    rpc.add_tx(&mined_while_down);
+    rpc.confirm(&mined_while_down.compute_txid(), 3);
+
+    assert_eq!(
+        worker.process_task(Task::Rescan).await?,
+        ProcessOutcome::Rescanned {
+            mined: 1,
+            pruned: 1
+        }
+    );
+    let status = |tx: &Transaction| -> Result<(Option<u64>, Option<u64>)> {
+        Ok(conn.query_row(
+            "SELECT mined_at, pruned_at FROM transactions WHERE tx_id = ?1",
+            [tx.compute_txid().to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?)
+    };
+    assert_eq!(status(&still_pending)?, (None, None));
+    assert!(status(&mined_while_down)?.0.is_some());
+    assert!(status(&evicted_while_down)?.1.is_some());
+
+    // Nothing left to reconcile the second time around
+    assert_eq!(
+        worker.process_task(Task::Rescan).await?,
+        ProcessOutcome::Rescanned {
+            mined: 0,
+            pruned: 0
+        }
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rescan_only_prunes_txs_unknown_to_the_node() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    db.insert_mempool_tx(
+        tx.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+
+    rpc.node().failing_methods.insert(
+        "getrawtransactioninfo".to_string(),
+        "Work queue depth exceeded".to_string(),
+    );
+    assert_eq!(
+        worker.process_task(Task::Rescan).await?,
+        ProcessOutcome::Rescanned {
+            mined: 0,
+            pruned: 0
+        }
+    );
+    assert!(db.is_pending(&tx.compute_txid())?);
+
+    rpc.node().failing_methods.clear();
+    assert_eq!(
+        worker.process_task(Task::Rescan).await?,
+        ProcessOutcome::Rescanned {
+            mined: 0,
+            pruned: 1
+        }
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_block_filter_fetches_only_matching_blocks() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db).with_mined_detection(MinedDetection::BlockFilter);
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+
+    // Distinct output scripts for the filter to tell them apart
+    let paying_to = |n: u8, tx: Transaction| {
+        let mut tx = tx;
+        tx.output[0].script_pubkey =
+            bitcoin::ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::from_byte_array([n; 20]));
+        tx
+    };
+    let confirmed = paying_to(1, dummy_tx(&[(dummy_txid(1), 0)], &[9_000]));
+    let pending = paying_to(2, dummy_tx(&[(dummy_txid(2), 0)], &[9_000]));
+    for tx in [&confirmed, &pending] {
+        db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(1_000), fee_rate)?;
+    }
+    rpc.add_to_mempool(&pending, 1_700_000_000, Amount::from_sat(1_000));
+    let block = dummy_block(
+        1_700_000_600,
+        vec![dummy_coinbase(101, 50_000), confirmed.clone()],
+    );
+    rpc.add_block(101, block.clone());
+    rpc.confirm(&confirmed.compute_txid(), 1);
+
+    assert_eq!(
+        worker
+            .process_task(Task::NewBlock(block.block_hash()))
+            .await?,
+        ProcessOutcome::NewBlock {
+            mined: 1,
+            out_of_band: 0,
+            pruned: 0
+        }
+    );
+    // The filter matched, so the block was fetched once and no tx looked up
+    assert_eq!(rpc.calls("getblock"), 1);
+    assert_eq!(rpc.calls("getrawtransactioninfo"), 0);
+    assert!(db.block_recorded(&block.block_hash())?);
+    let mined_at = |tx: &Transaction| -> Result<Option<u64>> {
+        Ok(conn.query_row(
+            "SELECT mined_at FROM transactions WHERE tx_id = ?1",
+            [tx.compute_txid().to_string()],
+            |row| row.get(0),
+        )?)
+    };
+    assert!(mined_at(&confirmed)?.is_some());
+    assert_eq!(mined_at(&pending)?, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_block_filter_records_unmatched_blocks_from_their_summary() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db).with_mined_detection(MinedDetection::BlockFilter);
+    let mut pending = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    pending.output[0].script_pubkey =
+        bitcoin::ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::from_byte_array([7; 20]));
+    db.insert_mempool_tx(
+        pending.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+    rpc.add_to_mempool(&pending, 1_700_000_000, Amount::from_sat(1_000));
+    let block = dummy_block(1_700_000_600, vec![dummy_coinbase(101, 50_000)]);
+    rpc.add_block(101, block.clone());
+
+    for _ in 0..2 {
+        worker
+            .process_task(Task::NewBlock(block.block_hash()))
+            .await?;
+    }
+    assert!(db.block_recorded(&block.block_hash())?);
+    assert_eq!(rpc.calls("getblock"), 0);
+    assert_eq!(rpc.calls("getblocksummary"), 1);
+    // Recorded the first time, so the filter isn't fetched again
+    assert_eq!(rpc.calls("getblockfilter"), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_new_block_marks_mined_before_pruning() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+
+    let confirmed = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let evicted = dummy_tx(&[(dummy_txid(2), 0)], &[9_000]);
+    let pending = dummy_tx(&[(dummy_txid(3), 0)], &[9_000]);
+    for tx in [&confirmed, &evicted, &pending] {
+        db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(1_000), fee_rate)?;
+    }
+    // Neither the confirmed nor the evicted tx is in the mempool anymore
+    rpc.add_to_mempool(&pending, 1_700_000_000, Amount::from_sat(1_000));
+    let block = dummy_block(
+        1_700_000_600,
+        vec![dummy_coinbase(900, 50_000), confirmed.clone()],
+    );
+    rpc.add_block(900, block.clone());
+
+    assert_eq!(
+        worker
+            .process_task(Task::NewBlock(block.block_hash()))
+            .await?,
+        ProcessOutcome::NewBlock {
+            mined: 1,
+            out_of_band: 0,
+            pruned: 1
+        }
+    );
+    let status = |tx: &Transaction| -> Result<(Option<u64>, Option<u64>)> {
+        Ok(conn.query_row(
+            "SELECT mined_at, pruned_at FROM transactions WHERE tx_id = ?1",
+            [tx.compute_txid().to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?)
+    };
+    let (mined_at, pruned_at) = status(&confirmed)?;
+    assert!(mined_at.is_some());
+    assert_eq!(pruned_at, None);
+    assert!(status(&evicted)?.1.is_some());
+    assert_eq!(status(&pending)?, (None, None));
+    assert!(db.tx_exists(&block.txdata[0])?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_new_block_publishes_mined_and_block_events() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let (_, control_rx) = bounded(1);
+    let (_, tasks_rx) = bounded(1);
+    let events = EventPublisher::disabled().with_broadcast(16);
+    let mut receiver = events.subscribe().unwrap();
+    let worker = TaskContext::new(
+        rpc.clone(),
+        db.clone(),
+        events,
+        Filter::default(),
+        control_rx,
+        tasks_rx,
+        TipTracker::new(Duration::from_secs(1800)),
+    );
+    let confirmed = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    db.insert_mempool_tx(
+        confirmed.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+    let block = dummy_block(
+        1_700_000_600,
+        vec![dummy_coinbase(900, 50_000), confirmed.clone()],
+    );
+    rpc.add_block(900, block.clone());
+
+    worker
+        .process_task(Task::NewBlock(block.block_hash()))
+        .await?;
+    let block_hash = block.block_hash().to_string();
+    assert_eq!(
+        receiver.try_recv()?,
+        MempoolEvent::Mined {
+            txid: confirmed.compute_txid().to_string(),
+            block_hash: Some(block_hash.clone()),
+        }
+    );
+    assert_eq!(
+        receiver.try_recv()?,
+        MempoolEvent::Block {
+            hash: block_hash,
+            height: Some(900),
+            tx_count: 2,
+            mined: 1,
+        }
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_only_txs_above_the_large_value_threshold_are_flagged() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let (_, control_rx) = bounded(1);
+    let (_, tasks_rx) = bounded(1);
+    let events = EventPublisher::disabled().with_broadcast(16);
+    let mut receiver = events.subscribe().unwrap();
+    let worker = TaskContext::new(
+        rpc.clone(),
+        db.clone()
+            .with_large_value_threshold(Amount::from_sat(50_000)),
+        events,
+        Filter::default(),
+        control_rx,
+        tasks_rx,
+        TipTracker::new(Duration::from_secs(1800)),
+    );
+    let small = dummy_tx(&[(dummy_txid(1), 0)], &[9_000, 40_000]);
+    let whale = dummy_tx(&[(dummy_txid(2), 0)], &[30_000, 40_000]);
+    for tx in [&small, &whale] {
+        rpc.add_to_mempool(tx, 1_700_000_000, Amount::from_sat(1_000));
+        assert_eq!(
+            worker.process_task(raw(tx)).await?,
+            ProcessOutcome::Inserted
+        );
+    }
+
+    let flagged = |tx: &Transaction| -> Result<bool> {
+        Ok(conn.query_row(
+            "SELECT is_large_value FROM transactions WHERE tx_id = ?1",
+            [tx.compute_txid().to_string()],
+            |row| row.get(0),
+        )?)
+    };
+    assert!(!flagged(&small)?);
+    assert!(flagged(&whale)?);
+    assert_eq!(
+        db.large_value_txids(0, 4_000_000_000)?,
+        vec![whale.compute_txid()]
+    );
+
+    // A replacement is judged on its own outputs
+    let bumped = dummy_tx(&[(dummy_txid(1), 0)], &[9_000, 45_000]);
+    rpc.node().mempool.remove(&small.compute_txid());
+    rpc.add_to_mempool(&bumped, 1_700_000_100, Amount::from_sat(1_500));
+    rpc.node()
+        .reject_reasons
+        .insert(small.compute_txid(), "txn-mempool-conflict".to_string());
+    assert_eq!(
+        worker.process_task(raw(&bumped)).await?,
+        ProcessOutcome::Rbf
+    );
+    assert!(flagged(&bumped)?);
+
+    let events: Vec<MempoolEvent> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+    let large_value: Vec<_> = events
+        .iter()
+        .filter(|event| matches!(event, MempoolEvent::LargeValue { .. }))
+        .collect();
+    assert_eq!(
+        large_value,
+        vec![
+            &MempoolEvent::LargeValue {
+                txid: whale.compute_txid().to_string(),
+                total_out_value: 70_000,
+                threshold: 50_000,
+            },
+            &MempoolEvent::LargeValue {
+                txid: bumped.compute_txid().to_string(),
+                total_out_value: 54_000,
+                threshold: 50_000,
+            },
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_prune_check_catches_up_with_unprocessed_block() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+
+    let txs: Vec<_> = (1..=3)
+        .map(|n| dummy_tx(&[(dummy_txid(n), 0)], &[9_000]))
+        .collect();
+    for tx in &txs {
+        db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(1_000), fee_rate)?;
+        rpc.add_to_mempool(tx, 1_700_000_000, Amount::from_sat(1_000));
+    }
+    assert_eq!(
+        worker.process_task(Task::PruneCheck).await?,
+        ProcessOutcome::Pruned(0)
+    );
+
+    // A block mines two of them, the prune check runs before its NewBlock task
+    let block = dummy_block(
+        1_700_000_600,
+        vec![dummy_coinbase(101, 50_000), txs[0].clone(), txs[1].clone()],
+    );
+    rpc.add_block(101, block.clone());
+    for tx in &txs[..2] {
+        rpc.confirm(&tx.compute_txid(), 1);
+    }
+    assert_eq!(
+        worker.process_task(Task::PruneCheck).await?,
+        ProcessOutcome::Pruned(0)
+    );
+    let status = |tx: &Transaction| -> Result<(Option<u64>, Option<u64>)> {
+        Ok(conn.query_row(
+            "SELECT mined_at, pruned_at FROM transactions WHERE tx_id = ?1",
+            [tx.compute_txid().to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?)
+    };
+    for tx in &txs[..2] {
+        let (mined_at, pruned_at) = status(tx)?;
+        assert!(mined_at.is_some());
+        assert_eq!(pruned_at, None);
+    }
+    assert_eq!(status(&txs[2])?, (None, None));
+
+    // The late NewBlock task finds the block already processed
+    assert_eq!(
+        worker
+            .process_task(Task::NewBlock(block.block_hash()))
+            .await?,
+        ProcessOutcome::NewBlock {
+            mined: 0,
+            out_of_band: 0,
+            pruned: 0
+        }
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_prune_check_catches_up_with_every_unprocessed_block() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let last_seen = dummy_block(1_700_000_000, vec![dummy_coinbase(100, 50_000)]);
+    rpc.add_block(100, last_seen.clone());
+    worker
+        .process_task(Task::NewBlock(last_seen.block_hash()))
+        .await?;
+
+    // Two blocks arrive while their NewBlock tasks are lost, each mining a tracked tx
+    let txs: Vec<_> = (1..=2)
+        .map(|n| dummy_tx(&[(dummy_txid(n), 0)], &[9_000]))
+        .collect();
+    let mut blocks = vec![];
+    for (n, tx) in txs.iter().enumerate() {
+        let height = 101 + n as u64;
+        db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(1_000), fee_rate)?;
+        let block = dummy_block(
+            1_700_000_600 + n as u32,
+            vec![dummy_coinbase(height as i64, 50_000), tx.clone()],
+        );
+        rpc.add_block(height, block.clone());
+        rpc.confirm(&tx.compute_txid(), 1);
+        blocks.push(block);
+    }
+    // Neither is in the mempool anymore, so both are pruned unless caught up with first
+    assert_eq!(
+        worker.process_task(Task::PruneCheck).await?,
+        ProcessOutcome::Pruned(0)
+    );
+    for tx in &txs {
+        let (mined_at, pruned_at): (Option<u64>, Option<u64>) = conn.query_row(
+            "SELECT mined_at, pruned_at FROM transactions WHERE tx_id = ?1",
+            [tx.compute_txid().to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        assert!(mined_at.is_some());
+        assert_eq!(pruned_at, None);
+    }
+    for block in &blocks {
+        assert!(db.block_recorded(&block.block_hash())?);
+    }
+    assert_eq!(db.last_recorded_block_height()?, Some(102));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_block_is_recorded_once_by_racing_workers() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let events = EventPublisher::disabled().with_broadcast(16);
+    let mut receiver = events.subscribe().unwrap();
+    let recorder = BlockRecorder::default();
+    let worker = || {
+        let (_, control_rx) = bounded(1);
+        let (_, tasks_rx) = bounded(1);
+        TaskContext::new(
+            rpc.clone(),
+            db.clone(),
+            events.clone(),
+            Filter::default(),
+            control_rx,
+            tasks_rx,
+            TipTracker::new(Duration::from_secs(1800)),
+        )
+        .with_block_recorder(recorder.clone())
+    };
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    db.insert_mempool_tx(
+        tx.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+    let block = dummy_block(1_700_000_600, vec![dummy_coinbase(101, 50_000), tx.clone()]);
+    rpc.add_block(101, block.clone());
+    rpc.confirm(&tx.compute_txid(), 1);
+
+    // The block's NewBlock task and a prune check catching up with it
+    let (new_block, prune_check) = tokio::join!(
+        worker().process_task(Task::NewBlock(block.block_hash())),
+        worker().process_task(Task::PruneCheck)
+    );
+    new_block?;
+    prune_check?;
+    let mut block_events = 0;
+    while let Ok(event) = receiver.try_recv() {
+        if matches!(event, MempoolEvent::Block { ref hash, .. } if *hash == block.block_hash().to_string())
+        {
+            block_events += 1;
+        }
+    }
+    assert_eq!(block_events, 1);
+    Ok(())
+}
+#[tokio::test]
+async fn test_mempool_state_tracks_tip_changes() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    rpc.node().blockchain.blocks = 100;
+    let tip = TipTracker::new(Duration::from_secs(1800));
+    let (_, control_rx) = bounded(1);
+    let (_, tasks_rx) = bounded(1);
+    let worker = TaskContext::new(
+        rpc.clone(),
+        db.clone(),
+        EventPublisher::disabled(),
+        Filter::default(),
+        control_rx,
+        tasks_rx,
+        tip.clone(),
+    );
+
+    worker.process_task(Task::MempoolState).await?;
+    let first = tip.last().expect("tip observed");
+    assert_eq!(first.height, 100);
+
+    // An unchanged tip keeps its original observation time
+    tip.set(mempool_tracker::tip::ObservedTip {
+        observed_at: first.observed_at - 600,
+        ..first
+    });
+    worker.process_task(Task::MempoolState).await?;
+    assert_eq!(tip.last().unwrap().observed_at, first.observed_at - 600);
+    assert!(tip.age_secs() >= 600);
+
+    rpc.node().blockchain.blocks = 101;
+    worker.process_task(Task::MempoolState).await?;
+    assert_eq!(tip.last().unwrap().height, 101);
+    assert!(tip.age_secs() < 600);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mempool_state_records_churn_between_polls() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let txs: Vec<_> = (1..=5)
+        .map(|n| dummy_tx(&[(dummy_txid(n), 0)], &[9_000]))
+        .collect();
+    for tx in &txs[..3] {
+        rpc.add_to_mempool(tx, 1_700_000_000, Amount::from_sat(200));
+    }
+    // Workers sharing the poll diff against each other's snapshots
+    let poll = MempoolPoll::default();
+    idle_worker(&rpc, &db)
+        .with_mempool_poll(poll.clone())
+        .process_task(Task::MempoolState)
+        .await?;
+
+    rpc.node().mempool.remove(&txs[0].compute_txid());
+    for tx in &txs[3..] {
+        rpc.add_to_mempool(tx, 1_700_000_001, Amount::from_sat(200));
+    }
+    idle_worker(&rpc, &db)
+        .with_mempool_poll(poll)
+        .process_task(Task::MempoolState)
+        .await?;
+
+    let snapshots = db.mempool_snapshots(0, u64::MAX, 10, 0)?;
+    assert_eq!(snapshots.len(), 2);
+    assert_eq!(snapshots[0].churn, None);
+    assert_eq!(
+        snapshots[1].churn,
+        Some(MempoolChurn {
+            added_since_last: 2,
+            removed_since_last: 1
+        })
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mass_prune_is_recorded_as_purge() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    let txs: Vec<_> = (1..=20)
+        .map(|n| dummy_tx(&[(dummy_txid(n), 0)], &[9_000]))
+        .collect();
+    for tx in &txs {
+        db.insert_mempool_tx(tx.clone(), None, None, Amount::from_sat(200), fee_rate)?;
+    }
+    for tx in &txs[..6] {
+        rpc.add_to_mempool(tx, 1_700_000_000, Amount::from_sat(200));
+    }
+    worker.process_task(Task::MempoolState).await?;
+
+    // The node trims its full mempool and raises its floor
+    rpc.node().mempool.remove(&txs[5].compute_txid());
+    rpc.node().mempool_min_fee = FeeRate::from_sat_per_vb_unchecked(5);
+    assert_eq!(
+        worker.process_task(Task::PruneCheck).await?,
+        ProcessOutcome::Pruned(15)
+    );
+    let events = db.purge_events(0, u32::MAX as u64)?;
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event.removed_count, 15);
+    assert_eq!(
+        event.removed_vbytes,
+        txs[5..].iter().map(|tx| tx.vsize() as u64).sum::<u64>()
+    );
+    assert_eq!(event.mempool_min_fee_before, Some(FeeRate::BROADCAST_MIN));
+    assert_eq!(
+        event.mempool_min_fee_after,
+        Some(FeeRate::from_sat_per_vb_unchecked(5))
+    );
+    let purged: u64 = conn.query_row(
+        "SELECT COUNT(*) FROM transactions WHERE removal_reason = 'purged'",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(purged, 15);
+
+    // A single eviction is an ordinary prune
+    rpc.node().mempool.remove(&txs[4].compute_txid());
+    assert_eq!(
+        worker.process_task(Task::PruneCheck).await?,
+        ProcessOutcome::Pruned(1)
+    );
+    assert_eq!(db.purge_events(0, u32::MAX as u64)?.len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_new_block_records_out_of_band_txs() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+
+    let seen = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    db.insert_mempool_tx(seen.clone(), None, None, Amount::from_sat(1_000), fee_rate)?;
+    // Submitted straight to the miner, its funding tx is already confirmed
+    let funding = dummy_tx(&[(dummy_txid(2), 0)], &[10_000]);
+    rpc.add_tx(&funding);
+    let accelerated = dummy_tx(&[(funding.compute_txid(), 0)], &[7_500]);
+    // Prevout unknown to the node, its fee can't be computed
+    let unpriced = dummy_tx(&[(dummy_txid(3), 0)], &[5_000]);
+    let block = dummy_block(
+        1_700_000_600,
+        vec![
+            dummy_coinbase(900, 50_000),
+            seen.clone(),
+            accelerated.clone(),
+            unpriced.clone(),
+        ],
+    );
+    rpc.add_block(900, block.clone());
+    rpc.add_to_mempool(&dummy_tx(&[(dummy_txid(4), 0)], &[1_000]), 0, Amount::ZERO);
+
+    assert_eq!(
+        worker
+            .process_task(Task::NewBlock(block.block_hash()))
+            .await?,
+        ProcessOutcome::NewBlock {
+            mined: 1,
+            out_of_band: 1,
+            pruned: 0
+        }
+    );
+    let (found_at, mined_at, out_of_band, seen_in_mempool): (u64, u64, bool, bool) = conn
+        .query_row(
+            "SELECT found_at, mined_at, out_of_band, seen_in_mempool FROM transactions WHERE tx_id = ?1",
+            [accelerated.compute_txid().to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+    assert_eq!((found_at, mined_at), (1_700_000_600_000, 1_700_000_600_000));
+    assert!(out_of_band && !seen_in_mempool);
+    let seen_out_of_band: bool = conn.query_row(
+        "SELECT out_of_band FROM transactions WHERE tx_id = ?1",
+        [seen.compute_txid().to_string()],
+        |row| row.get(0),
+    )?;
+    assert!(!seen_out_of_band);
+
+    let stats = db.out_of_band_stats(1_700_000_000, 1_700_001_000)?;
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].block_height, Some(900));
+    assert_eq!(stats[0].block_hash, block.block_hash());
+    assert_eq!(stats[0].tx_count, 1);
+    assert_eq!(stats[0].total_fee, Amount::from_sat(2_500));
+    assert_eq!(stats[0].vsize, accelerated.vsize() as u64);
+    let block_vsize = block.weight().to_vbytes_ceil() as f64;
+    assert_eq!(
+        stats[0].block_share,
+        accelerated.vsize() as f64 / block_vsize
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reconcile_mempool_stores_missed_txs() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let txs: Vec<_> = (1..=3)
+        .map(|n| dummy_tx(&[(dummy_txid(n), 0)], &[9_000]))
+        .collect();
+    for tx in &txs {
+        rpc.add_to_mempool(tx, 1_700_000_000, Amount::from_sat(1_000));
+    }
+    // Only the first announcement made it through before the gap
+    assert_eq!(
+        worker.process_task(raw(&txs[0])).await?,
+        ProcessOutcome::Inserted
+    );
+
+    assert_eq!(
+        worker.process_task(Task::ReconcileMempool).await?,
+        ProcessOutcome::Reconciled(2)
+    );
+    let stored: u64 = conn.query_row("SELECT COUNT(*) FROM transactions", [], |row| row.get(0))?;
+    assert_eq!(stored, 3);
+    assert!(db
+        .untracked_txids(&txs.iter().map(|tx| tx.compute_txid()).collect::<Vec<_>>())?
+        .is_empty());
+    // Batch inserted with the node's entry time and fee
+    for tx in &txs[1..] {
+        let (node_seen_at, absolute_fee): (Option<u64>, u64) = conn.query_row(
+            "SELECT node_seen_at, absolute_fee FROM transactions WHERE tx_id = ?1",
+            [tx.compute_txid().to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        assert_eq!(node_seen_at, Some(1_700_000_000_000));
+        assert_eq!(absolute_fee, 1_000);
+    }
+    assert_eq!(
+        worker.process_task(Task::ReconcileMempool).await?,
+        ProcessOutcome::Reconciled(0)
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_checkpoint_records_end_of_transactions() -> Result<()> {
+    let (_dir, db, _conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    assert_eq!(db.latest_checkpoint()?, None);
+
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+    for vout in 0..3 {
+        let tx = dummy_tx(&[(dummy_txid(1), vout)], &[10_000]);
+        db.insert_mempool_tx(tx, None, None, Amount::from_sat(200), fee_rate)?;
+    }
+    let outcome = worker.process_task(Task::Checkpoint).await?;
+    assert_eq!(outcome, ProcessOutcome::Checkpointed(3));
+
+    let checkpoint = db.latest_checkpoint()?.expect("checkpoint recorded");
+    assert_eq!(checkpoint.last_seq, 3);
+    assert_eq!(checkpoint.pending_count, 3);
+    assert_eq!(checkpoint.best_block_hash, rpc.get_block_hash(100).await?);
+
+    // A later checkpoint supersedes it
+    rpc.node().blockchain.blocks = 101;
+    worker.process_task(Task::Checkpoint).await?;
+    let latest = db.latest_checkpoint()?.unwrap();
+    assert_eq!(latest.best_block_hash, rpc.get_block_hash(101).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_raw_block_is_recorded_without_fetching_it() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    db.insert_mempool_tx(
+        tx.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+    let block = dummy_block(
+        1_700_000_000,
+        vec![dummy_coinbase(101, 312_500_000), tx.clone()],
+    );
+    // The node's tip, so the follow-up prune check has nothing to catch up with
+    rpc.add_block(101, block.clone());
+    let mut raw_block = vec![];
+    block.consensus_encode(&mut raw_block)?;
+
+    assert_eq!(
+        worker.process_task(Task::RawBlock(raw_block)).await?,
+        ProcessOutcome::NewBlock {
+            mined: 1,
+            out_of_band: 0,
+            pruned: 0
+        }
+    );
+    assert_eq!(rpc.calls("getblock"), 0);
+    let block_hash: Option<Vec<u8>> = conn.query_row(
+        "SELECT block_hash FROM transactions WHERE tx_id = ?1",
+        [tx.compute_txid().to_string()],
+        |row| row.get(0),
+    )?;
+    assert_eq!(
+        block_hash,
+        Some(block.block_hash().to_byte_array().to_vec())
+    );
+
+    // The same block announced again on another topic is not recorded twice
+    assert_eq!(
+        worker
+            .process_task(Task::Sequence(SequenceEvent::BlockConnected(
+                block.block_hash()
+            )))
+            .await?,
+        ProcessOutcome::NewBlock {
+            mined: 0,
+            out_of_band: 0,
+            pruned: 0
+        }
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replacement_after_sequence_removal_stays_pending() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let original = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    let replacement = dummy_tx(&[(dummy_txid(1), 0)], &[8_500]);
+    db.insert_mempool_tx(
+        original.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+    rpc.add_to_mempool(&replacement, 1_700_000_000, Amount::from_sat(1_500));
+    rpc.node()
+        .reject_reasons
+        .insert(original.compute_txid(), "txn-mempool-conflict".to_string());
+
+    // The node announces the removal of the replaced tx first, on the control queue
+    assert_eq!(
+        worker
+            .process_task(Task::Sequence(SequenceEvent::TxRemoved(
+                original.compute_txid()
+            )))
+            .await?,
+        ProcessOutcome::Pruned(1)
+    );
+    assert_eq!(
+        worker.process_task(raw(&replacement)).await?,
+        ProcessOutcome::Rbf
+    );
+
+    let (tx_id, pruned_at): (String, Option<u64>) =
+        conn.query_row("SELECT tx_id, pruned_at FROM transactions", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    assert_eq!(tx_id, replacement.compute_txid().to_string());
+    assert_eq!(pruned_at, None);
+    assert!(db.is_pending(&replacement.compute_txid())?);
+    let claimant: String = conn.query_row(
+        "SELECT txid FROM spent_outpoints WHERE outpoint = ?1",
+        [original.input[0].previous_output.to_string()],
+        |row| row.get(0),
+    )?;
+    assert_eq!(claimant, replacement.compute_txid().to_string());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sequence_removal_prunes_tracked_tx() -> Result<()> {
+    let (_dir, db, conn) = temp_db();
+    let rpc = MockRpc::default();
+    let worker = idle_worker(&rpc, &db);
+    let tx = dummy_tx(&[(dummy_txid(1), 0)], &[9_000]);
+    db.insert_mempool_tx(
+        tx.clone(),
+        None,
+        None,
+        Amount::from_sat(1_000),
+        FeeRate::from_sat_per_vb_unchecked(1),
+    )?;
+    let removed = Task::Sequence(SequenceEvent::TxRemoved(tx.compute_txid()));
+
+    assert_eq!(
+        worker.process_task(removed.clone()).await?,
+        ProcessOutcome::Pruned(1)
+    );
+    let pruned_at: Option<u64> = conn.query_row(
+        "SELECT pruned_at FROM transactions WHERE tx_id = ?1",
+        [tx.compute_txid().to_string()],
+        |row| row.get(0),
+    )?;
+    assert!(pruned_at.is_some());
+    // Already pruned, and untracked txs are ignored
+    assert_eq!(
+        worker.process_task(removed).await?,
+        ProcessOutcome::Pruned(0)
+    );
+    assert_eq!(
+        worker
+            .process_task(Task::Sequence(SequenceEvent::TxRemoved(dummy_txid(9))))
+            .await?,
+        ProcessOutcome::Pruned(0)
+    );
+    Ok(())
+}
@@ -0,0 +1,82 @@
+#![cfg(feature = "http")]
+
+use mempool_tracker::{
+    events::MempoolEvent,
+    ws::{FeedEvent, FeedKind, Subscription},
+};
+
+fn seen(fee_rate: u64) -> FeedEvent {
+    FeedEvent::from_event(&MempoolEvent::New {
+        txid: "aa".to_string(),
+        fee: 1_000,
+        fee_rate,
+        vsize: 200,
+        found_at: 0,
+    })
+    .unwrap()
+}
+
+#[test]
+fn test_feed_events_from_worker_events() {
+    assert_eq!(
+        FeedEvent::from_event(&MempoolEvent::Rbf {
+            txid: "bb".to_string(),
+            replaced_txid: Some("aa".to_string()),
+            fee: Some(1_500),
+            prev_fee: Some(1_000),
+            fee_bump_pct: Some(50.0),
+        }),
+        Some(FeedEvent::TxReplaced {
+            old: Some("aa".to_string()),
+            new: "bb".to_string(),
+            fee_delta: Some(500),
+        })
+    );
+    // The coinbase doesn't count towards coverage
+    assert_eq!(
+        FeedEvent::from_event(&MempoolEvent::Block {
+            hash: "cc".to_string(),
+            height: Some(101),
+            tx_count: 5,
+            mined: 3,
+        }),
+        Some(FeedEvent::Block {
+            hash: "cc".to_string(),
+            height: Some(101),
+            coverage: Some(0.75),
+        })
+    );
+    assert_eq!(
+        FeedEvent::from_event(&MempoolEvent::Conflict {
+            txid: "aa".to_string(),
+            conflicting_txid: "bb".to_string(),
+            outpoints: vec![],
+        }),
+        None
+    );
+    assert_eq!(
+        serde_json::to_value(seen(12)).unwrap(),
+        serde_json::json!({"type": "tx_seen", "txid": "aa", "fee_rate": 12, "vsize": 200})
+    );
+}
+
+#[test]
+fn test_subscription_filters_by_kind_and_fee_rate() {
+    let evicted = FeedEvent::TxEvicted {
+        txid: "aa".to_string(),
+    };
+    assert!(Subscription::default().matches(&evicted));
+
+    let blocks_only: Subscription = serde_json::from_str(r#"{"types": ["block"]}"#).unwrap();
+    assert_eq!(blocks_only.types, Some(vec![FeedKind::Block]));
+    assert!(!blocks_only.matches(&evicted));
+    assert!(!blocks_only.matches(&seen(50)));
+
+    // Only tx_seen carries a fee rate, other kinds pass
+    let min_rate: Subscription = serde_json::from_str(r#"{"min_fee_rate": 20}"#).unwrap();
+    assert!(min_rate.matches(&seen(20)));
+    assert!(!min_rate.matches(&seen(19)));
+    assert!(min_rate.matches(&evicted));
+
+    assert!(serde_json::from_str::<Subscription>(r#"{"kinds": ["block"]}"#).is_err());
+}
@@ -0,0 +1,18 @@
+use mempool_tracker::zmq_factory::SequenceGapDetector;
+
+#[test]
+fn test_sequence_gaps_are_sized_per_topic() {
+    let mut gaps = SequenceGapDetector::default();
+    assert_eq!(gaps.observe("rawtx", 7), None);
+    assert_eq!(gaps.observe("rawtx", 8), None);
+    // Another topic counts independently
+    assert_eq!(gaps.observe("hashblock", 0), None);
+    assert_eq!(gaps.observe("rawtx", 12), Some(3));
+    assert_eq!(gaps.observe("hashblock", 1), None);
+    // Restarted publisher, numbering starts over
+    assert_eq!(gaps.observe("rawtx", 3), None);
+    assert_eq!(gaps.observe("rawtx", 4), None);
+    // The counter wraps around
+    gaps.observe("rawtx", u32::MAX);
+    assert_eq!(gaps.observe("rawtx", 1), Some(1));
+}